@@ -40,6 +40,16 @@ struct BlockProducts {
     block_products: products::BlockProducts,
 }
 
+#[pyclass]
+struct WorkMap {
+    work_map: Arc<products::WorkProducts>,
+}
+
+#[pyclass]
+struct BlockWorkMap {
+    block_work: products::BlockWorkProducts,
+}
+
 
 
 
@@ -93,6 +103,12 @@ impl Products {
         Ok(pythonize(py, &self.products.config)?)
     }
 
+    /// Observer-frame timestamps, if the run has a reference epoch
+    #[getter]
+    fn observer_times(&self, py: Python) -> PyResult<PyObject> {
+        Ok(pythonize(py, &self.products.observer_times)?)
+    }
+
     /// A way to access radial profiles of the hydrodynamic data. In Python
     /// code, typing `products.radial_profile[10].scalar` would return a 1D
     /// numpy array of the scalar concentration for the zones at polar index
@@ -218,7 +234,9 @@ impl RadialProfileGetter {
 #[pyproto]
 impl PyMappingProtocol for RadialProfileGetter {
     fn __getitem__(&self, polar_index: usize) -> PyResult<RadialProfile> {
-        if polar_index >= self.products.config.mesh.num_polar_zones {
+        let num_polar_zones = self.products.blocks.values().next().map_or(0, |b| b.scalar.dim().1);
+
+        if polar_index >= num_polar_zones {
             pyo3::Python::with_gil(|py| {
                 Err(PyErr::from_instance(PyIndexError::new_err("invalid block index").instance(py)))
             })
@@ -337,6 +355,88 @@ impl BlockProducts {
 
 
 
+// ============================================================================
+#[pymethods]
+impl WorkMap {
+
+    /// The simulation time when the work map was written
+    #[getter]
+    fn time(&self) -> f64 {
+        self.work_map.time
+    }
+
+    /// A dict of the runtime configuration. This dict will mirror the
+    /// app::Configuration struct.
+    #[getter]
+    fn config(&self, py: Python) -> PyResult<PyObject> {
+        Ok(pythonize(py, &self.work_map.config)?)
+    }
+}
+
+#[pyproto]
+impl PyMappingProtocol for WorkMap {
+
+    fn __len__(&self) -> usize {
+        self.work_map.blocks.len()
+    }
+
+    fn __getitem__(&self, key: mesh::BlockIndex) -> PyResult<BlockWorkMap> {
+        if let Some(b) = self.work_map.blocks.get(&key) {
+            Ok(BlockWorkMap{block_work: b.clone()})
+        } else {
+            pyo3::Python::with_gil(|py| {
+                Err(PyErr::from_instance(PyKeyError::new_err("invalid block index").instance(py)))
+            })
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for WorkMap {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<Py<ProductsIter>> {
+        let keys: Vec<_> = slf.work_map.blocks.keys().cloned().collect();
+        let iter = ProductsIter {
+            inner: keys.into_iter()
+        };
+        Py::new(slf.py(), iter)
+    }
+}
+
+#[pymethods]
+impl BlockWorkMap {
+
+    #[getter]
+    fn radial_vertices(&self, py: Python) -> PyObject {
+        self.block_work.radial_vertices.to_pyarray(py).to_object(py)
+    }
+
+    #[getter]
+    fn polar_vertices(&self, py: Python) -> PyObject {
+        self.block_work.polar_vertices.to_pyarray(py).to_object(py)
+    }
+
+    /// Mean wall-clock seconds spent per zone, per Runge-Kutta stage
+    #[getter]
+    fn seconds_per_zone_update(&self) -> f64 {
+        self.block_work.seconds_per_zone_update
+    }
+
+    /// Number of Runge-Kutta stages the measurement is averaged over
+    #[getter]
+    fn updates(&self) -> u64 {
+        self.block_work.updates
+    }
+
+    /// Number of primitive recovery fallbacks in each zone
+    #[getter]
+    fn recovery_fallbacks(&self, py: Python) -> PyObject {
+        self.block_work.recovery_fallbacks.to_pyarray(py).to_object(py)
+    }
+}
+
+
+
+
 // ============================================================================
 #[pyfunction]
 fn app(filename: &str) -> PyResult<App> {
@@ -348,12 +448,20 @@ fn app(filename: &str) -> PyResult<App> {
 
 #[pyfunction]
 fn products(filename: &str) -> PyResult<Products> {
-    match io::read_cbor(filename) {
+    match products::Products::from_file(filename) {
         Ok(products) => Ok(Products{products: Arc::new(products)}),
         Err(e)       => Err(PyValueError::new_err(format!("{}", e))),
     }
 }
 
+#[pyfunction]
+fn work_map(filename: &str) -> PyResult<WorkMap> {
+    match io::read_cbor(filename) {
+        Ok(work_map) => Ok(WorkMap{work_map: Arc::new(work_map)}),
+        Err(e)       => Err(PyValueError::new_err(format!("{}", e))),
+    }
+}
+
 
 
 
@@ -362,5 +470,6 @@ fn products(filename: &str) -> PyResult<Products> {
 fn knc_loader(_: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(app, m)?)?;
     m.add_function(wrap_pyfunction!(products, m)?)?;
+    m.add_function(wrap_pyfunction!(work_map, m)?)?;
     Ok(())
 }
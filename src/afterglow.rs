@@ -0,0 +1,293 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::app::{AnyHydro, Configuration};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::products::Products;
+
+/// Number of logarithmic four-velocity bins per decade
+static GAMMA_BETA_BINS_PER_DECADE: usize = 10;
+
+/// Range of four-velocity covered by the distribution; material outside it
+/// is put in the first or last bin
+static GAMMA_BETA_RANGE: (f64, f64) = (1e-3, 1e3);
+
+/// Angular bins with less than this fraction of the peak isotropic-equivalent
+/// energy are left out of the structured-jet fits
+static FIT_ENERGY_FLOOR: f64 = 1e-6;
+
+
+
+
+/**
+ * The distribution of the kinetic plus thermal energy (excluding rest mass)
+ * over the angle from the jet axis and the radial four-velocity. The jet is
+ * assumed to be bipolar, so the two hemispheres are folded together.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KinematicDistribution {
+
+    /// Bin edges in the angle from the jet axis (radians)
+    pub theta_vertices: Vec<f64>,
+
+    /// Bin edges in the radial four-velocity gamma-beta
+    pub gamma_beta_vertices: Vec<f64>,
+
+    /// Energy (erg) in each (theta, gamma-beta) bin, indexed as
+    /// `energy[i][j]` for theta bin i and gamma-beta bin j
+    pub energy: Vec<Vec<f64>>,
+}
+
+
+
+
+/**
+ * A fitted structured-jet profile for the isotropic-equivalent energy versus
+ * angle, in the form used by afterglowpy.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StructuredJetFit {
+
+    /// The isotropic-equivalent energy on the jet axis (erg)
+    pub e0: f64,
+
+    /// The core angle (radians)
+    pub theta_core: f64,
+
+    /// The power-law index b of the wings, for power-law jets
+    pub wing_index: Option<f64>,
+
+    /// The truncation angle: the largest angle with energy included in the
+    /// fit (radians)
+    pub theta_wing: f64,
+
+    /// RMS deviation of log10(E_iso) between the data and the fit, over the
+    /// fitted angular bins
+    pub rms_log_residual: f64,
+
+    /// Number of angular bins included in the fit
+    pub num_points: usize,
+}
+
+
+
+
+/**
+ * The kinematic distribution of an output, together with the structured-jet
+ * fits for afterglowpy (jetType 0, Gaussian, and jetType 4, power law)
+ */
+#[derive(Serialize, Deserialize)]
+pub struct AfterglowProducts {
+    pub time: f64,
+    pub distribution: KinematicDistribution,
+    pub gaussian: Option<StructuredJetFit>,
+    pub power_law: Option<StructuredJetFit>,
+    pub config: Configuration,
+    pub version: String,
+}
+
+
+
+
+// ============================================================================
+impl KinematicDistribution {
+
+    /**
+     * Bin the energy in the given products into angle and four-velocity. The
+     * angular bins are evenly spaced from the axis to the equator, with as
+     * many bins as there are polar zones in a hemisphere.
+     */
+    pub fn from_products(products: &Products) -> Self {
+        let num_polar_zones = products.blocks.values().next().map_or(1, |b| b.scalar.dim().1);
+        let num_theta = (num_polar_zones / 2).max(1);
+        let (u0, u1) = GAMMA_BETA_RANGE;
+        let num_u = ((u1 / u0).log10() * GAMMA_BETA_BINS_PER_DECADE as f64).round() as usize;
+
+        let theta_vertices: Vec<_> = (0..=num_theta).map(|i| 0.5 * PI * i as f64 / num_theta as f64).collect();
+        let gamma_beta_vertices: Vec<_> = (0..=num_u).map(|j| u0 * (u1 / u0).powf(j as f64 / num_u as f64)).collect();
+        let mut energy = vec![vec![0.0; num_u]; num_theta];
+
+        for block in products.blocks.values() {
+            let (nr, nq) = block.primitive.dim();
+
+            for i in 0..nr {
+                for j in 0..nq {
+                    let (r0, r1) = (block.radial_vertices[i], block.radial_vertices[i + 1]);
+                    let (q0, q1) = (block.polar_vertices[j], block.polar_vertices[j + 1]);
+                    let dv = 2.0 * PI / 3.0 * (r1.powi(3) - r0.powi(3)) * (q0.cos() - q1.cos());
+                    let p = &block.primitive[(i, j)];
+                    let (u, e) = zone_gamma_beta_and_energy(p, &products.config.hydro);
+
+                    let theta = f64::min(0.5 * (q0 + q1), PI - 0.5 * (q0 + q1));
+                    let a = ((theta / (0.5 * PI)) * num_theta as f64) as usize;
+                    let b = ((u.max(u0) / u0).log10() * GAMMA_BETA_BINS_PER_DECADE as f64) as usize;
+
+                    energy[a.min(num_theta - 1)][b.min(num_u - 1)] += e * dv;
+                }
+            }
+        }
+        Self { theta_vertices, gamma_beta_vertices, energy }
+    }
+
+    /**
+     * Return the angular bin centers, and the isotropic-equivalent energy
+     * 4 pi dE/dOmega in each bin, counting material at or above the given
+     * four-velocity. The solid angle of each bin includes both hemispheres.
+     */
+    pub fn isotropic_energy(&self, min_gamma_beta: f64) -> Vec<(f64, f64)> {
+        self.theta_vertices.windows(2).zip(&self.energy).map(|(q, row)| {
+            let solid_angle = 4.0 * PI * (q[0].cos() - q[1].cos());
+            let e: f64 = row.iter()
+                .zip(self.gamma_beta_vertices.windows(2))
+                .filter(|(_, u)| u[0] >= min_gamma_beta)
+                .map(|(e, _)| e)
+                .sum();
+            (0.5 * (q[0] + q[1]), 4.0 * PI * e / solid_angle)
+        }).collect()
+    }
+}
+
+
+
+
+// ============================================================================
+impl StructuredJetFit {
+
+    /**
+     * Fit the afterglowpy Gaussian jet, E(θ) = E0 exp(-θ² / 2θc²), to the
+     * given isotropic-equivalent energy profile.
+     */
+    pub fn gaussian(profile: &[(f64, f64)]) -> Option<Self> {
+        let profile = fit_points(profile);
+        let theta_wing = profile.last()?.0;
+
+        core_angles()
+            .filter_map(|theta_core| {
+                let shape = |q: f64| -0.5 * (q / theta_core).powi(2) / std::f64::consts::LN_10;
+                Self::fit_shape(&profile, theta_core, None, theta_wing, shape)
+            })
+            .min_by(|a, b| a.rms_log_residual.partial_cmp(&b.rms_log_residual).unwrap())
+    }
+
+    /**
+     * Fit the afterglowpy power-law jet, E(θ) = E0 (1 + θ² / bθc²)^(-b/2),
+     * to the given isotropic-equivalent energy profile.
+     */
+    pub fn power_law(profile: &[(f64, f64)]) -> Option<Self> {
+        let profile = fit_points(profile);
+        let theta_wing = profile.last()?.0;
+
+        core_angles()
+            .flat_map(|theta_core| wing_indexes().map(move |b| (theta_core, b)))
+            .filter_map(|(theta_core, b)| {
+                let shape = |q: f64| -0.5 * b * (1.0 + q * q / (b * theta_core * theta_core)).log10();
+                Self::fit_shape(&profile, theta_core, Some(b), theta_wing, shape)
+            })
+            .min_by(|a, b| a.rms_log_residual.partial_cmp(&b.rms_log_residual).unwrap())
+    }
+
+    /**
+     * Given the log10 of a profile shape normalized to 1 on the axis, find
+     * the normalization E0 which minimizes the squared log residual, and
+     * return the fit.
+     */
+    fn fit_shape<F>(profile: &[(f64, f64)], theta_core: f64, wing_index: Option<f64>, theta_wing: f64, log_shape: F) -> Option<Self>
+    where
+        F: Fn(f64) -> f64
+    {
+        if profile.is_empty() {
+            return None
+        }
+        let n = profile.len() as f64;
+        let log_e0 = profile.iter().map(|&(q, e)| e.log10() - log_shape(q)).sum::<f64>() / n;
+        let rms = (profile.iter().map(|&(q, e)| (e.log10() - log_e0 - log_shape(q)).powi(2)).sum::<f64>() / n).sqrt();
+
+        Some(Self {
+            e0: 10f64.powf(log_e0),
+            theta_core,
+            wing_index,
+            theta_wing,
+            rms_log_residual: rms,
+            num_points: profile.len(),
+        })
+    }
+}
+
+
+
+
+// ============================================================================
+impl AfterglowProducts {
+
+    /**
+     * Compute the kinematic distribution of the given products, and fit the
+     * structured-jet profiles to the energy in material with at least the
+     * given four-velocity.
+     */
+    pub fn from_products(products: &Products, min_gamma_beta: f64) -> Self {
+        let distribution = KinematicDistribution::from_products(products);
+        let profile = distribution.isotropic_energy(min_gamma_beta);
+
+        Self {
+            time: products.time,
+            gaussian: StructuredJetFit::gaussian(&profile),
+            power_law: StructuredJetFit::power_law(&profile),
+            distribution,
+            config: products.config.clone(),
+            version: products.version.clone(),
+        }
+    }
+}
+
+
+
+
+/**
+ * Return the radial four-velocity of a zone, and its energy density
+ * excluding rest mass (erg/cm^3).
+ */
+fn zone_gamma_beta_and_energy(p: &AnyPrimitive, hydro: &AnyHydro) -> (f64, f64) {
+    let d = p.mass_density;
+
+    match hydro {
+        AnyHydro::Newtonian(hydro) => {
+            let v2 = p.velocity_r.powi(2) + p.velocity_q.powi(2);
+            let e = 0.5 * d * v2 + p.gas_pressure / (hydro.gamma_law_index - 1.0);
+            (p.velocity_r / light_speed(), e)
+        }
+        AnyHydro::Relativistic(hydro) => {
+            let g = hydro.gamma_law_index;
+            let u2 = p.velocity_r.powi(2) + p.velocity_q.powi(2);
+            let lorentz_factor = (1.0 + u2).sqrt();
+            let h = 1.0 + g / (g - 1.0) * p.gas_pressure / d;
+            let e = d * h * lorentz_factor.powi(2) - p.gas_pressure - d * lorentz_factor;
+            (p.velocity_r, e * light_speed() * light_speed())
+        }
+    }
+}
+
+/**
+ * Select the points of an isotropic-equivalent energy profile which take
+ * part in a fit: those above a small fraction of the peak energy.
+ */
+fn fit_points(profile: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let peak = profile.iter().map(|&(_, e)| e).fold(0.0, f64::max);
+    profile.iter()
+        .filter(|&&(_, e)| e > FIT_ENERGY_FLOOR * peak)
+        .cloned()
+        .collect()
+}
+
+/**
+ * Trial core angles for the fits, logarithmically spaced from 0.005 to 0.5
+ * radians
+ */
+fn core_angles() -> impl Iterator<Item = f64> {
+    (0..=200).map(|i| 0.005 * 100f64.powf(i as f64 / 200.0))
+}
+
+/**
+ * Trial wing power-law indexes for the power-law fits, from 0.5 to 20
+ */
+fn wing_indexes() -> impl Iterator<Item = f64> {
+    (0..=78).map(|i| 0.5 + 0.25 * i as f64)
+}
@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+use crate::app::{AnyModel, Configuration};
+use crate::models::EngineLuminosity;
+use crate::physics::light_speed;
+
+
+
+
+/**
+ * Return the unit of a configuration parameter, given its key. Keys are
+ * matched by name alone, since the same name is used consistently across the
+ * models.
+ */
+fn unit_of(key: &str) -> Option<&'static str> {
+    let unit = match key {
+        "cloud_mass" | "star_mass" | "envelope_mass" | "ejecta_mass" | "envelop_m1" => "g",
+        "engine_energy" | "core_energy" | "sheath_energy" => "erg, isotropic equivalent",
+        "engine_strength" => "E / M c^2",
+        "engine_delay" | "engine_duration" | "start_time" | "final_time" | "checkpoint_interval"
+            | "products_interval" | "work_map_interval" | "excision_delay" | "flare_time"
+            | "flare_duration" | "period" | "simulation_time" | "gps_time" => "s",
+        "engine_theta" | "core_theta" | "sheath_theta" | "tilt_angle" => "rad",
+        "inner_radius" | "outer_radius" | "reference_radius" | "envelope_radius" | "shock_location" => "cm",
+        "inner_excision_speed" | "outer_excision_speed" => "cm / s",
+        "ambient_density" => "g / cm^3",
+        "mass_loss_rate" | "wind_mass_outflow_rate" | "flare_outflow_rate" => "g / s",
+        "wind_pressure" | "post_shock_pressure" => "erg / cm^3",
+        "engine_u" | "core_u" | "sheath_u" | "wind_gamma_beta" | "post_shock_gamma_beta" | "flare_gamma_beta" => "gamma-beta",
+        "break_beta" | "max_beta" | "envelop_slowest_beta" | "envelop_fastest_beta" => "v / c",
+        _ => return None,
+    };
+    Some(unit)
+}
+
+
+
+
+/**
+ * Return the range of values of a parameter which are typical for the
+ * problems this code is used for. Values outside the range are not errors,
+ * but they are flagged in the annotated configuration, since they are more
+ * often than not typos.
+ */
+fn typical_range(key: &str) -> Option<(f64, f64)> {
+    match key {
+        "engine_energy" | "core_energy" | "sheath_energy" => Some((1e44, 1e56)),
+        "cloud_mass" | "star_mass" | "envelope_mass" | "ejecta_mass" => Some((1e25, 1e36)),
+        "engine_theta" | "core_theta" | "sheath_theta" => Some((1e-3, 0.5 * PI)),
+        "engine_u" | "core_u" | "sheath_u" => Some((1e-2, 1e4)),
+        _ => None,
+    }
+}
+
+
+
+
+/**
+ * Return a list of derived quantities, as (description, value, unit) tuples,
+ * that are useful to check at a glance before a run starts.
+ */
+pub fn derived_quantities(config: &Configuration) -> Vec<(String, f64, &'static str)> {
+    let c2 = light_speed() * light_speed();
+    let dq = config.mesh.zone_dq();
+    let gamma = |u: f64| (1.0 + u * u).sqrt();
+    let mut quantities = vec![
+        ("polar zone spacing".to_string(), dq, "rad"),
+        ("radial zone spacing d(log r)".to_string(), config.mesh.zone_dlogr(), ""),
+    ];
+
+    match &config.model {
+        AnyModel::JetInStar(m) => {
+            quantities.push(("engine luminosity".into(), m.engine_energy / m.engine_duration, "erg / s, isotropic equivalent"));
+            quantities.push(("engine Lorentz factor".into(), gamma(m.engine_u), ""));
+            quantities.push(("engine true energy".into(), m.engine_energy * (1.0 - m.engine_theta.cos()), "erg, both poles"));
+            quantities.push(("nozzle zones across".into(), m.engine_theta / dq, ""));
+            magnetar_quantities(&m.engine_luminosity, &mut quantities);
+        }
+        AnyModel::JetInCloud(m) => {
+            let e = m.engine_strength * m.cloud_mass * c2;
+            quantities.push(("engine energy".into(), e, "erg, isotropic equivalent"));
+            quantities.push(("engine luminosity".into(), e / m.engine_duration, "erg / s, isotropic equivalent"));
+            quantities.push(("engine Lorentz factor".into(), gamma(m.engine_u), ""));
+            quantities.push(("nozzle zones across".into(), m.engine_theta / dq, ""));
+            magnetar_quantities(&m.engine_luminosity, &mut quantities);
+        }
+        AnyModel::TwoComponentJet(m) => {
+            let (core, sheath) = m.true_energies();
+            quantities.push(("core luminosity".into(), m.core_energy / m.engine_duration, "erg / s, isotropic equivalent"));
+            quantities.push(("core Lorentz factor".into(), gamma(m.core_u), ""));
+            quantities.push(("core true energy".into(), core, "erg, both poles"));
+            quantities.push(("core zones across".into(), m.core_theta / dq, ""));
+            quantities.push(("sheath luminosity".into(), m.sheath_energy / m.engine_duration, "erg / s, isotropic equivalent"));
+            quantities.push(("sheath Lorentz factor".into(), gamma(m.sheath_u), ""));
+            quantities.push(("sheath true energy".into(), sheath, "erg, both poles"));
+            quantities.push(("sheath zones across".into(), m.sheath_theta / dq, ""));
+        }
+        AnyModel::ShellCollision(m) => {
+            quantities.push(("shell collision time".into(), m.collision_time(), "s"));
+            quantities.push(("shell collision radius".into(), m.collision_radius(), "cm"));
+        }
+        AnyModel::Wind(m) => {
+            quantities.push(("wind Lorentz factor".into(), gamma(m.wind_gamma_beta), ""));
+            quantities.push(("wind kinetic luminosity".into(), m.mass_loss_rate * (gamma(m.wind_gamma_beta) - 1.0) * c2, "erg / s"));
+        }
+        _ => {}
+    }
+    quantities
+}
+
+
+
+
+/**
+ * Append the initial luminosity, spin-down time, and rotational energy of a
+ * magnetar engine to the derived quantities.
+ */
+fn magnetar_quantities(luminosity: &EngineLuminosity, quantities: &mut Vec<(String, f64, &'static str)>) {
+    if let Some((l0, t_sd)) = luminosity.magnetar_spin_down() {
+        quantities.push(("magnetar initial luminosity".into(), l0, "erg / s"));
+        quantities.push(("magnetar spin-down time".into(), t_sd, "s"));
+        quantities.push(("magnetar rotational energy".into(), l0 * t_sd, "erg"));
+    }
+}
+
+
+
+
+/**
+ * Return the configuration as YAML, with each physical parameter annotated
+ * by its unit, suspicious values flagged, and followed by a list of derived
+ * quantities. The result is valid YAML, since the annotations are comments.
+ */
+pub fn annotated_config(config: &Configuration) -> anyhow::Result<String> {
+    let mut lines = Vec::new();
+
+    for line in serde_yaml::to_string(config)?.lines().skip(1) {
+        let mut annotation = Vec::new();
+
+        let mut key_value = line.trim_start().splitn(2, ':');
+
+        if let (Some(key), Some(value)) = (key_value.next(), key_value.next()) {
+            if let Some(unit) = unit_of(key) {
+                annotation.push(format!("[{}]", unit));
+            }
+            if let (Some((lo, hi)), Ok(x)) = (typical_range(key), value.trim().parse::<f64>()) {
+                if x < lo || x > hi {
+                    annotation.push(format!("<-- outside typical range {:.0e} to {:.0e}", lo, hi));
+                }
+            }
+        }
+        if annotation.is_empty() {
+            lines.push(line.to_string());
+        } else {
+            lines.push(format!("{:<40} # {}", line, annotation.join(" ")));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("# derived quantities:".into());
+
+    for (name, value, unit) in derived_quantities(config) {
+        let warning = if name.ends_with("zones across") && value < 4.0 {
+            " <-- under-resolved"
+        } else {
+            ""
+        };
+        lines.push(format!("# {:.<36} {:.4e} {}{}", format!("{} ", name), value, unit, warning));
+    }
+    Ok(lines.join("\n"))
+}
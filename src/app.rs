@@ -1,6 +1,15 @@
 pub static DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 pub static VERSION_AND_BUILD: &str = git_version::git_version!(prefix=concat!("v", env!("CARGO_PKG_VERSION"), " "));
 
+/// Optional subsystems which are enabled by cargo features, together with
+/// whether each one was compiled into this build. New optional subsystems
+/// should be registered here so they show up in `kilonova features`.
+pub static OPTIONAL_FEATURES: &[(&str, bool)] = &[
+    ("movie", cfg!(feature = "movie")),
+    ("hdf5", cfg!(feature = "hdf5")),
+    ("netcdf", cfg!(feature = "netcdf")),
+];
+
 
 use std::{
     ffi::OsStr,
@@ -12,6 +21,7 @@ use serde::{
     Deserialize,
 };
 use yaml_patch::Patch;
+use godunov_core::runge_kutta::RungeKuttaOrder;
 
 
 use crate::mesh::Mesh;
@@ -21,20 +31,44 @@ use crate::models::{
     JetInStar,
     WindShock,
     KineticBomb,
+    HomologousEjecta,
+    Wind,
+    TabulatedProgenitor,
+    TwoComponentJet,
+    CloudShock,
+    EjectaCsm,
+    AccretionTorus,
+    SphericalBlast,
+    KelvinHelmholtz,
+    RayleighTaylor,
+    JetInIsm,
+    DiskWindJet,
+    CollidingWinds,
+    ShellCollision,
+    Composite,
+    Custom,
+    Perturbed,
+    RefreshedShell,
+    ExtendedEnvelope,
 };
 use crate::physics::{
     AnyPrimitive,
+    Reconstruction,
     RelativisticHydro,
     NewtonianHydro,
 };
+use crate::physics::constants::UnitSystem;
+use crate::reductions::Summation;
+use crate::scheme::{LocalTimeStepping, SourceSplitting, StepRetry, TimeIntegrator};
 use crate::state::State;
 use crate::traits::{
+    BoundaryEdge,
     Conserved,
     Hydrodynamics,
     InitialModel,
 };
 use crate::tasks::Tasks;
-use crate::io;
+use crate::io::{self, Compression, OutputFormat};
 
 
 
@@ -57,6 +91,18 @@ pub enum Error {
 
     #[error("unknown input file type '{0}'")]
     UnknownInputType(String),
+
+    #[error("no checkpoint files in directory '{0}'")]
+    NoCheckpoint(String),
+
+    #[error("{0} cannot be changed when restarting from a checkpoint")]
+    FixedOnRestart(String),
+
+    #[error("could not load the mesh geometry: {0}")]
+    MeshGeometry(anyhow::Error),
+
+    #[error("could not install the unit system: {0}")]
+    UnitSystem(anyhow::Error),
 }
 
 
@@ -73,6 +119,25 @@ pub enum AnyModel {
     JetInStar(JetInStar),
     WindShock(WindShock),
     KineticBomb(KineticBomb),
+    HomologousEjecta(HomologousEjecta),
+    Wind(Wind),
+    TabulatedProgenitor(TabulatedProgenitor),
+    TwoComponentJet(TwoComponentJet),
+    CloudShock(CloudShock),
+    EjectaCsm(EjectaCsm),
+    AccretionTorus(AccretionTorus),
+    SphericalBlast(SphericalBlast),
+    KelvinHelmholtz(KelvinHelmholtz),
+    RayleighTaylor(RayleighTaylor),
+    JetInIsm(JetInIsm),
+    DiskWindJet(DiskWindJet),
+    CollidingWinds(CollidingWinds),
+    ShellCollision(ShellCollision),
+    Composite(Composite),
+    Custom(Custom),
+    Perturbed(Perturbed),
+    RefreshedShell(RefreshedShell),
+    ExtendedEnvelope(ExtendedEnvelope),
 }
 
 
@@ -118,18 +183,134 @@ pub struct Control {
     /// The simulation end time.
     pub final_time: f64,
 
-    /// The time between writing checkpoint  files.
-    pub checkpoint_interval: f64,
+    /// The time between writing checkpoint  files. If nil, zero, or negative,
+    /// no checkpoints are written, which is useful for I/O-free benchmarks.
+    pub checkpoint_interval: Option<f64>,
+
+    /// If given, older checkpoints in the output directory are deleted
+    /// after each new one has been written, except for the first, the most
+    /// recent ones, and optionally every so many. If omitted or nil, all
+    /// checkpoints are kept.
+    #[serde(default)]
+    pub checkpoint_retention: Option<CheckpointRetention>,
 
-    /// The time between writing products files. If omitted or nil, defaults
-    /// to no products output. This option should be considered deprecated.
+    /// The time between writing products files. If omitted, nil, zero, or
+    /// negative, defaults to no products output. This option should be considered deprecated.
     /// Write checkpoints and then convert them to products files in
     /// post-processing if needed.
     pub products_interval: Option<f64>,
 
+    /// If given, resample the products onto this many evenly spaced polar
+    /// zones. The resampling is conservative in mass, momentum, and energy,
+    /// which makes it easier to compare runs with different polar zonings.
+    #[serde(default)]
+    pub products_polar_regrid: Option<usize>,
+
+    /// If given, products files only include the blocks whose data changed
+    /// beyond a tolerance since the previous products file, with a complete
+    /// keyframe written periodically. Readers reconstruct the full snapshot
+    /// from the chain of files back to the keyframe.
+    #[serde(default)]
+    pub products_delta: Option<ProductsDelta>,
+
+    /// The maximum size (bytes) of a products file. Larger snapshots are
+    /// split into part files of at most this size (unless a single block is
+    /// larger), with an index manifest in the usual products file that lists
+    /// the blocks in each part. If omitted or nil, products are always
+    /// written to a single file.
+    #[serde(default)]
+    pub products_max_file_size: Option<u64>,
+
+    /// The time between samples of the conserved quantities time series:
+    /// the total mass, radial momentum, kinetic, thermal, and total energy,
+    /// scalar mass, and excised mass and energy, appended to
+    /// timeseries.csv in the output directory. If omitted, nil, zero, or
+    /// negative, no time series is written.
+    #[serde(default)]
+    pub time_series_interval: Option<f64>,
+
+    /// If given, one-dimensional radial cuts along the given polar angles
+    /// are written at their own (typically short) interval, as plain text
+    /// files cut.0012.angle0.dat, ... Only the zones along the cuts are
+    /// recovered, so they are much cheaper than products. If omitted or nil,
+    /// no cuts are written.
+    #[serde(default)]
+    pub angular_cuts: Option<AngularCuts>,
+
+    /// If given, plain text radial profiles are written with each products
+    /// file: one averaged over the polar angle (profile.0012.dat), and one
+    /// along each of the given polar angles (profile.0012.angle0.dat, ...).
+    /// If omitted or nil, no profiles are written.
+    #[serde(default)]
+    pub radial_profiles: Option<RadialProfiles>,
+
+    /// The time between writing work map files, which record the measured
+    /// compute cost of each block, and the number of primitive recovery
+    /// fallbacks and root-finder iterations in each zone. If omitted, nil,
+    /// zero, or negative, defaults to no work maps.
+    #[serde(default)]
+    pub work_map_interval: Option<f64>,
+
+    /// The time between reports of the mass and energy added to the grid by
+    /// each source term (such as the engine, gravity, and the floors) since
+    /// the previous report. The budget is also reported when the run ends.
+    /// If omitted, nil, zero, or negative, it is only reported at the end.
+    #[serde(default)]
+    pub source_budget_interval: Option<f64>,
+
+    /// Print the total mass and energy on the grid with each iteration
+    /// message, summed in either double or extended (double-double)
+    /// precision. With extended precision, the relative error of plain double
+    /// precision summation is also reported, which helps to tell genuine
+    /// solver drift from round-off in runs with very many zones. If omitted
+    /// or nil, the totals are not computed.
+    #[serde(default)]
+    pub conservation_monitor: Option<Summation>,
+
+    /// Physical reference epoch, such as a merger time inferred from
+    /// gravitational waves. If given, products files and the time series
+    /// include the time since the epoch, and observer-frame arrival times at
+    /// the extraction surfaces.
+    #[serde(default)]
+    pub reference_epoch: Option<ReferenceEpoch>,
+
+    /// The units of length, mass, and time (in cm, g, and s) in which the
+    /// model and mesh parameters are given. The physical constants are
+    /// expressed in these units throughout the run. If omitted, defaults to
+    /// cgs.
+    #[serde(default)]
+    pub units: UnitSystem,
+
     /// The number of iterations between performing side-effects
     pub fold: usize,
 
+    /// Time integrator: hydro (the default), which uses the Runge-Kutta
+    /// order in the hydro section, where RK3 is the third-order
+    /// strong-stability-preserving method that suits the higher-order
+    /// reconstructions, or muscl_hancock, a second-order single-stage
+    /// method for PLM reconstruction
+    #[serde(default = "Control::default_time_integrator")]
+    pub time_integrator: TimeIntegrator,
+
+    /// If given, the gravity and acceleration source terms are operator
+    /// split from the hydro update, and subcycled when their timescale is
+    /// shorter than the time step. If omitted or nil, they are applied in
+    /// the Runge-Kutta stages.
+    #[serde(default)]
+    pub source_splitting: Option<SourceSplitting>,
+
+    /// If given, blocks whose zones allow longer time steps (typically at
+    /// large radii) take fewer, longer steps than the blocks limiting the
+    /// time step. If omitted or nil, all blocks take the same time step.
+    #[serde(default)]
+    pub local_time_stepping: Option<LocalTimeStepping>,
+
+    /// If given, a step which fails in the primitive recovery, or produces
+    /// non-finite values, is retried from the same state at a reduced time
+    /// step. If omitted or nil, such failures stop the run.
+    #[serde(default)]
+    pub step_retry: Option<StepRetry>,
+
     /// Number of worker threads on the Tokio runtime. If omitted or nil,
     /// defaults to 2x the number of physical cores.
     pub num_threads: Option<usize>,
@@ -139,9 +320,31 @@ pub struct Control {
     pub snappy_compression: bool,
 
     /// The directory where data file will be output. If omitted or nil,
-    /// defaults to a the current directory.
+    /// defaults to a the current directory. Can also be set with the
+    /// --outdir=<directory> command line flag.
     #[serde(default = "Control::default_output_directory")]
     pub output_directory: String,
+
+    /// Format of the checkpoint and products files: cbor (the default),
+    /// hdf5, flat (raw arrays for the products, with a YAML sidecar), or
+    /// netcdf (NetCDF-4 products). Checkpoints are written as CBOR with
+    /// flat and netcdf products. HDF5 and NetCDF output need the optional
+    /// `hdf5` and `netcdf` features. Only cbor output supports
+    /// products_delta and products_max_file_size.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+
+    /// Compression of the CBOR checkpoint and products files: zstd or
+    /// gzip. Compressed files are detected automatically when they are
+    /// read, so runs can be restarted from either kind of checkpoint. If
+    /// omitted or nil, files are not compressed.
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Write checkpoints and products on a separate thread, so the solver
+    /// carries on while the files are written. Defaults to true.
+    #[serde(default = "Control::default_background_output")]
+    pub background_output: bool,
 }
 
 impl Control {
@@ -154,6 +357,154 @@ impl Control {
     fn default_output_directory() -> String {
         ".".into()
     }
+
+    fn default_background_output() -> bool {
+        true
+    }
+
+    fn default_time_integrator() -> TimeIntegrator {
+        TimeIntegrator::Hydro
+    }
+
+    /**
+     * The checkpoint interval, or `None` if checkpoints are disabled.
+     */
+    pub fn checkpoint_task_interval(&self) -> Option<f64> {
+        Self::enabled_interval(self.checkpoint_interval)
+    }
+
+    /**
+     * The products interval, or `None` if products output is disabled.
+     */
+    pub fn products_task_interval(&self) -> Option<f64> {
+        Self::enabled_interval(self.products_interval)
+    }
+
+    /**
+     * The work map interval, or `None` if work map output is disabled.
+     */
+    pub fn work_map_task_interval(&self) -> Option<f64> {
+        Self::enabled_interval(self.work_map_interval)
+    }
+
+    /**
+     * The source budget interval, or `None` if the budget is only reported
+     * at the end of the run.
+     */
+    pub fn source_budget_task_interval(&self) -> Option<f64> {
+        Self::enabled_interval(self.source_budget_interval)
+    }
+
+    /**
+     * The time series interval, or `None` if the time series is disabled.
+     */
+    pub fn time_series_task_interval(&self) -> Option<f64> {
+        Self::enabled_interval(self.time_series_interval)
+    }
+
+    /**
+     * Treat a missing, zero, or negative task interval as disabled. A zero
+     * interval would otherwise have the task performed after every fold.
+     */
+    fn enabled_interval(interval: Option<f64>) -> Option<f64> {
+        interval.filter(|&dt| dt > 0.0)
+    }
+}
+
+
+
+
+/**
+ * Settings for writing sparse ("delta") products files
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProductsDelta {
+
+    /// A block is written when any of its primitive or scalar fields changed
+    /// by more than this relative amount in some zone
+    pub tolerance: f64,
+
+    /// Every this many products files, a complete keyframe is written
+    pub keyframe_cadence: usize,
+}
+
+
+
+
+/**
+ * Settings for the plain text radial profiles written with the products
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RadialProfiles {
+
+    /// Polar angles (radians) along which profiles are written, in addition
+    /// to the angle-averaged profile
+    #[serde(default)]
+    pub polar_angles: Vec<f64>,
+}
+
+
+
+
+/**
+ * Settings for the one-dimensional radial cuts written at a high cadence
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AngularCuts {
+
+    /// The time between writing cuts
+    pub interval: f64,
+
+    /// Polar angles (radians) of the cuts
+    pub polar_angles: Vec<f64>,
+}
+
+
+
+
+/**
+ * Rule for deleting old checkpoints. The first checkpoint (number 0) is
+ * always kept.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointRetention {
+
+    /// Keep this many of the most recent checkpoints
+    pub keep_last: usize,
+
+    /// If given, also keep every checkpoint whose number is a multiple of
+    /// this
+    #[serde(default)]
+    pub keep_every: Option<usize>,
+}
+
+
+
+
+/**
+ * A physical reference epoch, which ties simulation times to observations
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReferenceEpoch {
+
+    /// The simulation time corresponding to the epoch. For example, if the
+    /// epoch is the merger time and the engine turns on 1.7 s later at t = 0,
+    /// this would be -1.7.
+    pub simulation_time: f64,
+
+    /// GPS time (s) of the epoch, if known
+    #[serde(default)]
+    pub gps_time: Option<f64>,
+
+    /// Radii (cm) of the extraction surfaces where observer-frame arrival
+    /// times are reported
+    #[serde(default)]
+    pub extraction_radii: Vec<f64>,
 }
 
 
@@ -196,6 +547,27 @@ impl AnyHydro {
             AnyHydro::Relativistic(hydro) => hydro.validate(),
         }        
     }
+
+    pub fn reconstruction(&self) -> Reconstruction {
+        match self {
+            AnyHydro::Newtonian(hydro) => hydro.reconstruction(),
+            AnyHydro::Relativistic(hydro) => hydro.reconstruction(),
+        }
+    }
+
+    pub fn well_balanced(&self) -> bool {
+        match self {
+            AnyHydro::Newtonian(hydro) => hydro.well_balanced(),
+            AnyHydro::Relativistic(hydro) => hydro.well_balanced(),
+        }
+    }
+
+    pub fn runge_kutta_order(&self) -> RungeKuttaOrder {
+        match self {
+            AnyHydro::Newtonian(hydro) => hydro.runge_kutta_order(),
+            AnyHydro::Relativistic(hydro) => hydro.runge_kutta_order(),
+        }
+    }
 }
 
 impl Control {
@@ -203,11 +575,64 @@ impl Control {
         if self.num_threads() == 0 || self.num_threads() >= 1024 {
             anyhow::bail!("num_threads must be > 0 and < 1024")
         }
-        if self.checkpoint_interval < 0.0 {
-            anyhow::bail!("checkpoint_interval <= 0.0")
+        self.units.validate()?;
+
+        if self.products_polar_regrid == Some(0) {
+            anyhow::bail!("products_polar_regrid must be at least 1")
+        }
+        if let Some(delta) = &self.products_delta {
+            if delta.tolerance < 0.0 || delta.keyframe_cadence == 0 {
+                anyhow::bail!("products_delta must have tolerance >= 0 and keyframe_cadence >= 1")
+            }
+        }
+        if let Some(splitting) = &self.source_splitting {
+            splitting.validate()?
+        }
+        if let Some(local_time_stepping) = &self.local_time_stepping {
+            local_time_stepping.validate()?
         }
-        if self.products_interval.unwrap_or(0.0) < 0.0 {
-            anyhow::bail!("products_interval <= 0.0")
+        if let Some(step_retry) = &self.step_retry {
+            step_retry.validate()?
+        }
+        if let Some(profiles) = &self.radial_profiles {
+            if profiles.polar_angles.iter().any(|&q| !(0.0..=std::f64::consts::PI).contains(&q)) {
+                anyhow::bail!("radial_profiles.polar_angles must be between 0 and pi")
+            }
+        }
+        if let Some(cuts) = &self.angular_cuts {
+            if cuts.interval <= 0.0 || cuts.polar_angles.is_empty() {
+                anyhow::bail!("angular_cuts must have a positive interval and at least one polar angle")
+            }
+            if cuts.polar_angles.iter().any(|&q| !(0.0..=std::f64::consts::PI).contains(&q)) {
+                anyhow::bail!("angular_cuts.polar_angles must be between 0 and pi")
+            }
+        }
+        if let Some(retention) = &self.checkpoint_retention {
+            if retention.keep_last == 0 || retention.keep_every == Some(0) {
+                anyhow::bail!("checkpoint_retention must have keep_last >= 1 and keep_every >= 1")
+            }
+        }
+        if self.products_max_file_size == Some(0) {
+            anyhow::bail!("products_max_file_size must be positive")
+        }
+        if matches!(self.output_format, OutputFormat::Flat | OutputFormat::Netcdf) && (self.products_delta.is_some() || self.products_max_file_size.is_some()) {
+            anyhow::bail!("products_delta and products_max_file_size are only supported with output_format: cbor")
+        }
+        if self.output_format == OutputFormat::Netcdf && !cfg!(feature = "netcdf") {
+            anyhow::bail!("output_format: netcdf needs the netcdf feature; rebuild with `cargo install --path . --features netcdf`")
+        }
+        if self.output_format == OutputFormat::Hdf5 {
+            if !cfg!(feature = "hdf5") {
+                anyhow::bail!("output_format: hdf5 needs the hdf5 feature; rebuild with `cargo install --path . --features hdf5`")
+            }
+            if self.products_delta.is_some() || self.products_max_file_size.is_some() || self.compression.is_some() {
+                anyhow::bail!("products_delta, products_max_file_size, and compression are not supported with output_format: hdf5")
+            }
+        }
+        if let Some(epoch) = &self.reference_epoch {
+            if epoch.extraction_radii.iter().any(|&r| r <= 0.0) {
+                anyhow::bail!("reference_epoch.extraction_radii must be positive")
+            }
         }
         Ok(())
     }
@@ -226,6 +651,25 @@ impl InitialModel for AnyModel {
             AnyModel::JetInStar(m)    => m.validate(),
             AnyModel::WindShock(m)    => m.validate(),
             AnyModel::KineticBomb(m) => m.validate(),
+            AnyModel::HomologousEjecta(m) => m.validate(),
+            AnyModel::Wind(m)         => m.validate(),
+            AnyModel::TabulatedProgenitor(m) => m.validate(),
+            AnyModel::TwoComponentJet(m) => m.validate(),
+            AnyModel::CloudShock(m) => m.validate(),
+            AnyModel::EjectaCsm(m) => m.validate(),
+            AnyModel::AccretionTorus(m) => m.validate(),
+            AnyModel::SphericalBlast(m) => m.validate(),
+            AnyModel::KelvinHelmholtz(m) => m.validate(),
+            AnyModel::RayleighTaylor(m) => m.validate(),
+            AnyModel::JetInIsm(m) => m.validate(),
+            AnyModel::DiskWindJet(m) => m.validate(),
+            AnyModel::CollidingWinds(m) => m.validate(),
+            AnyModel::ShellCollision(m) => m.validate(),
+            AnyModel::Composite(m) => m.validate(),
+            AnyModel::Custom(m) => m.validate(),
+            AnyModel::Perturbed(m) => m.validate(),
+            AnyModel::RefreshedShell(m) => m.validate(),
+            AnyModel::ExtendedEnvelope(m) => m.validate(),
         }
     }
 
@@ -236,6 +680,25 @@ impl InitialModel for AnyModel {
             AnyModel::JetInStar(m)    => m.primitive_at(coordinate, time),
             AnyModel::WindShock(m)    => m.primitive_at(coordinate, time),
             AnyModel::KineticBomb(m)  => m.primitive_at(coordinate, time),
+            AnyModel::HomologousEjecta(m) => m.primitive_at(coordinate, time),
+            AnyModel::Wind(m)         => m.primitive_at(coordinate, time),
+            AnyModel::TabulatedProgenitor(m) => m.primitive_at(coordinate, time),
+            AnyModel::TwoComponentJet(m) => m.primitive_at(coordinate, time),
+            AnyModel::CloudShock(m) => m.primitive_at(coordinate, time),
+            AnyModel::EjectaCsm(m) => m.primitive_at(coordinate, time),
+            AnyModel::AccretionTorus(m) => m.primitive_at(coordinate, time),
+            AnyModel::SphericalBlast(m) => m.primitive_at(coordinate, time),
+            AnyModel::KelvinHelmholtz(m) => m.primitive_at(coordinate, time),
+            AnyModel::RayleighTaylor(m) => m.primitive_at(coordinate, time),
+            AnyModel::JetInIsm(m) => m.primitive_at(coordinate, time),
+            AnyModel::DiskWindJet(m) => m.primitive_at(coordinate, time),
+            AnyModel::CollidingWinds(m) => m.primitive_at(coordinate, time),
+            AnyModel::ShellCollision(m) => m.primitive_at(coordinate, time),
+            AnyModel::Composite(m) => m.primitive_at(coordinate, time),
+            AnyModel::Custom(m) => m.primitive_at(coordinate, time),
+            AnyModel::Perturbed(m) => m.primitive_at(coordinate, time),
+            AnyModel::RefreshedShell(m) => m.primitive_at(coordinate, time),
+            AnyModel::ExtendedEnvelope(m) => m.primitive_at(coordinate, time),
         } 
     }
 
@@ -246,6 +709,83 @@ impl InitialModel for AnyModel {
             AnyModel::JetInStar(m)    => m.scalar_at(coordinate, time),
             AnyModel::WindShock(m)    => m.scalar_at(coordinate, time),
             AnyModel::KineticBomb(m)  => m.scalar_at(coordinate, time),
+            AnyModel::HomologousEjecta(m) => m.scalar_at(coordinate, time),
+            AnyModel::Wind(m)         => m.scalar_at(coordinate, time),
+            AnyModel::TabulatedProgenitor(m) => m.scalar_at(coordinate, time),
+            AnyModel::TwoComponentJet(m) => m.scalar_at(coordinate, time),
+            AnyModel::CloudShock(m) => m.scalar_at(coordinate, time),
+            AnyModel::EjectaCsm(m) => m.scalar_at(coordinate, time),
+            AnyModel::AccretionTorus(m) => m.scalar_at(coordinate, time),
+            AnyModel::SphericalBlast(m) => m.scalar_at(coordinate, time),
+            AnyModel::KelvinHelmholtz(m) => m.scalar_at(coordinate, time),
+            AnyModel::RayleighTaylor(m) => m.scalar_at(coordinate, time),
+            AnyModel::JetInIsm(m) => m.scalar_at(coordinate, time),
+            AnyModel::DiskWindJet(m) => m.scalar_at(coordinate, time),
+            AnyModel::CollidingWinds(m) => m.scalar_at(coordinate, time),
+            AnyModel::ShellCollision(m) => m.scalar_at(coordinate, time),
+            AnyModel::Composite(m) => m.scalar_at(coordinate, time),
+            AnyModel::Custom(m) => m.scalar_at(coordinate, time),
+            AnyModel::Perturbed(m) => m.scalar_at(coordinate, time),
+            AnyModel::RefreshedShell(m) => m.scalar_at(coordinate, time),
+            AnyModel::ExtendedEnvelope(m) => m.scalar_at(coordinate, time),
+        }
+    }
+
+    fn boundary_primitive_at(&self, edge: BoundaryEdge, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+        match self {
+            AnyModel::HaloKilonova(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::JetInCloud(m)   => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::JetInStar(m)    => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::WindShock(m)    => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::KineticBomb(m)  => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::HomologousEjecta(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::Wind(m)         => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::TabulatedProgenitor(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::TwoComponentJet(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::CloudShock(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::EjectaCsm(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::AccretionTorus(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::SphericalBlast(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::KelvinHelmholtz(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::RayleighTaylor(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::JetInIsm(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::DiskWindJet(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::CollidingWinds(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::ShellCollision(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::Composite(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::Custom(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::Perturbed(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::RefreshedShell(m) => m.boundary_primitive_at(edge, coordinate, time),
+            AnyModel::ExtendedEnvelope(m) => m.boundary_primitive_at(edge, coordinate, time),
+        }
+    }
+
+    fn acceleration_at(&self, coordinate: (f64, f64), time: f64) -> (f64, f64) {
+        match self {
+            AnyModel::HaloKilonova(m) => m.acceleration_at(coordinate, time),
+            AnyModel::JetInCloud(m)   => m.acceleration_at(coordinate, time),
+            AnyModel::JetInStar(m)    => m.acceleration_at(coordinate, time),
+            AnyModel::WindShock(m)    => m.acceleration_at(coordinate, time),
+            AnyModel::KineticBomb(m)  => m.acceleration_at(coordinate, time),
+            AnyModel::HomologousEjecta(m) => m.acceleration_at(coordinate, time),
+            AnyModel::Wind(m)         => m.acceleration_at(coordinate, time),
+            AnyModel::TabulatedProgenitor(m) => m.acceleration_at(coordinate, time),
+            AnyModel::TwoComponentJet(m) => m.acceleration_at(coordinate, time),
+            AnyModel::CloudShock(m) => m.acceleration_at(coordinate, time),
+            AnyModel::EjectaCsm(m) => m.acceleration_at(coordinate, time),
+            AnyModel::AccretionTorus(m) => m.acceleration_at(coordinate, time),
+            AnyModel::SphericalBlast(m) => m.acceleration_at(coordinate, time),
+            AnyModel::KelvinHelmholtz(m) => m.acceleration_at(coordinate, time),
+            AnyModel::RayleighTaylor(m) => m.acceleration_at(coordinate, time),
+            AnyModel::JetInIsm(m) => m.acceleration_at(coordinate, time),
+            AnyModel::DiskWindJet(m) => m.acceleration_at(coordinate, time),
+            AnyModel::CollidingWinds(m) => m.acceleration_at(coordinate, time),
+            AnyModel::ShellCollision(m) => m.acceleration_at(coordinate, time),
+            AnyModel::Composite(m) => m.acceleration_at(coordinate, time),
+            AnyModel::Custom(m) => m.acceleration_at(coordinate, time),
+            AnyModel::Perturbed(m) => m.acceleration_at(coordinate, time),
+            AnyModel::RefreshedShell(m) => m.acceleration_at(coordinate, time),
+            AnyModel::ExtendedEnvelope(m) => m.acceleration_at(coordinate, time),
         }
     }
 }
@@ -275,6 +815,35 @@ impl Configuration {
         self.model.validate()?;
         self.mesh.validate(self.control.start_time)?;
         self.control.validate()?;
+
+        if self.mesh.num_guard_zones < self.hydro.reconstruction().num_guard_zones() {
+            anyhow::bail!("the reconstruction needs at least {} guard zones", self.hydro.reconstruction().num_guard_zones())
+        }
+        if self.hydro.well_balanced() && self.control.source_splitting.is_some() {
+            anyhow::bail!("well-balanced gravity cannot be combined with source splitting")
+        }
+        if let TimeIntegrator::MusclHancock = self.control.time_integrator {
+            if !matches!(self.hydro.reconstruction(), Reconstruction::PLM) {
+                anyhow::bail!("the muscl_hancock time integrator needs PLM reconstruction")
+            }
+            if self.hydro.well_balanced() {
+                anyhow::bail!("the muscl_hancock time integrator cannot be combined with well-balanced gravity")
+            }
+        }
+        let (q0, q1) = self.mesh.polar_extent();
+        let outside_extent = |angles: &Vec<f64>| angles.iter().any(|&q| q < q0 || q > q1);
+
+        if self.control.radial_profiles.as_ref().map_or(false, |profiles| outside_extent(&profiles.polar_angles)) {
+            anyhow::bail!("radial_profiles.polar_angles must be within the polar extent of the mesh")
+        }
+        if self.control.angular_cuts.as_ref().map_or(false, |cuts| outside_extent(&cuts.polar_angles)) {
+            anyhow::bail!("angular_cuts.polar_angles must be within the polar extent of the mesh")
+        }
+        if self.control.local_time_stepping.is_some() {
+            if self.mesh.face_motion.is_some() {
+                anyhow::bail!("local time stepping cannot be combined with moving mesh faces")
+            }
+        }
         Ok(())
     }
 
@@ -315,6 +884,8 @@ impl App {
     pub fn from_config(mut config: Configuration, overrides: Vec<String>) -> Result<Self, Error> {
 
         config.patch_from(overrides)?;
+        config.control.units.install().map_err(Error::UnitSystem)?;
+        config.mesh.load_geometry().map_err(Error::MeshGeometry)?;
 
         let geometry = config.mesh.grid_blocks_geometry(config.control.start_time);
         let state = match &config.hydro {
@@ -330,28 +901,80 @@ impl App {
     }
 
     /**
-     * Patch the config struct with inputs from the command line.
+     * Patch the config struct of a checkpoint with inputs from the command
+     * line. Only settings which leave the solution in the checkpoint valid
+     * may be changed: the control settings other than the start time, and
+     * the hydro settings other than the kind of hydro, the equation of
+     * state, and well-balancing. The model and mesh are fixed.
      */
     pub fn with_patched_config(mut self, overrides: Vec<String>) -> Result<Self, Error> {
+        let before = serde_yaml::to_value(&self.config)?;
         self.config.patch_from(overrides)?;
+        let after = serde_yaml::to_value(&self.config)?;
+        let mut changed = Vec::new();
+
+        changed_keys(&before, &after, &mut Vec::new(), &mut changed);
+
+        for path in changed {
+            if !may_change_on_restart(&path) {
+                return Err(Error::FixedOnRestart(path.join(".")))
+            }
+            println!("restart: changed {}", path.join("."));
+        }
         Ok(self)
     }
 
     /**
-     * Construct a new App instance from a file: may be a config.yaml or a
-     * chkpt.0000.cbor.
+     * Initialize any solution fields missing from a checkpoint, from the
+     * model in the (patched) configuration, and report them.
+     */
+    pub fn with_missing_fields_initialized(mut self) -> Self {
+        let model = &self.config.model;
+        let mesh = &self.config.mesh;
+        let initialized = match &mut self.state {
+            AnyState::Newtonian(state) => state.initialize_missing_fields(model, mesh, VERSION_AND_BUILD),
+            AnyState::Relativistic(state) => state.initialize_missing_fields(model, mesh, VERSION_AND_BUILD),
+        };
+        for record in initialized {
+            println!("restart: initialized {} in {} blocks from the {}", record.field, record.num_blocks, record.source);
+        }
+        self
+    }
+
+    /**
+     * Construct a new App instance from a file: may be a config.yaml, a
+     * chkpt.0000.cbor, or a chkpt.0000.h5.
      */
     pub fn from_file(filename: &str, overrides: Vec<String>) -> Result<Self, Error> {
         match Path::new(&filename).extension().and_then(OsStr::to_str) {
             Some("yaml") => Self::from_config(serde_yaml::from_str(&read_to_string(filename)?)?, overrides),
-            Some("cbor") => Ok(io::read_cbor::<Self>(filename)?.with_patched_config(overrides)?),
+            Some("cbor") => Ok(io::read_cbor::<Self>(filename)?.with_patched_config(overrides)?.with_missing_fields_initialized()),
+            Some("h5") => Ok(io::read_hdf5_checkpoint(filename)?.with_patched_config(overrides)?.with_missing_fields_initialized()),
             _ => Err(Error::UnknownInputType(filename.to_string())),
         }
     }
 
+    /**
+     * Construct a new App instance from the most recent checkpoint in a
+     * directory: the one with the largest number in its file name, like
+     * chkpt.0012.cbor. The task counters are restored from the checkpoint,
+     * so the output numbering carries on from there.
+     */
+    pub fn from_latest_checkpoint(directory: &str, overrides: Vec<String>) -> Result<Self, Error> {
+        match io::checkpoint_files(directory)?.last() {
+            Some((_, path)) => {
+                println!("resume from {}", path.display());
+                Self::from_file(&path.to_string_lossy(), overrides)
+            }
+            None => Err(Error::NoCheckpoint(directory.to_string())),
+        }
+    }
+
     /**
      * Construct a new App instance from a preset (hard-coded) configuration
-     * name, or otherwise an input file if no matching preset is found.
+     * name, or otherwise an input file if no matching preset is found. If
+     * the input is a directory, the run is resumed from the most recent
+     * checkpoint in it.
      */
     pub fn from_preset_or_file(input: &str, overrides: Vec<String>) -> Result<Self, Error> {
         for (key, yaml) in Self::presets() {
@@ -359,6 +982,9 @@ impl App {
                 return Ok(Self::from_config(serde_yaml::from_str(yaml)?, overrides)?)
             }
         }
+        if Path::new(input).is_dir() {
+            return Self::from_latest_checkpoint(input, overrides)
+        }
         Self::from_file(input, overrides)
     }
 
@@ -382,6 +1008,11 @@ impl App {
         }
     }
 
+    /**
+     * Return the names and YAML text of the preset configurations. There is
+     * no preset for the tabulated progenitor model, since its profile table
+     * is a file which has to be supplied with the run.
+     */
     pub fn presets() -> Vec<(&'static str, &'static str)> {
         vec![
             ("jet_in_cloud", include_str!("../setups/jet_in_cloud.yaml")),
@@ -389,6 +1020,65 @@ impl App {
             ("halo_kilonova", include_str!("../setups/halo_kilonova.yaml")),
             ("wind_shock", include_str!("../setups/wind_shock.yaml")),
             ("kinetic_bomb", include_str!("../setups/kinetic_bomb.yaml")),
+            ("homologous_ejecta", include_str!("../setups/homologous_ejecta.yaml")),
+            ("two_component_jet", include_str!("../setups/two_component_jet.yaml")),
+            ("cloud_shock", include_str!("../setups/cloud_shock.yaml")),
+            ("ejecta_csm", include_str!("../setups/ejecta_csm.yaml")),
+            ("spherical_blast", include_str!("../setups/spherical_blast.yaml")),
+            ("kelvin_helmholtz", include_str!("../setups/kelvin_helmholtz.yaml")),
+            ("rayleigh_taylor", include_str!("../setups/rayleigh_taylor.yaml")),
+            ("jet_in_ism", include_str!("../setups/jet_in_ism.yaml")),
+            ("disk_wind_jet", include_str!("../setups/disk_wind_jet.yaml")),
+            ("colliding_winds", include_str!("../setups/colliding_winds.yaml")),
+            ("shell_collision", include_str!("../setups/shell_collision.yaml")),
+            ("custom", include_str!("../setups/custom.yaml")),
+            ("refreshed_shell", include_str!("../setups/refreshed_shell.yaml")),
+            ("wind", include_str!("../setups/wind.yaml")),
+            ("accretion_torus", include_str!("../setups/accretion_torus.yaml")),
+            ("composite", include_str!("../setups/composite.yaml")),
+            ("perturbed", include_str!("../setups/perturbed.yaml")),
+            ("extended_envelope", include_str!("../setups/extended_envelope.yaml")),
         ]
     }
 }
+
+
+
+
+/**
+ * Collect the dotted key paths of the leaves which differ between two YAML
+ * documents. A key present in only one of them counts as a changed leaf.
+ */
+fn changed_keys(a: &serde_yaml::Value, b: &serde_yaml::Value, path: &mut Vec<String>, changed: &mut Vec<Vec<String>>) {
+    use serde_yaml::Value;
+
+    match (a, b) {
+        (Value::Mapping(a), Value::Mapping(b)) => {
+            let keys: Vec<_> = a.iter().map(|(k, _)| k).chain(b.iter().map(|(k, _)| k).filter(|k| !a.contains_key(*k))).collect();
+
+            for key in keys {
+                path.push(key.as_str().map_or_else(|| format!("{:?}", key), str::to_string));
+
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => changed_keys(x, y, path, changed),
+                    _ => changed.push(path.clone()),
+                }
+                path.pop();
+            }
+        }
+        (a, b) if a != b => changed.push(path.clone()),
+        _ => {}
+    }
+}
+
+/**
+ * Determine whether the configuration item at the given key path may be
+ * changed when restarting from a checkpoint.
+ */
+fn may_change_on_restart(path: &[String]) -> bool {
+    match path.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["control", key, ..] => !matches!(*key, "start_time" | "units"),
+        ["hydro", _, key, ..] => !matches!(*key, "gamma_law_index" | "well_balanced"),
+        _ => false,
+    }
+}
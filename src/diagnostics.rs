@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use ndarray::{Array, ArcArray, Ix2};
+use crate::mesh::BlockIndex;
+use crate::physics::RecoveryReport;
+use crate::reductions::ConservedTotals;
+
+
+
+
+/**
+ * The measured compute cost of advancing a single grid block, accumulated
+ * over some number of updates
+ */
+#[derive(Clone)]
+pub struct BlockWork {
+
+    /// Wall-clock seconds spent recovering primitives and updating the block
+    pub seconds: f64,
+
+    /// Number of times the block was updated (one per Runge-Kutta stage)
+    pub updates: u64,
+
+    /// Number of times the primitive recovery needed a fallback, per zone
+    pub recovery_fallbacks: Array<u64, Ix2>,
+
+    /// Total number of primitive recovery root-finder iterations, per zone
+    pub recovery_iterations: Array<u64, Ix2>,
+
+    /// Largest number of primitive recovery root-finder iterations taken in
+    /// a single update, per zone
+    pub max_recovery_iterations: Array<u64, Ix2>,
+}
+
+
+
+
+/**
+ * The ways that conserved quantities are added to or removed from the grid,
+ * other than by fluxes between zones
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceTerm {
+
+    /// The gravitational source terms of the hydrodynamics system
+    Gravity,
+
+    /// The acceleration imposed by the model
+    Acceleration,
+
+    /// Heating and cooling, integrated implicitly
+    HeatingCooling,
+
+    /// Drag against a static medium, integrated implicitly
+    Drag,
+
+    /// Flux through the inner boundary, which includes any engine injection
+    InnerBoundary,
+
+    /// Flux through the outer boundary
+    OuterBoundary,
+}
+
+
+
+
+/**
+ * A summary of the time steps taken since the last iteration message
+ */
+#[derive(Clone)]
+pub struct TimeStepSummary {
+
+    /// Number of time steps taken
+    pub steps: u64,
+
+    /// The most recent time step
+    pub last: f64,
+
+    /// The smallest time step
+    pub smallest: f64,
+
+    /// The block whose CFL condition set the smallest time step
+    pub smallest_block: BlockIndex,
+
+    /// Number of failed steps which were retried with a smaller time step
+    pub retries: u64,
+}
+
+
+
+
+/**
+ * A copy of the tallies in a work ledger, taken before a step so they can be
+ * restored if the step is discarded
+ */
+pub struct WorkSnapshot {
+    blocks: HashMap<BlockIndex, BlockWork>,
+    recent: HashMap<BlockIndex, BlockWork>,
+    costs: HashMap<BlockIndex, f64>,
+    sources: BTreeMap<SourceTerm, ConservedTotals>,
+}
+
+
+
+
+/**
+ * Weight of the most recent update in the running average of the cost of
+ * each block
+ */
+static COST_AVERAGING_WEIGHT: f64 = 0.2;
+
+
+
+
+/**
+ * Collects the measured compute cost of each block, as the blocks are updated
+ * from the worker threads. Two independent tallies are kept: one for the work
+ * maps, and one for the verbose diagnostics printed with iteration messages.
+ * A running average of the cost of each update is also kept, and used to
+ * schedule the most expensive blocks first. The ledger also accumulates the
+ * conserved quantities injected by each source term, for energy budget
+ * checks, and a summary of the time steps for the iteration messages.
+ */
+#[derive(Default)]
+pub struct WorkLedger {
+    blocks: Mutex<HashMap<BlockIndex, BlockWork>>,
+    recent: Mutex<HashMap<BlockIndex, BlockWork>>,
+    costs: Mutex<HashMap<BlockIndex, f64>>,
+    sources: Mutex<BTreeMap<SourceTerm, ConservedTotals>>,
+    time_steps: Mutex<Option<TimeStepSummary>>,
+}
+
+
+
+
+// ============================================================================
+impl BlockWork {
+
+    /**
+     * Return the total number of primitive recovery fallbacks in the block.
+     */
+    pub fn total_fallbacks(&self) -> u64 {
+        self.recovery_fallbacks.sum()
+    }
+
+    /**
+     * Return the largest number of primitive recovery fallbacks in any zone
+     * of the block, and the index of that zone.
+     */
+    pub fn worst_zone(&self) -> ((usize, usize), u64) {
+        self.recovery_fallbacks
+            .indexed_iter()
+            .fold(((0, 0), 0), |worst, (index, &n)| if n > worst.1 { (index, n) } else { worst })
+    }
+
+    /**
+     * Return the largest number of primitive recovery root-finder iterations
+     * taken in any zone update of the block.
+     */
+    pub fn max_iterations(&self) -> u64 {
+        self.max_recovery_iterations.fold(0, |a, &b| a.max(b))
+    }
+
+    /**
+     * Return the mean number of primitive recovery root-finder iterations
+     * per zone update of the block, or zero if the block was not updated.
+     */
+    pub fn mean_iterations(&self) -> f64 {
+        let zone_updates = self.updates as f64 * self.recovery_iterations.len() as f64;
+
+        if zone_updates > 0.0 {
+            self.recovery_iterations.sum() as f64 / zone_updates
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * Return the measured compute time per zone update, or zero if the block
+     * was not updated, such as a frozen block.
+     */
+    pub fn seconds_per_zone_update(&self) -> f64 {
+        let zone_updates = self.updates as f64 * self.recovery_fallbacks.len() as f64;
+
+        if zone_updates > 0.0 {
+            self.seconds / zone_updates
+        } else {
+            0.0
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl SourceTerm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gravity        => "gravity",
+            Self::Acceleration   => "acceleration",
+            Self::HeatingCooling => "heating and cooling",
+            Self::Drag           => "drag",
+            Self::InnerBoundary  => "inner boundary",
+            Self::OuterBoundary  => "outer boundary",
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl WorkLedger {
+
+    /**
+     * Record a single update of the block at the given index, which took the
+     * given number of seconds. The `recovery` array has the outcome of the
+     * primitive recovery in each zone: whether it needed a fallback, and the
+     * number of root-finder iterations it took.
+     */
+    pub fn record(&self, index: BlockIndex, seconds: f64, recovery: &ArcArray<RecoveryReport, Ix2>) {
+        for tally in &[&self.blocks, &self.recent] {
+            let mut blocks = tally.lock().unwrap();
+            let work = blocks.entry(index).or_insert_with(|| BlockWork {
+                seconds: 0.0,
+                updates: 0,
+                recovery_fallbacks: Array::zeros(recovery.dim()),
+                recovery_iterations: Array::zeros(recovery.dim()),
+                max_recovery_iterations: Array::zeros(recovery.dim()),
+            });
+            if work.recovery_fallbacks.dim() != recovery.dim() {
+                work.recovery_fallbacks = Array::zeros(recovery.dim());
+                work.recovery_iterations = Array::zeros(recovery.dim());
+                work.max_recovery_iterations = Array::zeros(recovery.dim());
+            }
+            work.seconds += seconds;
+            work.updates += 1;
+            ndarray::azip!((n in &mut work.recovery_fallbacks, r in recovery) *n += r.fallback as u64);
+            ndarray::azip!((n in &mut work.recovery_iterations, r in recovery) *n += r.iterations as u64);
+            ndarray::azip!((n in &mut work.max_recovery_iterations, r in recovery) *n = (*n).max(r.iterations as u64));
+        }
+        let mut costs = self.costs.lock().unwrap();
+        let cost = costs.entry(index).or_insert(seconds);
+        *cost += COST_AVERAGING_WEIGHT * (seconds - *cost);
+    }
+
+    /**
+     * Return the given block indexes in the order they should be scheduled
+     * on the worker threads: blocks without a measured cost first, and the
+     * others from the most to the least expensive. Starting the expensive
+     * blocks first keeps the threads busy when the blocks have very different
+     * costs, or do not divide evenly among the threads.
+     */
+    pub fn schedule<I: IntoIterator<Item = BlockIndex>>(&self, indexes: I) -> Vec<BlockIndex> {
+        let costs = self.costs.lock().unwrap();
+        let mut schedule: Vec<_> = indexes.into_iter().collect();
+        let cost = |index: &BlockIndex| costs.get(index).cloned().unwrap_or(f64::INFINITY);
+        schedule.sort_by(|a, b| cost(b).partial_cmp(&cost(a)).unwrap().then(a.cmp(b)));
+        schedule
+    }
+
+    /**
+     * Return the work recorded since the last call to this function, and
+     * reset the work map tally.
+     */
+    pub fn take(&self) -> HashMap<BlockIndex, BlockWork> {
+        std::mem::take(&mut *self.blocks.lock().unwrap())
+    }
+
+    /**
+     * Return the work recorded since the last call to this function, and
+     * reset the verbose diagnostics tally.
+     */
+    pub fn take_recent(&self) -> HashMap<BlockIndex, BlockWork> {
+        std::mem::take(&mut *self.recent.lock().unwrap())
+    }
+
+    /**
+     * Record the mass and energy added to the grid by a source term.
+     */
+    pub fn record_source(&self, term: SourceTerm, totals: ConservedTotals) {
+        *self.sources.lock().unwrap().entry(term).or_default() += totals;
+    }
+
+    /**
+     * Return the mass and energy added by each source term since the last
+     * call to this function, and reset the tally.
+     */
+    pub fn take_sources(&self) -> BTreeMap<SourceTerm, ConservedTotals> {
+        std::mem::take(&mut *self.sources.lock().unwrap())
+    }
+
+    /**
+     * Record an accepted time step, the block whose CFL condition set it,
+     * and the number of failed attempts at the step which were discarded and
+     * retried with a smaller time step.
+     */
+    pub fn record_time_step(&self, dt: f64, limiting_block: BlockIndex, retries: u64) {
+        let mut summary = self.time_steps.lock().unwrap();
+        let summary = summary.get_or_insert(TimeStepSummary {
+            steps: 0,
+            last: dt,
+            smallest: dt,
+            smallest_block: limiting_block,
+            retries: 0,
+        });
+        if dt < summary.smallest {
+            summary.smallest = dt;
+            summary.smallest_block = limiting_block;
+        }
+        summary.steps += 1;
+        summary.last = dt;
+        summary.retries += retries;
+    }
+
+    /**
+     * Return a copy of the block work, cost, and source term tallies, so
+     * they can be restored if the step which follows is discarded.
+     */
+    pub fn snapshot(&self) -> WorkSnapshot {
+        WorkSnapshot {
+            blocks: self.blocks.lock().unwrap().clone(),
+            recent: self.recent.lock().unwrap().clone(),
+            costs: self.costs.lock().unwrap().clone(),
+            sources: self.sources.lock().unwrap().clone(),
+        }
+    }
+
+    /**
+     * Replace the tallies with ones saved by `snapshot`, discarding the work
+     * recorded since.
+     */
+    pub fn restore(&self, snapshot: WorkSnapshot) {
+        *self.blocks.lock().unwrap() = snapshot.blocks;
+        *self.recent.lock().unwrap() = snapshot.recent;
+        *self.costs.lock().unwrap() = snapshot.costs;
+        *self.sources.lock().unwrap() = snapshot.sources;
+    }
+
+    /**
+     * Return a summary of the time steps recorded since the last call to
+     * this function, and reset it.
+     */
+    pub fn take_time_steps(&self) -> Option<TimeStepSummary> {
+        self.time_steps.lock().unwrap().take()
+    }
+}
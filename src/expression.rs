@@ -0,0 +1,357 @@
+use std::convert::TryFrom;
+use serde::{Serialize, Deserialize};
+use crate::physics::light_speed;
+
+
+
+
+// ============================================================================
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+
+    #[error("unexpected character '{0}' at position {1}")]
+    UnexpectedCharacter(char, usize),
+
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+
+    #[error("unknown variable or function '{0}'")]
+    UnknownIdentifier(String),
+
+    #[error("function '{0}' takes {1} argument(s)")]
+    WrongNumberOfArguments(String, usize),
+}
+
+
+
+
+/**
+ * A math expression in the radius `r`, polar angle `theta` (or `q`), and time
+ * `t`, which is parsed once and can then be evaluated quickly. Expressions
+ * support the operators + - * / ^, parentheses, the constants `pi` and `c`
+ * (the speed of light in cm/s), the functions sin, cos, tan, asin, acos,
+ * atan, sinh, cosh, tanh, exp, ln, log10, sqrt, abs, floor, ceil, and step
+ * (1 for positive arguments, otherwise 0), and the two-argument functions
+ * min, max, pow, and atan2.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+
+
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(f64),
+    Radius,
+    Theta,
+    Time,
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(&'static str, Vec<Node>),
+}
+
+
+
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+
+
+
+// ============================================================================
+impl Expression {
+
+    /**
+     * Parse an expression from a string.
+     */
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser { chars: source.chars().collect(), position: 0 };
+        let root = parser.expression()?;
+        parser.skip_whitespace();
+
+        if let Some(c) = parser.peek() {
+            return Err(Error::UnexpectedCharacter(c, parser.position))
+        }
+        Ok(Self { source: source.to_string(), root })
+    }
+
+    /**
+     * Evaluate the expression at the given radius, polar angle, and time.
+     */
+    pub fn evaluate(&self, r: f64, theta: f64, t: f64) -> f64 {
+        self.root.evaluate(r, theta, t)
+    }
+
+    /**
+     * The text the expression was parsed from.
+     */
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self { source: "0".to_string(), root: Node::Number(0.0) }
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = Error;
+    fn try_from(source: String) -> Result<Self, Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> String {
+        expression.source
+    }
+}
+
+
+
+
+// ============================================================================
+impl Node {
+    fn evaluate(&self, r: f64, theta: f64, t: f64) -> f64 {
+        match self {
+            Node::Number(x) => *x,
+            Node::Radius => r,
+            Node::Theta => theta,
+            Node::Time => t,
+            Node::Negate(a) => -a.evaluate(r, theta, t),
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate(r, theta, t), b.evaluate(r, theta, t));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '^' => a.powf(b),
+                    _ => unreachable!(),
+                }
+            }
+            Node::Call(name, args) => {
+                let x: Vec<_> = args.iter().map(|a| a.evaluate(r, theta, t)).collect();
+                match *name {
+                    "sin"   => x[0].sin(),
+                    "cos"   => x[0].cos(),
+                    "tan"   => x[0].tan(),
+                    "asin"  => x[0].asin(),
+                    "acos"  => x[0].acos(),
+                    "atan"  => x[0].atan(),
+                    "sinh"  => x[0].sinh(),
+                    "cosh"  => x[0].cosh(),
+                    "tanh"  => x[0].tanh(),
+                    "exp"   => x[0].exp(),
+                    "ln"    => x[0].ln(),
+                    "log10" => x[0].log10(),
+                    "sqrt"  => x[0].sqrt(),
+                    "abs"   => x[0].abs(),
+                    "floor" => x[0].floor(),
+                    "ceil"  => x[0].ceil(),
+                    "step"  => if x[0] > 0.0 { 1.0 } else { 0.0 },
+                    "min"   => x[0].min(x[1]),
+                    "max"   => x[0].max(x[1]),
+                    "pow"   => x[0].powf(x[1]),
+                    "atan2" => x[0].atan2(x[1]),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl Parser {
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.position += 1
+        }
+    }
+
+    fn next_operator(&mut self, operators: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if operators.contains(&c) => {
+                self.position += 1;
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(c) => Err(Error::UnexpectedCharacter(c, self.position)),
+            None => Err(Error::UnexpectedEnd),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Node, Error> {
+        let mut node = self.term()?;
+
+        while let Some(op) = self.next_operator(&['+', '-']) {
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<Node, Error> {
+        let mut node = self.unary()?;
+
+        while let Some(op) = self.next_operator(&['*', '/']) {
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, Error> {
+        match self.next_operator(&['-', '+']) {
+            Some('-') => Ok(Node::Negate(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Node, Error> {
+        let base = self.primary()?;
+
+        match self.next_operator(&['^']) {
+            Some(op) => Ok(Node::Binary(op, Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, Error> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Err(Error::UnexpectedEnd),
+            Some('(') => {
+                self.position += 1;
+                let node = self.expression()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => self.identifier(),
+            Some(c) => Err(Error::UnexpectedCharacter(c, self.position)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Node, Error> {
+        let start = self.position;
+
+        while self.peek().map_or(false, |c| c.is_ascii_digit() || c == '.') {
+            self.position += 1
+        }
+        if self.peek().map_or(false, |c| c == 'e' || c == 'E') {
+            self.position += 1;
+            if self.peek().map_or(false, |c| c == '+' || c == '-') {
+                self.position += 1
+            }
+            while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+                self.position += 1
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map(Node::Number)
+            .map_err(|_| Error::UnexpectedCharacter(self.chars[start], start))
+    }
+
+    fn identifier(&mut self) -> Result<Node, Error> {
+        let start = self.position;
+
+        while self.peek().map_or(false, |c| c.is_ascii_alphanumeric() || c == '_') {
+            self.position += 1
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+        self.skip_whitespace();
+
+        if self.peek() == Some('(') {
+            self.position += 1;
+            let mut args = vec![self.expression()?];
+
+            while self.next_operator(&[',']).is_some() {
+                args.push(self.expression()?);
+            }
+            self.expect(')')?;
+            return Self::call(name, args)
+        }
+        match name.as_str() {
+            "r" => Ok(Node::Radius),
+            "theta" | "q" => Ok(Node::Theta),
+            "t" => Ok(Node::Time),
+            "pi" => Ok(Node::Number(std::f64::consts::PI)),
+            "c" => Ok(Node::Number(light_speed())),
+            _ => Err(Error::UnknownIdentifier(name)),
+        }
+    }
+
+    fn call(name: String, args: Vec<Node>) -> Result<Node, Error> {
+        static FUNCTIONS: &[(&str, usize)] = &[
+            ("sin", 1), ("cos", 1), ("tan", 1), ("asin", 1), ("acos", 1), ("atan", 1),
+            ("sinh", 1), ("cosh", 1), ("tanh", 1), ("exp", 1), ("ln", 1), ("log10", 1),
+            ("sqrt", 1), ("abs", 1), ("floor", 1), ("ceil", 1), ("step", 1),
+            ("min", 2), ("max", 2), ("pow", 2), ("atan2", 2),
+        ];
+        match FUNCTIONS.iter().find(|(f, _)| *f == name) {
+            Some(&(f, n)) if n == args.len() => Ok(Node::Call(f, args)),
+            Some(&(_, n)) => Err(Error::WrongNumberOfArguments(name, n)),
+            None => Err(Error::UnknownIdentifier(name)),
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_evaluate_with_the_usual_precedence() {
+        let e = |s: &str| Expression::parse(s).unwrap().evaluate(2.0, 0.5, 3.0);
+        assert_eq!(e("1 + 2 * 3"), 7.0);
+        assert_eq!(e("(1 + 2) * 3"), 9.0);
+        assert_eq!(e("-2^2"), -4.0);
+        assert_eq!(e("2^3^2"), 512.0);
+        assert_eq!(e("r * t - 1e1 / 5"), 4.0);
+        assert_eq!(e("max(r, t) + step(theta - 1)"), 3.0);
+        assert!((e("cos(pi)") + 1.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("(r").is_err());
+        assert!(Expression::parse("x * 2").is_err());
+        assert!(Expression::parse("min(r)").is_err());
+        assert!(Expression::parse("r r").is_err());
+    }
+}
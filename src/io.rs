@@ -1,4 +1,24 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
+use crate::app::{AnyHydro, App, CheckpointRetention, Configuration};
+use crate::products::Products;
+#[cfg(feature = "hdf5")]
+use crate::{app::AnyState, mesh::BlockIndex, physics::AnyPrimitive, state::{BlockState, State}, traits::Conserved};
+#[cfg(feature = "hdf5")]
+use ndarray::Array;
+#[cfg(feature = "netcdf")]
+use ndarray::{ArcArray, Ix1};
+
+/// Leading bytes of a zstd frame, used to detect compressed files on read
+static ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Leading bytes of a gzip member, used to detect compressed files on read
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression level used for zstd; levels above the library default trade
+/// a lot of speed for a few percent in size on floating point data
+static ZSTD_LEVEL: i32 = 3;
 
 
 
@@ -15,6 +35,38 @@ pub enum Error {
 
     #[error("{0}")]
     IO(#[from] std::io::Error),
+
+    #[error("{0}")]
+    SerdeYaml(#[from] serde_yaml::Error),
+
+    #[cfg(feature = "hdf5")]
+    #[error("{0}")]
+    Hdf5(#[from] hdf5::Error),
+
+    #[cfg(feature = "netcdf")]
+    #[error("{0}")]
+    Netcdf(#[from] netcdf::error::Error),
+
+    #[error("this build does not include the {0} feature; rebuild with `cargo install --path . --features {0}`")]
+    MissingFeature(&'static str),
+}
+
+
+
+
+/**
+ * Compression of CBOR files. Compressed files are detected from their
+ * leading bytes when they are read, so the setting only affects writing.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+
+    /// Zstandard: fast, and the better choice for large runs
+    Zstd,
+
+    /// Gzip: slower, but readable with standard tools everywhere
+    Gzip,
 }
 
 
@@ -22,14 +74,643 @@ pub enum Error {
 
 // ============================================================================
 pub fn write_cbor<T: Serialize>(value: &T, path_str: &str) -> Result<(), Error> {
+    write_cbor_compressed(value, path_str, None)
+}
+
+pub fn write_cbor_compressed<T: Serialize>(value: &T, path_str: &str, compression: Option<Compression>) -> Result<(), Error> {
     println!("write {}", path_str);
     let file = std::fs::File::create(&path_str)?;
     let mut buffer = std::io::BufWriter::new(file);
-    Ok(ciborium::ser::into_writer(&value, &mut buffer)?)
+
+    match compression {
+        None => ciborium::ser::into_writer(&value, &mut buffer)?,
+        Some(Compression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(buffer, ZSTD_LEVEL)?;
+            ciborium::ser::into_writer(&value, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Some(Compression::Gzip) => {
+            let mut encoder = flate2::write::GzEncoder::new(buffer, flate2::Compression::default());
+            ciborium::ser::into_writer(&value, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    Ok(())
 }
 
 pub fn read_cbor<T: for<'de> Deserialize<'de>>(path_str: &str) -> Result<T, Error> {
     let file = std::fs::File::open(path_str)?;
-    let buffer = std::io::BufReader::new(file);
-    Ok(ciborium::de::from_reader(buffer)?)
+    let mut buffer = std::io::BufReader::new(file);
+    let header = buffer.fill_buf()?;
+    let is_zstd = header.starts_with(&ZSTD_MAGIC);
+    let is_gzip = header.starts_with(&GZIP_MAGIC);
+
+    if is_zstd {
+        Ok(ciborium::de::from_reader(zstd::Decoder::with_buffer(buffer)?)?)
+    } else if is_gzip {
+        Ok(ciborium::de::from_reader(flate2::bufread::GzDecoder::new(buffer))?)
+    } else {
+        Ok(ciborium::de::from_reader(buffer)?)
+    }
+}
+
+
+
+
+/**
+ * Return the checkpoint files in a directory (those named like
+ * chkpt.0012.cbor or chkpt.0012.h5), together with their numbers, sorted by
+ * number.
+ */
+pub fn checkpoint_files(directory: &str) -> Result<Vec<(usize, PathBuf)>, Error> {
+    let mut checkpoints: Vec<_> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_string();
+            let mut parts = name.split('.');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("chkpt"), Some(number), Some("cbor"), None) |
+                (Some("chkpt"), Some(number), Some("h5"), None) => Some((number.parse().ok()?, path)),
+                _ => None,
+            }
+        })
+        .collect();
+    checkpoints.sort();
+    Ok(checkpoints)
+}
+
+/**
+ * Delete the checkpoints in a directory which are not retained by the given
+ * rule, counting back from the checkpoint with the given number.
+ * Checkpoints with larger numbers (left over from an earlier run) are not
+ * touched.
+ */
+pub fn prune_checkpoints(directory: &str, newest: usize, retention: &CheckpointRetention) -> Result<(), Error> {
+    for (number, path) in checkpoint_files(directory)? {
+        let keep = number == 0
+            || number > newest
+            || number + retention.keep_last > newest
+            || retention.keep_every.map_or(false, |m| number % m == 0);
+
+        if !keep {
+            println!("remove {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+
+
+
+/// A file-writing job handed to an `OutputWriter`
+type OutputJob = Box<dyn FnOnce() -> anyhow::Result<()> + Send>;
+
+
+
+
+/**
+ * Writes output files, either right away or on a dedicated thread so the
+ * solver does not wait for the serialization and the file system. The
+ * caller snapshots the data (cloning a state is cheap, since the block
+ * arrays are reference counted) and submits a job which writes it. At most
+ * one job waits while another one is written; beyond that, `submit` blocks,
+ * which bounds the memory held by pending snapshots. An error in a
+ * background job is returned by the next call to `submit` or `finish`.
+ */
+pub struct OutputWriter {
+    sender: Option<std::sync::mpsc::SyncSender<OutputJob>>,
+    thread: Option<std::thread::JoinHandle<anyhow::Result<()>>>,
+}
+
+
+
+
+// ============================================================================
+impl OutputWriter {
+
+    /**
+     * Create a writer, which runs the jobs on a background thread if
+     * `background` is true, and otherwise in `submit`.
+     */
+    pub fn new(background: bool) -> Self {
+        if !background {
+            return Self { sender: None, thread: None }
+        }
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<OutputJob>(1);
+        let thread = std::thread::spawn(move || {
+            for job in receiver {
+                job()?
+            }
+            Ok(())
+        });
+        Self { sender: Some(sender), thread: Some(thread) }
+    }
+
+    /**
+     * Write a file, or queue it for the background thread.
+     */
+    pub fn submit<F>(&mut self, job: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() -> anyhow::Result<()> + Send + 'static
+    {
+        match &self.sender {
+            None => job(),
+            Some(sender) => match sender.send(Box::new(job)) {
+                Ok(()) => Ok(()),
+                Err(_) => self.join(),
+            },
+        }
+    }
+
+    /**
+     * Wait for all the queued files to be written. Dropping the writer
+     * also waits, but only prints any error.
+     */
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> anyhow::Result<()> {
+        self.sender.take();
+
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| anyhow::anyhow!("the output thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("{}", e)
+        }
+    }
+}
+
+
+
+
+/**
+ * File format for checkpoints and products
+ */
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+
+    /// CBOR-encoded serde data, which the code and the Python tools read
+    Cbor,
+
+    /// HDF5 files, with one group per block and the configuration in
+    /// attributes, for analysis toolchains such as yt, h5py, and VisIt.
+    /// Needs the optional `hdf5` feature.
+    Hdf5,
+
+    /// Products as raw float64 arrays, one file per field, with a YAML
+    /// sidecar describing the blocks (see `write_flat_products`), for
+    /// scripts without a CBOR parser. Checkpoints are written as CBOR.
+    Flat,
+
+    /// Products as NetCDF-4 files with CF-style metadata, one group per
+    /// block, for NetCDF tools such as ncview. Checkpoints are written as
+    /// CBOR. Needs the optional `netcdf` feature.
+    Netcdf,
+}
+
+
+
+
+// ============================================================================
+impl OutputFormat {
+
+    /**
+     * The file name extension for checkpoints in this format.
+     */
+    pub fn checkpoint_extension(&self) -> &'static str {
+        match self {
+            Self::Cbor | Self::Flat | Self::Netcdf => "cbor",
+            Self::Hdf5 => "h5",
+        }
+    }
+
+    /**
+     * The file name extension for products in this format. For flat
+     * products, this is the extension of the sidecar file.
+     */
+    pub fn products_extension(&self) -> &'static str {
+        match self {
+            Self::Cbor => "cbor",
+            Self::Hdf5 => "h5",
+            Self::Flat => "yaml",
+            Self::Netcdf => "nc",
+        }
+    }
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self::Cbor
+    }
+}
+
+
+
+
+/**
+ * Sidecar metadata of a flat-array products snapshot
+ */
+#[derive(Serialize)]
+struct FlatProducts<'a> {
+    time: f64,
+    version: &'a str,
+    byte_order: &'static str,
+    dtype: &'static str,
+    fields: Vec<FlatField>,
+    blocks: Vec<FlatBlock>,
+    config: &'a Configuration,
+}
+
+/**
+ * One field of a flat-array products snapshot, stored in its own file
+ */
+#[derive(Serialize)]
+struct FlatField {
+    name: &'static str,
+    file: String,
+    units: &'static str,
+    centering: &'static str,
+}
+
+/**
+ * Location of one block in the field files of a flat-array products
+ * snapshot. Offsets are in array elements, not bytes.
+ */
+#[derive(Serialize)]
+struct FlatBlock {
+    radial_block: i32,
+    polar_block: usize,
+    shape: [usize; 2],
+    zone_offset: usize,
+    radial_vertex_offset: usize,
+    polar_vertex_offset: usize,
+}
+
+
+
+
+/**
+ * Write products as flat binary arrays, for scripts which read the data with
+ * `numpy.fromfile`. Each field goes to its own file of little-endian float64
+ * values, named after the given file, e.g. `prods.0012.mass_density.f64` for
+ * `prods.0012.yaml`. The blocks are concatenated in the order of their
+ * indexes, each in row-major (radial, polar) order. The given file is a YAML
+ * sidecar with the time, the fields and their units, the shape and offsets
+ * of each block, and the configuration.
+ */
+pub fn write_flat_products(products: &Products, path_str: &str) -> Result<(), Error> {
+    let path = std::path::Path::new(path_str);
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let mut indexes: Vec<_> = products.blocks.keys().cloned().collect();
+    indexes.sort();
+
+    let fields: Vec<_> = vec![("radial_vertices", "cm", "radial_vertex"), ("polar_vertices", "rad", "polar_vertex")]
+        .into_iter()
+        .chain(products_field_units(products).iter().map(|&(name, units)| (name, units, "zone")))
+        .collect();
+    let mut data = vec![Vec::new(); fields.len()];
+    let mut blocks = Vec::new();
+
+    for index in &indexes {
+        let block = &products.blocks[index];
+        let (nr, nq) = block.primitive.dim();
+
+        blocks.push(FlatBlock {
+            radial_block: index.0,
+            polar_block: index.1,
+            shape: [nr, nq],
+            zone_offset: data[2].len(),
+            radial_vertex_offset: data[0].len(),
+            polar_vertex_offset: data[1].len(),
+        });
+        data[0].extend(block.radial_vertices.iter());
+        data[1].extend(block.polar_vertices.iter());
+        data[2].extend(block.primitive.iter().map(|p| p.mass_density));
+        data[3].extend(block.primitive.iter().map(|p| p.velocity_r));
+        data[4].extend(block.primitive.iter().map(|p| p.velocity_q));
+        data[5].extend(block.primitive.iter().map(|p| p.gas_pressure));
+        data[6].extend(block.scalar.iter());
+    }
+
+    let mut flat_fields = Vec::new();
+
+    for (&(name, units, centering), values) in fields.iter().zip(&data) {
+        let file = format!("{}.{}.f64", stem, name);
+        let mut buffer = std::io::BufWriter::new(std::fs::File::create(path.with_file_name(&file))?);
+
+        for x in values {
+            buffer.write_all(&x.to_le_bytes())?;
+        }
+        buffer.flush()?;
+        flat_fields.push(FlatField { name, file, units, centering });
+    }
+
+    let metadata = FlatProducts {
+        time: products.time,
+        version: &products.version,
+        byte_order: "little",
+        dtype: "float64",
+        fields: flat_fields,
+        blocks,
+        config: &products.config,
+    };
+    println!("write {}", path_str);
+    std::fs::write(path, serde_yaml::to_string(&metadata)?)?;
+    Ok(())
+}
+
+
+
+
+/**
+ * Write products to a NetCDF-4 file, following the CF conventions where they
+ * apply. The file has global attributes for the time, the code version, and
+ * the configuration (as YAML), and a scalar `time` variable. Each block is
+ * a group named like `block_0012_0` (the radial and polar block indexes),
+ * with coordinate variables `r` and `theta` at the zone centers, their cell
+ * bounds, and the primitive fields and scalar on the (r, theta) grid, each
+ * with a `units` attribute.
+ */
+#[cfg(feature = "netcdf")]
+pub fn write_netcdf_products(products: &Products, path_str: &str) -> Result<(), Error> {
+    println!("write {}", path_str);
+    let mut file = netcdf::create(path_str)?;
+    let mut indexes: Vec<_> = products.blocks.keys().cloned().collect();
+    indexes.sort();
+
+    file.add_attribute("Conventions", "CF-1.8")?;
+    file.add_attribute("title", "kilonova products")?;
+    file.add_attribute("source", products.version.as_str())?;
+    file.add_attribute("config", serde_yaml::to_string(&products.config)?.as_str())?;
+    file.add_attribute("time", products.time)?;
+
+    let mut time = file.add_variable::<f64>("time", &[])?;
+    time.add_attribute("units", "s")?;
+    time.add_attribute("standard_name", "time")?;
+    time.put_value(products.time, None)?;
+
+    for index in indexes {
+        let block = &products.blocks[&index];
+        let (nr, nq) = block.primitive.dim();
+        let mut group = file.add_group(&format!("block_{:04}_{}", index.0, index.1))?;
+        let centers = |v: &ArcArray<f64, Ix1>| v.iter().zip(v.iter().skip(1)).map(|(a, b)| 0.5 * (a + b)).collect::<Vec<_>>();
+        let bounds = |v: &ArcArray<f64, Ix1>| v.iter().zip(v.iter().skip(1)).flat_map(|(a, b)| vec![*a, *b]).collect::<Vec<_>>();
+
+        group.add_dimension("r", nr)?;
+        group.add_dimension("theta", nq)?;
+        group.add_dimension("nv", 2)?;
+
+        for (name, units, values, bounds) in vec![
+            ("r", "cm", centers(&block.radial_vertices), bounds(&block.radial_vertices)),
+            ("theta", "rad", centers(&block.polar_vertices), bounds(&block.polar_vertices)),
+        ] {
+            let bounds_name = format!("{}_bounds", name);
+            let mut coordinate = group.add_variable::<f64>(name, &[name])?;
+            coordinate.add_attribute("units", units)?;
+            coordinate.add_attribute("bounds", bounds_name.as_str())?;
+            coordinate.put_values(&values, None, None)?;
+            group.add_variable::<f64>(&bounds_name, &[name, "nv"])?.put_values(&bounds, None, None)?;
+        }
+        for (name, units) in products_field_units(products) {
+            let values: Vec<_> = match name {
+                "mass_density" => block.primitive.iter().map(|p| p.mass_density).collect(),
+                "velocity_r" => block.primitive.iter().map(|p| p.velocity_r).collect(),
+                "velocity_q" => block.primitive.iter().map(|p| p.velocity_q).collect(),
+                "gas_pressure" => block.primitive.iter().map(|p| p.gas_pressure).collect(),
+                _ => block.scalar.iter().cloned().collect(),
+            };
+            let mut variable = group.add_variable::<f64>(name, &["r", "theta"])?;
+            variable.add_attribute("units", units)?;
+            variable.put_values(&values, None, None)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "netcdf"))]
+pub fn write_netcdf_products(_products: &Products, _path_str: &str) -> Result<(), Error> {
+    Err(Error::MissingFeature("netcdf"))
+}
+
+/**
+ * Return the names and units of the zone fields in products files. The
+ * velocities are gamma-beta for relativistic hydro, and the relativistic
+ * pressure is in units of the mass density times c².
+ */
+fn products_field_units(products: &Products) -> [(&'static str, &'static str); 5] {
+    let relativistic = matches!(products.config.hydro, AnyHydro::Relativistic(_));
+    [
+        ("mass_density", "g/cm^3"),
+        ("velocity_r", if relativistic { "1" } else { "cm/s" }),
+        ("velocity_q", if relativistic { "1" } else { "cm/s" }),
+        ("gas_pressure", if relativistic { "g/cm^3 c^2" } else { "erg/cm^3" }),
+        ("scalar", "1"),
+    ]
+}
+
+
+
+
+/**
+ * Write products to an HDF5 file. The root group has attributes for the
+ * time, the code version, and the configuration (as YAML), and each block
+ * has a group named like `block.0012.0` (the radial and polar block
+ * indexes), with datasets for the vertices, the primitive fields, and the
+ * scalar.
+ */
+#[cfg(feature = "hdf5")]
+pub fn write_hdf5_products(products: &Products, path_str: &str) -> Result<(), Error> {
+    println!("write {}", path_str);
+    let file = hdf5::File::create(path_str)?;
+
+    write_hdf5_string(&file, "config", &serde_yaml::to_string(&products.config)?)?;
+    write_hdf5_string(&file, "version", &products.version)?;
+    file.new_attr::<f64>().create("time", ())?.write_scalar(&products.time)?;
+
+    if let Some(observer_times) = &products.observer_times {
+        file.new_attr::<f64>().create("time_since_epoch", ())?.write_scalar(&observer_times.time_since_epoch)?;
+    }
+    for (index, block) in &products.blocks {
+        let group = file.create_group(&hdf5_block_name(index))?;
+        let shape = block.primitive.dim();
+        let field = |f: fn(&AnyPrimitive) -> f64| block.primitive.iter().map(f).collect::<Vec<_>>();
+
+        group.new_attr::<i32>().create("radial_block", ())?.write_scalar(&index.0)?;
+        group.new_attr::<u64>().create("polar_block", ())?.write_scalar(&(index.1 as u64))?;
+        write_hdf5_dataset(&group, "radial_vertices", &block.radial_vertices.to_vec(), &[block.radial_vertices.len()])?;
+        write_hdf5_dataset(&group, "polar_vertices", &block.polar_vertices.to_vec(), &[block.polar_vertices.len()])?;
+        write_hdf5_dataset(&group, "mass_density", &field(|p| p.mass_density), &[shape.0, shape.1])?;
+        write_hdf5_dataset(&group, "velocity_r", &field(|p| p.velocity_r), &[shape.0, shape.1])?;
+        write_hdf5_dataset(&group, "velocity_q", &field(|p| p.velocity_q), &[shape.0, shape.1])?;
+        write_hdf5_dataset(&group, "gas_pressure", &field(|p| p.gas_pressure), &[shape.0, shape.1])?;
+        write_hdf5_dataset(&group, "scalar", &block.scalar.iter().cloned().collect::<Vec<_>>(), &[shape.0, shape.1])?;
+    }
+    Ok(())
+}
+
+/**
+ * Write a checkpoint to an HDF5 file. The root group has attributes for the
+ * time, the iteration, the code version, the configuration, and the task
+ * list (the latter two as YAML), and each block has a group with the
+ * conserved quantities (an array of shape [nr, nq, 4]) and the scalar mass.
+ * So that runs can be restarted from the file, the dataset `app` holds the
+ * CBOR encoding of the checkpoint without its blocks, which are restored
+ * from the block groups when the file is read.
+ */
+#[cfg(feature = "hdf5")]
+pub fn write_hdf5_checkpoint(app: &App, path_str: &str) -> Result<(), Error> {
+    println!("write {}", path_str);
+    let file = hdf5::File::create(path_str)?;
+    let mut metadata = app.clone();
+    let mut encoded = Vec::new();
+
+    match &mut metadata.state {
+        AnyState::Newtonian(state) => state.solution.clear(),
+        AnyState::Relativistic(state) => state.solution.clear(),
+    }
+    ciborium::ser::into_writer(&metadata, &mut encoded)?;
+
+    write_hdf5_string(&file, "config", &serde_yaml::to_string(&app.config)?)?;
+    write_hdf5_string(&file, "tasks", &serde_yaml::to_string(&app.tasks)?)?;
+    write_hdf5_string(&file, "version", &app.version)?;
+    write_hdf5_dataset(&file, "app", &encoded, &[encoded.len()])?;
+
+    match &app.state {
+        AnyState::Newtonian(state) => write_hdf5_state(&file, state, |u| [u.0, u.1, u.2, u.3]),
+        AnyState::Relativistic(state) => write_hdf5_state(&file, state, |u| [u.0, u.1, u.2, u.3]),
+    }
+}
+
+/**
+ * Read a checkpoint written by `write_hdf5_checkpoint`.
+ */
+#[cfg(feature = "hdf5")]
+pub fn read_hdf5_checkpoint(path_str: &str) -> Result<App, Error> {
+    let file = hdf5::File::open(path_str)?;
+    let encoded = file.dataset("app")?.read_raw::<u8>()?;
+    let mut app: App = ciborium::de::from_reader(encoded.as_slice())?;
+
+    match &mut app.state {
+        AnyState::Newtonian(state) => read_hdf5_state(&file, state, |u| hydro_euler::euler_2d::Conserved(u[0], u[1], u[2], u[3]))?,
+        AnyState::Relativistic(state) => read_hdf5_state(&file, state, |u| hydro_srhd::srhd_2d::Conserved(u[0], u[1], u[2], u[3]))?,
+    }
+    Ok(app)
+}
+
+#[cfg(not(feature = "hdf5"))]
+pub fn write_hdf5_products(_products: &Products, _path_str: &str) -> Result<(), Error> {
+    Err(Error::MissingFeature("hdf5"))
+}
+
+#[cfg(not(feature = "hdf5"))]
+pub fn write_hdf5_checkpoint(_app: &App, _path_str: &str) -> Result<(), Error> {
+    Err(Error::MissingFeature("hdf5"))
+}
+
+#[cfg(not(feature = "hdf5"))]
+pub fn read_hdf5_checkpoint(_path_str: &str) -> Result<App, Error> {
+    Err(Error::MissingFeature("hdf5"))
+}
+
+
+
+
+// ============================================================================
+#[cfg(feature = "hdf5")]
+fn write_hdf5_state<C, F>(file: &hdf5::File, state: &State<C>, components: F) -> Result<(), Error>
+where
+    C: Conserved,
+    F: Fn(&C) -> [f64; 4],
+{
+    file.new_attr::<f64>().create("time", ())?.write_scalar(&state.time)?;
+    file.new_attr::<f64>().create("iteration", ())?.write_scalar(&(*state.iteration.numer() as f64 / *state.iteration.denom() as f64))?;
+
+    for (index, block) in &state.solution {
+        let group = file.create_group(&hdf5_block_name(index))?;
+        let (nr, nq) = block.conserved.dim();
+        let conserved: Vec<_> = block.conserved.iter().flat_map(|u| components(u).to_vec()).collect();
+
+        group.new_attr::<i32>().create("radial_block", ())?.write_scalar(&index.0)?;
+        group.new_attr::<u64>().create("polar_block", ())?.write_scalar(&(index.1 as u64))?;
+        write_hdf5_dataset(&group, "conserved", &conserved, &[nr, nq, 4])?;
+        write_hdf5_dataset(&group, "scalar_mass", &block.scalar_mass.iter().cloned().collect::<Vec<_>>(), &[nr, nq])?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "hdf5")]
+fn read_hdf5_state<C, F>(file: &hdf5::File, state: &mut State<C>, conserved: F) -> Result<(), Error>
+where
+    C: Conserved,
+    F: Fn(&[f64]) -> C,
+{
+    let shape_error = |e: ndarray::ShapeError| hdf5::Error::from(e.to_string());
+
+    for name in file.member_names()?.iter().filter(|name| name.starts_with("block.")) {
+        let group = file.group(name)?;
+        let index = (group.attr("radial_block")?.read_scalar::<i32>()?, group.attr("polar_block")?.read_scalar::<u64>()? as usize);
+        let dataset = group.dataset("conserved")?;
+        let shape = (dataset.shape()[0], dataset.shape()[1]);
+        let u: Vec<_> = dataset.read_raw::<f64>()?.chunks(4).map(&conserved).collect();
+        let scalar_mass = group.dataset("scalar_mass")?.read_raw::<f64>()?;
+        let block = BlockState {
+            conserved: Array::from_shape_vec(shape, u).map_err(shape_error)?.to_shared(),
+            scalar_mass: Array::from_shape_vec(shape, scalar_mass).map_err(shape_error)?.to_shared(),
+        };
+        state.solution.insert(index, block);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "hdf5")]
+fn write_hdf5_dataset<T: hdf5::H5Type>(group: &hdf5::Group, name: &str, data: &[T], shape: &[usize]) -> Result<(), Error> {
+    Ok(group.new_dataset::<T>().create(name, shape)?.write_raw(data)?)
+}
+
+#[cfg(feature = "hdf5")]
+fn write_hdf5_string(group: &hdf5::Group, name: &str, value: &str) -> Result<(), Error> {
+    let value: hdf5::types::VarLenUnicode = value.parse().map_err(|e: hdf5::types::StringError| hdf5::Error::from(e.to_string()))?;
+    Ok(group.new_attr::<hdf5::types::VarLenUnicode>().create(name, ())?.write_scalar(&value)?)
+}
+
+#[cfg(feature = "hdf5")]
+fn hdf5_block_name(index: &BlockIndex) -> String {
+    format!("block.{:04}.{}", index.0, index.1)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_checkpoints_keeps_the_first_the_most_recent_and_the_multiples() {
+        let directory = std::env::temp_dir().join(format!("kilonova-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for number in 0..12 {
+            std::fs::write(directory.join(format!("chkpt.{:04}.cbor", number)), b"").unwrap();
+        }
+        std::fs::write(directory.join("prods.0003.cbor"), b"").unwrap();
+
+        let retention = CheckpointRetention { keep_last: 2, keep_every: Some(5) };
+        prune_checkpoints(&directory.to_string_lossy(), 10, &retention).unwrap();
+
+        let remaining: Vec<_> = checkpoint_files(&directory.to_string_lossy()).unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(remaining, vec![0, 5, 9, 10, 11]);
+        assert!(directory.join("prods.0003.cbor").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
 }
@@ -1,13 +1,21 @@
+pub mod afterglow;
+pub mod annotations;
 pub mod app;
+pub mod diagnostics;
+pub mod expression;
 pub mod galmod;
 pub mod io;
 pub mod lookup_table;
 pub mod lookup_table_v2;
 pub mod mesh;
 pub mod models;
+pub mod movie;
 pub mod physics;
 pub mod products;
+pub mod reductions;
 pub mod scheme;
 pub mod state;
 pub mod tasks;
 pub mod traits;
+pub mod validation;
+pub mod vtk;
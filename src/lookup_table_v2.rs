@@ -16,6 +16,9 @@ pub enum Error {
 
     #[error("the table must have at least two rows")]
     TableTooSmall,
+
+    #[error("line {0} of the table has {1} columns, expected {2}")]
+    WrongNumberOfColumns(usize, usize, usize),
 }
 
 /// Encapsulates the logic to read and sample a tabulated function of a single
@@ -76,6 +79,57 @@ impl<const NUM_COLS: usize> LookupTable<NUM_COLS> {
         Self::from_ascii_table(&read_to_string(filename)?)
     }
 
+    /// Create a `LookupTable` from a string of delimited ASCII data, such as a
+    /// CSV file or a MESA-style profile. Columns may be separated by commas or
+    /// whitespace, anything following a `#` is a comment, and any lines
+    /// before the first row of numbers (column names, units, etc.) are
+    /// skipped. Every data row must have exactly `NUM_COLS` entries. Tables
+    /// listed in decreasing order of the left-most column (MESA profiles list
+    /// the stellar surface first) are reversed.
+    pub fn from_delimited_table(contents: &str) -> Result<Self, Error> {
+        let mut rows: Vec<[f64; NUM_COLS]> = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue
+            }
+            let values: Result<Vec<f64>, _> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.parse())
+                .collect();
+
+            let values = match values {
+                Ok(values) => values,
+                Err(_) if rows.is_empty() => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if values.len() != NUM_COLS {
+                return Err(Error::WrongNumberOfColumns(n + 1, values.len(), NUM_COLS))
+            }
+            let mut row = [0.0; NUM_COLS];
+            row.copy_from_slice(&values);
+            rows.push(row);
+        }
+        if rows.len() >= 2 && rows.first().unwrap()[0] > rows.last().unwrap()[0] {
+            rows.reverse()
+        }
+        Self::from_rows(rows)
+    }
+
+    /// Convenience method to load the contents of a delimited ASCII file and
+    /// pass the resulting string to `LookupTable::from_delimited_table`.
+    pub fn from_delimited_file(filename: &str) -> Result<Self, Error> {
+        Self::from_delimited_table(&read_to_string(filename)?)
+    }
+
+    /// Return the smallest and largest values of the left-most column.
+    pub fn domain(&self) -> (f64, f64) {
+        (self.rows.first().unwrap()[0], self.rows.last().unwrap()[0])
+    }
+
     /// Return a fixed-length array of data at the given independent variable
     /// value `x`. The result is interpolated linearly between the two nearest
     /// tabulated points. This function panics if `x` is out of range (not
@@ -177,4 +231,20 @@ mod tests {
         assert!(f64::abs(table.sample(1.0)[1] - 0.20) < 1e-10);
         assert!(f64::abs(table.sample(1.5)[1] - 0.25) < 1e-10);
     }
+
+    #[test]
+    fn lookup_table_can_be_read_from_delimited_text_with_headers() {
+        let contents = "
+            # a comment line
+            radius, density
+            cm, g/cm^3
+            2.0, 0.3 # surface
+            1.0, 0.2
+            0.0, 0.1
+        ";
+        let table = LookupTable::<2>::from_delimited_table(contents).unwrap();
+        assert_eq!(table.domain(), (0.0, 2.0));
+        assert!(f64::abs(table.sample(1.5)[1] - 0.25) < 1e-10);
+        assert!(LookupTable::<3>::from_delimited_table(contents).is_err());
+    }
 }
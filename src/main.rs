@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use kilonova::*;
 use app::{
     AnyHydro,
@@ -7,11 +8,33 @@ use app::{
     Configuration,
     Control,
 };
+use afterglow::{
+    AfterglowProducts,
+};
+use diagnostics::{
+    BlockWork,
+    WorkLedger,
+};
+use io::{
+    OutputFormat,
+    OutputWriter,
+};
 use mesh::{
+    BlockIndex,
     Mesh,
 };
 use products::{
+    DeltaEncoder,
     Products,
+    RadialProfile,
+    WorkProducts,
+};
+use reductions::{
+    Summation,
+    TimeSeriesSample,
+};
+use scheme::{
+    SchemeSettings,
 };
 use state::{
     State,
@@ -24,12 +47,91 @@ use traits::{
 use tasks::{
     Tasks,
 };
+use validation::{
+    BlastProfile,
+    JetHeadSample,
+    ReferenceCurve,
+};
+
+
+
+
+/// Creating a file with this name in the output directory switches on the
+/// verbose per-block solver diagnostics, and removing it switches them off.
+/// The file is checked with each iteration message, so the diagnostics can be
+/// toggled while the code is running.
+static VERBOSE_CONTROL_FILE: &str = "verbose";
+
+
+
+
+// ============================================================================
+fn print_block_diagnostics(work: HashMap<BlockIndex, BlockWork>, control_file: &str) {
+    let mut blocks: Vec<_> = work.into_iter().collect();
+    blocks.sort_by_key(|(index, _)| *index);
+
+    println!("        verbose block diagnostics (remove {} to disable):", control_file);
+    println!("        {:>8} {:>8} {:>12} {:>10} {:>16} {:>10} {:>10}", "block", "updates", "us/zone", "fallbacks", "worst zone", "max iter", "mean iter");
+
+    for (index, work) in &blocks {
+        let ((i, j), worst) = work.worst_zone();
+        println!("        {:>8} {:>8} {:>12.3} {:>10} {:>16} {:>10} {:>10.2}",
+            index.0,
+            work.updates,
+            1e6 * work.seconds_per_zone_update(),
+            work.total_fallbacks(),
+            if worst > 0 { format!("({}, {}): {}", i, j, worst) } else { "-".to_string() },
+            work.max_iterations(),
+            work.mean_iterations());
+    }
+    let total: u64 = blocks.iter().map(|(_, work)| work.total_fallbacks()).sum();
+    println!("        total primitive recovery fallbacks: {}", total);
+}
+
+
+
+
+// ============================================================================
+fn print_source_budget(work: &WorkLedger) {
+    let sources = work.take_sources();
+
+    if sources.is_empty() {
+        return
+    }
+    println!("        conserved quantities added by source terms since the last report:");
+
+    for (term, totals) in sources {
+        println!("        {:.<20} mass={:+.6e} energy={:+.6e}", format!("{} ", term.name()), totals.mass, totals.energy);
+    }
+}
 
 
 
 
 // ============================================================================
-fn side_effects<C, M, H>(state: &State<C>, tasks: &mut Tasks, hydro: &H, model: &M, mesh: &Mesh, control: &Control)
+fn radial_profiles(products: &Products, control: &Control, number: usize) -> Vec<(RadialProfile, String)> {
+    let settings = match &control.radial_profiles {
+        Some(settings) => settings,
+        None => return Vec::new(),
+    };
+    let mut profiles = vec![(
+        RadialProfile::from_products(products, None),
+        format!("{}/profile.{:04}.dat", control.output_directory, number),
+    )];
+    for (n, &angle) in settings.polar_angles.iter().enumerate() {
+        profiles.push((
+            RadialProfile::from_products(products, Some(angle)),
+            format!("{}/profile.{:04}.angle{}.dat", control.output_directory, number, n),
+        ));
+    }
+    profiles
+}
+
+
+
+
+// ============================================================================
+fn side_effects<C, M, H>(state: &State<C>, tasks: &mut Tasks, hydro: &H, model: &M, mesh: &Mesh, control: &Control, work: &WorkLedger, delta: &mut DeltaEncoder, writer: &mut OutputWriter)
     -> anyhow::Result<()>
 where
     H: Hydrodynamics<Conserved = C>,
@@ -40,30 +142,146 @@ where
     AnyState: From<State<C>>,
 {
     if tasks.iteration_message.next_time <= state.time {
+        let iterations = (state.iteration - tasks.iteration_message.last_iteration).to_integer();
         let time = tasks.iteration_message.advance(0.0);
-        let mzps = 1e-6 * state.total_zones() as f64 / time * control.fold as f64;
+        let mzps = 1e-6 * state.total_zones() as f64 / time * iterations as f64;
+        tasks.iteration_message.last_iteration = state.iteration;
         if tasks.iteration_message.count_this_run > 1 {
             println!("[{:05}] t={:.5} blocks={} Mzps={:.2})", state.iteration, state.time, state.solution.len(), mzps);
         }
+        if let Some(steps) = work.take_time_steps() {
+            if tasks.iteration_message.count_this_run > 1 {
+                println!("        dt={:.4e} (smallest {:.4e} over {} steps, set by block {})",
+                    steps.last, steps.smallest, steps.steps, steps.smallest_block.0);
+
+                if steps.retries > 0 {
+                    println!("        {} failed step(s) retried at a reduced dt", steps.retries);
+                }
+            }
+        }
+        let verbose_control_file = format!("{}/{}", control.output_directory, VERBOSE_CONTROL_FILE);
+        let recent_work = work.take_recent();
+
+        if std::path::Path::new(&verbose_control_file).exists() {
+            print_block_diagnostics(recent_work, &verbose_control_file);
+        }
+        match control.conservation_monitor {
+            Some(Summation::Double) => {
+                let totals = state.totals(Summation::Double);
+                println!("        mass={:.10e} energy={:.10e}", totals.mass, totals.energy);
+            }
+            Some(Summation::Extended) => {
+                let totals = state.totals(Summation::Extended);
+                let error = state.totals(Summation::Double).relative_difference(&totals);
+                println!("        mass={:.10e} energy={:.10e} (double precision summation error: mass={:.2e} energy={:.2e})",
+                    totals.mass, totals.energy, error.mass, error.energy);
+            }
+            None => {}
+        }
     }
 
-    if let Some(products_interval) = control.products_interval {
+    if let Some(products_interval) = control.products_task_interval() {
         if tasks.write_products.next_time <= state.time {
             tasks.write_products.advance(products_interval);
-            let filename = format!("{}/prods.{:04}.cbor", control.output_directory, tasks.write_products.count - 1);
+            let filename = format!("{}/prods.{:04}.{}", control.output_directory, tasks.write_products.count - 1, control.output_format.products_extension());
             let config = Configuration::package(hydro, model, mesh, control);
             let products = Products::try_from_state(state, hydro, &config)?;
+            let profiles = radial_profiles(&products, control, tasks.write_products.count - 1);
+            let products = match &control.products_delta {
+                Some(settings) => delta.encode(products, &filename, settings),
+                None => products,
+            };
+            let (format, max_file_size, compression) = (control.output_format, control.products_max_file_size, control.compression);
             std::fs::create_dir_all(&control.output_directory)?;
-            io::write_cbor(&products, &filename)?;
+            writer.submit(move || {
+                match format {
+                    OutputFormat::Cbor => products.write(&filename, max_file_size, compression)?,
+                    OutputFormat::Hdf5 => io::write_hdf5_products(&products, &filename)?,
+                    OutputFormat::Flat => io::write_flat_products(&products, &filename)?,
+                    OutputFormat::Netcdf => io::write_netcdf_products(&products, &filename)?,
+                }
+                for (profile, filename) in profiles {
+                    profile.write_text(&filename)?;
+                }
+                Ok(())
+            })?;
         }
     }
 
-    if tasks.write_checkpoint.next_time <= state.time {
-        tasks.write_checkpoint.advance(control.checkpoint_interval);
-        let filename = format!("{}/chkpt.{:04}.cbor", control.output_directory, tasks.write_checkpoint.count - 1);
-        let app = App::package(state, tasks, hydro, model, mesh, control);
-        std::fs::create_dir_all(&control.output_directory)?;
-        io::write_cbor(&app, &filename)?;
+    if let Some(time_series_interval) = control.time_series_task_interval() {
+        if tasks.write_time_series.next_time <= state.time {
+            tasks.write_time_series.advance(time_series_interval);
+            let relativistic = matches!(AnyHydro::from(hydro.clone()), AnyHydro::Relativistic(_));
+            let sample = state.time_series_sample(hydro, mesh, relativistic, control.reference_epoch.as_ref())?;
+            let filename = format!("{}/timeseries.csv", control.output_directory);
+            std::fs::create_dir_all(&control.output_directory)?;
+
+            if tasks.write_time_series.count_this_run == 1 {
+                TimeSeriesSample::truncate_csv(&filename, sample.time)?;
+            }
+            sample.append_csv(&filename)?;
+        }
+    }
+
+    if let Some(cuts) = &control.angular_cuts {
+        if tasks.write_angular_cuts.next_time <= state.time {
+            tasks.write_angular_cuts.advance(cuts.interval);
+            let relativistic = matches!(AnyHydro::from(hydro.clone()), AnyHydro::Relativistic(_));
+            let number = tasks.write_angular_cuts.count - 1;
+            std::fs::create_dir_all(&control.output_directory)?;
+
+            for (n, &angle) in cuts.polar_angles.iter().enumerate() {
+                let cut = RadialProfile::try_from_state(state, hydro, mesh, angle, relativistic)?;
+                cut.write_text(&format!("{}/cut.{:04}.angle{}.dat", control.output_directory, number, n))?;
+            }
+        }
+    }
+
+    if let Some(work_map_interval) = control.work_map_task_interval() {
+        if tasks.write_work_map.next_time <= state.time {
+            tasks.write_work_map.advance(work_map_interval);
+            let filename = format!("{}/work.{:04}.cbor", control.output_directory, tasks.write_work_map.count - 1);
+            let config = Configuration::package(hydro, model, mesh, control);
+            let work_map = WorkProducts::from_work(work.take(), state.time, state.scale_factor, &config);
+            std::fs::create_dir_all(&control.output_directory)?;
+            writer.submit(move || Ok(io::write_cbor(&work_map, &filename)?))?;
+        }
+    }
+
+    if let Some(source_budget_interval) = control.source_budget_task_interval() {
+        if tasks.print_source_budget.next_time <= state.time {
+            tasks.print_source_budget.advance(source_budget_interval);
+            print_source_budget(work);
+        }
+    }
+
+    if let Some(checkpoint_interval) = control.checkpoint_task_interval() {
+        if tasks.write_checkpoint.next_time <= state.time {
+            tasks.write_checkpoint.advance(checkpoint_interval);
+            let filename = format!("{}/chkpt.{:04}.{}", control.output_directory, tasks.write_checkpoint.count - 1, control.output_format.checkpoint_extension());
+            let app = App::package(state, tasks, hydro, model, mesh, control);
+            let (format, compression) = (control.output_format, control.compression);
+            let (directory, retention) = (control.output_directory.clone(), control.checkpoint_retention.clone());
+            let number = tasks.write_checkpoint.count - 1;
+            let annotated = if number == 0 { Some(annotations::annotated_config(&app.config)?) } else { None };
+            std::fs::create_dir_all(&control.output_directory)?;
+            writer.submit(move || {
+                if let Some(annotated) = annotated {
+                    std::fs::write(format!("{}/config.annotated.yaml", directory), annotated + "\n")?;
+                }
+                let partial = format!("{}.partial", filename);
+                match format {
+                    OutputFormat::Cbor | OutputFormat::Flat | OutputFormat::Netcdf => io::write_cbor_compressed(&app, &partial, compression)?,
+                    OutputFormat::Hdf5 => io::write_hdf5_checkpoint(&app, &partial)?,
+                }
+                std::fs::rename(&partial, &filename)?;
+
+                if let Some(retention) = retention {
+                    io::prune_checkpoints(&directory, number, &retention)?;
+                }
+                Ok(())
+            })?;
+        }
     }
 
     Ok(())
@@ -73,7 +291,7 @@ where
 
 
 // ============================================================================
-fn run<C, M, H>(mut state: State<C>, mut tasks: Tasks, hydro: H, model: M, mesh: Mesh, control: Control)
+fn run<C, M, H>(mut state: State<C>, mut tasks: Tasks, hydro: H, model: M, mut mesh: Mesh, control: Control)
     -> anyhow::Result<()>
 where
     H: Hydrodynamics<Conserved = C>,
@@ -83,24 +301,312 @@ where
     AnyModel: From<M>,
     AnyState: From<State<C>>,
 {
-    let mut block_geometry = mesh.grid_blocks_geometry(state.time);
+    let mut block_geometry = state.block_geometry(&mesh);
+    let work = WorkLedger::default();
+    let settings = SchemeSettings::new(&control, &hydro);
+    let mut delta = DeltaEncoder::default();
+    let mut writer = OutputWriter::new(control.background_output);
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(control.num_threads())
         .build()?;
 
     while state.time < control.final_time {
-        side_effects(&state, &mut tasks, &hydro, &model, &mesh, &control)?;
-        state = scheme::advance(state, &hydro, &model, &mesh, &mut block_geometry, &runtime, control.fold)?;
+        side_effects(&state, &mut tasks, &hydro, &model, &mesh, &control, &work, &mut delta, &mut writer)?;
+
+        if let Some(refocusing) = mesh.polar_refocusing.clone() {
+            if tasks.refocus_polar_zones.next_time <= state.time {
+                tasks.refocus_polar_zones.advance(refocusing.interval);
+                state = scheme::refocus_polar_zones(state, &hydro, &mut mesh, &refocusing)?;
+                block_geometry = state.block_geometry(&mesh);
+            }
+        }
+        let until = tasks.next_output_time(&control, state.time);
+        state = scheme::advance(state, &hydro, &model, &mesh, &mut block_geometry, &runtime, &work, &settings, until)?;
+    }
+
+    side_effects(&state, &mut tasks, &hydro, &model, &mesh, &control, &work, &mut delta, &mut writer)?;
+    print_source_budget(&work);
+    writer.finish()
+}
+
+
+
+
+// ============================================================================
+fn print_features() {
+    println!("build profile ....... {}", if cfg!(debug_assertions) { "debug" } else { "release" });
+    println!("hydrodynamics ....... newtonian relativistic");
+    println!("optional features:");
+    println!();
+
+    if app::OPTIONAL_FEATURES.is_empty() {
+        println!("  (none)");
+    }
+    for (name, enabled) in app::OPTIONAL_FEATURES {
+        println!("  {:.<18} {}", format!("{} ", name), if *enabled { "yes" } else { "no" });
+    }
+    println!();
+}
+
+
+
+
+// ============================================================================
+fn jet_head(args: Vec<String>) -> anyhow::Result<()> {
+    let mut reference = None;
+    let mut threshold = validation::JET_SCALAR_THRESHOLD;
+    let mut samples = Vec::new();
+    let mut breakout_radius = None;
+    let mut predicted_breakout = None;
+
+    for arg in &args {
+        if let Some(filename) = arg.strip_prefix("reference=") {
+            reference = Some(ReferenceCurve::from_file(filename)?);
+        } else if let Some(value) = arg.strip_prefix("threshold=") {
+            threshold = value.parse()?;
+        }
     }
 
-    side_effects(&state, &mut tasks, &hydro, &model, &mesh, &control)?;
+    for filename in args.iter().filter(|arg| !arg.contains('=')) {
+        let products = match Products::from_file(filename) {
+            Ok(products) => products,
+            Err(_) => Products::try_from_app(&io::read_cbor::<App>(filename)?)?,
+        };
+        if let AnyModel::JetInStar(model) = &products.config.model {
+            if reference.is_none() {
+                reference = Some(ReferenceCurve::jet_in_star(model)?);
+            }
+            predicted_breakout = Some(validation::scaled_breakout_time(model)?);
+        }
+        breakout_radius = validation::breakout_radius(&products.config.model);
+        samples.push(JetHeadSample::from_products(&products, reference.as_ref(), threshold));
+    }
+    samples.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+    let format = |x: Option<f64>| x.map_or("-".to_string(), |x| format!("{:.4e}", x));
+
+    println!("{:>12} {:>12} {:>12} {:>12}", "time", "r_head", "r_reference", "deviation");
+
+    for sample in &samples {
+        println!("{:>12.4e} {:>12} {:>12} {:>12}",
+            sample.time,
+            format(sample.radius),
+            format(sample.reference),
+            sample.relative_deviation().map_or("-".to_string(), |x| format!("{:+.2}%", 100.0 * x)));
+    }
+    println!();
+
+    let deviations: Vec<_> = samples.iter().filter_map(JetHeadSample::relative_deviation).collect();
+
+    if !deviations.is_empty() {
+        let max = deviations.iter().fold(0.0, |a: f64, b| a.max(b.abs()));
+        let rms = (deviations.iter().map(|x| x * x).sum::<f64>() / deviations.len() as f64).sqrt();
+        println!("deviation from reference: max {:.2}% rms {:.2}% ({} samples)", 100.0 * max, 100.0 * rms, deviations.len());
+    }
+    if let Some(radius) = breakout_radius {
+        match validation::breakout_time(&samples, radius) {
+            Some(t) => println!("breakout time ({:.3e} cm): {:.4e} s", radius, t),
+            None => println!("breakout time ({:.3e} cm): jet has not broken out", radius),
+        }
+        if let Some(predicted) = predicted_breakout {
+            match validation::breakout_time(&samples, radius) {
+                Some(t) => println!("predicted breakout time: {:.4e} s (deviation {:+.2}%)", predicted, 100.0 * (t - predicted) / predicted),
+                None => println!("predicted breakout time: {:.4e} s", predicted),
+            }
+        }
+    }
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+fn blast_check(args: Vec<String>) -> anyhow::Result<()> {
+    let mut refinement = 4;
+    let mut filename = None;
+
+    for arg in &args {
+        if let Some(value) = arg.strip_prefix("refinement=") {
+            refinement = value.parse()?;
+        } else {
+            filename = Some(arg);
+        }
+    }
+    let filename = filename.ok_or_else(|| anyhow::anyhow!("usage: kilonova blast-check <chkpt.cbor> [refinement=4]"))?;
+    let app: App = io::read_cbor(filename)?;
+
+    let model = match &app.config.model {
+        AnyModel::SphericalBlast(model) => model.clone(),
+        _ => anyhow::bail!("blast-check requires a checkpoint of the spherical_blast model"),
+    };
+    let products = Products::try_from_app(&app)?;
+
+    println!("computing 1D reference solution at {}x radial resolution...", refinement);
+
+    let reference = match &app.config.hydro {
+        AnyHydro::Newtonian(hydro) => validation::one_dimensional_reference(hydro, &app.config, products.time, refinement)?,
+        AnyHydro::Relativistic(hydro) => validation::one_dimensional_reference(hydro, &app.config, products.time, refinement)?,
+    };
+    let measure = |ray: &Vec<_>| BlastProfile::from_ray(ray, model.blast_radius, model.outer_pressure);
+    let reference_profile = validation::radial_rays(&reference)
+        .first()
+        .and_then(measure)
+        .ok_or_else(|| anyhow::anyhow!("no shock found in the reference solution"))?;
+    let errors: Vec<_> = validation::radial_rays(&products)
+        .iter()
+        .filter_map(measure)
+        .map(|profile| profile.relative_error(&reference_profile))
+        .collect();
+
+    if errors.is_empty() {
+        anyhow::bail!("no shock found in {}", filename)
+    }
+    let max_abs = |f: fn(&BlastProfile) -> f64| errors.iter().map(|e| f(e).abs()).fold(0.0, f64::max);
 
+    println!();
+    println!("time: run {:.6e} reference {:.6e}", products.time, reference.time);
+    println!("{:<20} {:>14} {:>14}", "quantity", "reference", "max rel. error");
+    println!("{:<20} {:>14.6e} {:>13.3}%", "shock radius", reference_profile.shock_radius, 100.0 * max_abs(|e| e.shock_radius));
+    println!("{:<20} {:>14.6e} {:>13.3}%", "peak density", reference_profile.peak_density, 100.0 * max_abs(|e| e.peak_density));
+    println!("{:<20} {:>14.6e} {:>13.3}%", "peak pressure", reference_profile.peak_pressure, 100.0 * max_abs(|e| e.peak_pressure));
+    println!("{:<20} {:>14.6e} {:>13.3}%", "peak gamma-beta", reference_profile.peak_gamma_beta, 100.0 * max_abs(|e| e.peak_gamma_beta));
+    println!();
+    println!("errors are the largest over {} polar zones", errors.len());
     Ok(())
 }
 
 
 
 
+// ============================================================================
+fn afterglow(args: Vec<String>) -> anyhow::Result<()> {
+    let mut min_gamma_beta = 1.0;
+    let mut filename = None;
+    let mut output = None;
+
+    for arg in &args {
+        if let Some(value) = arg.strip_prefix("min_gamma_beta=") {
+            min_gamma_beta = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("output=") {
+            output = Some(value.to_string());
+        } else {
+            filename = Some(arg.clone());
+        }
+    }
+    let filename = filename.ok_or_else(|| anyhow::anyhow!("usage: kilonova afterglow <prods.cbor|chkpt.cbor> [min_gamma_beta=1] [output=afterglow.cbor]"))?;
+    let products = match Products::from_file(&filename) {
+        Ok(products) => products,
+        Err(_) => Products::try_from_app(&io::read_cbor::<App>(&filename)?)?,
+    };
+    let afterglow = AfterglowProducts::from_products(&products, min_gamma_beta);
+
+    println!("time: {:.6e} s, energy in material with gamma-beta >= {}", afterglow.time, min_gamma_beta);
+    println!();
+    println!("{:<12} {:>12} {:>12} {:>12} {:>12} {:>14}", "profile", "E0 (erg)", "theta_core", "b", "theta_wing", "rms log resid.");
+
+    for (name, fit) in &[("gaussian", &afterglow.gaussian), ("power_law", &afterglow.power_law)] {
+        match fit {
+            Some(fit) => println!("{:<12} {:>12.4e} {:>12.4} {:>12} {:>12.4} {:>14.4}",
+                name,
+                fit.e0,
+                fit.theta_core,
+                fit.wing_index.map_or("-".to_string(), |b| format!("{:.2}", b)),
+                fit.theta_wing,
+                fit.rms_log_residual),
+            None => println!("{:<12} (no material to fit)", name),
+        }
+    }
+    println!();
+
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&filename).with_extension("afterglow.cbor").to_string_lossy().into_owned()
+    });
+    io::write_cbor(&afterglow, &output)?;
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+fn vtk(args: Vec<String>) -> anyhow::Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("usage: kilonova vtk <prods.cbor|chkpt.cbor> [...]")
+    }
+    for filename in &args {
+        let products = match Products::from_file(filename) {
+            Ok(products) => products,
+            Err(_) => Products::try_from_app(&io::read_cbor::<App>(filename)?)?,
+        };
+        let output = std::path::Path::new(filename).with_extension("vtk");
+        kilonova::vtk::write_vtk(&products, &output.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+fn movie(args: Vec<String>) -> anyhow::Result<()> {
+    let mut frames_per_second = 10.0;
+    let mut directory = None;
+    let mut output = None;
+
+    for arg in &args {
+        if let Some(value) = arg.strip_prefix("fps=") {
+            frames_per_second = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("output=") {
+            output = Some(value.to_string());
+        } else {
+            directory = Some(arg.clone());
+        }
+    }
+    let directory = directory.ok_or_else(|| anyhow::anyhow!("usage: kilonova movie <directory> [fps=10] [output=movie.gif]"))?;
+    let output = output.unwrap_or_else(|| format!("{}/movie.gif", directory));
+
+    if frames_per_second <= 0.0 {
+        anyhow::bail!("fps must be positive")
+    }
+    let frames = kilonova::movie::png_sequence(&directory)?;
+
+    if frames.is_empty() {
+        anyhow::bail!("no PNG files found in {}", directory)
+    }
+    write_movie(&frames, &output, frames_per_second)?;
+    println!("wrote {} ({} frames at {} fps)", output, frames.len(), frames_per_second);
+    Ok(())
+}
+
+#[cfg(feature = "movie")]
+fn write_movie(frames: &[std::path::PathBuf], output: &str, frames_per_second: f64) -> anyhow::Result<()> {
+    kilonova::movie::assemble_gif(frames, output, frames_per_second)
+}
+
+#[cfg(not(feature = "movie"))]
+fn write_movie(_frames: &[std::path::PathBuf], _output: &str, _frames_per_second: f64) -> anyhow::Result<()> {
+    anyhow::bail!("this build does not include the movie feature; rebuild with `cargo install --path . --features movie`")
+}
+
+
+
+
+// ============================================================================
+/**
+ * Translate a command line flag to the configuration override it stands
+ * for. Other arguments are returned unchanged.
+ */
+fn expand_flag(arg: String) -> String {
+    match arg.strip_prefix("--outdir=") {
+        Some(directory) => format!("control.output_directory={}", directory),
+        None => arg,
+    }
+}
+
+
+
+
 // ============================================================================
 fn main() -> anyhow::Result<()> {
 
@@ -111,7 +617,7 @@ fn main() -> anyhow::Result<()> {
 
     match std::env::args().nth(1) {
         None => {
-            println!("usage: kilonova <input.yaml|chkpt.cbor|preset> [opts.yaml|group.key=value] [...]");
+            println!("usage: kilonova <input.yaml|chkpt.cbor|directory|preset> [opts.yaml|group.key=value] [--outdir=<directory>] [...]");
             println!();
             println!("These are the preset model setups:");
             println!();
@@ -120,15 +626,47 @@ fn main() -> anyhow::Result<()> {
             }
             println!();
             println!("To run any of these presets, run e.g. `kilonova jet_in_star`.");
+            println!("To resume a run from the latest checkpoint in its output directory, run e.g. `kilonova data`.");
+            println!("To list the optional features compiled into this build, run `kilonova features`.");
+            println!("To compare the jet head position against a reference curve, run");
+            println!("`kilonova jet-head <prods.cbor|chkpt.cbor> [...] [reference=curve.csv] [threshold=10]`.");
+            println!("To check a spherical_blast checkpoint against a 1D reference solution, run");
+            println!("`kilonova blast-check <chkpt.cbor> [refinement=4]`.");
+            println!("To fit afterglowpy structured-jet profiles to the energy distribution of an output, run");
+            println!("`kilonova afterglow <prods.cbor|chkpt.cbor> [min_gamma_beta=1] [output=afterglow.cbor]`.");
+            println!("To convert outputs to VTK files for ParaView or VisIt, run");
+            println!("`kilonova vtk <prods.cbor|chkpt.cbor> [...]`.");
+            println!("To assemble the PNG images in a directory into an animated GIF, run");
+            println!("`kilonova movie <directory> [fps=10] [output=movie.gif]`.");
+            println!("To toggle verbose per-block solver diagnostics during a run, create or remove");
+            println!("a file named `{}` in the output directory.", VERBOSE_CONTROL_FILE);
             Ok(())
         }
+        Some(command) if command == "features" => {
+            print_features();
+            Ok(())
+        }
+        Some(command) if command == "jet-head" => {
+            jet_head(std::env::args().skip(2).collect())
+        }
+        Some(command) if command == "blast-check" => {
+            blast_check(std::env::args().skip(2).collect())
+        }
+        Some(command) if command == "afterglow" => {
+            afterglow(std::env::args().skip(2).collect())
+        }
+        Some(command) if command == "vtk" => {
+            vtk(std::env::args().skip(2).collect())
+        }
+        Some(command) if command == "movie" => {
+            movie(std::env::args().skip(2).collect())
+        }
         Some(input) => {
-            let overrides = std::env::args().skip(2).collect();
+            let overrides = std::env::args().skip(2).map(expand_flag).collect();
             let App{state, tasks, config, ..} = App::from_preset_or_file(&input, overrides)?.validate()?;
+            config.control.units.install()?;
 
-            for line in serde_yaml::to_string(&config)?.split("\n").skip(1) {
-                println!("{}", line);
-            }
+            println!("{}", annotations::annotated_config(&config)?);
             println!();
 
             let Configuration{hydro, model, mesh, control} = config;
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::f64::consts::PI;
 use ndarray::{ArcArray, Array, Ix1, Ix2};
 use serde::{Serialize, Deserialize};
+use crate::expression::Expression;
 
 
 
@@ -11,6 +12,12 @@ use serde::{Serialize, Deserialize};
  */
 pub type BlockIndex = (i32, usize);
 
+/// Polar vertices within this angle of 0 or pi are considered to be on a pole
+pub static POLE_TOLERANCE: f64 = 1e-12;
+
+/// The largest geometric polar spacing ratio chosen by polar refocusing
+pub static MAX_REFOCUSED_RATIO: f64 = 2.0;
+
 
 
 
@@ -26,6 +33,8 @@ pub struct GridGeometry {
     pub polar_face_areas:  ArcArray<f64, Ix2>,
     pub cell_volumes:      ArcArray<f64, Ix2>,
     pub cell_centers:      ArcArray<(f64, f64), Ix2>,
+    pub source_centers:    ArcArray<(f64, f64), Ix2>,
+    pub axis_zones:        (usize, usize),
 }
 
 
@@ -34,13 +43,108 @@ pub struct GridGeometry {
 // ============================================================================
 impl GridGeometry {
 
+    /**
+     * Return the geometry of a patch with the given radial and polar vertex
+     * coordinates, which need not be evenly spaced.
+     */
+    pub fn from_vertices(radial_vertices: Array<f64, Ix1>, polar_vertices: Array<f64, Ix1>) -> Self {
+        let nr = radial_vertices.len() - 1;
+        let nq = polar_vertices.len() - 1;
+        let r = &radial_vertices;
+        let q = &polar_vertices;
+
+        let radial_face_areas = ArcArray::from_shape_fn((nr + 1, nq), |(i, j)| face_area((r[i], q[j]), (r[i], q[j + 1])));
+        let polar_face_areas  = ArcArray::from_shape_fn((nr, nq + 1), |(i, j)| face_area((r[i], q[j]), (r[i + 1], q[j])));
+        let cell_volumes      = ArcArray::from_shape_fn((nr, nq), |(i, j)| cell_volume((r[i], q[j]), (r[i + 1], q[j + 1])));
+        let cell_centers      = ArcArray::from_shape_fn((nr, nq), |(i, j)| ((r[i] * r[i + 1]).sqrt(), 0.5 * (q[j] + q[j + 1])));
+
+        GridGeometry{
+            radial_vertices: radial_vertices.to_shared(),
+            radial_face_areas,
+            polar_vertices: polar_vertices.to_shared(),
+            polar_face_areas,
+            cell_volumes,
+            source_centers: cell_centers.clone(),
+            cell_centers,
+            axis_zones: (0, 0),
+        }
+    }
+
+    /**
+     * Return this geometry with the given treatment of the zones next to the
+     * poles. The axis group at each pole of the patch is the zones within
+     * the merge angle of it, or just the zone touching it; patches which do
+     * not reach a pole have no axis group there. With balanced sources, the
+     * geometrical source terms in the axis groups are evaluated at the
+     * radius r = 2/3 (r1^3 - r0^3) / (r1^2 - r0^2), where they balance the
+     * pressure forces on the zone faces exactly for a uniform pressure. The
+     * polar angle is already balanced at the mid-point.
+     */
+    pub fn with_axis_treatment(self, axis: &AxisTreatment) -> Self {
+        let q = &self.polar_vertices;
+        let nq = q.len() - 1;
+        let merge_angle = axis.merge_angle.unwrap_or(0.0);
+        let group = |reaches_pole: bool, num_within: usize| if reaches_pole { num_within.max(1) } else { 0 };
+
+        let num_north = (0..nq).take_while(|&j| q[j + 1] <= merge_angle).count();
+        let num_south = (0..nq).rev().take_while(|&j| q[j] >= PI - merge_angle).count();
+        let north = group(q[0] <= POLE_TOLERANCE, num_north);
+        let south = group(q[nq] >= PI - POLE_TOLERANCE, num_south).min(nq - north);
+
+        let source_centers = if axis.balanced_sources {
+            let r = &self.radial_vertices;
+            ArcArray::from_shape_fn(self.cell_centers.dim(), |(i, j)| {
+                let (r0, r1) = (r[i], r[i + 1]);
+                if j < north || j >= nq - south {
+                    (2.0 / 3.0 * (r1.powi(3) - r0.powi(3)) / (r1 * r1 - r0 * r0), self.cell_centers[(i, j)].1)
+                } else {
+                    self.cell_centers[(i, j)]
+                }
+            })
+        } else {
+            self.cell_centers.clone()
+        };
+        Self { source_centers, axis_zones: (north, south), ..self }
+    }
+
+    /**
+     * Return this geometry with every radius multiplied by the given factor,
+     * as for a mesh whose radial faces move homologously.
+     */
+    pub fn expanded(&self, factor: f64) -> Self {
+        let expand = |centers: &ArcArray<(f64, f64), Ix2>| centers.mapv(|(r, q)| (r * factor, q)).to_shared();
+
+        GridGeometry{
+            radial_vertices: (&self.radial_vertices * factor).to_shared(),
+            radial_face_areas: (&self.radial_face_areas * factor.powi(2)).to_shared(),
+            polar_vertices: self.polar_vertices.clone(),
+            polar_face_areas: (&self.polar_face_areas * factor.powi(2)).to_shared(),
+            cell_volumes: (&self.cell_volumes * factor.powi(3)).to_shared(),
+            cell_centers: expand(&self.cell_centers),
+            source_centers: expand(&self.source_centers),
+            axis_zones: self.axis_zones,
+        }
+    }
+
     /**
      * Return a 2D array of the smallest linear dimension of each grid cell.
+     * Zones in a merged axis group count the polar extent of the whole
+     * group, since they are averaged together after each update.
      */
     pub fn cell_linear_dimension(&self) -> Array<f64, Ix2> {
+        let q = &self.polar_vertices;
+        let nq = q.len() - 1;
+        let (north, south) = self.axis_zones;
+
         Array::from_shape_fn(self.cell_centers.dim(), |(i, j)| {
             let dr = self.radial_vertices[i + 1] - self.radial_vertices[i];
-            let dq = self.polar_vertices[j + 1] - self.polar_vertices[j];
+            let dq = if j < north {
+                q[north] - q[0]
+            } else if j >= nq - south {
+                q[nq] - q[nq - south]
+            } else {
+                q[j + 1] - q[j]
+            };
             dr.min(dq * self.radial_vertices[i])
         })
     }
@@ -72,6 +176,308 @@ pub struct SphericalPolarGrid {
     pub extent: SphericalPolarExtent,
     pub num_zones_r: usize,
     pub num_zones_q: usize,
+    pub polar_spacing: PolarSpacing,
+    pub linear_radial_spacing: bool,
+    pub polar_vertices: Option<ArcArray<f64, Ix1>>,
+    pub axis_treatment: Option<AxisTreatment>,
+}
+
+
+
+
+/**
+ * Law for the radial spacing of the mesh blocks and their zones
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RadialSpacing {
+
+    /// Zones evenly spaced in log r
+    Logarithmic,
+
+    /// Zones evenly spaced in r, with the width of the logarithmic zones at
+    /// the reference radius
+    Linear,
+
+    /// Linear spacing out to the transition radius (rounded up to a block
+    /// boundary), and logarithmic spacing beyond it
+    Hybrid { transition_radius: f64 },
+}
+
+
+
+
+/**
+ * Boundary condition on the edges of the polar extent which are not on the
+ * poles
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolarBoundary {
+
+    /// A wall, where the polar velocity is reflected
+    Reflecting,
+
+    /// Zero-gradient outflow, where material leaves the mesh freely
+    Outflow,
+}
+
+
+
+
+/**
+ * Distribution of the polar zones over the polar extent of the mesh
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolarSpacing {
+
+    /// Zones of equal angular width
+    Uniform,
+
+    /// Zones concentrated toward the poles: the width of each zone is
+    /// `ratio` times that of its neighbor closer to the pole. If the polar
+    /// extent touches neither pole, zones are concentrated toward its lower
+    /// edge.
+    Geometric { ratio: f64 },
+}
+
+
+
+
+/**
+ * Refocusing of a geometric polar spacing on the jet as its opening angle
+ * evolves. At regular intervals the jet opening angle is measured, the
+ * spacing ratio is moved toward the one which puts a given number of zones
+ * within it at each pole, and the solution is remapped conservatively onto
+ * the new polar zones.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolarRefocusing {
+
+    /// Simulation time between refocusings
+    pub interval: f64,
+
+    /// Zones where the radial velocity (the four-velocity for relativistic
+    /// hydrodynamics) exceeds this value count as jet material
+    pub velocity_threshold: f64,
+
+    /// Number of zones to place within the jet opening angle at each pole
+    pub zones_in_jet: usize,
+
+    /// The largest relative change of the spacing ratio in one refocusing,
+    /// which keeps the remapping diffusion small
+    #[serde(default = "PolarRefocusing::default_max_ratio_change")]
+    pub max_ratio_change: f64,
+}
+
+
+
+
+/**
+ * Mesh geometry given explicitly, rather than generated from the spacing
+ * laws, for example to use an externally optimized grid
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportedGeometry {
+
+    /// Radii (cm) of the block boundaries, increasing from the reference
+    /// radius: block i spans `block_radii[i]` to `block_radii[i + 1]`.
+    /// Blocks outside the listed radii continue with the ratio of the
+    /// nearest two radii.
+    pub block_radii: Vec<f64>,
+
+    /// Polar angles of the zone vertices at the base resolution, which
+    /// replace the polar spacing law and extent; there must be
+    /// `num_polar_zones + 1` of them. Refined blocks subdivide these zones
+    /// evenly.
+    #[serde(default)]
+    pub polar_vertices: Option<Vec<f64>>,
+}
+
+
+
+
+/**
+ * Quantity used to decide whether a block should be refined or coarsened
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefinementIndicator {
+
+    /// The largest jump in mass density between adjacent zones, relative to
+    /// the smaller of the two densities
+    DensityGradient,
+
+    /// The largest jump in the passive scalar concentration between adjacent
+    /// zones
+    ScalarContrast,
+}
+
+
+
+
+/**
+ * Parameters for adaptive mesh refinement. Each block has a refinement level
+ * L, and has 2^L times as many zones in each direction as a level-0 block,
+ * covering the same extent. Blocks are refined or coarsened by one level at
+ * a time, at the beginning of each batch of `fold` iterations.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Refinement {
+
+    /// The refinement indicator: density_gradient or scalar_contrast
+    pub indicator: RefinementIndicator,
+
+    /// Blocks where the indicator exceeds this value are refined
+    pub refine_threshold: f64,
+
+    /// Blocks where the indicator is below this value are coarsened
+    pub coarsen_threshold: f64,
+
+    /// The largest refinement level allowed
+    pub max_level: u32,
+}
+
+
+
+
+/**
+ * Parameters for coarsening the blocks well behind the forward shock, where
+ * the flow has become smooth, to below the base resolution of the mesh. The
+ * forward shock is the outermost zone where the gas pressure exceeds that of
+ * the initial model by the given factor.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Derefinement {
+
+    /// Blocks entirely inside this fraction of the forward shock radius may
+    /// be coarsened
+    pub shock_radius_fraction: f64,
+
+    /// The largest jump in mass density between adjacent zones, relative to
+    /// the smaller density, for a block to count as smooth
+    pub smoothness: f64,
+
+    /// The number of levels by which blocks may be coarsened below the base
+    /// resolution; the block size and number of polar zones must be
+    /// divisible by 2 to this power
+    pub max_levels: u32,
+
+    /// Factor by which the gas pressure must exceed the initial model
+    /// pressure to count as shocked
+    #[serde(default = "ShockTracking::default_pressure_ratio")]
+    pub pressure_ratio: f64,
+}
+
+
+
+
+/**
+ * A region of the mesh, in radius and optionally in polar angle, which is
+ * kept at a fixed, higher resolution. Since mesh blocks span the full polar
+ * range, every block overlapping the region is refined, at all polar angles.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefinedRegion {
+
+    /// Inner radius of the region (cm)
+    pub inner_radius: f64,
+
+    /// Outer radius of the region (cm)
+    pub outer_radius: f64,
+
+    /// Smallest polar angle of the region. If omitted or nil, the region
+    /// extends to the lower polar edge of the mesh.
+    #[serde(default)]
+    pub inner_polar_angle: Option<f64>,
+
+    /// Largest polar angle of the region. If omitted or nil, the region
+    /// extends to the upper polar edge of the mesh.
+    #[serde(default)]
+    pub outer_polar_angle: Option<f64>,
+
+    /// Factor by which the resolution is increased in each direction; must
+    /// be a power of two
+    pub refinement_factor: usize,
+}
+
+
+
+
+/**
+ * Parameters for a mesh whose outer edge follows the outermost shock. Blocks
+ * are added at the outer edge as the shock reaches it, and optionally
+ * removed at the inner edge to keep the mesh within a range of radii, so
+ * that the ejecta can be followed over many decades in radius with a fixed
+ * number of zones.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShockTracking {
+
+    /// The outermost block is considered shocked where the gas pressure
+    /// exceeds the initial model pressure by this factor
+    #[serde(default = "ShockTracking::default_pressure_ratio")]
+    pub pressure_ratio: f64,
+
+    /// The largest number of decades in radius spanned by the mesh; the
+    /// innermost blocks are removed beyond it
+    #[serde(default)]
+    pub max_decades: Option<f64>,
+}
+
+
+
+
+/**
+ * Motion of the radial faces of the mesh. The faces move homologously, all
+ * radii growing by a common factor, so that ejecta in homologous expansion
+ * are nearly at rest relative to the mesh, which greatly reduces the
+ * advection error when they are followed over decades in radius. The fluxes
+ * through the moving faces are corrected for the volume they sweep out.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaceMotion {
+
+    /// Faces move with the velocity r / (t + time_offset), as for material
+    /// launched from the origin at t = -time_offset
+    Homologous { time_offset: f64 },
+
+    /// Faces move with the velocity H r, where the expansion rate H is the
+    /// mass-weighted mean of v_r / r over the mesh, updated every step
+    MeanFlow,
+}
+
+
+
+
+/**
+ * Treatment of the zones next to the poles. The zones touching the axis are
+ * very narrow, so on a uniform polar grid they dictate the time step, and the
+ * large geometrical source terms there are prone to axis artifacts in jet
+ * runs.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisTreatment {
+
+    /// Zones within this polar angle of a pole are conservatively averaged
+    /// together in each row after every update, and the time step is set by
+    /// the polar extent of the merged group rather than of each zone
+    #[serde(default)]
+    pub merge_angle: Option<f64>,
+
+    /// Evaluate the geometrical source terms next to the poles at the radius
+    /// where they exactly balance a uniform pressure
+    #[serde(default)]
+    pub balanced_sources: bool,
 }
 
 
@@ -102,14 +508,234 @@ pub struct Mesh {
     /// makes the zones square.
     pub num_radial_zones: Option<usize>,
 
-    /// Number of zones from pole to pole
+    /// Number of zones over the polar extent. Use 1 for a one-dimensional,
+    /// spherically symmetric mesh, which requires `num_radial_zones`.
     pub num_polar_zones: usize,
 
+    /// Radial spacing law: logarithmic (the default), linear, or hybrid
+    #[serde(default)]
+    pub radial_spacing: RadialSpacing,
+
+    /// Distribution of the polar zones: uniform (the default), or geometric
+    /// to concentrate them toward the poles
+    #[serde(default)]
+    pub polar_spacing: PolarSpacing,
+
+    /// Optional refocusing of the geometric polar spacing on the jet, as its
+    /// opening angle evolves
+    #[serde(default)]
+    pub polar_refocusing: Option<PolarRefocusing>,
+
+    /// Range of polar angles covered by the mesh, such as [0, pi/2] for
+    /// problems which are symmetric about the equator, or a cone around the
+    /// pole for jet propagation studies. Defaults to [0, pi].
+    #[serde(default)]
+    pub polar_extent: Option<(f64, f64)>,
+
+    /// Boundary condition on edges of the polar extent away from the poles,
+    /// such as the lateral edge of a cone [0, theta_max] around the jet axis:
+    /// reflecting (the default) or outflow
+    #[serde(default)]
+    pub polar_boundary: PolarBoundary,
+
+    /// Optional treatment of the zones next to the poles, to keep them from
+    /// dictating the time step or producing artifacts along the axis
+    #[serde(default)]
+    pub axis_treatment: Option<AxisTreatment>,
+
     /// Number of radial zones in each block
     pub block_size: usize,
 
+    /// Number of guard zones exchanged with each radial neighbor; the PLM
+    /// reconstruction needs 2, and wider stencils need more
+    #[serde(default = "Mesh::default_num_guard_zones")]
+    pub num_guard_zones: usize,
+
     /// Time after which the mesh excision starts
     pub excision_delay: Option<f64>,
+
+    /// Optional radius (cm) of the inner excision surface as an expression
+    /// in the time `t`, such as "1e9 * (1 + t / 100)". When given, it
+    /// replaces the IES defined by `inner_radius` and `inner_excision_speed`.
+    #[serde(default)]
+    pub inner_excision_radius: Option<Expression>,
+
+    /// Optional adaptive refinement of the mesh blocks
+    #[serde(default)]
+    pub refinement: Option<Refinement>,
+
+    /// Regions, in radius and polar angle, which are kept at a higher
+    /// resolution
+    #[serde(default)]
+    pub refined_regions: Vec<RefinedRegion>,
+
+    /// Optional coarsening of the blocks well behind the forward shock
+    #[serde(default)]
+    pub derefinement: Option<Derefinement>,
+
+    /// Optionally extend the mesh outwards to follow the outermost shock
+    #[serde(default)]
+    pub shock_tracking: Option<ShockTracking>,
+
+    /// Optional motion of the radial faces with the ejecta. The radii of the
+    /// blocks given here are then their radii at the start of the run.
+    #[serde(default)]
+    pub face_motion: Option<FaceMotion>,
+
+    /// Blocks whose conserved mass and energy change by less than this
+    /// fraction in an iteration, along with their neighbors, are frozen
+    /// (not updated) until a neighbor is disturbed. Disabled by default.
+    #[serde(default)]
+    pub freeze_tolerance: Option<f64>,
+
+    /// Optional YAML file with the block radii and polar vertices to use
+    /// instead of the spacing laws (see `ImportedGeometry`). The file is read
+    /// when a run is started, and its contents are kept in `geometry`, so
+    /// that restarts use identical geometry even if the file changes.
+    #[serde(default)]
+    pub geometry_file: Option<String>,
+
+    /// Geometry read from the `geometry_file`, or given inline
+    #[serde(default)]
+    pub geometry: Option<ImportedGeometry>,
+}
+
+
+
+
+// ============================================================================
+impl Default for PolarBoundary {
+    fn default() -> Self {
+        Self::Reflecting
+    }
+}
+
+
+
+
+// ============================================================================
+impl Default for RadialSpacing {
+    fn default() -> Self {
+        Self::Logarithmic
+    }
+}
+
+
+
+
+// ============================================================================
+impl Default for PolarSpacing {
+    fn default() -> Self {
+        Self::Uniform
+    }
+}
+
+impl PolarSpacing {
+
+    /**
+     * Return the polar angle of vertex `j` of `n` zones spanning the given
+     * polar extent.
+     */
+    pub fn vertex(&self, j: usize, n: usize, extent: (f64, f64)) -> f64 {
+        let (q0, q1) = extent;
+
+        match *self {
+            PolarSpacing::Uniform => q0 + (q1 - q0) * j as f64 / n as f64,
+            PolarSpacing::Geometric { ratio } => {
+                let sum = |m: usize| if ratio == 1.0 {
+                    m as f64
+                } else {
+                    (ratio.powi(m as i32) - 1.0) / (ratio - 1.0)
+                };
+                let upper_pole = q1 >= PI - POLE_TOLERANCE;
+                let lower_pole = q0 <= POLE_TOLERANCE || !upper_pole;
+
+                // The sum of the widths of the zones below vertex j, and of
+                // all the zones, where the zone nearest a pole has width 1.
+                let (below, total) = match (lower_pole, upper_pole) {
+                    (true, true) => {
+                        let total = sum(n / 2) + sum(n - n / 2);
+                        (if 2 * j <= n { sum(j) } else { total - sum(n - j) }, total)
+                    }
+                    (true, false) => (sum(j), sum(n)),
+                    _ => (sum(n) - sum(n - j), sum(n)),
+                };
+                q0 + (q1 - q0) * below / total
+            }
+        }
+    }
+
+    /**
+     * Return the spacing for blocks at the given refinement level, whose
+     * vertices include all the vertices at level 0.
+     */
+    pub fn at_level(&self, level: i32) -> Self {
+        match *self {
+            PolarSpacing::Uniform => PolarSpacing::Uniform,
+            PolarSpacing::Geometric { ratio } => PolarSpacing::Geometric { ratio: ratio.powf(0.5f64.powi(level)) },
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl PolarRefocusing {
+    fn default_max_ratio_change() -> f64 {
+        0.02
+    }
+
+    /**
+     * Return the geometric spacing which moves the given one toward putting
+     * `zones_in_jet` of the `n` polar zones within the given opening angle
+     * of the pole, by at most `max_ratio_change`. The ratio is kept between
+     * 1 (uniform spacing) and `MAX_REFOCUSED_RATIO`.
+     */
+    pub fn refocused(&self, spacing: PolarSpacing, opening_angle: f64, n: usize, extent: (f64, f64)) -> PolarSpacing {
+        let (q0, q1) = extent;
+        let m = self.zones_in_jet;
+        let ratio = match spacing {
+            PolarSpacing::Uniform => 1.0,
+            PolarSpacing::Geometric { ratio } => ratio,
+        };
+
+        // The polar extent of the m zones nearest the pole, which decreases
+        // as the ratio grows.
+        let covered = |ratio: f64| {
+            let spacing = PolarSpacing::Geometric { ratio };
+            if q0 <= POLE_TOLERANCE {
+                spacing.vertex(m, n, extent) - q0
+            } else {
+                q1 - spacing.vertex(n - m, n, extent)
+            }
+        };
+        let target = if covered(1.0) <= opening_angle {
+            1.0
+        } else if covered(MAX_REFOCUSED_RATIO) >= opening_angle {
+            MAX_REFOCUSED_RATIO
+        } else {
+            let (mut lower, mut upper) = (1.0, MAX_REFOCUSED_RATIO);
+
+            for _ in 0..64 {
+                let middle = 0.5 * (lower + upper);
+                if covered(middle) > opening_angle { lower = middle } else { upper = middle }
+            }
+            0.5 * (lower + upper)
+        };
+        let step = 1.0 + self.max_ratio_change;
+        PolarSpacing::Geometric { ratio: target.min(ratio * step).max(ratio / step).max(1.0) }
+    }
+}
+
+
+
+
+// ============================================================================
+impl ShockTracking {
+    fn default_pressure_ratio() -> f64 {
+        2.0
+    }
 }
 
 
@@ -148,6 +774,10 @@ impl SphericalPolarExtent {
             extent: self.clone(),
             num_zones_r,
             num_zones_q,
+            polar_spacing: PolarSpacing::Uniform,
+            linear_radial_spacing: false,
+            polar_vertices: None,
+            axis_treatment: None,
         }
     }
 
@@ -203,10 +833,63 @@ impl SphericalPolarGrid {
         let (y0, y1) = (self.extent.inner_radius.log(10.0), self.extent.outer_radius.log(10.0));
         let (q0, q1) = (self.extent.lower_theta, self.extent.upper_theta);
         let dy = (y1 - y0) / self.num_zones_r as f64;
-        let dq = (q1 - q0) / self.num_zones_q as f64;
-        let y = y0 + dy * i as f64;
-        let q = q0 + dq * j as f64;
-        (f64::powf(10.0, y), q)
+        let n = self.num_zones_q as i64;
+        let r = if self.linear_radial_spacing {
+            let (r0, r1) = (self.extent.inner_radius, self.extent.outer_radius);
+            r0 + (r1 - r0) * i as f64 / self.num_zones_r as f64
+        } else {
+            f64::powf(10.0, y0 + dy * i as f64)
+        };
+        let q = match (&self.polar_vertices, self.polar_spacing) {
+            (None, PolarSpacing::Uniform) => q0 + (q1 - q0) * j as f64 / n as f64,
+            _ if j < 0 => q0 + (self.polar_vertex(1) - q0) * j as f64,
+            _ if j > n => q1 + (q1 - self.polar_vertex(self.num_zones_q - 1)) * (j - n) as f64,
+            _ => self.polar_vertex(j as usize),
+        };
+        (r, q)
+    }
+
+    /**
+     * Return the polar angle of vertex j, which must be within the grid.
+     */
+    fn polar_vertex(&self, j: usize) -> f64 {
+        match &self.polar_vertices {
+            Some(vertices) => vertices[j],
+            None => {
+                let extent = (self.extent.lower_theta, self.extent.upper_theta);
+                self.polar_spacing.vertex(j, self.num_zones_q, extent)
+            }
+        }
+    }
+
+    /**
+     * Return this grid with zones evenly spaced in r, rather than log r.
+     */
+    pub fn with_linear_radial_spacing(self) -> Self {
+        Self { linear_radial_spacing: true, ..self }
+    }
+
+    /**
+     * Return this grid with the given distribution of polar zones.
+     */
+    pub fn with_polar_spacing(self, polar_spacing: PolarSpacing) -> Self {
+        Self { polar_spacing, ..self }
+    }
+
+    /**
+     * Return this grid with the given polar vertices, which replace the
+     * polar spacing. There must be one more vertex than polar zones.
+     */
+    pub fn with_polar_vertices(self, polar_vertices: ArcArray<f64, Ix1>) -> Self {
+        Self { polar_vertices: Some(polar_vertices), ..self }
+    }
+
+    /**
+     * Return this grid with the given treatment of the zones next to the
+     * poles.
+     */
+    pub fn with_axis_treatment(self, axis_treatment: AxisTreatment) -> Self {
+        Self { axis_treatment: Some(axis_treatment), ..self }
     }
 
     /**
@@ -244,22 +927,13 @@ impl SphericalPolarGrid {
      * cached geometry data.
      */
     pub fn geometry(&self) -> GridGeometry {
-        let nr = self.num_zones_r;
-        let nq = self.num_zones_q;
-        let radial_vertices   = ArcArray::from_shape_fn(nr + 1, |i| self.vertex_coordinate(i, 0).0);
-        let polar_vertices    = ArcArray::from_shape_fn(nq + 1, |j| self.vertex_coordinate(0, j).1);
-        let radial_face_areas = ArcArray::from_shape_fn((nr + 1, nq), |index| self.zone(index).face_area_r());
-        let polar_face_areas  = ArcArray::from_shape_fn((nr, nq + 1), |index| self.zone(index).face_area_q());
-        let cell_volumes      = ArcArray::from_shape_fn((nr, nq), |index| self.zone(index).volume());
-        let cell_centers      = ArcArray::from_shape_fn((nr, nq), |index| self.zone(index).centroid());
+        let radial_vertices = Array::from_shape_fn(self.num_zones_r + 1, |i| self.vertex_coordinate(i, 0).0);
+        let polar_vertices  = Array::from_shape_fn(self.num_zones_q + 1, |j| self.vertex_coordinate(0, j).1);
+        let geometry = GridGeometry::from_vertices(radial_vertices, polar_vertices);
 
-        GridGeometry{
-            radial_vertices,
-            radial_face_areas,
-            polar_vertices,
-            polar_face_areas,
-            cell_volumes,
-            cell_centers,
+        match &self.axis_treatment {
+            Some(axis) => geometry.with_axis_treatment(axis),
+            None => geometry,
         }
     }
 }
@@ -270,6 +944,10 @@ impl SphericalPolarGrid {
 // ============================================================================
 impl Mesh {
 
+    pub fn default_num_guard_zones() -> usize {
+        2
+    }
+
     pub fn validate(&self, time: f64) -> anyhow::Result<()> {
         if self.reference_radius <= 0.0 || self.inner_radius < 0.0 || self.outer_radius < 0.0 {
             anyhow::bail!("all radii must be positive")
@@ -289,37 +967,189 @@ impl Mesh {
         if self.block_size < 2 {
             anyhow::bail!("must have at least 2 radial zones per block")
         }
-        if self.num_polar_zones != 1 && self.num_polar_zones < 16 {
-            anyhow::bail!("must have at least 2 radial zones per block")
+        if self.num_guard_zones < 2 || self.num_guard_zones > self.block_size {
+            anyhow::bail!("must have at least 2 guard zones, and no more than the block size")
+        }
+        if !self.one_dimensional() && self.num_polar_zones < 16 {
+            anyhow::bail!("must have num_polar_zones = 1 (one-dimensional) or at least 16 polar zones")
         }
-        if self.num_polar_zones == 1 && self.num_radial_zones.is_none() {
+        if self.one_dimensional() && self.num_radial_zones.is_none() {
             anyhow::bail!("num_radial_zones is not optional when num_polar_zones=1")            
         }
+        if let Some((q0, q1)) = self.polar_extent {
+            if q0 < 0.0 || q1 > PI || q0 >= q1 {
+                anyhow::bail!("the polar extent must be an increasing range within [0, pi]")
+            }
+        }
+        if let Some(refinement) = &self.refinement {
+            if refinement.coarsen_threshold < 0.0 || refinement.refine_threshold <= refinement.coarsen_threshold {
+                anyhow::bail!("must have 0 <= coarsen_threshold < refine_threshold")
+            }
+            if refinement.max_level > 6 {
+                anyhow::bail!("the maximum refinement level is 6")
+            }
+        }
+        if let Some(axis) = &self.axis_treatment {
+            if axis.merge_angle.map_or(false, |q| q <= 0.0 || q >= 0.5 * PI) {
+                anyhow::bail!("the axis merge_angle must be between 0 and pi/2")
+            }
+        }
+        if let Some(face_motion) = self.face_motion {
+            if self.moving_excision_surfaces() || self.refinement.is_some() || self.derefinement.is_some() || self.shock_tracking.is_some() {
+                anyhow::bail!("moving faces cannot be combined with moving excision surfaces, adaptive refinement, derefinement, or shock tracking")
+            }
+            if let FaceMotion::Homologous { time_offset } = face_motion {
+                if time + time_offset <= 0.0 {
+                    anyhow::bail!("homologous face motion needs t + time_offset > 0")
+                }
+            }
+        }
+        if let Some(tracking) = &self.shock_tracking {
+            if tracking.pressure_ratio <= 1.0 {
+                anyhow::bail!("the shock tracking pressure ratio must be greater than 1")
+            }
+            if tracking.max_decades.map_or(false, |d| d <= self.block_dlogr()) {
+                anyhow::bail!("the shock tracking max_decades must be larger than one block")
+            }
+        }
+        match self.radial_spacing {
+            RadialSpacing::Logarithmic => {}
+            RadialSpacing::Linear | RadialSpacing::Hybrid { .. } if self.block_dlogr() >= 1.0 => {
+                anyhow::bail!("linear radial spacing needs blocks narrower than the reference radius")
+            }
+            RadialSpacing::Hybrid { transition_radius } if transition_radius <= self.reference_radius => {
+                anyhow::bail!("the hybrid spacing transition radius must be outside the reference radius")
+            }
+            _ => {}
+        }
+        if let PolarSpacing::Geometric { ratio } = self.polar_spacing {
+            if ratio < 1.0 {
+                anyhow::bail!("the geometric polar spacing ratio must be at least 1")
+            }
+            if self.refinement.is_some() || !self.refined_regions.is_empty() {
+                if self.num_polar_zones % 2 != 0 {
+                    anyhow::bail!("refinement with geometric polar spacing needs an even number of polar zones")
+                }
+            }
+        }
+        if let Some(refocusing) = &self.polar_refocusing {
+            let (q0, q1) = self.polar_extent();
+            let (lower_pole, upper_pole) = (q0 <= POLE_TOLERANCE, q1 >= PI - POLE_TOLERANCE);
+            let zones_per_pole = if lower_pole && upper_pole { self.num_polar_zones / 2 } else { self.num_polar_zones };
+
+            if !matches!(self.polar_spacing, PolarSpacing::Geometric { .. }) || self.imported_polar_vertices().is_some() {
+                anyhow::bail!("polar refocusing needs geometric polar spacing, and no imported polar vertices")
+            }
+            if !lower_pole && !upper_pole {
+                anyhow::bail!("polar refocusing needs a polar extent which touches a pole")
+            }
+            if refocusing.interval <= 0.0 || refocusing.max_ratio_change <= 0.0 {
+                anyhow::bail!("the polar refocusing interval and max_ratio_change must be positive")
+            }
+            if refocusing.zones_in_jet == 0 || refocusing.zones_in_jet >= zones_per_pole {
+                anyhow::bail!("the polar refocusing zones_in_jet must be at least 1, and less than the number of zones next to each pole")
+            }
+        }
+        if let Some(derefinement) = &self.derefinement {
+            let n = 1 << derefinement.max_levels;
+            let mq = self.polar_refinement_ratio().pow(derefinement.max_levels);
+
+            if derefinement.shock_radius_fraction <= 0.0 || derefinement.shock_radius_fraction >= 1.0 {
+                anyhow::bail!("the derefinement shock radius fraction must be between 0 and 1")
+            }
+            if derefinement.smoothness <= 0.0 || derefinement.pressure_ratio <= 1.0 {
+                anyhow::bail!("the derefinement smoothness must be positive, and the pressure ratio greater than 1")
+            }
+            if self.block_size % n != 0 || self.num_polar_zones % mq != 0 || self.block_size / n < self.num_guard_zones {
+                anyhow::bail!("the block size and number of polar zones must be divisible by 2^max_levels, leaving at least the guard zones")
+            }
+        }
+        if let Some(geometry) = &self.geometry {
+            let increasing = |x: &[f64]| x.windows(2).all(|w| w[0] < w[1]);
+
+            if geometry.block_radii.len() < 2 || !increasing(&geometry.block_radii) {
+                anyhow::bail!("the imported geometry needs at least two increasing block radii")
+            }
+            if geometry.block_radii[0] != self.reference_radius {
+                anyhow::bail!("the first imported block radius must be the reference radius")
+            }
+            if let Some(vertices) = &geometry.polar_vertices {
+                if vertices.len() != self.num_polar_zones + 1 || !increasing(vertices) {
+                    anyhow::bail!("the imported geometry needs num_polar_zones + 1 increasing polar vertices")
+                }
+                if vertices[0] < 0.0 || vertices[self.num_polar_zones] > PI {
+                    anyhow::bail!("the imported polar vertices must be within [0, pi]")
+                }
+            }
+        } else if self.geometry_file.is_some() {
+            anyhow::bail!("the mesh geometry file was not loaded")
+        }
+        if self.freeze_tolerance.map_or(false, |t| t <= 0.0) {
+            anyhow::bail!("the freeze tolerance must be positive")
+        }
+        for region in &self.refined_regions {
+            let (q0, q1) = self.polar_extent();
+            let inner_polar_angle = region.inner_polar_angle.unwrap_or(q0);
+            let outer_polar_angle = region.outer_polar_angle.unwrap_or(q1);
+
+            if region.inner_radius >= region.outer_radius {
+                anyhow::bail!("refined regions must have inner_radius < outer_radius")
+            }
+            if inner_polar_angle >= outer_polar_angle {
+                anyhow::bail!("refined regions must have inner_polar_angle < outer_polar_angle")
+            }
+            if inner_polar_angle < q0 || outer_polar_angle > q1 {
+                anyhow::bail!("the polar angles of refined regions must be within the polar extent of the mesh")
+            }
+            if !region.refinement_factor.is_power_of_two() || region.refinement_factor > 64 {
+                anyhow::bail!("the refinement factor must be a power of two, at most 64")
+            }
+        }
         Ok(())
     }
 
     /**
-     * Return the smallest grid spacing on the given block.
+     * Return the smallest grid spacing on the given block. For non-uniform
+     * polar spacing, the radial spacing is scaled by the ratio of the
+     * smallest to the mean polar zone width.
      */
     pub fn smallest_spacing(&self, index: BlockIndex) -> f64 {
-        let zone = self.subgrid(index).zone((0, 0));
-        zone.outer_radius - zone.inner_radius
+        let grid = self.subgrid(index);
+        let zone = grid.zone((0, 0));
+        let dq_min = (0..grid.num_zones_q).map(|j| {
+            let zone = grid.zone((0, j));
+            zone.upper_theta - zone.lower_theta
+        }).fold(f64::MAX, f64::min);
+        let dq_mean = (grid.extent.upper_theta - grid.extent.lower_theta) / grid.num_zones_q as f64;
+
+        (zone.outer_radius - zone.inner_radius) * dq_min / dq_mean
     }
 
     /**
-     * Return true if either of the IES or the OES have non-zero speeds.
+     * Return true if this is a one-dimensional (radial-only) mesh.
+     */
+    pub fn one_dimensional(&self) -> bool {
+        self.num_polar_zones == 1
+    }
+
+    /**
+     * Return true if either of the IES or the OES have non-zero speeds, or
+     * the IES is given as a function of time.
      */
     pub fn moving_excision_surfaces(&self) -> bool {
-        self.inner_excision_speed > 0.0 || self.outer_excision_speed > 0.0
+        self.inner_excision_speed > 0.0 || self.outer_excision_speed > 0.0 || self.inner_excision_radius.is_some()
     }
 
     /**
      * Radius of the inner excision surface (IES). The IES is at the
      * `inner_radius` at t=0, and moves outwards at the speed
-     * `inner_excision_speed`. Mesh blocks are removed from the mesh if they are
-     * fully within the IES.
+     * `inner_excision_speed`, unless `inner_excision_radius` is given. Mesh
+     * blocks are removed from the mesh if they are fully within the IES.
      */
     pub fn inner_excision_surface(&self, time: f64) -> f64 {
+        if let Some(radius) = &self.inner_excision_radius {
+            return radius.evaluate(0.0, 0.0, time)
+        }
         let t_start = self.excision_delay.unwrap_or(0.0);
         self.inner_radius + (time - t_start).max(0.0) * self.inner_excision_speed
     }
@@ -335,13 +1165,50 @@ impl Mesh {
         self.outer_radius + (time - t_start).max(0.0) * self.outer_excision_speed
     }
 
+    /**
+     * Read the `geometry_file`, if there is one and the geometry has not
+     * already been loaded.
+     */
+    pub fn load_geometry(&mut self) -> anyhow::Result<()> {
+        if let (Some(filename), None) = (&self.geometry_file, &self.geometry) {
+            let geometry = serde_yaml::from_str(&std::fs::read_to_string(filename)?)?;
+            self.geometry = Some(geometry);
+        }
+        Ok(())
+    }
+
+    /**
+     * Return the imported polar vertices at the base resolution, if any.
+     */
+    fn imported_polar_vertices(&self) -> Option<&Vec<f64>> {
+        self.geometry.as_ref().and_then(|g| g.polar_vertices.as_ref())
+    }
+
+    /**
+     * Return the range of polar angles covered by the mesh.
+     */
+    pub fn polar_extent(&self) -> (f64, f64) {
+        match self.imported_polar_vertices() {
+            Some(vertices) => (vertices[0], vertices[vertices.len() - 1]),
+            None => self.polar_extent.unwrap_or((0.0, PI)),
+        }
+    }
+
+    /**
+     * Return the polar zone spacing.
+     */
+    pub fn zone_dq(&self) -> f64 {
+        let (q0, q1) = self.polar_extent();
+        (q1 - q0) / self.num_polar_zones as f64
+    }
+
     /**
      * Return the radial zone spacing, dlogr = log(r1 / r0).
      */
     pub fn zone_dlogr(&self) -> f64 {
         match self.num_radial_zones {
             Some(nr) => 1.0 / nr as f64,
-            None => PI / self.num_polar_zones as f64,
+            None => self.zone_dq(),
         }
     }
 
@@ -352,20 +1219,80 @@ impl Mesh {
         self.block_size as f64 * self.zone_dlogr()
     }
 
+    /**
+     * Return the radius of the inner surface of the block with radial index
+     * `i`, according to the imported geometry or the radial spacing law.
+     */
+    pub fn block_radius(&self, i: i32) -> f64 {
+        if let Some(geometry) = &self.geometry {
+            let radii = &geometry.block_radii;
+            let n = radii.len() as i32 - 1;
+
+            return if i < 0 {
+                radii[0] * (radii[1] / radii[0]).powi(i)
+            } else if i > n {
+                radii[n as usize] * (radii[n as usize] / radii[n as usize - 1]).powi(i - n)
+            } else {
+                radii[i as usize]
+            }
+        }
+        let dr = self.reference_radius * self.block_dlogr();
+        let logarithmic = |r0: f64, k: f64| r0 * (1.0 + self.block_dlogr()).powf(k);
+
+        match self.radial_spacing {
+            RadialSpacing::Logarithmic => logarithmic(self.reference_radius, i as f64),
+            RadialSpacing::Linear => self.reference_radius + dr * i as f64,
+            RadialSpacing::Hybrid { .. } => {
+                let k = self.num_linear_blocks() as f64;
+                if i as f64 <= k {
+                    self.reference_radius + dr * i as f64
+                } else {
+                    logarithmic(self.reference_radius + dr * k, i as f64 - k)
+                }
+            }
+        }
+    }
+
+    /**
+     * Return the number of blocks, starting at the reference radius, with
+     * linear radial spacing for the hybrid spacing law.
+     */
+    fn num_linear_blocks(&self) -> i32 {
+        match self.radial_spacing {
+            RadialSpacing::Hybrid { transition_radius } => {
+                let dr = self.reference_radius * self.block_dlogr();
+                ((transition_radius - self.reference_radius) / dr).ceil().max(0.0) as i32
+            }
+            _ => 0,
+        }
+    }
+
+    /**
+     * Return true if the zones of the block with radial index `i` are evenly
+     * spaced in r.
+     */
+    pub fn linear_radial_spacing(&self, i: i32) -> bool {
+        match self.radial_spacing {
+            RadialSpacing::Logarithmic => false,
+            RadialSpacing::Linear => true,
+            RadialSpacing::Hybrid { .. } => i < self.num_linear_blocks(),
+        }
+    }
+
     /**
      * Return the extent of the subgrid at this index.
      */
     pub fn subgrid_extent(&self, index: BlockIndex) -> SphericalPolarExtent {
 
-        let (q0, q1) = if self.num_polar_zones == 1 {
+        let (q0, q1) = if self.one_dimensional() {
             (PI * 0.5 - self.zone_dlogr(), PI * 0.5 + self.zone_dlogr())
         } else {
-            (0.0, PI)
+            self.polar_extent()
         };
 
         SphericalPolarExtent {
-            inner_radius: self.reference_radius * (1.0 + self.block_dlogr()).powf(index.0 as f64),
-            outer_radius: self.reference_radius * (1.0 + self.block_dlogr()).powf(index.0 as f64 + 1.0),
+            inner_radius: self.block_radius(index.0),
+            outer_radius: self.block_radius(index.0 + 1),
             lower_theta: q0,
             upper_theta: q1,
         }
@@ -375,11 +1302,107 @@ impl Mesh {
      * Return the subgrid object at the given index.
      */
     pub fn subgrid(&self, index: BlockIndex) -> SphericalPolarGrid {
-        self.subgrid_extent(index).grid(self.block_size, self.num_polar_zones)
+        self.subgrid_at_level(index, 0)
+    }
+
+    /**
+     * Return the subgrid object at the given index and refinement level.
+     */
+    pub fn subgrid_at_level(&self, index: BlockIndex, level: i32) -> SphericalPolarGrid {
+        let mq = self.polar_refinement_ratio().pow(level.abs() as u32);
+        let (num_zones_r, num_zones_q) = if level >= 0 {
+            (self.block_size << level, self.num_polar_zones * mq)
+        } else {
+            (self.block_size >> -level, self.num_polar_zones / mq)
+        };
+        let grid = self.subgrid_extent(index).grid(num_zones_r, num_zones_q);
+        let grid = if self.linear_radial_spacing(index.0) {
+            grid.with_linear_radial_spacing()
+        } else {
+            grid
+        };
+
+        if self.one_dimensional() {
+            return grid
+        }
+        let grid = if let Some(vertices) = self.imported_polar_vertices() {
+            grid.with_polar_vertices(Self::polar_vertices_at_level(vertices, level))
+        } else {
+            grid.with_polar_spacing(self.polar_spacing.at_level(level))
+        };
+
+        match self.axis_treatment {
+            Some(axis) => grid.with_axis_treatment(axis),
+            None => grid,
+        }
+    }
+
+    /**
+     * Return the polar vertices at the given refinement level, from those at
+     * the base resolution: each zone is split evenly on finer levels, and
+     * coarser levels keep every 2^-level'th vertex.
+     */
+    fn polar_vertices_at_level(vertices: &[f64], level: i32) -> ArcArray<f64, Ix1> {
+        if level >= 0 {
+            let m = 1 << level;
+            let n = (vertices.len() - 1) * m;
+            Array::from_shape_fn(n + 1, |j| {
+                let (k, f) = (j / m, (j % m) as f64 / m as f64);
+                if f == 0.0 {
+                    vertices[k]
+                } else {
+                    vertices[k] + (vertices[k + 1] - vertices[k]) * f
+                }
+            }).to_shared()
+        } else {
+            vertices.iter().step_by(1 << -level).cloned().collect::<Array<_, Ix1>>().to_shared()
+        }
+    }
+
+    /**
+     * Return the refinement level required at the given block index by the
+     * refined regions it overlaps in radius and polar angle. Adaptive refinement does not coarsen blocks below this
+     * level.
+     */
+    pub fn static_level(&self, index: BlockIndex) -> i32 {
+        let extent = self.subgrid_extent(index);
+
+        self.refined_regions
+            .iter()
+            .filter(|region| region.inner_radius < extent.outer_radius && region.outer_radius > extent.inner_radius)
+            .filter(|region| {
+                region.inner_polar_angle.map_or(true, |q| q < extent.upper_theta) &&
+                region.outer_polar_angle.map_or(true, |q| q > extent.lower_theta)
+            })
+            .map(|region| region.refinement_factor.trailing_zeros() as i32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /**
+     * Return the refinement level of a block with the given array shape. The
+     * level is negative for blocks coarser than the base resolution.
+     */
+    pub fn block_level(&self, dim: (usize, usize)) -> i32 {
+        (dim.0 as f64 / self.block_size as f64).log2().round() as i32
+    }
+
+    /**
+     * Return the factor by which the number of polar zones increases from
+     * one refinement level to the next. One-dimensional meshes are only
+     * refined in the radial direction.
+     */
+    pub fn polar_refinement_ratio(&self) -> usize {
+        if self.one_dimensional() {
+            1
+        } else {
+            2
+        }
     }
 
     /**
-     * Return a map of the subgrid objects on this mesh.
+     * Return a map of the subgrid objects on this mesh, at the levels of the
+     * refined regions.
      */
     pub fn grid_blocks(&self, time: f64) -> HashMap<BlockIndex, SphericalPolarGrid> {
         let mut blocks = HashMap::new();
@@ -390,7 +1413,7 @@ impl Mesh {
             if extent.inner_radius >= self.outer_excision_surface(time) {
                 break
             } else {
-                blocks.insert(index, extent.grid(self.block_size, self.num_polar_zones));
+                blocks.insert(index, self.subgrid_at_level(index, self.static_level(index)));
             }
         }
         blocks
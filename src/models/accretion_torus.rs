@@ -0,0 +1,162 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::AnyPrimitive;
+use crate::physics::constants::gravitational_constant;
+
+
+
+
+/**
+ * An equilibrium torus with constant specific angular momentum, orbiting a
+ * central point mass (Papaloizou & Pringle 1984). The torus is a polytrope,
+ * p = K rho^gamma, with its pressure maximum on the equator, and it is
+ * surrounded by a uniform, non-rotating atmosphere. The structure is
+ * Newtonian.
+ *
+ * The hydrodynamics systems do not yet carry azimuthal momentum, and the
+ * point mass gravity is not yet included in the source terms, so the torus is
+ * not in equilibrium on the grid: the orbital velocity is available through
+ * [`AccretionTorus::orbital_velocity`], but is not part of the primitive
+ * state.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccretionTorus {
+
+    /// Mass of the central object (g)
+    pub central_mass: f64,
+
+    /// Radius of the inner edge of the torus on the equator (cm)
+    pub inner_edge_radius: f64,
+
+    /// Radius of the pressure maximum on the equator (cm); sets the specific
+    /// angular momentum, l^2 = G M r_max
+    pub pressure_max_radius: f64,
+
+    /// Mass density at the pressure maximum (g/cm^3)
+    pub max_density: f64,
+
+    /// Polytropic index of the torus
+    pub polytropic_index: f64,
+
+    /// Mass density of the atmosphere (g/cm^3)
+    pub ambient_density: f64,
+
+    /// Pressure of the atmosphere (erg/cm^3)
+    pub ambient_pressure: f64,
+}
+
+
+
+
+// ============================================================================
+impl AccretionTorus {
+
+    fn gm(&self) -> f64 {
+        gravitational_constant() * self.central_mass
+    }
+
+    /**
+     * The specific angular momentum of the torus (cm^2/s).
+     */
+    pub fn specific_angular_momentum(&self) -> f64 {
+        (self.gm() * self.pressure_max_radius).sqrt()
+    }
+
+    /**
+     * The specific enthalpy gamma / (gamma - 1) p / rho at the given
+     * coordinate, from the Bernoulli constant fixed at the torus inner edge.
+     * The torus occupies the region where it is positive.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     */
+    fn enthalpy(&self, r: f64, q: f64) -> f64 {
+        let l2 = self.gm() * self.pressure_max_radius;
+        let effective_potential = |r: f64, cylindrical_radius: f64| -self.gm() / r + 0.5 * l2 / cylindrical_radius.powi(2);
+        let c = effective_potential(self.inner_edge_radius, self.inner_edge_radius);
+        c - effective_potential(r, r * q.sin())
+    }
+
+    /**
+     * The orbital velocity (cm/s) of the torus at the given coordinate, or
+     * zero outside the torus.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     */
+    pub fn orbital_velocity(&self, r: f64, q: f64) -> f64 {
+        if self.enthalpy(r, q) > 0.0 {
+            self.specific_angular_momentum() / (r * q.sin())
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * The mass density and pressure of the torus at the given coordinate,
+     * or `None` outside the torus.
+     */
+    fn torus_density_pressure(&self, r: f64, q: f64) -> Option<(f64, f64)> {
+        let h = self.enthalpy(r, q);
+
+        if h > 0.0 {
+            let g = self.polytropic_index;
+            let h_max = self.enthalpy(self.pressure_max_radius, 0.5 * PI);
+            let k = h_max * (g - 1.0) / g / self.max_density.powf(g - 1.0);
+            let d = self.max_density * (h / h_max).powf(1.0 / (g - 1.0));
+            Some((d, k * d.powf(g)))
+        } else {
+            None
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for AccretionTorus {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.central_mass <= 0.0 || self.max_density <= 0.0 {
+            anyhow::bail!("the central mass and torus density must be positive")
+        }
+        if self.inner_edge_radius <= 0.0 || self.pressure_max_radius <= self.inner_edge_radius {
+            anyhow::bail!("must have 0 < inner_edge_radius < pressure_max_radius")
+        }
+        if self.polytropic_index <= 1.0 {
+            anyhow::bail!("the polytropic index must be > 1")
+        }
+        if self.ambient_density <= 0.0 || self.ambient_pressure <= 0.0 {
+            anyhow::bail!("the ambient density and pressure must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+        let (d, p) = match self.torus_density_pressure(r, q) {
+            Some((d, p)) => (d.max(self.ambient_density), p.max(self.ambient_pressure)),
+            None => (self.ambient_density, self.ambient_pressure),
+        };
+
+        AnyPrimitive {
+            velocity_r: 0.0,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: p,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, q) = coordinate;
+
+        if self.torus_density_pressure(r, q).is_some() {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
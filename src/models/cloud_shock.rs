@@ -0,0 +1,122 @@
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::AnyPrimitive;
+
+
+
+
+/**
+ * An overdense spherical clump embedded in a uniform ambient medium, struck
+ * by an outgoing spherical shock. The clump is in pressure equilibrium with
+ * the ambient medium. Because the setup is axisymmetric, a clump centered
+ * off the polar axis is really a ring.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloudShock {
+
+    /// Mass density of the ambient medium (g/cm^3)
+    pub ambient_density: f64,
+
+    /// Pressure of the ambient medium and the clump (erg/cm^3)
+    pub ambient_pressure: f64,
+
+    /// Mass density of the clump (g/cm^3)
+    pub clump_density: f64,
+
+    /// Radius of the clump (cm)
+    pub clump_radius: f64,
+
+    /// Distance of the clump center from the origin (cm)
+    pub clump_distance: f64,
+
+    /// Polar angle of the clump center; zero puts it on the axis
+    #[serde(default)]
+    pub clump_theta: f64,
+
+    /// Radius of the shock at the start time (cm); the post-shock state
+    /// fills the region inside it
+    pub shock_radius: f64,
+
+    /// Mass density behind the shock (g/cm^3)
+    pub post_shock_density: f64,
+
+    /// Pressure behind the shock (erg/cm^3)
+    pub post_shock_pressure: f64,
+
+    /// Radial four-velocity (gamma-beta) behind the shock, or the radial
+    /// velocity for Newtonian hydrodynamics
+    pub post_shock_gamma_beta: f64,
+}
+
+
+
+
+// ============================================================================
+impl CloudShock {
+
+    /**
+     * Determine whether a point is inside the clump.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     */
+    pub fn in_clump(&self, r: f64, q: f64) -> bool {
+        let rc = self.clump_distance;
+        let d2 = r * r + rc * rc - 2.0 * r * rc * (q - self.clump_theta).cos();
+        d2 < self.clump_radius * self.clump_radius
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for CloudShock {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.ambient_density <= 0.0 || self.clump_density <= 0.0 || self.post_shock_density <= 0.0 {
+            anyhow::bail!("the ambient, clump, and post-shock densities must be positive")
+        }
+        if self.ambient_pressure <= 0.0 || self.post_shock_pressure <= 0.0 {
+            anyhow::bail!("the ambient and post-shock pressures must be positive")
+        }
+        if self.clump_radius <= 0.0 {
+            anyhow::bail!("the clump radius must be positive")
+        }
+        if self.clump_distance - self.clump_radius <= self.shock_radius {
+            anyhow::bail!("the clump must lie entirely outside the initial shock radius")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+
+        if r < self.shock_radius {
+            AnyPrimitive {
+                velocity_r: self.post_shock_gamma_beta,
+                velocity_q: 0.0,
+                mass_density: self.post_shock_density,
+                gas_pressure: self.post_shock_pressure,
+            }
+        } else {
+            AnyPrimitive {
+                velocity_r: 0.0,
+                velocity_q: 0.0,
+                mass_density: if self.in_clump(r, q) { self.clump_density } else { self.ambient_density },
+                gas_pressure: self.ambient_pressure,
+            }
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, q) = coordinate;
+
+        if self.in_clump(r, q) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
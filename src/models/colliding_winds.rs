@@ -0,0 +1,104 @@
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::{AnyPrimitive, light_speed};
+use super::Wind;
+
+
+
+
+/**
+ * A fast inner wind blowing into a slow wind established earlier. The fast
+ * wind turns on at the onset time at the origin, and initially fills the
+ * sphere its leading edge has reached; the slow wind fills the rest of the
+ * domain. The interaction develops the four-zone wind-bubble structure: free
+ * fast wind, shocked fast wind, shocked slow wind, and free slow wind. The
+ * scalar is 1 in the fast wind and 0 in the slow wind.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollidingWinds {
+
+    /// Mass-loss rate of the slow wind (g/s)
+    pub slow_mass_loss_rate: f64,
+
+    /// Four-velocity (gamma-beta) of the slow wind
+    pub slow_gamma_beta: f64,
+
+    /// Mass-loss rate of the fast wind (g/s)
+    pub fast_mass_loss_rate: f64,
+
+    /// Four-velocity (gamma-beta) of the fast wind
+    pub fast_gamma_beta: f64,
+
+    /// Time (s) when the fast wind turns on
+    #[serde(default)]
+    pub fast_wind_onset: f64,
+}
+
+
+
+
+// ============================================================================
+impl CollidingWinds {
+
+    fn slow_wind(&self) -> Wind {
+        Wind {
+            mass_loss_rate: self.slow_mass_loss_rate,
+            wind_gamma_beta: self.slow_gamma_beta,
+            scalar: 0.0,
+        }
+    }
+
+    fn fast_wind(&self) -> Wind {
+        Wind {
+            mass_loss_rate: self.fast_mass_loss_rate,
+            wind_gamma_beta: self.fast_gamma_beta,
+            scalar: 1.0,
+        }
+    }
+
+    /**
+     * The radius (cm) of the leading edge of the free fast wind at time t,
+     * ignoring its interaction with the slow wind.
+     */
+    pub fn fast_wind_radius(&self, t: f64) -> f64 {
+        let u = self.fast_gamma_beta;
+        let v = u / (1.0 + u * u).sqrt() * light_speed();
+        (v * (t - self.fast_wind_onset)).max(0.0)
+    }
+
+    fn wind_at(&self, r: f64, t: f64) -> Wind {
+        if r < self.fast_wind_radius(t) {
+            self.fast_wind()
+        } else {
+            self.slow_wind()
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for CollidingWinds {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.slow_wind().validate()?;
+        self.fast_wind().validate()?;
+
+        if self.fast_gamma_beta <= self.slow_gamma_beta {
+            anyhow::bail!("the fast wind must be faster than the slow wind")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        self.wind_at(r, t).primitive_at(coordinate, t)
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        self.wind_at(r, t).scalar_at(coordinate, t)
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Serialize, Deserialize};
+use crate::app::AnyModel;
+use crate::physics::AnyPrimitive;
+use crate::traits::{BoundaryEdge, InitialModel};
+
+
+
+
+/**
+ * Rule used to decide which layer of a composite model provides the data at
+ * a given point
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precedence {
+
+    /// The first layer, in the order given, whose region contains the point
+    InOrder,
+
+    /// The layer with the largest mass density at the point
+    Densest,
+
+    /// The layer with the largest radial four-velocity at the point
+    Fastest,
+}
+
+
+
+
+/**
+ * One model in a composite, optionally restricted to a region of the
+ * r-theta plane
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+
+    /// The model providing the data in this layer
+    pub model: AnyModel,
+
+    /// The layer only covers radii at or above this value (cm)
+    #[serde(default)]
+    pub inner_radius: Option<f64>,
+
+    /// The layer only covers radii below this value (cm)
+    #[serde(default)]
+    pub outer_radius: Option<f64>,
+
+    /// The layer only covers polar angles within this angle of either pole
+    #[serde(default)]
+    pub polar_half_angle: Option<f64>,
+}
+
+
+
+
+/**
+ * Superposition of several models, such as a tabulated progenitor, a wind
+ * outside it, and a jet. At each point, the layers whose regions contain the
+ * point are candidates, and the precedence rule picks one of them to supply
+ * the primitive state and scalar. The accelerations of all the layers are
+ * added together.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Composite {
+
+    /// The layers, in order of precedence for the in_order rule
+    pub layers: Vec<Layer>,
+
+    /// Rule to select the layer at each point: in_order (the default),
+    /// densest, or fastest
+    #[serde(default)]
+    pub precedence: Precedence,
+}
+
+
+
+
+// ============================================================================
+impl Default for Precedence {
+    fn default() -> Self {
+        Self::InOrder
+    }
+}
+
+
+
+
+// ============================================================================
+impl Layer {
+
+    /**
+     * Determine whether the region of this layer contains the given point.
+     */
+    pub fn contains(&self, coordinate: (f64, f64)) -> bool {
+        let (r, q) = coordinate;
+        let theta = f64::min(q, std::f64::consts::PI - q);
+
+        self.inner_radius.map_or(true, |r0| r >= r0)
+            && self.outer_radius.map_or(true, |r1| r < r1)
+            && self.polar_half_angle.map_or(true, |q1| theta < q1)
+    }
+}
+
+
+
+
+// ============================================================================
+impl Composite {
+
+    /**
+     * Return the layer which supplies the data at the given point, with the
+     * primitive state obtained from it by the given function. Returns `None`
+     * if no layer covers the point.
+     */
+    fn select<F>(&self, coordinate: (f64, f64), primitive: F) -> Option<(&Layer, AnyPrimitive)>
+    where
+        F: Fn(&AnyModel) -> AnyPrimitive
+    {
+        let mut candidates = self.layers.iter()
+            .filter(|layer| layer.contains(coordinate))
+            .map(|layer| (layer, primitive(&layer.model)));
+
+        match self.precedence {
+            Precedence::InOrder => candidates.next(),
+            Precedence::Densest => candidates.fold(None, |best, (layer, p)| match best {
+                Some((l, q)) if q.mass_density >= p.mass_density => Some((l, q)),
+                _ => Some((layer, p)),
+            }),
+            Precedence::Fastest => candidates.fold(None, |best, (layer, p)| match best {
+                Some((l, q)) if q.velocity_r >= p.velocity_r => Some((l, q)),
+                _ => Some((layer, p)),
+            }),
+        }
+    }
+
+    /**
+     * Same as `select`, except that points not covered by any layer are
+     * given to the last layer, so that every point has data.
+     */
+    fn select_or_last<F>(&self, coordinate: (f64, f64), primitive: F) -> (&Layer, AnyPrimitive)
+    where
+        F: Fn(&AnyModel) -> AnyPrimitive
+    {
+        match self.select(coordinate, &primitive) {
+            Some(selected) => selected,
+            None => {
+                let last = self.layers.last().unwrap();
+                (last, primitive(&last.model))
+            }
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for Composite {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.layers.is_empty() {
+            anyhow::bail!("a composite model needs at least one layer")
+        }
+        for layer in &self.layers {
+            if let (Some(r0), Some(r1)) = (layer.inner_radius, layer.outer_radius) {
+                if r1 <= r0 {
+                    anyhow::bail!("composite layer must have inner_radius < outer_radius")
+                }
+            }
+            layer.model.validate()?;
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+        self.select_or_last(coordinate, |m| m.primitive_at(coordinate, time)).1
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), time: f64) -> f64 {
+        self.select_or_last(coordinate, |m| m.primitive_at(coordinate, time)).0.model.scalar_at(coordinate, time)
+    }
+
+    fn boundary_primitive_at(&self, edge: BoundaryEdge, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+        self.select_or_last(coordinate, |m| m.boundary_primitive_at(edge, coordinate, time)).1
+    }
+
+    fn acceleration_at(&self, coordinate: (f64, f64), time: f64) -> (f64, f64) {
+        self.layers.iter().fold((0.0, 0.0), |(ar, aq), layer| {
+            let (br, bq) = layer.model.acceleration_at(coordinate, time);
+            (ar + br, aq + bq)
+        })
+    }
+}
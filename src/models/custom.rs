@@ -0,0 +1,65 @@
+use serde::{Serialize, Deserialize};
+use crate::expression::Expression;
+use crate::physics::AnyPrimitive;
+use crate::traits::InitialModel;
+
+
+
+
+/**
+ * A user-defined model, whose primitive fields are given as math expressions
+ * of the radius `r` (cm), polar angle `theta` (radians), and time `t` (s).
+ * The expressions are parsed when the configuration is loaded, so syntax
+ * errors and unknown names are reported before the run starts. See
+ * `expression::Expression` for the supported operators and functions.
+ * Velocities are four-velocities (gamma-beta) for relativistic hydrodynamics.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Custom {
+
+    /// Mass density (g/cm^3)
+    pub mass_density: Expression,
+
+    /// Gas pressure
+    pub gas_pressure: Expression,
+
+    /// Radial velocity; defaults to zero
+    #[serde(default)]
+    pub velocity_r: Expression,
+
+    /// Polar velocity; defaults to zero
+    #[serde(default)]
+    pub velocity_q: Expression,
+
+    /// Passive scalar concentration; defaults to zero
+    #[serde(default)]
+    pub scalar: Expression,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for Custom {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+
+        AnyPrimitive {
+            velocity_r: self.velocity_r.evaluate(r, q, t),
+            velocity_q: self.velocity_q.evaluate(r, q, t),
+            mass_density: self.mass_density.evaluate(r, q, t),
+            gas_pressure: self.gas_pressure.evaluate(r, q, t),
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, q) = coordinate;
+        self.scalar.evaluate(r, q, t)
+    }
+}
@@ -0,0 +1,209 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::traits::InitialModel;
+use super::JetStructure;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * A slow equatorial disk wind and a fast polar jet, injected together with
+ * independent parameters, into a power-law ambient medium. Each component
+ * occupies the shell between the material launched when it turned on and
+ * when it turned off. Where the two overlap, the jet takes precedence.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskWindJet {
+
+    /// Time (s) when the jet turns on
+    #[serde(default)]
+    pub jet_delay: f64,
+
+    /// Duration of the jet (s)
+    pub jet_duration: f64,
+
+    /// Isotropic-equivalent energy of the jet on its axis (erg)
+    pub jet_energy: f64,
+
+    /// Jet opening angle (the core angle for structured jets)
+    pub jet_theta: f64,
+
+    /// Jet four-velocity on the jet axis
+    pub jet_u: f64,
+
+    /// Angular structure of the jet: top_hat (the default), gaussian, or
+    /// power_law
+    #[serde(default)]
+    pub jet_structure: JetStructure,
+
+    /// Time (s) when the wind turns on
+    #[serde(default)]
+    pub wind_delay: f64,
+
+    /// Duration of the wind (s)
+    pub wind_duration: f64,
+
+    /// Isotropic-equivalent mass-loss rate of the wind (g/s)
+    pub wind_mass_loss_rate: f64,
+
+    /// Four-velocity (gamma-beta) of the wind
+    pub wind_gamma_beta: f64,
+
+    /// Half-opening angle of the wind, measured from the equator
+    pub wind_half_angle: f64,
+
+    /// Ambient mass density (g/cm^3) at the reference radius
+    pub ambient_density: f64,
+
+    /// Radius (cm) where the ambient density is given
+    pub reference_radius: f64,
+
+    /// Power-law index of the ambient medium, rho ~ r^-index
+    #[serde(default)]
+    pub ambient_index: f64,
+}
+
+
+
+
+/**
+ * Different space-time zones in the setup
+ */
+pub enum Zone {
+    Jet,
+    Wind,
+    Ambient,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for DiskWindJet {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.jet_structure.validate()?;
+
+        if self.jet_energy <= 0.0 || self.jet_duration <= 0.0 || self.jet_u <= 0.0 {
+            anyhow::bail!("the jet energy, duration, and four-velocity must be positive")
+        }
+        if self.jet_theta <= 0.0 || self.jet_theta >= 0.5 * PI {
+            anyhow::bail!("must have 0 < jet_theta < pi / 2")
+        }
+        if self.wind_mass_loss_rate <= 0.0 || self.wind_duration <= 0.0 || self.wind_gamma_beta <= 0.0 {
+            anyhow::bail!("the wind mass-loss rate, duration, and four-velocity must be positive")
+        }
+        if self.wind_half_angle <= 0.0 || self.wind_half_angle > 0.5 * PI {
+            anyhow::bail!("must have 0 < wind_half_angle <= pi / 2")
+        }
+        if self.jet_delay < 0.0 || self.wind_delay < 0.0 {
+            anyhow::bail!("the jet and wind delays must be non-negative")
+        }
+        if self.ambient_density <= 0.0 || self.reference_radius <= 0.0 {
+            anyhow::bail!("the ambient density and reference radius must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+
+        let (d, u) = match self.zone(r, q, t) {
+            Zone::Jet => {
+                (self.jet_mass_density(r, q), self.jet_u_at(q))
+            }
+            Zone::Wind => {
+                (self.wind_mass_density(r), self.wind_gamma_beta)
+            }
+            Zone::Ambient => {
+                (self.ambient_density * (r / self.reference_radius).powf(-self.ambient_index), 0.0)
+            }
+        };
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, q) = coordinate;
+
+        match self.zone(r, q, t) {
+            Zone::Jet     => 1.0,
+            Zone::Wind    => 0.5,
+            Zone::Ambient => 0.0,
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl DiskWindJet {
+
+    /**
+     * Jet four-velocity at the given polar angle, accounting for the jet
+     * angular structure.
+     *
+     * * `q` - The polar angle theta
+     */
+    pub fn jet_u_at(&self, q: f64) -> f64 {
+        let theta = JetStructure::angle_from_axis(q);
+        self.jet_structure.gamma_beta(self.jet_u, theta, self.jet_theta)
+    }
+
+    /**
+     * Determine the zone for a given radius, polar angle, and time.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     * * `t` - Time
+     */
+    pub fn zone(&self, r: f64, q: f64, t: f64) -> Zone {
+        let in_shell = |u: f64, delay: f64, duration: f64| {
+            let v = u / (1.0 + u * u).sqrt() * light_speed();
+            let launch_time = t - delay - r / v;
+            launch_time > 0.0 && launch_time < duration
+        };
+        let theta = JetStructure::angle_from_axis(q);
+        let in_jet_cone = theta < self.jet_structure.truncation_angle(self.jet_theta);
+        let in_wind_cone = (0.5 * PI - q).abs() < self.wind_half_angle;
+
+        if in_jet_cone && in_shell(self.jet_u_at(q), self.jet_delay, self.jet_duration) {
+            Zone::Jet
+        } else if in_wind_cone && in_shell(self.wind_gamma_beta, self.wind_delay, self.wind_duration) {
+            Zone::Wind
+        } else {
+            Zone::Ambient
+        }
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of the jet at radius r and
+     * polar angle q.
+     */
+    fn jet_mass_density(&self, r: f64, q: f64) -> f64 {
+        let theta = JetStructure::angle_from_axis(q);
+        let u = self.jet_u_at(q);
+        let gamma = (1.0 + u * u).sqrt();
+        let e = self.jet_energy * self.jet_structure.energy_fraction(theta, self.jet_theta);
+        let l = e / (4.0 * PI * self.jet_duration);
+        let mdot = l / (gamma * light_speed() * light_speed());
+        mdot / (r * r * u * light_speed())
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of the wind at radius r.
+     */
+    fn wind_mass_density(&self, r: f64) -> f64 {
+        self.wind_mass_loss_rate / (4.0 * PI * r * r * self.wind_gamma_beta * light_speed())
+    }
+}
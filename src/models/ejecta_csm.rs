@@ -0,0 +1,180 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::{AnyPrimitive, light_speed};
+use super::HomologousEjecta;
+
+const UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Circumstellar medium surrounding the supernova ejecta
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum Csm {
+
+    /// A uniform-density shell between two radii, at rest
+    Shell {
+
+        /// Inner radius of the shell (cm)
+        inner_radius: f64,
+
+        /// Outer radius of the shell (cm)
+        outer_radius: f64,
+
+        /// Mass density of the shell (g/cm^3)
+        density: f64,
+    },
+
+    /// A steady wind with constant mass-loss rate and speed
+    Wind {
+
+        /// Mass-loss rate of the progenitor (g/s)
+        mass_loss_rate: f64,
+
+        /// Speed of the wind (cm/s)
+        wind_speed: f64,
+    },
+}
+
+
+
+
+/**
+ * Freely expanding supernova ejecta with a broken power-law density profile,
+ * running into a circumstellar shell or wind. The scalar is 1 in the ejecta,
+ * 0.5 in the CSM, and 0 in the ambient medium.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EjectaCsm {
+
+    /// Total mass of the ejecta (g)
+    pub ejecta_mass: f64,
+
+    /// Velocity (v/c) where the ejecta density profile breaks
+    pub break_beta: f64,
+
+    /// Velocity (v/c) of the fastest ejecta
+    pub max_beta: f64,
+
+    /// Index delta of the inner ejecta density profile (must be < 3)
+    pub inner_index: f64,
+
+    /// Index n of the outer ejecta density profile (must be > 3)
+    pub outer_index: f64,
+
+    /// The circumstellar medium
+    pub csm: Csm,
+
+    /// Mass density of the medium outside the CSM (g/cm^3). The ejecta also
+    /// sweep this up before reaching a shell.
+    pub ambient_density: f64,
+}
+
+
+
+
+// ============================================================================
+impl EjectaCsm {
+
+    fn ejecta(&self) -> HomologousEjecta {
+        HomologousEjecta {
+            ejecta_mass: self.ejecta_mass,
+            break_beta: self.break_beta,
+            max_beta: self.max_beta,
+            inner_index: self.inner_index,
+            outer_index: self.outer_index,
+            ambient_density: self.ambient_density,
+            inner_scalar: 1.0,
+            outer_scalar: 1.0,
+        }
+    }
+
+    /**
+     * The primitive state of the circumstellar (or ambient) medium at radius
+     * r.
+     */
+    fn csm_primitive(&self, r: f64) -> AnyPrimitive {
+        let (d, u) = match self.csm {
+            Csm::Shell { inner_radius, outer_radius, density } => {
+                if r > inner_radius && r < outer_radius {
+                    (density, 0.0)
+                } else {
+                    (self.ambient_density, 0.0)
+                }
+            }
+            Csm::Wind { mass_loss_rate, wind_speed } => {
+                let b = wind_speed / light_speed();
+                let d = mass_loss_rate / (4.0 * PI * r * r * wind_speed);
+                (d.max(self.ambient_density), b / (1.0 - b * b).sqrt())
+            }
+        };
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn in_csm(&self, r: f64) -> bool {
+        match self.csm {
+            Csm::Shell { inner_radius, outer_radius, .. } => r > inner_radius && r < outer_radius,
+            Csm::Wind { .. } => true,
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for EjectaCsm {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.ejecta().validate()?;
+
+        match self.csm {
+            Csm::Shell { inner_radius, outer_radius, density } => {
+                if inner_radius <= 0.0 || outer_radius <= inner_radius || density <= 0.0 {
+                    anyhow::bail!("the CSM shell must have 0 < inner_radius < outer_radius, and positive density")
+                }
+            }
+            Csm::Wind { mass_loss_rate, wind_speed } => {
+                if mass_loss_rate <= 0.0 || wind_speed <= 0.0 || wind_speed >= light_speed() {
+                    anyhow::bail!("the CSM wind must have positive mass-loss rate, and speed between 0 and c")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let b = r / t / light_speed();
+
+        if b < self.max_beta {
+            self.ejecta().primitive_at(coordinate, t)
+        } else {
+            self.csm_primitive(r)
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        let b = r / t / light_speed();
+
+        if b < self.max_beta {
+            1.0
+        } else if self.in_csm(r) {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
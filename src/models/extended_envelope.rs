@@ -0,0 +1,154 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::AnyPrimitive;
+use crate::traits::InitialModel;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * A progenitor with a compact core surrounded by a very extended, low-mass
+ * envelope, as in Type IIb supernova progenitors which have lost most of
+ * their hydrogen, and a wind outside the envelope. The core density is
+ * rho_c (1 - r / R_core)^n, the envelope density is a power law in radius,
+ * and the wind density falls off as r^-2. The model is cold and at rest, so
+ * an explosion is added by layering it with another model, for example a
+ * spherical blast in a composite model. The scalar is 1 in the core, 0.5 in
+ * the envelope, and 0 in the wind.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtendedEnvelope {
+
+    /// Mass of the core (g)
+    pub core_mass: f64,
+
+    /// Radius of the core (cm)
+    pub core_radius: f64,
+
+    /// Index n of the core density profile, rho ~ (1 - r / R_core)^n
+    pub core_index: f64,
+
+    /// Mass of the envelope (g)
+    pub envelope_mass: f64,
+
+    /// Outer radius of the envelope (cm)
+    pub envelope_radius: f64,
+
+    /// Index s of the envelope density profile, rho ~ r^-s
+    pub envelope_index: f64,
+
+    /// Mass density (g/cm^3) of the wind at the envelope radius
+    pub wind_density: f64,
+}
+
+
+
+
+/**
+ * Different zones in the setup
+ */
+pub enum Zone {
+    Core,
+    Envelope,
+    Wind,
+}
+
+
+
+
+// ============================================================================
+impl ExtendedEnvelope {
+
+    /**
+     * Determine the zone at a given radius.
+     */
+    pub fn zone(&self, r: f64) -> Zone {
+        if r < self.core_radius {
+            Zone::Core
+        } else if r < self.envelope_radius {
+            Zone::Envelope
+        } else {
+            Zone::Wind
+        }
+    }
+
+    /**
+     * The central density rho_c (g/cm^3), from the core mass and the mass
+     * integral 4 pi R^3 int_0^1 (1 - x)^n x^2 dx = 8 pi R^3 / ((n + 1)(n + 2)(n + 3)).
+     */
+    pub fn central_density(&self) -> f64 {
+        let n = self.core_index;
+        self.core_mass * (n + 1.0) * (n + 2.0) * (n + 3.0) / (8.0 * PI * self.core_radius.powi(3))
+    }
+
+    /**
+     * The envelope density (g/cm^3) at the core radius, from the envelope
+     * mass.
+     */
+    pub fn envelope_base_density(&self) -> f64 {
+        let s = self.envelope_index;
+        let x = self.envelope_radius / self.core_radius;
+        let shape_integral = if (s - 3.0).abs() < 1e-12 {
+            x.ln()
+        } else {
+            (x.powf(3.0 - s) - 1.0) / (3.0 - s)
+        };
+        self.envelope_mass / (4.0 * PI * self.core_radius.powi(3) * shape_integral)
+    }
+
+    /**
+     * The mass density (g/cm^3) at radius r.
+     */
+    pub fn mass_density(&self, r: f64) -> f64 {
+        match self.zone(r) {
+            Zone::Core     => self.central_density() * (1.0 - r / self.core_radius).powf(self.core_index),
+            Zone::Envelope => self.envelope_base_density() * (r / self.core_radius).powf(-self.envelope_index),
+            Zone::Wind     => self.wind_density * (r / self.envelope_radius).powi(-2),
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for ExtendedEnvelope {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.core_mass <= 0.0 || self.envelope_mass <= 0.0 || self.wind_density <= 0.0 {
+            anyhow::bail!("the core mass, envelope mass, and wind density must be positive")
+        }
+        if self.core_radius <= 0.0 || self.envelope_radius <= self.core_radius {
+            anyhow::bail!("must have 0 < core_radius < envelope_radius")
+        }
+        if self.core_index < 0.0 {
+            anyhow::bail!("the core density index must be non-negative")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let d = self.mass_density(r);
+
+        AnyPrimitive {
+            velocity_r: 0.0,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, _q) = coordinate;
+
+        match self.zone(r) {
+            Zone::Core     => 1.0,
+            Zone::Envelope => 0.5,
+            Zone::Wind     => 0.0,
+        }
+    }
+}
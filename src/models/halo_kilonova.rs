@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex};
 use crate::lookup_table_v2::LookupTable;
-use crate::physics::{AnyPrimitive, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, light_speed};
 use crate::traits::InitialModel;
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
@@ -56,12 +56,12 @@ impl HaloKilonova {
 // ============================================================================
 impl InitialModel for HaloKilonova {
     fn validate(&self) -> anyhow::Result<()> {
-        if self.shell_velocity() > 0.25 * LIGHT_SPEED {
+        if self.shell_velocity() > 0.25 * light_speed() {
             anyhow::bail! {"
             The shell is moving faster (v/c = {}) than 0.25 c, but
             this problem assumes Newtonian expressions for the
             kinetic energy. Consider reducing the kinetic energy or
-            increasing the shell mass.", self.shell_velocity() / LIGHT_SPEED}
+            increasing the shell mass.", self.shell_velocity() / light_speed()}
         } else if let Some(initial_data_table) = &self.initial_data_table {
             LookupTable::<3>::from_ascii_file(initial_data_table)?;
         }
@@ -79,7 +79,7 @@ impl InitialModel for HaloKilonova {
             let p = d * UNIFORM_TEMPERATURE;
 
             AnyPrimitive {
-                velocity_r: v / LIGHT_SPEED,
+                velocity_r: v / light_speed(),
                 velocity_q: 0.0,
                 mass_density: d,
                 gas_pressure: p,
@@ -90,7 +90,7 @@ impl InitialModel for HaloKilonova {
             let table = table_borrow.as_ref().unwrap();
             let sample = table.sample(z);
             let d = sample[2];
-            let p = sample[1] / LIGHT_SPEED / LIGHT_SPEED;
+            let p = sample[1] / light_speed() / light_speed();
 
             AnyPrimitive {
                 velocity_r: 0.0,
@@ -0,0 +1,146 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::{AnyPrimitive, light_speed};
+
+const UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Freely expanding (homologous, v = r / t) ejecta with a broken power-law
+ * density profile, surrounded by a uniform ambient medium
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HomologousEjecta {
+
+    /// Total mass of the ejecta (g)
+    pub ejecta_mass: f64,
+
+    /// Velocity (v/c) where the density profile breaks from the inner to the
+    /// outer power law
+    pub break_beta: f64,
+
+    /// Velocity (v/c) of the fastest ejecta
+    pub max_beta: f64,
+
+    /// Index delta of the inner density profile, rho ~ v^-delta (must be < 3)
+    pub inner_index: f64,
+
+    /// Index n of the outer density profile, rho ~ v^-n (must be > 3)
+    pub outer_index: f64,
+
+    /// Mass density of the medium outside the ejecta (g/cm^3)
+    pub ambient_density: f64,
+
+    /// Composition scalar of the ejecta slower than the break velocity
+    pub inner_scalar: f64,
+
+    /// Composition scalar of the ejecta faster than the break velocity
+    pub outer_scalar: f64,
+}
+
+
+
+
+// ============================================================================
+impl HomologousEjecta {
+
+    /**
+     * The dimensionless mass integral of the density profile shape, with
+     * velocities in units of the break velocity.
+     */
+    fn shape_integral(&self) -> f64 {
+        let x_max = self.max_beta / self.break_beta;
+        let inner = 1.0 / (3.0 - self.inner_index);
+        let outer = (1.0 - x_max.powf(3.0 - self.outer_index)) / (self.outer_index - 3.0);
+        inner + outer
+    }
+
+    /**
+     * The lab-frame mass density (g/cm^3) of the ejecta at velocity (v/c)
+     * beta and time t. The density is zero above the maximum velocity.
+     *
+     * * `beta` - The velocity v/c = r / (c t)
+     * * `t`    - The time since the ejecta were launched
+     */
+    pub fn ejecta_density(&self, beta: f64, t: f64) -> f64 {
+        let v_break = self.break_beta * light_speed();
+        let a = self.ejecta_mass / (4.0 * PI * v_break.powi(3) * self.shape_integral());
+        let x = beta / self.break_beta;
+
+        if beta >= self.max_beta {
+            0.0
+        } else if x < 1.0 {
+            a / t.powi(3) * x.powf(-self.inner_index)
+        } else {
+            a / t.powi(3) * x.powf(-self.outer_index)
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for HomologousEjecta {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.ejecta_mass <= 0.0 || self.ambient_density <= 0.0 {
+            anyhow::bail!("the ejecta mass and ambient density must be positive")
+        }
+        if self.break_beta <= 0.0 || self.max_beta <= self.break_beta || self.max_beta >= 1.0 {
+            anyhow::bail!("must have 0 < break_beta < max_beta < 1")
+        }
+        if self.inner_index >= 3.0 {
+            anyhow::bail!("the inner density index must be < 3 for the ejecta mass to be finite")
+        }
+        if self.outer_index <= 3.0 {
+            anyhow::bail!("the outer density index must be > 3")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let b = r / t / light_speed();
+
+        if b < self.max_beta {
+            let lorentz_factor = 1.0 / (1.0 - b * b).sqrt();
+            let d = self.ejecta_density(b, t) / lorentz_factor;
+            let p = d * UNIFORM_TEMPERATURE;
+
+            AnyPrimitive {
+                velocity_r: b * lorentz_factor,
+                velocity_q: 0.0,
+                mass_density: d,
+                gas_pressure: p,
+            }
+        } else {
+            let d = self.ambient_density;
+            let p = d * UNIFORM_TEMPERATURE;
+
+            AnyPrimitive {
+                velocity_r: 0.0,
+                velocity_q: 0.0,
+                mass_density: d,
+                gas_pressure: p,
+            }
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        let b = r / t / light_speed();
+
+        if b < self.break_beta {
+            self.inner_scalar
+        } else if b < self.max_beta {
+            self.outer_scalar
+        } else {
+            0.0
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::f64::consts::PI;
 use serde::{Serialize, Deserialize};
-use crate::physics::{AnyPrimitive, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, light_speed};
 use crate::traits::InitialModel;
+use super::{EngineLuminosity, EnginePulses, JetStructure, JetTilt};
 
 static NOMINAL_LAUNCH_RADIUS: f64 = 1e8;
 static UNIFORM_TEMPERATURE: f64 = 1e-3;
@@ -35,6 +36,26 @@ pub struct JetInCloud {
     /// Engine four-velocity
     pub engine_u: f64,
 
+    /// Angular structure of the engine: top_hat (the default), gaussian, or
+    /// power_law. The engine opening angle is the core angle of the
+    /// structured profiles, and the engine four-velocity is the on-axis value.
+    #[serde(default)]
+    pub engine_structure: JetStructure,
+
+    /// Optional effective tilted-jet approximation, alternating the engine
+    /// between the two poles
+    #[serde(default)]
+    pub engine_tilt: Option<JetTilt>,
+
+    /// Optional on/off duty cycle, making the engine fire in pulses
+    #[serde(default)]
+    pub engine_pulses: Option<EnginePulses>,
+
+    /// Time dependence of the engine luminosity: constant (the default),
+    /// magnetar_spin_down, or tabulated (without a Lorentz factor table)
+    #[serde(default)]
+    pub engine_luminosity: EngineLuminosity,
+
     /// Mass coordinate of the u=1 shell
     pub envelop_m1: f64,
 
@@ -67,6 +88,17 @@ pub enum Zone {
 impl InitialModel for JetInCloud {
 
     fn validate(&self) -> anyhow::Result<()> {
+        self.engine_structure.validate()?;
+        if let Some(tilt) = &self.engine_tilt {
+            tilt.validate()?;
+        }
+        if let Some(pulses) = &self.engine_pulses {
+            pulses.validate()?;
+        }
+        self.engine_luminosity.validate()?;
+        if self.engine_luminosity.has_lorentz_factor_table() {
+            anyhow::bail!("jet_in_cloud does not support a tabulated engine Lorentz factor")
+        }
         self.print(&mut std::io::stdout());
         Ok(())
     }
@@ -75,7 +107,7 @@ impl InitialModel for JetInCloud {
         let (r, q) = coordinate;
         let f = self.mass_rate_per_steradian(r, q, t);
         let u = self.gamma_beta(r, q, t);
-        let d = f / (r * r * u) / LIGHT_SPEED;
+        let d = f / (r * r * u) / light_speed();
         let p = d * UNIFORM_TEMPERATURE;
 
         AnyPrimitive{
@@ -125,7 +157,7 @@ impl JetInCloud
      * The time when the slowest envelope shell comes through the launch radius
      */
     pub fn get_t1(&self) -> f64 {
-        NOMINAL_LAUNCH_RADIUS / self.envelop_slowest_beta / LIGHT_SPEED
+        NOMINAL_LAUNCH_RADIUS / self.envelop_slowest_beta / light_speed()
     }
 
     /**
@@ -139,7 +171,7 @@ impl JetInCloud
      * Time when the jet comes through the lauch radius
      */
     pub fn get_t3(&self) -> f64 {
-        self.get_t2() + NOMINAL_LAUNCH_RADIUS / self.engine_beta() / LIGHT_SPEED
+        self.get_t2() + NOMINAL_LAUNCH_RADIUS / self.engine_beta() / light_speed()
     }
 
     /**
@@ -165,12 +197,57 @@ impl JetInCloud
     }
 
     /**
-     * Determine if a polar angle is within theta_jet of either pole.
+     * Angle from the jet axis at the given polar angle, accounting for the
+     * jet tilt if there is one.
+     *
+     * * `q` - The polar angle theta
+     */
+    fn engine_angle(&self, q: f64) -> f64 {
+        match &self.engine_tilt {
+            Some(tilt) => tilt.angle_from_axis(q),
+            None => JetStructure::angle_from_axis(q),
+        }
+    }
+
+    /**
+     * Factor multiplying the jet power at the given polar angle, for
+     * material launched at time t after the engine turned on.
+     *
+     * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
+     */
+    fn engine_weight(&self, q: f64, t: f64) -> f64 {
+        let tilt = match &self.engine_tilt {
+            Some(tilt) => tilt.pole_weight(q, t, self.engine_theta),
+            None => 1.0,
+        };
+        let pulses = match &self.engine_pulses {
+            Some(pulses) => pulses.power_weight(t),
+            None => 1.0,
+        };
+        let isotropic_luminosity = self.engine_strength * self.cloud_mass * light_speed() * light_speed() / self.engine_duration;
+        let luminosity = self.engine_luminosity.power_weight(t, isotropic_luminosity, &self.engine_structure, self.engine_theta);
+        tilt * pulses * luminosity
+    }
+
+    /**
+     * Engine four-velocity at the given polar angle, accounting for the jet
+     * angular structure.
+     *
+     * * `q` - The polar angle theta
+     */
+    pub fn engine_u_at(&self, q: f64) -> f64 {
+        self.engine_structure.gamma_beta(self.engine_u, self.engine_angle(q), self.engine_theta)
+    }
+
+    /**
+     * Determine if a polar angle is within the jet truncation angle of either
+     * pole (theta_jet for a top-hat jet).
      *
      * * `q` - The polar angle theta
      */
     pub fn in_nozzle(&self, q: f64) -> bool {
-        q < self.engine_theta || q > PI - self.engine_theta
+        self.engine_angle(q) < self.engine_structure.truncation_angle(self.engine_theta)
     }
 
     /**
@@ -181,14 +258,17 @@ impl JetInCloud
      * * `t` - Time
      */
     pub fn zone(&self, r: f64, q: f64, t: f64) -> Zone {
-        let v_min = self.envelop_slowest_beta * LIGHT_SPEED;
-        let v_jet = self.engine_beta() * LIGHT_SPEED;
+        let v_min = self.envelop_slowest_beta * light_speed();
+        let u_jet = self.engine_u_at(q);
+        let v_jet = u_jet / (1.0 + u_jet * u_jet).sqrt() * light_speed();
 
         let r_cloud_envelop_interface = v_min * t;
         let r_jet_head = v_jet * (t - self.engine_delay);
         let r_jet_tail = v_jet * (t - self.engine_delay - self.engine_duration);
 
-        if self.in_nozzle(q) && r < r_jet_head  && r > r_jet_tail {
+        let launch_time = t - self.engine_delay - r / v_jet;
+
+        if self.in_nozzle(q) && r < r_jet_head  && r > r_jet_tail && self.engine_weight(q, launch_time) > 0.0 {
             Zone::Jet
         } else if r > r_cloud_envelop_interface {
             Zone::Envelope
@@ -210,12 +290,12 @@ impl JetInCloud
                 self.envelop_slowest_u()
             }
             Zone::Envelope => {
-                let b = f64::min(r / t / LIGHT_SPEED, self.envelop_fastest_beta);
+                let b = f64::min(r / t / light_speed(), self.envelop_fastest_beta);
                 let u = b / f64::sqrt(1.0 - b * b);
                 u
             }
             Zone::Jet => {
-                self.engine_u
+                self.engine_u_at(q)
             }
         }
     }
@@ -234,20 +314,25 @@ impl JetInCloud
                 self.cloud_mass_rate_per_steradian()
             }
             Zone::Envelope => {
-                let s = f64::min(r / t / LIGHT_SPEED, self.envelop_fastest_beta);
+                let s = f64::min(r / t / light_speed(), self.envelop_fastest_beta);
                 let f = f64::powf(s, -1.0 / self.envelop_psi) * f64::powf(1.0 - s * s, 0.5 / self.envelop_psi - 1.0);
                 self.envelop_m1 / (4.0 * PI * self.envelop_psi * t) * f
             }
             Zone::Jet => {
-                self.jet_mass_rate_per_steradian()
+                let u_jet = self.engine_u_at(q);
+                let v_jet = u_jet / (1.0 + u_jet * u_jet).sqrt() * light_speed();
+                let launch_time = t - self.engine_delay - r / v_jet;
+                self.jet_mass_rate_per_steradian(q) * self.engine_weight(q, launch_time)
             }
         }
     }
 
     // fn taper(x: f64)
-    fn jet_mass_rate_per_steradian(&self) -> f64 {
-        let engine_gamma = f64::sqrt(1.0 + self.engine_u * self.engine_u);
-        let e = self.engine_strength * self.cloud_mass;
+    fn jet_mass_rate_per_steradian(&self, q: f64) -> f64 {
+        let engine_u = self.engine_u_at(q);
+        let engine_gamma = f64::sqrt(1.0 + engine_u * engine_u);
+        let theta = JetStructure::angle_from_axis(q);
+        let e = self.engine_strength * self.cloud_mass * self.engine_structure.energy_fraction(theta, self.engine_theta);
         let l = e / (4.0 * PI * self.engine_duration);
         l / engine_gamma
     }
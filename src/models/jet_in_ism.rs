@@ -0,0 +1,157 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::traits::InitialModel;
+use super::JetStructure;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Relativistic jet injected directly into an external medium, with no
+ * progenitor star or merger ejecta. The medium is a power law in radius,
+ * rho ~ r^-k: uniform (ISM-like) for k = 0, or wind-like for k = 2. This is
+ * meant for afterglow-phase runs, with the inner boundary placed well outside
+ * the engine.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JetInIsm {
+
+    /// Duration of the engine (s)
+    pub engine_duration: f64,
+
+    /// Isotropic-equivalent energy of the jet on its axis (erg)
+    pub engine_energy: f64,
+
+    /// Engine opening angle (the core angle for structured jets)
+    pub engine_theta: f64,
+
+    /// Engine four-velocity on the jet axis
+    pub engine_u: f64,
+
+    /// Angular structure of the engine: top_hat (the default), gaussian, or
+    /// power_law
+    #[serde(default)]
+    pub engine_structure: JetStructure,
+
+    /// Mass density (g/cm^3) of the external medium at the reference radius
+    pub ambient_density: f64,
+
+    /// Radius (cm) where the external density is given
+    pub reference_radius: f64,
+
+    /// Power-law index k of the external medium, rho ~ r^-k; 0 for a uniform
+    /// medium, 2 for a wind
+    #[serde(default)]
+    pub ambient_index: f64,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for JetInIsm {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.engine_structure.validate()?;
+
+        if self.engine_energy <= 0.0 || self.engine_duration <= 0.0 || self.engine_u <= 0.0 {
+            anyhow::bail!("the engine energy, duration, and four-velocity must be positive")
+        }
+        if self.engine_theta <= 0.0 || self.engine_theta >= 0.5 * PI {
+            anyhow::bail!("must have 0 < engine_theta < pi / 2")
+        }
+        if self.ambient_density <= 0.0 || self.reference_radius <= 0.0 {
+            anyhow::bail!("the ambient density and reference radius must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+
+        let (d, u) = if self.in_jet(r, q, t) {
+            (self.jet_mass_density(r, q), self.engine_u_at(q))
+        } else {
+            (self.ambient_mass_density(r), 0.0)
+        };
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, q) = coordinate;
+
+        if self.in_jet(r, q, t) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl JetInIsm {
+
+    /**
+     * Engine four-velocity at the given polar angle, accounting for the jet
+     * angular structure.
+     *
+     * * `q` - The polar angle theta
+     */
+    pub fn engine_u_at(&self, q: f64) -> f64 {
+        let theta = JetStructure::angle_from_axis(q);
+        self.engine_structure.gamma_beta(self.engine_u, theta, self.engine_theta)
+    }
+
+    /**
+     * Determine whether a point is inside the jet: within the truncation
+     * angle of either pole, and between the material launched when the
+     * engine turned on and when it turned off.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     * * `t` - Time
+     */
+    pub fn in_jet(&self, r: f64, q: f64, t: f64) -> bool {
+        let theta = JetStructure::angle_from_axis(q);
+        let u = self.engine_u_at(q);
+        let v = u / (1.0 + u * u).sqrt() * light_speed();
+
+        theta < self.engine_structure.truncation_angle(self.engine_theta)
+            && r < v * t
+            && r > v * (t - self.engine_duration)
+    }
+
+    /**
+     * Return the mass density (g/cm^3) of the external medium at radius r.
+     */
+    pub fn ambient_mass_density(&self, r: f64) -> f64 {
+        self.ambient_density * (r / self.reference_radius).powf(-self.ambient_index)
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of the jet at radius r and
+     * polar angle q.
+     */
+    fn jet_mass_density(&self, r: f64, q: f64) -> f64 {
+        let theta = JetStructure::angle_from_axis(q);
+        let u = self.engine_u_at(q);
+        let gamma = (1.0 + u * u).sqrt();
+        let e = self.engine_energy * self.engine_structure.energy_fraction(theta, self.engine_theta);
+        let l = e / (4.0 * PI * self.engine_duration);
+        let mdot = l / (gamma * light_speed() * light_speed());
+        mdot / (r * r * u * light_speed())
+    }
+}
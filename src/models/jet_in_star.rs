@@ -1,28 +1,14 @@
 use std::f64::consts::PI;
 use serde::{Serialize, Deserialize};
-use crate::physics::{AnyPrimitive, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::physics::constants::{solar_mass, solar_radius};
 use crate::traits::InitialModel;
+use super::{EngineLuminosity, EnginePulses, JetPrecession, JetStructure, JetTilt};
 
 
 
 
 static UNIFORM_TEMPERATURE: f64 = 1e-10;
-
-// Constants as given in Duffell & MacDayen(2015)
-// source: https://arxiv.org/pdf/1407.8250.pdf
-static R0:                  f64 = 7e10;
-static M0:                  f64 = 2e33;
-static RHO_C:               f64 = 3e7 * M0 / (1.33 * PI * R0 * R0 * R0);
-static R1:                  f64 = 0.0017 * R0;
-static R2:                  f64 = 0.0125 * R0;
-static R3:                  f64 = 0.65   * R0;
-static K1:                  f64 = 3.24;
-static K2:                  f64 = 2.57;
-static N:                   f64 = 16.7;
-static RHO_WIND:            f64 = 1e-9 * M0 / (1.33 * PI * R0 * R0 * R0);
-// static RHO_ENV:             f64 = 1e-7 * M0 / (1.33 * PI * R0 * R0 * R0);
-static R_NOZZ:              f64 = 0.01 * R0; 
-static R_ENV:               f64 = 1.2  * R0;
 static ALPHA:               f64 = 2.5;
 
 
@@ -51,6 +37,31 @@ pub struct JetInStar {
     /// Engine four-velocity
     pub engine_u: f64,
 
+    /// Angular structure of the engine: top_hat (the default), gaussian, or
+    /// power_law. The engine opening angle is the core angle of the
+    /// structured profiles, and the engine four-velocity is the on-axis value.
+    #[serde(default)]
+    pub engine_structure: JetStructure,
+
+    /// Optional effective tilted-jet approximation, alternating the engine
+    /// between the two poles
+    #[serde(default)]
+    pub engine_tilt: Option<JetTilt>,
+
+    /// Optional on/off duty cycle, making the engine fire in pulses
+    #[serde(default)]
+    pub engine_pulses: Option<EnginePulses>,
+
+    /// Time dependence of the engine luminosity: constant (the default),
+    /// magnetar_spin_down, or tabulated
+    #[serde(default)]
+    pub engine_luminosity: EngineLuminosity,
+
+    /// Optional precession of the jet axis around the pole, which makes the
+    /// nozzle wobble in time; cannot be combined with the engine tilt
+    #[serde(default)]
+    pub engine_precession: Option<JetPrecession>,
+
     /// Radius of the Envelope
     pub envelope_radius: f64,
 
@@ -59,6 +70,60 @@ pub struct JetInStar {
 
     /// Hydrogen Volume Filling Factor
     pub volume_factor: f64,
+
+    /// Progenitor length unit R0 (cm); defaults to the solar radius. The
+    /// progenitor profile follows Duffell & MacFadyen (2015),
+    /// https://arxiv.org/pdf/1407.8250.pdf
+    #[serde(default = "JetInStar::default_progenitor_r0")]
+    pub progenitor_r0: f64,
+
+    /// Progenitor central density (g/cm^3); defaults to
+    /// 3e7 M_sun / (1.33 pi R0^3)
+    #[serde(default = "JetInStar::default_progenitor_rho_c")]
+    pub progenitor_rho_c: f64,
+
+    /// Inner break radius of the core density profile, in units of R0
+    #[serde(default = "JetInStar::default_progenitor_r1")]
+    pub progenitor_r1: f64,
+
+    /// Outer break radius of the core density profile, in units of R0
+    #[serde(default = "JetInStar::default_progenitor_r2")]
+    pub progenitor_r2: f64,
+
+    /// Radius of the stellar surface, where the core density vanishes, in
+    /// units of R0
+    #[serde(default = "JetInStar::default_progenitor_r3")]
+    pub progenitor_r3: f64,
+
+    /// Power-law index of the core density profile inside R2
+    #[serde(default = "JetInStar::default_progenitor_k1")]
+    pub progenitor_k1: f64,
+
+    /// Power-law index of the core density profile outside R2
+    #[serde(default = "JetInStar::default_progenitor_k2")]
+    pub progenitor_k2: f64,
+
+    /// Exponent of the core density cutoff (1 - r / R3)^n
+    #[serde(default = "JetInStar::default_progenitor_n")]
+    pub progenitor_n: f64,
+
+    /// Wind density (g/cm^3) at the wind reference radius; defaults to
+    /// 1e-9 M_sun / (1.33 pi R0^3)
+    #[serde(default = "JetInStar::default_wind_density")]
+    pub wind_density: f64,
+
+    /// Envelope density (g/cm^3) at the stellar surface. If omitted, it is
+    /// obtained from the envelope mass, radius, and volume factor.
+    #[serde(default)]
+    pub envelope_density: Option<f64>,
+
+    /// Radius of the wind density normalization, in units of R0
+    #[serde(default = "JetInStar::default_wind_radius")]
+    pub wind_radius: f64,
+
+    /// Radius of the engine nozzle, in units of R0
+    #[serde(default = "JetInStar::default_nozzle_radius")]
+    pub nozzle_radius: f64,
 }
 
 
@@ -81,7 +146,38 @@ pub enum Zone {
 impl InitialModel for JetInStar {
 
     fn validate(&self) -> anyhow::Result<()> {
-        Ok(())
+        if self.progenitor_r0 <= 0.0 || self.progenitor_rho_c <= 0.0 || self.wind_density <= 0.0 {
+            anyhow::bail!("progenitor_r0, progenitor_rho_c, and wind_density must be positive")
+        }
+        if !(0.0 < self.progenitor_r1 && self.progenitor_r1 < self.progenitor_r2 && self.progenitor_r2 < self.progenitor_r3) {
+            anyhow::bail!("must have 0 < progenitor_r1 < progenitor_r2 < progenitor_r3")
+        }
+        if self.progenitor_r3 * self.progenitor_r0 >= self.envelope_radius {
+            anyhow::bail!("the stellar surface progenitor_r3 must be inside the envelope radius")
+        }
+        if self.progenitor_k1 < 0.0 || self.progenitor_k2 < 0.0 || self.progenitor_n < 0.0 {
+            anyhow::bail!("progenitor_k1, progenitor_k2, and progenitor_n must be non-negative")
+        }
+        if self.envelope_density.map_or(false, |d| d <= 0.0) {
+            anyhow::bail!("envelope_density must be positive")
+        }
+        if self.wind_radius <= 0.0 || self.nozzle_radius <= 0.0 {
+            anyhow::bail!("wind_radius and nozzle_radius must be positive")
+        }
+        if let Some(tilt) = &self.engine_tilt {
+            tilt.validate()?;
+        }
+        if let Some(pulses) = &self.engine_pulses {
+            pulses.validate()?;
+        }
+        self.engine_luminosity.validate()?;
+        if let Some(precession) = &self.engine_precession {
+            if self.engine_tilt.is_some() {
+                anyhow::bail!("engine_tilt and engine_precession cannot be used together")
+            }
+            precession.validate()?;
+        }
+        self.engine_structure.validate()
     }
 
     fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
@@ -105,8 +201,8 @@ impl InitialModel for JetInStar {
         match zone {
             Zone::Core     => 1e+0,
             Zone::Jet      => 1e+2,
-            Zone::Envelope => 1e-2 * (r / R3).powf(-2.0),
-            Zone::Wind     => 1e-5 * (r / R_ENV).powf(-2.0),
+            Zone::Envelope => 1e-2 * (r / self.r3()).powf(-2.0),
+            Zone::Wind     => 1e-5 * (r / self.r_env()).powf(-2.0),
         }
     }
 }
@@ -117,32 +213,96 @@ impl InitialModel for JetInStar {
 // ============================================================================
 impl JetInStar
 {
+    fn default_progenitor_r0() -> f64 {
+        solar_radius()
+    }
+    fn default_progenitor_rho_c() -> f64 {
+        3e7 * solar_mass() / (1.33 * PI * solar_radius().powi(3))
+    }
+    fn default_progenitor_r1() -> f64 {
+        0.0017
+    }
+    fn default_progenitor_r2() -> f64 {
+        0.0125
+    }
+    fn default_progenitor_r3() -> f64 {
+        0.65
+    }
+    fn default_progenitor_k1() -> f64 {
+        3.24
+    }
+    fn default_progenitor_k2() -> f64 {
+        2.57
+    }
+    fn default_progenitor_n() -> f64 {
+        16.7
+    }
+    fn default_wind_density() -> f64 {
+        1e-9 * solar_mass() / (1.33 * PI * solar_radius().powi(3))
+    }
+    fn default_wind_radius() -> f64 {
+        1.2
+    }
+    fn default_nozzle_radius() -> f64 {
+        0.01
+    }
+
+    fn r1(&self) -> f64 {
+        self.progenitor_r1 * self.progenitor_r0
+    }
+    fn r2(&self) -> f64 {
+        self.progenitor_r2 * self.progenitor_r0
+    }
+    fn r3(&self) -> f64 {
+        self.progenitor_r3 * self.progenitor_r0
+    }
+    fn r_env(&self) -> f64 {
+        self.wind_radius * self.progenitor_r0
+    }
+    fn r_nozz(&self) -> f64 {
+        self.nozzle_radius * self.progenitor_r0
+    }
+
     /**
      * The comoving mass density in g/cc
      */
     fn mass_density(&self, r: f64, q: f64, t: f64) -> f64{
         let zone      = self.zone(r, q, t);
-        let num       = RHO_C * ((1.0 - r / R3)).powf(N);
-        let denom     = 1.0 + (r / R1).powf(K1) / (1.0 + (r / R2).powf(K2));
+        let r3        = self.r3();
+        let num       = self.progenitor_rho_c * ((1.0 - r / r3)).powf(self.progenitor_n);
+        let denom     = 1.0 + (r / self.r1()).powf(self.progenitor_k1) / (1.0 + (r / self.r2()).powf(self.progenitor_k2));
         let core_zone = num / denom;
-        let rho_env   = self.envelope_mass / (4.0 * PI * self.envelope_radius.powi(2) * (self.envelope_radius - R3) * self.volume_factor);
+        let rho_env   = self.envelope_density.unwrap_or_else(|| {
+            self.envelope_mass / (4.0 * PI * self.envelope_radius.powi(2) * (self.envelope_radius - r3) * self.volume_factor)
+        });
 
         match zone {
             Zone::Core => {
-                core_zone + rho_env * (r/R3).powf(-2.0)
+                core_zone + rho_env * (r/r3).powf(-2.0)
             }
             Zone::Envelope => {
-                rho_env *(r/R3).powf(-ALPHA)
+                rho_env *(r/r3).powf(-ALPHA)
             }
             Zone::Jet => {
-                self.jet_mass_rate_per_steradian(r, q) / (r * r * self.engine_u * LIGHT_SPEED)
+                let t0 = self.launch_time(r, t);
+                let u_jet = self.engine_u_at(q, t0);
+                let v_jet = u_jet / (1.0 + u_jet * u_jet).sqrt() * light_speed();
+                let w = self.engine_weight(q, t - r / v_jet);
+                w * self.jet_mass_rate_per_steradian(r, q, t0) / (r * r * u_jet * light_speed())
             }
             Zone::Wind => {
-                RHO_WIND * (r/R_ENV).powf(-2.0)
+                self.wind_density * (r/self.r_env()).powf(-2.0)
             }
         }
     }
 
+    /**
+     * The radius of the stellar surface, where the core density vanishes
+     */
+    pub fn stellar_radius(&self) -> f64 {
+        self.r3()
+    }
+
     /**
      * Dimensionless jet velocity: v_jet / c
      */
@@ -151,12 +311,92 @@ impl JetInStar
     }
 
     /**
-     * Determine if a polar angle is within theta_jet of either pole.
+     * Time when material at the given radius and time would have been
+     * launched, at the on-axis engine velocity. This is used to find the
+     * orientation of a precessing nozzle when the material was launched.
+     *
+     * * `r` - The radius
+     * * `t` - The time
+     */
+    fn launch_time(&self, r: f64, t: f64) -> f64 {
+        t - r / (self.engine_beta() * light_speed())
+    }
+
+    /**
+     * Angle from the jet axis at the given polar angle and launch time,
+     * accounting for the precession of the axis if there is one, but not the
+     * jet tilt.
      *
      * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
      */
-    pub fn in_nozzle(&self, q: f64) -> bool {
-        q < self.engine_theta || q > PI - self.engine_theta
+    fn precessed_angle(&self, q: f64, t: f64) -> f64 {
+        match &self.engine_precession {
+            Some(precession) => precession.angle_from_axis(q, t),
+            None => JetStructure::angle_from_axis(q),
+        }
+    }
+
+    /**
+     * Angle from the jet axis at the given polar angle and launch time,
+     * accounting for the jet tilt or precession if there is one.
+     *
+     * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
+     */
+    fn engine_angle(&self, q: f64, t: f64) -> f64 {
+        match &self.engine_tilt {
+            Some(tilt) => tilt.angle_from_axis(q),
+            None => self.precessed_angle(q, t),
+        }
+    }
+
+    /**
+     * Factor multiplying the jet power at the given polar angle, for
+     * material launched at time t after the engine turned on.
+     *
+     * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
+     */
+    fn engine_weight(&self, q: f64, t: f64) -> f64 {
+        let tilt = match &self.engine_tilt {
+            Some(tilt) => tilt.pole_weight(q, t, self.engine_theta),
+            None => 1.0,
+        };
+        let precession = match &self.engine_precession {
+            Some(precession) => precession.power_weight(t, self.engine_theta),
+            None => 1.0,
+        };
+        let pulses = match &self.engine_pulses {
+            Some(pulses) => pulses.power_weight(t),
+            None => 1.0,
+        };
+        let isotropic_luminosity = self.engine_energy / self.engine_duration;
+        let luminosity = self.engine_luminosity.power_weight(t, isotropic_luminosity, &self.engine_structure, self.engine_theta);
+        tilt * precession * pulses * luminosity
+    }
+
+    /**
+     * Engine four-velocity at the given polar angle, accounting for the jet
+     * angular structure, and a tabulated Lorentz factor if there is one.
+     *
+     * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
+     */
+    pub fn engine_u_at(&self, q: f64, t: f64) -> f64 {
+        let u0 = self.engine_luminosity.gamma_beta(t).unwrap_or(self.engine_u);
+        self.engine_structure.gamma_beta(u0, self.engine_angle(q, t), self.engine_theta)
+    }
+
+    /**
+     * Determine if a polar angle is within the jet truncation angle of either
+     * pole (theta_jet for a top-hat jet), or of the precessing jet axis.
+     *
+     * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
+     */
+    pub fn in_nozzle(&self, q: f64, t: f64) -> bool {
+        self.engine_angle(q, t) < self.engine_structure.truncation_angle(self.engine_theta)
     }
 
     /**
@@ -165,7 +405,7 @@ impl JetInStar
      * * `t` - Time
      */
     pub fn get_jet_head(&self, t: f64) -> f64 {
-        let v_jet = self.engine_beta() * LIGHT_SPEED;
+        let v_jet = self.engine_beta() * light_speed();
         v_jet * t
     }
 
@@ -177,14 +417,16 @@ impl JetInStar
      * * `t` - Time
      */
     pub fn zone(&self, r: f64, q: f64, t: f64) -> Zone {
-        let v_jet = self.engine_beta() * LIGHT_SPEED;
+        let t0 = self.launch_time(r, t);
+        let u_jet = self.engine_u_at(q, t0);
+        let v_jet = u_jet / (1.0 + u_jet * u_jet).sqrt() * light_speed();
         let r_jet_head = v_jet * t;
 
-        if self.in_nozzle(q) && r < r_jet_head {
+        if self.in_nozzle(q, t0) && r < r_jet_head && self.engine_weight(q, t - r / v_jet) > 0.0 {
             Zone::Jet
-        } else if r < R3 {
+        } else if r < self.r3() {
             Zone::Core
-        } else if R3 < r && r < self.envelope_radius {
+        } else if self.r3() < r && r < self.envelope_radius {
             Zone:: Envelope
         } else {
             Zone::Wind
@@ -200,7 +442,7 @@ impl JetInStar
      */
     pub fn gamma_beta(&self, r: f64, q: f64, t: f64) -> f64 {
         match self.zone(r, q, t) {
-            Zone::Jet => self.engine_u,
+            Zone::Jet => self.engine_u_at(q, self.launch_time(r, t)),
             _ => 0.0
 
         }
@@ -208,30 +450,94 @@ impl JetInStar
 
     /**
      * Return the fictitious nozzle function as described in
-     * Duffel & MAcFadyen (2015)
+     * Duffel & MAcFadyen (2015). With precession, the angular factor is
+     * centered on the jet axis at the launch time. For a structured jet, the
+     * angular factor of the nozzle is replaced by the energy profile of the
+     * structure, and the normalization by its integral over solid angle.
      * 
      * * `r' - The radius
      * * `q` - The polar angle theta
+     * * `t` - The launch time, relative to the engine start
      */
-    pub fn nozzle_function(&self, r: f64, q: f64) -> f64 {
+    pub fn nozzle_function(&self, r: f64, q: f64, t: f64) -> f64 {
         // Normalize the Nozzle Radius
-        let r0 = R_NOZZ/R0;
-        let q2 = self.engine_theta.powi(2);
+        let r_nozz = self.r_nozz();
+        let r0 = self.nozzle_radius;
+        let theta = self.precessed_angle(q, t);
 
-        // Nozzle Function Normalization Factor
-        // N0 = 4 * PI * r0^3 * exp(-2/theta0^2) * theta0^2
-        let n_0 =  4.0 * PI * r0 * r0 * r0 * (1.0 - (-2.0 / q2).exp()) * q2;
+        // Nozzle Function Normalization Factor of the radial part; the
+        // angular factor is normalized over solid angle on its own
+        let n_0 = 2.0 * r0 * r0 * r0;
 
-        // Nozzle Function: g = (r/r0) * exp(-(r/r0)^2) * exp[(cos^2(q) - 1)/theta0^2] / N0
-        let g = (r / R_NOZZ) * f64::exp(-(r / R_NOZZ).powf(2.0) / 2.0) * f64::exp((q.cos().powf(2.0) - 1.0) / q2);
+        // Nozzle Function: g = (r/r0) * exp(-(r/r0)^2) * A(q) / N0
+        let g = (r / r_nozz) * f64::exp(-(r / r_nozz).powf(2.0) / 2.0) * self.nozzle_angular_profile(theta);
 
         g / n_0
     }
 
-    fn jet_mass_rate_per_steradian(&self, r: f64, q: f64) -> f64 {
-        let engine_gamma = f64::sqrt(1.0 + self.engine_u * self.engine_u);
+    /**
+     * Return the fraction of the engine energy per steradian at the given
+     * angle from the jet axis, which integrates to one over the sphere. For
+     * a top-hat jet this is the angular factor of the nozzle function,
+     * exp[(cos^2(q) - 1)/theta0^2], and otherwise it is the energy profile of
+     * the jet structure.
+     *
+     * * `theta` - The angle from the jet axis
+     */
+    pub fn nozzle_angular_profile(&self, theta: f64) -> f64 {
+        let q2 = self.engine_theta.powi(2);
+
+        match self.engine_structure {
+            JetStructure::TopHat => {
+                // N = 2 * PI * theta0^2 * (1 - exp(-2/theta0^2))
+                let n = 2.0 * PI * q2 * (1.0 - (-2.0 / q2).exp());
+                f64::exp((theta.cos().powf(2.0) - 1.0) / q2) / n
+            }
+            _ => {
+                let n = 4.0 * PI * self.engine_structure.beaming_fraction(self.engine_theta);
+                self.engine_structure.energy_fraction(theta, self.engine_theta) / n
+            }
+        }
+    }
+
+    fn jet_mass_rate_per_steradian(&self, r: f64, q: f64, t: f64) -> f64 {
+        let engine_u = self.engine_u_at(q, t);
+        let engine_gamma = f64::sqrt(1.0 + engine_u * engine_u);
         let e = self.engine_energy;
-        let l = self.nozzle_function(r, q) * e / (4.0 * PI * self.engine_duration);
-        l / (engine_gamma * LIGHT_SPEED * LIGHT_SPEED)
+        let l = self.nozzle_function(r, q, t) * e / (4.0 * PI * self.engine_duration);
+        l / (engine_gamma * light_speed() * light_speed())
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jet_in_star(engine_structure: JetStructure) -> JetInStar {
+        let config: serde_yaml::Value = serde_yaml::from_str(include_str!("../../setups/jet_in_star.yaml")).unwrap();
+        let model: JetInStar = serde_yaml::from_value(config["model"]["jet_in_star"].clone()).unwrap();
+        JetInStar { engine_structure, ..model }
+    }
+
+    #[test]
+    fn injected_energy_matches_the_engine_energy_for_each_structure() {
+        let structures = vec![
+            JetStructure::TopHat,
+            JetStructure::Gaussian,
+            JetStructure::PowerLaw { energy_index: 2.0, lorentz_factor_index: 1.0 },
+        ];
+        for structure in structures {
+            let model = jet_in_star(structure);
+            let num_steps = 100000;
+            let dq = PI / num_steps as f64;
+            let energy: f64 = (0..num_steps).map(|i| {
+                let q = (i as f64 + 0.5) * dq;
+                model.engine_energy * model.nozzle_angular_profile(model.precessed_angle(q, 0.0)) * 2.0 * PI * q.sin() * dq
+            }).sum();
+            assert!((energy / model.engine_energy - 1.0).abs() < 1e-2);
+        }
     }
 }
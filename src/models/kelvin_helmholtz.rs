@@ -0,0 +1,96 @@
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::AnyPrimitive;
+
+
+
+
+/**
+ * Kelvin-Helmholtz test: a shear layer at constant radius, with the inner and
+ * outer fluids moving in opposite polar directions, and a radial velocity
+ * perturbation seeded at the interface. The pressure is uniform. Velocities
+ * are four-velocities (gamma-beta) for relativistic hydrodynamics.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KelvinHelmholtz {
+
+    /// Radius of the shear layer (cm)
+    pub interface_radius: f64,
+
+    /// Thickness of the smoothed shear layer (cm)
+    pub layer_thickness: f64,
+
+    /// Mass density of the fluid inside the interface
+    pub inner_density: f64,
+
+    /// Mass density of the fluid outside the interface
+    pub outer_density: f64,
+
+    /// Uniform gas pressure
+    pub pressure: f64,
+
+    /// Polar velocity of the inner fluid; the outer fluid moves at the
+    /// opposite velocity
+    pub shear_velocity: f64,
+
+    /// Amplitude of the radial velocity perturbation
+    pub perturbation_amplitude: f64,
+
+    /// Number of perturbation wavelengths from pole to pole
+    pub perturbation_mode: usize,
+}
+
+
+
+
+// ============================================================================
+impl KelvinHelmholtz {
+
+    /**
+     * Return the smoothed step function which goes from 1 well inside the
+     * interface to 0 well outside it.
+     */
+    fn inner_fraction(&self, r: f64) -> f64 {
+        0.5 * (1.0 - f64::tanh((r - self.interface_radius) / self.layer_thickness))
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for KelvinHelmholtz {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.interface_radius <= 0.0 || self.layer_thickness <= 0.0 {
+            anyhow::bail!("the interface radius and layer thickness must be positive")
+        }
+        if self.inner_density <= 0.0 || self.outer_density <= 0.0 || self.pressure <= 0.0 {
+            anyhow::bail!("the densities and pressure must be positive")
+        }
+        if self.perturbation_mode == 0 {
+            anyhow::bail!("the perturbation mode must be at least 1")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+        let f = self.inner_fraction(r);
+        let x = (r - self.interface_radius) / self.layer_thickness;
+        let k = 2.0 * self.perturbation_mode as f64;
+
+        AnyPrimitive {
+            velocity_r: self.perturbation_amplitude * f64::sin(k * q) * f64::exp(-x * x),
+            velocity_q: self.shear_velocity * (2.0 * f - 1.0),
+            mass_density: self.inner_density * f + self.outer_density * (1.0 - f),
+            gas_pressure: self.pressure,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        self.inner_fraction(r)
+    }
+}
@@ -1,7 +1,7 @@
 use std::f64::consts::PI;
 use serde::{Serialize, Deserialize};
 use crate::traits::InitialModel;
-use crate::physics::{AnyPrimitive, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, light_speed};
 
 const UNIFORM_TEMPERATURE: f64 = 1e-3;
 
@@ -64,12 +64,12 @@ impl KineticBomb {
 impl InitialModel for KineticBomb {
 
     fn validate(&self) -> anyhow::Result<()> {
-        if self.shell_velocity() > 0.25 * LIGHT_SPEED {
+        if self.shell_velocity() > 0.25 * light_speed() {
             anyhow::bail!{"
              The shell is moving faster (v/c = {}) than 0.25 c, but
              this problem assumes Newtonian expressions for the
              kinetic energy. Consider reducing the kinetic energy or
-             increasing the shell mass.", self.shell_velocity() / LIGHT_SPEED}
+             increasing the shell mass.", self.shell_velocity() / light_speed()}
         } else {
             Ok(())
         }
@@ -85,7 +85,7 @@ impl InitialModel for KineticBomb {
             let p = d * UNIFORM_TEMPERATURE;
 
             AnyPrimitive {
-                velocity_r: v / LIGHT_SPEED,
+                velocity_r: v / light_speed(),
                 velocity_q: 0.0,
                 mass_density: d,
                 gas_pressure: p,
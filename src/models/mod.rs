@@ -1,11 +1,51 @@
+mod engine;
 mod jet_in_cloud;
 mod halo_kilonova;
 mod jet_in_star;
 mod wind_shock;
 mod kinetic_bomb;
+mod homologous_ejecta;
+mod wind;
+mod tabulated_progenitor;
+mod two_component_jet;
+mod cloud_shock;
+mod ejecta_csm;
+mod accretion_torus;
+mod spherical_blast;
+mod kelvin_helmholtz;
+mod rayleigh_taylor;
+mod jet_in_ism;
+mod disk_wind_jet;
+mod colliding_winds;
+mod shell_collision;
+mod composite;
+mod custom;
+mod perturbed;
+mod refreshed_shell;
+mod extended_envelope;
 
+pub use engine::{EngineLuminosity, EnginePulses, JetPrecession, JetStructure, JetTilt};
 pub use jet_in_cloud::JetInCloud;
 pub use halo_kilonova::HaloKilonova;
 pub use jet_in_star::JetInStar;
 pub use wind_shock::WindShock;
 pub use kinetic_bomb::KineticBomb;
+pub use homologous_ejecta::HomologousEjecta;
+pub use wind::Wind;
+pub use tabulated_progenitor::TabulatedProgenitor;
+pub use two_component_jet::TwoComponentJet;
+pub use cloud_shock::CloudShock;
+pub use ejecta_csm::EjectaCsm;
+pub use accretion_torus::AccretionTorus;
+pub use spherical_blast::SphericalBlast;
+pub use kelvin_helmholtz::KelvinHelmholtz;
+pub use rayleigh_taylor::RayleighTaylor;
+pub use jet_in_ism::JetInIsm;
+pub use disk_wind_jet::DiskWindJet;
+pub use colliding_winds::CollidingWinds;
+pub use shell_collision::ShellCollision;
+pub use composite::{Composite, Layer, Precedence};
+pub use custom::Custom;
+pub use perturbed::Perturbed;
+pub use refreshed_shell::RefreshedShell;
+pub use extended_envelope::ExtendedEnvelope;
@@ -0,0 +1,178 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::app::AnyModel;
+use crate::physics::AnyPrimitive;
+use crate::traits::{BoundaryEdge, InitialModel};
+
+
+
+
+/**
+ * Reproducible random density perturbations layered on top of another model.
+ * The perturbation is a superposition of plane waves in the (log r, theta)
+ * plane, with random phases drawn from the seed, and a power-law power
+ * spectrum between the minimum and maximum wavenumbers. Wavenumbers count
+ * wavelengths from pole to pole in theta, or per factor of e^pi in radius. The
+ * field is a continuous function of position, so it does not depend on the
+ * mesh resolution or block decomposition. The mass density of the underlying
+ * model is multiplied by exp(delta), where delta is the perturbation field,
+ * and the pressure, velocity, scalar, boundary data, and accelerations are
+ * unchanged.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Perturbed {
+
+    /// The model whose density is perturbed
+    pub model: Box<AnyModel>,
+
+    /// RMS amplitude of the perturbation field delta
+    pub amplitude: f64,
+
+    /// Power-law index of the power spectrum, P(k) ~ k^-index; zero (the
+    /// default) is white noise
+    #[serde(default)]
+    pub spectral_index: f64,
+
+    /// Smallest wavenumber included
+    #[serde(default = "Perturbed::default_min_wavenumber")]
+    pub min_wavenumber: f64,
+
+    /// Largest wavenumber included
+    #[serde(default = "Perturbed::default_max_wavenumber")]
+    pub max_wavenumber: f64,
+
+    /// Seed for the random phases; the same seed gives the same field
+    #[serde(default)]
+    pub seed: u64,
+}
+
+
+
+
+/**
+ * A single plane wave in the perturbation field
+ */
+struct Mode {
+    kr: f64,
+    kq: f64,
+    phase: f64,
+    amplitude: f64,
+}
+
+
+
+
+// ============================================================================
+impl Perturbed {
+
+    fn default_min_wavenumber() -> f64 {
+        1.0
+    }
+
+    fn default_max_wavenumber() -> f64 {
+        16.0
+    }
+
+    /**
+     * Return the plane waves making up the perturbation field. Wave vectors
+     * lie on an integer lattice in the half-plane kq >= 0, and each has a
+     * phase drawn from the seeded generator. The amplitudes are normalized so
+     * that the field has the requested RMS amplitude.
+     */
+    fn modes(&self) -> Vec<Mode> {
+        let kmax = self.max_wavenumber.floor() as i64;
+        let mut state = self.seed;
+        let mut modes = Vec::new();
+
+        for kq in 0..=kmax {
+            for kr in -kmax..=kmax {
+                let k = ((kr * kr + kq * kq) as f64).sqrt();
+                let phase = 2.0 * PI * uniform(&mut state);
+
+                if (kq == 0 && kr <= 0) || k < self.min_wavenumber || k > self.max_wavenumber {
+                    continue
+                }
+                modes.push(Mode {
+                    kr: kr as f64,
+                    kq: kq as f64,
+                    phase,
+                    amplitude: k.powf(-0.5 * self.spectral_index),
+                })
+            }
+        }
+        let rms = (modes.iter().map(|m| 0.5 * m.amplitude * m.amplitude).sum::<f64>()).sqrt();
+
+        for mode in &mut modes {
+            mode.amplitude *= self.amplitude / rms;
+        }
+        modes
+    }
+
+    /**
+     * Evaluate the perturbation field at the given coordinate.
+     */
+    pub fn delta(&self, coordinate: (f64, f64)) -> f64 {
+        let (r, q) = coordinate;
+        let x = r.ln();
+
+        self.modes()
+            .iter()
+            .map(|m| m.amplitude * (2.0 * (m.kr * x + m.kq * q) + m.phase).cos())
+            .sum()
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for Perturbed {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.amplitude < 0.0 {
+            anyhow::bail!("perturbation amplitude must be non-negative")
+        }
+        if self.min_wavenumber <= 0.0 || self.max_wavenumber < self.min_wavenumber.max(1.0) {
+            anyhow::bail!("must have 0 < min_wavenumber <= max_wavenumber, and max_wavenumber >= 1")
+        }
+        self.model.validate()
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+        let mut primitive = self.model.primitive_at(coordinate, time);
+
+        if self.amplitude > 0.0 {
+            primitive.mass_density *= self.delta(coordinate).exp();
+        }
+        primitive
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), time: f64) -> f64 {
+        self.model.scalar_at(coordinate, time)
+    }
+
+    fn boundary_primitive_at(&self, edge: BoundaryEdge, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+        self.model.boundary_primitive_at(edge, coordinate, time)
+    }
+
+    fn acceleration_at(&self, coordinate: (f64, f64), time: f64) -> (f64, f64) {
+        self.model.acceleration_at(coordinate, time)
+    }
+}
+
+
+
+
+/**
+ * Return a uniform random number in [0, 1), advancing the state of a
+ * SplitMix64 generator.
+ */
+fn uniform(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z = z ^ (z >> 31);
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,117 @@
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::AnyPrimitive;
+
+
+
+
+/**
+ * Rayleigh-Taylor test: a dense fluid outside a spherical interface, resting
+ * on a light fluid inside it, under a uniform acceleration pointing toward
+ * the origin. The smoothed density profile is in hydrostatic equilibrium with
+ * the imposed acceleration, and a radial velocity perturbation is seeded at
+ * the interface. The setup is Newtonian; the interface pressure must be large
+ * enough that the pressure stays positive out to the edge of the domain.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RayleighTaylor {
+
+    /// Radius of the interface (cm)
+    pub interface_radius: f64,
+
+    /// Thickness of the smoothed interface (cm)
+    pub layer_thickness: f64,
+
+    /// Mass density of the light fluid inside the interface (g/cm^3)
+    pub light_density: f64,
+
+    /// Mass density of the heavy fluid outside the interface (g/cm^3)
+    pub heavy_density: f64,
+
+    /// Gas pressure at the interface (erg/cm^3)
+    pub interface_pressure: f64,
+
+    /// Magnitude of the acceleration toward the origin (cm/s^2)
+    pub acceleration: f64,
+
+    /// Amplitude of the radial velocity perturbation (cm/s)
+    pub perturbation_amplitude: f64,
+
+    /// Number of perturbation wavelengths from pole to pole
+    pub perturbation_mode: usize,
+}
+
+
+
+
+// ============================================================================
+impl RayleighTaylor {
+
+    /**
+     * Return the smoothed step function which goes from 1 well inside the
+     * interface to 0 well outside it.
+     */
+    fn light_fraction(&self, r: f64) -> f64 {
+        0.5 * (1.0 - f64::tanh((r - self.interface_radius) / self.layer_thickness))
+    }
+
+    /**
+     * Return the hydrostatic gas pressure at radius r. The column mass
+     * between the interface and r is integrated exactly for the tanh density
+     * profile.
+     */
+    pub fn pressure(&self, r: f64) -> f64 {
+        let x = (r - self.interface_radius) / self.layer_thickness;
+        let d_mean = 0.5 * (self.heavy_density + self.light_density);
+        let d_diff = 0.5 * (self.heavy_density - self.light_density);
+        let column = d_mean * (r - self.interface_radius) + d_diff * self.layer_thickness * f64::cosh(x).ln();
+        self.interface_pressure - self.acceleration * column
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for RayleighTaylor {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.interface_radius <= 0.0 || self.layer_thickness <= 0.0 {
+            anyhow::bail!("the interface radius and layer thickness must be positive")
+        }
+        if self.light_density <= 0.0 || self.heavy_density <= 0.0 || self.interface_pressure <= 0.0 {
+            anyhow::bail!("the densities and interface pressure must be positive")
+        }
+        if self.acceleration < 0.0 {
+            anyhow::bail!("the acceleration must be non-negative (it points toward the origin)")
+        }
+        if self.perturbation_mode == 0 {
+            anyhow::bail!("the perturbation mode must be at least 1")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+        let f = self.light_fraction(r);
+        let x = (r - self.interface_radius) / self.layer_thickness;
+        let k = 2.0 * self.perturbation_mode as f64;
+
+        AnyPrimitive {
+            velocity_r: self.perturbation_amplitude * f64::cos(k * q) * f64::exp(-x * x),
+            velocity_q: 0.0,
+            mass_density: self.light_density * f + self.heavy_density * (1.0 - f),
+            gas_pressure: self.pressure(r),
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        1.0 - self.light_fraction(r)
+    }
+
+    fn acceleration_at(&self, _coordinate: (f64, f64), _t: f64) -> (f64, f64) {
+        (-self.acceleration, 0.0)
+    }
+}
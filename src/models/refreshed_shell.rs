@@ -0,0 +1,137 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::traits::InitialModel;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Refreshed shocks: a cold, spherical, freely coasting shell launched from
+ * the origin at t = 0, whose kinetic energy is stratified in four-velocity as
+ * E(>u) = E (u / u_min)^-s between the minimum and maximum four-velocities.
+ * Once the fastest material decelerates in the ambient medium, slower shells
+ * catch up with the forward shock and re-energize it. The scalar is the
+ * log10 of the four-velocity of the shell material, which shows where each
+ * layer has ended up, and zero in the ambient medium.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefreshedShell {
+
+    /// Isotropic-equivalent kinetic energy (erg) of the whole shell
+    pub energy: f64,
+
+    /// Four-velocity (gamma-beta) of the slowest shell material
+    pub min_u: f64,
+
+    /// Four-velocity (gamma-beta) of the fastest shell material
+    pub max_u: f64,
+
+    /// Index s of the energy stratification, E(>u) ~ u^-s
+    pub energy_index: f64,
+
+    /// Ambient mass density (g/cm^3) at the reference radius
+    pub ambient_density: f64,
+
+    /// Radius (cm) where the ambient density is given
+    pub reference_radius: f64,
+
+    /// Power-law index of the ambient medium, rho ~ r^-index
+    #[serde(default)]
+    pub ambient_index: f64,
+}
+
+
+
+
+// ============================================================================
+impl RefreshedShell {
+
+    /**
+     * Return the kinetic energy (erg) in material faster than the given
+     * four-velocity, within the shell.
+     */
+    pub fn energy_above(&self, u: f64) -> f64 {
+        let u = u.max(self.min_u).min(self.max_u);
+        let e0 = self.energy / (1.0 - (self.max_u / self.min_u).powf(-self.energy_index));
+        e0 * ((u / self.min_u).powf(-self.energy_index) - (self.max_u / self.min_u).powf(-self.energy_index))
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of the shell material with
+     * four-velocity u, at radius r and time t, from the mass per unit
+     * four-velocity dM/du = (dE/du) / ((gamma - 1) c^2).
+     */
+    fn shell_mass_density(&self, u: f64, r: f64, t: f64) -> f64 {
+        let s = self.energy_index;
+        let gamma = (1.0 + u * u).sqrt();
+        let e0 = self.energy / (1.0 - (self.max_u / self.min_u).powf(-s));
+        let de_du = s * e0 / self.min_u * (u / self.min_u).powf(-s - 1.0);
+        let dm_du = de_du / ((gamma - 1.0) * light_speed() * light_speed());
+        let du_dr = gamma.powi(3) / (light_speed() * t);
+        dm_du * du_dr / (4.0 * PI * r * r) / gamma
+    }
+
+    /**
+     * Return the four-velocity of freely coasting material at radius r and
+     * time t, if it is within the shell.
+     */
+    fn shell_u(&self, r: f64, t: f64) -> Option<f64> {
+        let b = r / (light_speed() * t);
+
+        if b >= 1.0 {
+            return None
+        }
+        let u = b / (1.0 - b * b).sqrt();
+
+        if u >= self.min_u && u < self.max_u {
+            Some(u)
+        } else {
+            None
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for RefreshedShell {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.energy <= 0.0 || self.energy_index <= 0.0 {
+            anyhow::bail!("the shell energy and energy index must be positive")
+        }
+        if self.min_u <= 0.0 || self.max_u <= self.min_u {
+            anyhow::bail!("must have 0 < min_u < max_u")
+        }
+        if self.ambient_density <= 0.0 || self.reference_radius <= 0.0 {
+            anyhow::bail!("the ambient density and reference radius must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+
+        let (d, u) = match self.shell_u(r, t) {
+            Some(u) => (self.shell_mass_density(u, r, t), u),
+            None => (self.ambient_density * (r / self.reference_radius).powf(-self.ambient_index), 0.0),
+        };
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        self.shell_u(r, t).map_or(0.0, f64::log10)
+    }
+}
@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::traits::InitialModel;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Internal shocks: two cold, spherical shells launched one after the other
+ * from the origin, the second faster than the first, into a tenuous power-law
+ * medium. Each shell is a steady outflow lasting the shell duration. The fast
+ * shell catches up with the slow one at the collision time, and the
+ * dissipation in the resulting forward and reverse shocks can be followed with
+ * the relativistic solver. The scalar is 1 in the slow shell, 2 in the fast
+ * shell, and 0 in the ambient medium.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShellCollision {
+
+    /// Duration (s) of the outflow making up each shell
+    pub shell_duration: f64,
+
+    /// Isotropic-equivalent kinetic energy of the slow shell (erg)
+    pub slow_energy: f64,
+
+    /// Four-velocity (gamma-beta) of the slow shell
+    pub slow_u: f64,
+
+    /// Isotropic-equivalent kinetic energy of the fast shell (erg)
+    pub fast_energy: f64,
+
+    /// Four-velocity (gamma-beta) of the fast shell
+    pub fast_u: f64,
+
+    /// Time (s) between launching the slow and fast shells; must be at least
+    /// the shell duration, so the shells start out separated
+    pub launch_delay: f64,
+
+    /// Ambient mass density (g/cm^3) at the reference radius
+    pub ambient_density: f64,
+
+    /// Radius (cm) where the ambient density is given
+    pub reference_radius: f64,
+
+    /// Power-law index of the ambient medium, rho ~ r^-index
+    #[serde(default)]
+    pub ambient_index: f64,
+}
+
+
+
+
+/**
+ * Different space-time zones in the setup
+ */
+pub enum Zone {
+    SlowShell,
+    FastShell,
+    Ambient,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for ShellCollision {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.shell_duration <= 0.0 || self.slow_energy <= 0.0 || self.fast_energy <= 0.0 {
+            anyhow::bail!("the shell duration and energies must be positive")
+        }
+        if self.slow_u <= 0.0 || self.fast_u <= self.slow_u {
+            anyhow::bail!("must have 0 < slow_u < fast_u")
+        }
+        if self.launch_delay < self.shell_duration {
+            anyhow::bail!("the launch delay must be at least the shell duration")
+        }
+        if self.ambient_density <= 0.0 || self.reference_radius <= 0.0 {
+            anyhow::bail!("the ambient density and reference radius must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+
+        let (d, u) = match self.zone(r, t) {
+            Zone::SlowShell => (self.shell_mass_density(r, self.slow_energy, self.slow_u), self.slow_u),
+            Zone::FastShell => (self.shell_mass_density(r, self.fast_energy, self.fast_u), self.fast_u),
+            Zone::Ambient   => (self.ambient_density * (r / self.reference_radius).powf(-self.ambient_index), 0.0),
+        };
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: d * UNIFORM_TEMPERATURE,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, _q) = coordinate;
+
+        match self.zone(r, t) {
+            Zone::SlowShell => 1.0,
+            Zone::FastShell => 2.0,
+            Zone::Ambient   => 0.0,
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl ShellCollision {
+
+    fn speed(u: f64) -> f64 {
+        u / (1.0 + u * u).sqrt() * light_speed()
+    }
+
+    /**
+     * Determine the zone for a given radius and time, from the time when the
+     * material there would have been launched.
+     *
+     * * `r` - Radius
+     * * `t` - Time
+     */
+    pub fn zone(&self, r: f64, t: f64) -> Zone {
+        let launched = |u: f64, start: f64| {
+            let launch_time = t - r / Self::speed(u);
+            launch_time > start && launch_time < start + self.shell_duration
+        };
+
+        if launched(self.fast_u, self.launch_delay) {
+            Zone::FastShell
+        } else if launched(self.slow_u, 0.0) {
+            Zone::SlowShell
+        } else {
+            Zone::Ambient
+        }
+    }
+
+    /**
+     * The time (s) when the head of the fast shell reaches the tail of the
+     * slow shell.
+     */
+    pub fn collision_time(&self) -> f64 {
+        let v1 = Self::speed(self.slow_u);
+        let v2 = Self::speed(self.fast_u);
+        (v2 * self.launch_delay - v1 * self.shell_duration) / (v2 - v1)
+    }
+
+    /**
+     * The radius (cm) where the head of the fast shell reaches the tail of
+     * the slow shell.
+     */
+    pub fn collision_radius(&self) -> f64 {
+        Self::speed(self.fast_u) * (self.collision_time() - self.launch_delay)
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of a shell with the given
+     * isotropic-equivalent energy and four-velocity, at radius r.
+     */
+    fn shell_mass_density(&self, r: f64, e: f64, u: f64) -> f64 {
+        let gamma = (1.0 + u * u).sqrt();
+        let l = e / self.shell_duration;
+        let mdot = l / ((gamma - 1.0) * light_speed() * light_speed());
+        mdot / (4.0 * PI * r * r * u * light_speed())
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::AnyPrimitive;
+
+
+
+
+/**
+ * Spherical Riemann problem: a uniform, high-pressure sphere at rest,
+ * embedded in a uniform, low-pressure medium. With relativistic
+ * hydrodynamics this is the standard relativistic spherical blast wave test.
+ * Pressures are in units of rho c^2 for relativistic hydrodynamics.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SphericalBlast {
+
+    /// Initial radius of the high-pressure sphere (cm)
+    pub blast_radius: f64,
+
+    /// Mass density inside the blast radius
+    pub inner_density: f64,
+
+    /// Gas pressure inside the blast radius
+    pub inner_pressure: f64,
+
+    /// Mass density outside the blast radius
+    pub outer_density: f64,
+
+    /// Gas pressure outside the blast radius
+    pub outer_pressure: f64,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for SphericalBlast {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.blast_radius <= 0.0 {
+            anyhow::bail!("the blast radius must be positive")
+        }
+        if self.inner_density <= 0.0 || self.outer_density <= 0.0 || self.inner_pressure <= 0.0 || self.outer_pressure <= 0.0 {
+            anyhow::bail!("the densities and pressures must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let (d, p) = if r < self.blast_radius {
+            (self.inner_density, self.inner_pressure)
+        } else {
+            (self.outer_density, self.outer_pressure)
+        };
+
+        AnyPrimitive {
+            velocity_r: 0.0,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: p,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, _q) = coordinate;
+
+        if r < self.blast_radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::{Arc, Mutex};
+use serde::{Serialize, Deserialize};
+use crate::lookup_table_v2::LookupTable;
+use crate::traits::InitialModel;
+use crate::physics::{AnyPrimitive, light_speed};
+
+
+
+
+/**
+ * A spherically symmetric progenitor, whose radial profile is read from a
+ * tabulated stellar model, such as a MESA profile exported to text or CSV
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TabulatedProgenitor {
+
+    /// Relative path to the profile table. The columns are expected to be
+    /// (radius [cm], mass density [g / cm^3], gas pressure [erg / cm^3],
+    /// radial velocity [cm / s], composition scalar). Columns may be
+    /// separated by commas or whitespace, lines starting with `#` are
+    /// ignored, and any header lines before the first row of numbers are
+    /// skipped. The rows may be listed from the center outward or from the
+    /// surface inward.
+    pub profile_table: String,
+
+    /// Power-law index of the density and pressure outside the tabulated
+    /// profile, rho ~ r^-index. The default of zero continues the outermost
+    /// tabulated values. Inside the innermost tabulated radius the innermost
+    /// values are used.
+    #[serde(default)]
+    pub outer_density_index: f64,
+
+    #[serde(skip)]
+    pub lookup_table: Arc<Mutex<Option<LookupTable<5>>>>,
+}
+
+
+
+
+// ============================================================================
+impl TabulatedProgenitor {
+
+    fn require_lookup_table(&self) {
+        let mut self_table = self.lookup_table.as_ref().lock().unwrap();
+
+        if self_table.is_none() {
+            let table = LookupTable::<5>::from_delimited_file(&self.profile_table).unwrap();
+            *self_table = Some(table);
+        }
+    }
+
+    /**
+     * Sample the profile table at radius r, returning the row (r, rho, p, v,
+     * scalar). Radii outside the table are handled as described for
+     * `outer_density_index`.
+     */
+    fn sample(&self, r: f64) -> [f64; 5] {
+        self.require_lookup_table();
+        let table_borrow = self.lookup_table.as_ref().lock().unwrap();
+        let table = table_borrow.as_ref().unwrap();
+        let (r_inner, r_outer) = table.domain();
+
+        if r <= r_inner {
+            table.sample(r_inner + (r_outer - r_inner) * 1e-12)
+        } else if r <= r_outer {
+            table.sample(r)
+        } else {
+            let mut row = table.sample(r_outer);
+            let f = (r / r_outer).powf(-self.outer_density_index);
+            row[1] *= f;
+            row[2] *= f;
+            row
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for TabulatedProgenitor {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let table = LookupTable::<5>::from_delimited_file(&self.profile_table)?;
+        let (r_inner, _) = table.domain();
+
+        if r_inner < 0.0 {
+            anyhow::bail!("the profile table radii must be non-negative")
+        }
+        if self.outer_density_index < 0.0 {
+            anyhow::bail!("the outer density index must be non-negative")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let [_, d, p, v, _] = self.sample(r);
+        let b = v / light_speed();
+
+        AnyPrimitive {
+            velocity_r: b / (1.0 - b * b).sqrt(),
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: p,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), _t: f64) -> f64 {
+        let (r, _q) = coordinate;
+        self.sample(r)[4]
+    }
+}
@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::physics::{AnyPrimitive, light_speed};
+use crate::traits::InitialModel;
+use super::JetStructure;
+
+static UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * Two-component jet: a narrow, ultra-relativistic core surrounded by a wider,
+ * mildly relativistic sheath (or cocoon), propagating through a power-law
+ * ambient medium
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TwoComponentJet {
+
+    /// Duration of the engine (s)
+    pub engine_duration: f64,
+
+    /// Isotropic-equivalent energy of the core (erg)
+    pub core_energy: f64,
+
+    /// Opening angle of the core
+    pub core_theta: f64,
+
+    /// Four-velocity of the core
+    pub core_u: f64,
+
+    /// Scalar tag of the core material
+    pub core_scalar: f64,
+
+    /// Isotropic-equivalent energy of the sheath (erg)
+    pub sheath_energy: f64,
+
+    /// Opening angle of the sheath; must be larger than the core opening angle
+    pub sheath_theta: f64,
+
+    /// Four-velocity of the sheath
+    pub sheath_u: f64,
+
+    /// Scalar tag of the sheath material
+    pub sheath_scalar: f64,
+
+    /// Ambient mass density (g/cm^3) at the reference radius
+    pub ambient_density: f64,
+
+    /// Radius (cm) where the ambient density is given
+    pub reference_radius: f64,
+
+    /// Power-law index of the ambient medium, rho ~ r^-index
+    #[serde(default)]
+    pub ambient_index: f64,
+
+    /// Scalar tag of the ambient medium
+    #[serde(default)]
+    pub ambient_scalar: f64,
+}
+
+
+
+
+/**
+ * Different space-time zones in the setup
+ */
+pub enum Zone {
+    Core,
+    Sheath,
+    Ambient,
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for TwoComponentJet {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.core_energy < 0.0 || self.sheath_energy < 0.0 {
+            anyhow::bail!("the core and sheath energies must be non-negative")
+        }
+        if self.core_u <= 0.0 || self.sheath_u <= 0.0 {
+            anyhow::bail!("the core and sheath four-velocities must be positive")
+        }
+        if self.core_theta <= 0.0 || self.sheath_theta <= self.core_theta || self.sheath_theta >= 0.5 * PI {
+            anyhow::bail!("must have 0 < core_theta < sheath_theta < pi / 2")
+        }
+        if self.engine_duration <= 0.0 || self.ambient_density <= 0.0 || self.reference_radius <= 0.0 {
+            anyhow::bail!("the engine duration, ambient density, and reference radius must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), t: f64) -> AnyPrimitive {
+        let (r, q) = coordinate;
+
+        let (d, u) = match self.zone(r, q, t) {
+            Zone::Core => {
+                (self.jet_mass_density(r, self.core_energy, self.core_u), self.core_u)
+            }
+            Zone::Sheath => {
+                (self.jet_mass_density(r, self.sheath_energy, self.sheath_u), self.sheath_u)
+            }
+            Zone::Ambient => {
+                (self.ambient_density * (r / self.reference_radius).powf(-self.ambient_index), 0.0)
+            }
+        };
+        let p = d * UNIFORM_TEMPERATURE;
+
+        AnyPrimitive {
+            velocity_r: u,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: p,
+        }
+    }
+
+    fn scalar_at(&self, coordinate: (f64, f64), t: f64) -> f64 {
+        let (r, q) = coordinate;
+
+        match self.zone(r, q, t) {
+            Zone::Core    => self.core_scalar,
+            Zone::Sheath  => self.sheath_scalar,
+            Zone::Ambient => self.ambient_scalar,
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl TwoComponentJet {
+
+    /**
+     * Determine the zone for a given radius, polar angle, and time. Each jet
+     * component occupies the shell between the material launched when the
+     * engine turned on and when it turned off.
+     *
+     * * `r` - Radius
+     * * `q` - Polar angle
+     * * `t` - Time
+     */
+    pub fn zone(&self, r: f64, q: f64, t: f64) -> Zone {
+        let theta = JetStructure::angle_from_axis(q);
+        let in_shell = |u: f64| {
+            let v = u / (1.0 + u * u).sqrt() * light_speed();
+            r < v * t && r > v * (t - self.engine_duration)
+        };
+
+        if theta < self.core_theta && in_shell(self.core_u) {
+            Zone::Core
+        } else if theta < self.sheath_theta && in_shell(self.sheath_u) {
+            Zone::Sheath
+        } else {
+            Zone::Ambient
+        }
+    }
+
+    /**
+     * Return the comoving mass density (g/cm^3) of a jet component with the
+     * given isotropic-equivalent energy and four-velocity, at radius r.
+     *
+     * * `r` - Radius
+     * * `e` - Isotropic-equivalent energy of the component
+     * * `u` - Four-velocity of the component
+     */
+    fn jet_mass_density(&self, r: f64, e: f64, u: f64) -> f64 {
+        let gamma = (1.0 + u * u).sqrt();
+        let l = e / (4.0 * PI * self.engine_duration);
+        let mdot = l / (gamma * light_speed() * light_speed());
+        mdot / (r * r * u * light_speed())
+    }
+
+    /**
+     * Return the true (beaming-corrected) energy of the core and the sheath,
+     * counting both poles.
+     */
+    pub fn true_energies(&self) -> (f64, f64) {
+        let solid_angle = |q0: f64, q1: f64| 4.0 * PI * (q0.cos() - q1.cos());
+        let core = self.core_energy * solid_angle(0.0, self.core_theta) / (4.0 * PI);
+        let sheath = self.sheath_energy * solid_angle(self.core_theta, self.sheath_theta) / (4.0 * PI);
+        (core, sheath)
+    }
+}
@@ -0,0 +1,76 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::traits::InitialModel;
+use crate::physics::{AnyPrimitive, light_speed};
+
+const UNIFORM_TEMPERATURE: f64 = 1e-6;
+
+
+
+
+/**
+ * A steady, spherically symmetric wind with constant mass-loss rate and
+ * velocity, so the density falls off as r^-2
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Wind {
+
+    /// Mass-loss rate of the wind (g/s)
+    pub mass_loss_rate: f64,
+
+    /// Four-velocity (gamma-beta) of the wind
+    pub wind_gamma_beta: f64,
+
+    /// Scalar concentration of the wind material
+    #[serde(default)]
+    pub scalar: f64,
+}
+
+
+
+
+// ============================================================================
+impl Wind {
+
+    /**
+     * The comoving mass density (g/cm^3) at radius r.
+     */
+    pub fn mass_density(&self, r: f64) -> f64 {
+        self.mass_loss_rate / (4.0 * PI * r * r * self.wind_gamma_beta * light_speed())
+    }
+}
+
+
+
+
+// ============================================================================
+impl InitialModel for Wind {
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.mass_loss_rate <= 0.0 {
+            anyhow::bail!("the wind mass-loss rate must be positive")
+        }
+        if self.wind_gamma_beta <= 0.0 {
+            anyhow::bail!("the wind four-velocity must be positive")
+        }
+        Ok(())
+    }
+
+    fn primitive_at(&self, coordinate: (f64, f64), _t: f64) -> AnyPrimitive {
+        let (r, _q) = coordinate;
+        let d = self.mass_density(r);
+        let p = d * UNIFORM_TEMPERATURE;
+
+        AnyPrimitive {
+            velocity_r: self.wind_gamma_beta,
+            velocity_q: 0.0,
+            mass_density: d,
+            gas_pressure: p,
+        }
+    }
+
+    fn scalar_at(&self, _coordinate: (f64, f64), _t: f64) -> f64 {
+        self.scalar
+    }
+}
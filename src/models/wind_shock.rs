@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex};
 use crate::lookup_table_v2::LookupTable;
-use crate::physics::{AnyPrimitive, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, light_speed};
 use crate::traits::InitialModel;
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
@@ -90,7 +90,7 @@ impl InitialModel for WindShock {
         if t >= self.flare_time && t < self.flare_time + self.flare_duration {
             let r = coordinate.0;
             let u = self.flare_gamma_beta;
-            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * LIGHT_SPEED);
+            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * light_speed());
             let rho = n * (self.flare_time + self.flare_duration - t) / self.flare_duration;
             let p = rho * UNIFORM_TEMPERATURE;
 
@@ -103,7 +103,7 @@ impl InitialModel for WindShock {
         } else if t >= self.flare_time + 3.0 && t < self.flare_time + 3.0 + self.flare_duration {
             let r = coordinate.0;
             let u = self.flare_gamma_beta;
-            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * LIGHT_SPEED);
+            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * light_speed());
             let rho = n * (self.flare_time + 3.0 + self.flare_duration - t) / self.flare_duration;
             let p = rho * UNIFORM_TEMPERATURE;
 
@@ -116,7 +116,7 @@ impl InitialModel for WindShock {
         } else if t >= self.flare_time + 6.0 && t < self.flare_time + 6.0 + self.flare_duration {
             let r = coordinate.0;
             let u = self.flare_gamma_beta;
-            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * LIGHT_SPEED);
+            let n = self.flare_outflow_rate / (4.0 * PI * r * r * u * light_speed());
             let rho = n * (self.flare_time + 6.0 + self.flare_duration - t) / self.flare_duration;
             let p = rho * UNIFORM_TEMPERATURE;
 
@@ -134,7 +134,7 @@ impl InitialModel for WindShock {
             let u = sample[1];
             let d = sample[2];
             let h = sample[3];
-            let mu = h / LIGHT_SPEED / LIGHT_SPEED - 1.0;
+            let mu = h / light_speed() / light_speed() - 1.0;
             let e = mu / (4.0 / 3.0);
             let p = d * e * (4.0 / 3.0 - 1.0);
             AnyPrimitive {
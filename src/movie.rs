@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+
+
+
+
+/**
+ * Return the PNG files in a directory, sorted by file name. Image sequences
+ * are expected to be numbered with zero-padded indexes (e.g. `prods.0012.png`)
+ * so that this is also the frame order.
+ */
+pub fn png_sequence<P: AsRef<Path>>(directory: P) -> anyhow::Result<Vec<PathBuf>> {
+    let mut frames: Vec<_> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("png")))
+        .collect();
+    frames.sort();
+    Ok(frames)
+}
+
+
+
+
+/**
+ * Assemble a sequence of PNG frames into an animated, looping GIF. All the
+ * frames must have the same dimensions as the first one.
+ *
+ * * `frames` - The PNG files, in order
+ * * `output` - The GIF file to write
+ * * `frames_per_second` - The frame rate of the animation
+ */
+#[cfg(feature = "movie")]
+pub fn assemble_gif<P: AsRef<Path>>(frames: &[PathBuf], output: P, frames_per_second: f64) -> anyhow::Result<()> {
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    let delay = (100.0 / frames_per_second).round().max(1.0) as u16;
+    let first = frames.first().ok_or_else(|| anyhow::anyhow!("there are no frames to assemble"))?;
+    let (width, height, _) = read_png_rgba(first)?;
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    for path in frames {
+        let (w, h, mut rgba) = read_png_rgba(path)?;
+
+        if (w, h) != (width, height) {
+            anyhow::bail!("frame {} is {}x{}, but the movie is {}x{}", path.display(), w, h, width, height)
+        }
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+
+
+
+/**
+ * Read a PNG file, and return its width, height, and pixel data as 8-bit
+ * RGBA.
+ */
+#[cfg(feature = "movie")]
+fn read_png_rgba(path: &Path) -> anyhow::Result<(u16, u16, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(std::fs::File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| vec![g, g, g, 255]).collect(),
+        png::ColorType::Indexed => anyhow::bail!("{}: indexed color was not expanded", path.display()),
+    };
+    if info.width > u16::MAX as u32 || info.height > u16::MAX as u32 {
+        anyhow::bail!("{}: frame is too large for a GIF", path.display())
+    }
+    Ok((info.width as u16, info.height as u16, rgba))
+}
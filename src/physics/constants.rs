@@ -0,0 +1,201 @@
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+
+
+
+
+/// Speed of light (cm/s)
+pub const LIGHT_SPEED: f64 = 3e10;
+
+/// Newton's gravitational constant (cm^3 / g / s^2)
+pub const GRAVITATIONAL_CONSTANT: f64 = 6.67e-8;
+
+/// Radiation constant a, where u = a T^4 (erg / cm^3 / K^4)
+pub const RADIATION_CONSTANT: f64 = 7.566e-15;
+
+/// Solar mass (g)
+pub const SOLAR_MASS: f64 = 2e33;
+
+/// Solar radius (cm)
+pub const SOLAR_RADIUS: f64 = 7e10;
+
+
+
+
+/// The unit system of the run, installed once at startup
+static UNIT_SYSTEM: OnceLock<UnitSystem> = OnceLock::new();
+
+
+
+
+/**
+ * A system of units, given by the size of its unit of length, mass, and time
+ * in cgs. The physical constants above are in cgs; a unit system expresses
+ * them in its own units, for code that runs in scaled (e.g. dimensionless)
+ * units rather than cgs.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitSystem {
+
+    /// The unit of length (cm)
+    pub length: f64,
+
+    /// The unit of mass (g)
+    pub mass: f64,
+
+    /// The unit of time (s)
+    pub time: f64,
+}
+
+
+
+
+// ============================================================================
+impl Default for UnitSystem {
+    fn default() -> Self {
+        Self::cgs()
+    }
+}
+
+
+
+
+// ============================================================================
+impl UnitSystem {
+
+    /**
+     * The cgs unit system, in which every constant takes its tabulated value.
+     */
+    pub fn cgs() -> Self {
+        Self { length: 1.0, mass: 1.0, time: 1.0 }
+    }
+
+    /**
+     * A unit system in which G = c = 1, with the given unit of mass (g). The
+     * units of length and time are then G M / c^2 and G M / c^3.
+     */
+    pub fn geometrized(mass: f64) -> Self {
+        let length = GRAVITATIONAL_CONSTANT * mass / LIGHT_SPEED.powi(2);
+        Self { length, mass, time: length / LIGHT_SPEED }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.length <= 0.0 || self.mass <= 0.0 || self.time <= 0.0 {
+            anyhow::bail!("the units of length, mass, and time must all be positive")
+        }
+        Ok(())
+    }
+
+    /**
+     * Make this the unit system of the constant accessors below, for the
+     * rest of the process. It is an error to install a unit system different
+     * from one installed earlier.
+     */
+    pub fn install(self) -> anyhow::Result<()> {
+        let installed = UNIT_SYSTEM.get_or_init(|| self);
+
+        if *installed != self {
+            anyhow::bail!("the unit system {:?} cannot replace the installed one {:?}", self, installed)
+        }
+        Ok(())
+    }
+
+    /**
+     * Express a cgs quantity in this unit system, given the powers of length,
+     * mass, and time in its dimensions.
+     *
+     * * `value` - The quantity in cgs
+     * * `dimensions` - The exponents of (length, mass, time)
+     */
+    pub fn scale(&self, value: f64, dimensions: (i32, i32, i32)) -> f64 {
+        let (l, m, t) = dimensions;
+        value / (self.length.powi(l) * self.mass.powi(m) * self.time.powi(t))
+    }
+
+    /**
+     * The speed of light in this unit system
+     */
+    pub fn light_speed(&self) -> f64 {
+        self.scale(LIGHT_SPEED, (1, 0, -1))
+    }
+
+    /**
+     * The gravitational constant in this unit system
+     */
+    pub fn gravitational_constant(&self) -> f64 {
+        self.scale(GRAVITATIONAL_CONSTANT, (3, -1, -2))
+    }
+
+    /**
+     * The radiation constant in this unit system, with temperature in K
+     */
+    pub fn radiation_constant(&self) -> f64 {
+        self.scale(RADIATION_CONSTANT, (-1, 1, -2))
+    }
+
+    /**
+     * The solar mass in this unit system
+     */
+    pub fn solar_mass(&self) -> f64 {
+        self.scale(SOLAR_MASS, (0, 1, 0))
+    }
+
+    /**
+     * The solar radius in this unit system
+     */
+    pub fn solar_radius(&self) -> f64 {
+        self.scale(SOLAR_RADIUS, (1, 0, 0))
+    }
+}
+
+
+
+
+/**
+ * Return the installed unit system, or cgs if none was installed.
+ */
+pub fn unit_system() -> UnitSystem {
+    UNIT_SYSTEM.get().copied().unwrap_or_default()
+}
+
+/// The speed of light in the installed unit system
+pub fn light_speed() -> f64 {
+    unit_system().light_speed()
+}
+
+/// The gravitational constant in the installed unit system
+pub fn gravitational_constant() -> f64 {
+    unit_system().gravitational_constant()
+}
+
+/// The radiation constant in the installed unit system
+pub fn radiation_constant() -> f64 {
+    unit_system().radiation_constant()
+}
+
+/// The solar mass in the installed unit system
+pub fn solar_mass() -> f64 {
+    unit_system().solar_mass()
+}
+
+/// The solar radius in the installed unit system
+pub fn solar_radius() -> f64 {
+    unit_system().solar_radius()
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometrized_units_have_unit_light_speed_and_gravitational_constant() {
+        let units = UnitSystem::geometrized(SOLAR_MASS);
+        assert!((units.light_speed() - 1.0).abs() < 1e-12);
+        assert!((units.gravitational_constant() - 1.0).abs() < 1e-12);
+        assert!((units.solar_mass() - 1.0).abs() < 1e-12);
+    }
+}
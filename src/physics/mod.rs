@@ -1,10 +1,13 @@
+pub mod constants;
 mod relativistic_hydro;
 mod newtonian_hydro;
+mod recovery;
 
 use serde::{Serialize, Deserialize};
 pub use relativistic_hydro::RelativisticHydro;
 pub use newtonian_hydro::NewtonianHydro;
-pub static LIGHT_SPEED: f64 = 3e10;
+pub use recovery::{RecoveryMethod, RecoveryReport};
+pub use constants::light_speed;
 
 
 
@@ -27,6 +30,266 @@ pub enum Direction {
 pub enum RiemannSolver {
     HLLE,
     HLLC,
+    Exact,
+}
+
+
+impl Default for RiemannSolver {
+    fn default() -> Self {
+        Self::HLLE
+    }
+}
+
+
+
+
+/**
+ * Enum for the spatial reconstruction of the zone states onto the faces
+ */
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Reconstruction {
+    PLM,
+    PPM,
+    WENO5,
+}
+
+impl Default for Reconstruction {
+    fn default() -> Self {
+        Self::PLM
+    }
+}
+
+impl Reconstruction {
+
+    /**
+     * Return the number of guard zones needed on either side of a block by
+     * the reconstruction stencil.
+     */
+    pub fn num_guard_zones(&self) -> usize {
+        match self {
+            Reconstruction::PLM => 2,
+            Reconstruction::PPM => 3,
+            Reconstruction::WENO5 => 3,
+        }
+    }
+}
+
+
+
+
+/**
+ * How the density and pressure floors are set
+ */
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum FloorStrategy {
+
+    /// The floors are the same everywhere
+    Absolute,
+
+    /// The floors are fractions of the density and pressure of the initial
+    /// model, evaluated at the zone and the current time, so they follow a
+    /// stratified ambient medium
+    Ambient,
+}
+
+impl Default for FloorStrategy {
+    fn default() -> Self {
+        Self::Absolute
+    }
+}
+
+
+
+
+/**
+ * Floors on the mass density and gas pressure, applied together with a
+ * positivity-preserving flux limiter
+ */
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Positivity {
+
+    /// Smallest mass density (comoving for relativistic) allowed in a zone,
+    /// or with the Ambient strategy, its fraction of the model density
+    pub density_floor: f64,
+
+    /// Smallest gas pressure allowed in a zone, or with the Ambient
+    /// strategy, its fraction of the model pressure
+    pub pressure_floor: f64,
+
+    /// How the floors are set: [Absolute | Ambient]. Defaults to Absolute.
+    #[serde(default)]
+    pub strategy: FloorStrategy,
+}
+
+impl Positivity {
+
+    /**
+     * Check that the floors are positive, and that fractions of the model
+     * values are below one.
+     */
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.density_floor <= 0.0 || self.pressure_floor <= 0.0 {
+            anyhow::bail!("the density and pressure floors must be positive")
+        }
+        if let FloorStrategy::Ambient = self.strategy {
+            if self.density_floor >= 1.0 || self.pressure_floor >= 1.0 {
+                anyhow::bail!("ambient density and pressure floors must be fractions below 1")
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Return whether the floors depend on the model state at each zone.
+     */
+    pub fn uses_ambient(&self) -> bool {
+        matches!(self.strategy, FloorStrategy::Ambient)
+    }
+
+    /**
+     * Return the absolute floors at a zone, given the model state there.
+     * The model state is only needed with the Ambient strategy; without it,
+     * the floors are taken as absolute.
+     */
+    pub fn at(&self, ambient: Option<&AnyPrimitive>) -> Self {
+        match (self.strategy, ambient) {
+            (FloorStrategy::Ambient, Some(a)) => Self {
+                density_floor: self.density_floor * a.mass_density,
+                pressure_floor: self.pressure_floor * a.gas_pressure,
+                strategy: FloorStrategy::Absolute,
+            },
+            _ => *self,
+        }
+    }
+
+    /**
+     * Return a state at rest, with the density and pressure at the floors.
+     */
+    pub fn floor_primitive(&self) -> AnyPrimitive {
+        AnyPrimitive {
+            velocity_r: 0.0,
+            velocity_q: 0.0,
+            mass_density: self.density_floor,
+            gas_pressure: self.pressure_floor,
+        }
+    }
+
+    /**
+     * Return the given state with its density and pressure raised to the
+     * floors, or `None` if it is already above both of them.
+     */
+    pub fn clamp(&self, p: &AnyPrimitive) -> Option<AnyPrimitive> {
+        if p.mass_density >= self.density_floor && p.gas_pressure >= self.pressure_floor {
+            return None
+        }
+        Some(AnyPrimitive {
+            mass_density: p.mass_density.max(self.density_floor),
+            gas_pressure: p.gas_pressure.max(self.pressure_floor),
+            ..p.clone()
+        })
+    }
+}
+
+
+
+
+/**
+ * Source terms which may be too stiff to integrate explicitly: optically thin
+ * cooling, uniform heating, and drag against a static medium. Each is linear
+ * in the quantity it changes, so it is integrated exactly over the step; this
+ * is stable for timescales much shorter than the time step, and keeps the
+ * Strang splitting second-order accurate. Temperatures here are the ratio of gas pressure
+ * to comoving mass density, in the units of the hydro's primitive variables.
+ */
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StiffSources {
+
+    /// If given, the cooling time (s) at the reference density. The cooling
+    /// time is inversely proportional to the density (two-body cooling).
+    #[serde(default)]
+    pub cooling_time: Option<f64>,
+
+    /// Mass density (comoving for relativistic) at which the cooling time is
+    /// given
+    #[serde(default = "StiffSources::default_reference_density")]
+    pub reference_density: f64,
+
+    /// Temperature toward which cooling relaxes the gas
+    #[serde(default)]
+    pub floor_temperature: f64,
+
+    /// Rate at which heating raises the temperature (per second)
+    #[serde(default)]
+    pub heating_rate: f64,
+
+    /// If given, the timescale (s) on which drag against a static medium
+    /// brings the gas to rest. The momentum and kinetic energy are removed
+    /// from the grid.
+    #[serde(default)]
+    pub drag_time: Option<f64>,
+}
+
+impl StiffSources {
+
+    pub fn default_reference_density() -> f64 {
+        1.0
+    }
+
+    /**
+     * Check that the timescales and the reference density are positive, and
+     * the heating rate and floor temperature are not negative.
+     */
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.cooling_time.map_or(false, |t| t <= 0.0) || self.drag_time.map_or(false, |t| t <= 0.0) {
+            anyhow::bail!("the cooling and drag times must be positive")
+        }
+        if self.reference_density <= 0.0 {
+            anyhow::bail!("the cooling reference density must be positive")
+        }
+        if self.heating_rate < 0.0 || self.floor_temperature < 0.0 {
+            anyhow::bail!("the heating rate and floor temperature must not be negative")
+        }
+        Ok(())
+    }
+
+    /**
+     * Return the given state after the drag acts for a time `dt`, with the
+     * velocity components (four-velocity for relativistic) decaying
+     * exponentially. The density and pressure are unchanged.
+     */
+    pub fn drag(&self, p: &AnyPrimitive, dt: f64) -> AnyPrimitive {
+        let factor = (-self.drag_time.map_or(0.0, |t| dt / t)).exp();
+
+        AnyPrimitive {
+            velocity_r: p.velocity_r * factor,
+            velocity_q: p.velocity_q * factor,
+            ..p.clone()
+        }
+    }
+
+    /**
+     * Return the given state after the heating and cooling act for a time
+     * `dt`, changing the temperature at fixed density and velocity. The
+     * temperature relaxes exponentially toward the one where the heating
+     * balances the cooling, so it never overshoots the floor temperature.
+     */
+    pub fn heat_cool(&self, p: &AnyPrimitive, dt: f64) -> AnyPrimitive {
+        let k = self.cooling_time.map_or(0.0, |t| dt * p.mass_density / (self.reference_density * t));
+        let temperature = p.gas_pressure / p.mass_density;
+        let temperature = if k > 0.0 {
+            let equilibrium = self.floor_temperature + dt * self.heating_rate / k;
+            equilibrium + (temperature - equilibrium) * (-k).exp()
+        } else {
+            temperature + dt * self.heating_rate
+        };
+
+        AnyPrimitive {
+            gas_pressure: p.mass_density * temperature,
+            ..p.clone()
+        }
+    }
 }
 
 
@@ -91,7 +354,10 @@ pub enum HydroErrorType {
     NegativeEnergyDensity(f64),
 
     #[error("The root finder failed to converge \n {0:?}")]
-    RootFinderFailed(hydro_srhd::srhd_2d::Conserved)
+    RootFinderFailed(hydro_srhd::srhd_2d::Conserved),
+
+    #[error("Non-finite mass or energy")]
+    NotFinite,
 }
 
 impl HydroErrorType {
@@ -1,7 +1,7 @@
 use serde::{Serialize, Deserialize};
 use godunov_core::piecewise_linear;
 use godunov_core::runge_kutta::RungeKuttaOrder;
-use crate::physics::{AnyPrimitive, Direction, HydroErrorType};
+use crate::physics::{AnyPrimitive, Direction, HydroErrorType, Positivity, Reconstruction, StiffSources, RiemannSolver};
 use crate::traits::Hydrodynamics;
 
 
@@ -25,6 +25,66 @@ pub struct NewtonianHydro {
 
     /// Runge-Kutta order: [RK1 | RK2 | RK3]
     pub runge_kutta_order: RungeKuttaOrder,
+
+    /// Spatial reconstruction: [PLM | PPM | WENO5]. PPM is less diffusive
+    /// at contacts and shocks, and WENO5 is fifth-order accurate in smooth
+    /// flows; both need at least 3 guard zones. Defaults to PLM.
+    #[serde(default)]
+    pub reconstruction: Reconstruction,
+
+    /// If given, zones inside strong shocks are reconstructed at first
+    /// order, which suppresses post-shock oscillations. A zone is flagged
+    /// when its neighbors approach each other, and the pressure between them
+    /// differs by more than this fraction of the smaller one (e.g. 0.5).
+    #[serde(default)]
+    pub shock_flattening: Option<f64>,
+
+    /// If given, the fluxes through all the faces of zones in or next to a
+    /// strong shock are computed with the HLLE solver, whatever the Riemann
+    /// solver, which suppresses the carbuncle and odd-even decoupling at
+    /// grid-aligned shocks. Shocks are detected as for shock flattening,
+    /// with this pressure jump threshold (e.g. 0.5).
+    #[serde(default)]
+    pub carbuncle_fix: Option<f64>,
+
+    /// Compute the flux of the passive scalar from the mass flux and the
+    /// scalar concentration on the upwind side of each face, limited to the
+    /// range of the concentrations in the two zones. This keeps the
+    /// concentration within the range of its initial values, so it can be
+    /// used as a reliable material mask.
+    #[serde(default)]
+    pub consistent_scalar_advection: bool,
+
+    /// If given, face fluxes which would leave a zone with negative mass or
+    /// energy are recomputed at first order, and zones which are still
+    /// unphysical, or whose density or pressure falls below the floors, are
+    /// reset to the floors. Applications are counted for each block and
+    /// reported in the products.
+    #[serde(default)]
+    pub positivity: Option<Positivity>,
+
+    /// Discretize the model acceleration so that the initial model, which
+    /// must then be a static, hydrostatic equilibrium, is preserved to
+    /// machine precision. The deviations from the initial model are
+    /// reconstructed radially, and the radial acceleration in each zone is
+    /// the one which balances the discretized pressure gradient of the
+    /// initial model. Cannot be combined with source splitting.
+    #[serde(default)]
+    pub well_balanced: bool,
+
+    /// If given, cooling, heating, and drag source terms, which are
+    /// integrated implicitly in half steps before and after each hydro
+    /// update, so that short cooling or drag times do not limit the time
+    /// step.
+    #[serde(default)]
+    pub stiff_sources: Option<StiffSources>,
+
+    /// Riemann solver: [HLLE | HLLC | Exact]. HLLC resolves contact
+    /// discontinuities, and the scalar interfaces carried with them, more
+    /// sharply. The exact (iterative) solver is slower, and is meant for
+    /// verification runs. Defaults to HLLE.
+    #[serde(default)]
+    pub riemann_solver: RiemannSolver,
 }
 
 
@@ -39,6 +99,18 @@ impl Hydrodynamics for NewtonianHydro {
         if self.plm_theta < 1.0 || self.plm_theta > 2.0 {
             anyhow::bail!("plm_theta must be in the range [1, 2]")            
         }
+        if self.shock_flattening.map_or(false, |x| x <= 0.0) {
+            anyhow::bail!("the shock flattening threshold must be positive")
+        }
+        if self.carbuncle_fix.map_or(false, |x| x <= 0.0) {
+            anyhow::bail!("the carbuncle fix threshold must be positive")
+        }
+        if let Some(positivity) = &self.positivity {
+            positivity.validate()?
+        }
+        if let Some(stiff_sources) = &self.stiff_sources {
+            stiff_sources.validate()?
+        }
         if self.cfl_number < 0.0 || self.cfl_number > 0.7 {
             anyhow::bail!("cfl_number must be in the range [0.0, 0.7]")
         }
@@ -49,6 +121,34 @@ impl Hydrodynamics for NewtonianHydro {
         self.runge_kutta_order
     }
 
+    fn reconstruction(&self) -> Reconstruction {
+        self.reconstruction
+    }
+
+    fn shock_flattening(&self) -> Option<f64> {
+        self.shock_flattening
+    }
+
+    fn carbuncle_fix(&self) -> Option<f64> {
+        self.carbuncle_fix
+    }
+
+    fn consistent_scalar_advection(&self) -> bool {
+        self.consistent_scalar_advection
+    }
+
+    fn positivity(&self) -> Option<Positivity> {
+        self.positivity
+    }
+
+    fn well_balanced(&self) -> bool {
+        self.well_balanced
+    }
+
+    fn stiff_sources(&self) -> Option<StiffSources> {
+        self.stiff_sources
+    }
+
     fn plm_gradient_primitive(&self, a: &Self::Primitive, b: &Self::Primitive, c: &Self::Primitive) -> Self::Primitive {
         piecewise_linear::plm_gradient4(self.plm_theta, a, b, c)
     }
@@ -94,6 +194,18 @@ impl Hydrodynamics for NewtonianHydro {
     }
 
     fn intercell_flux(&self, pl: Self::Primitive, pr: Self::Primitive, sl: f64, sr: f64, direction: Direction) -> (Self::Conserved, f64) {
+        let axis = match direction {
+            Direction::Radial => hydro_euler::geometry::Direction::X,
+            Direction::Polar  => hydro_euler::geometry::Direction::Y,
+        };
+        match self.riemann_solver {
+            RiemannSolver::HLLE => hydro_euler::euler_2d::riemann_hlle_scalar(pl, pr, sl, sr, axis, self.gamma_law_index),
+            RiemannSolver::HLLC => riemann_hllc_scalar(pl, pr, sl, sr, direction, self.gamma_law_index),
+            RiemannSolver::Exact => riemann_exact_scalar(pl, pr, sl, sr, direction, self.gamma_law_index),
+        }
+    }
+
+    fn intercell_flux_hlle(&self, pl: Self::Primitive, pr: Self::Primitive, sl: f64, sr: f64, direction: Direction) -> (Self::Conserved, f64) {
         let axis = match direction {
             Direction::Radial => hydro_euler::geometry::Direction::X,
             Direction::Polar  => hydro_euler::geometry::Direction::Y,
@@ -113,6 +225,12 @@ impl Hydrodynamics for NewtonianHydro {
         hydro_euler::euler_2d::Conserved(gd, gr, gq, ge)
     }
 
+    fn acceleration_source_terms(&self, p: Self::Primitive, acceleration: (f64, f64)) -> Self::Conserved {
+        let (ar, aq) = acceleration;
+        let d = p.0;
+        hydro_euler::euler_2d::Conserved(0.0, d * ar, d * aq, d * (p.1 * ar + p.2 * aq))
+    }
+
     fn cfl_number(&self) -> f64 {
         self.cfl_number
     }
@@ -129,6 +247,14 @@ impl crate::traits::Conserved for hydro_euler::euler_2d::Conserved {
     fn lab_frame_mass(&self) -> f64 {
         self.mass_density()
     }
+
+    fn energy(&self) -> f64 {
+        self.3
+    }
+
+    fn radial_momentum(&self) -> f64 {
+        self.1
+    }
 }
 
 impl crate::traits::Arithmetic for hydro_euler::euler_2d::Primitive {
@@ -139,3 +265,250 @@ impl crate::traits::Primitive for hydro_euler::euler_2d::Primitive {
         1.0
     }
 }
+
+
+
+
+/**
+ * Return a primitive state as (density, normal velocity, transverse velocity,
+ * pressure) with respect to the given direction.
+ */
+fn normal_state(p: hydro_euler::euler_2d::Primitive, direction: &Direction) -> [f64; 4] {
+    match direction {
+        Direction::Radial => [p.0, p.1, p.2, p.3],
+        Direction::Polar  => [p.0, p.2, p.1, p.3],
+    }
+}
+
+/**
+ * Return conserved quantities or fluxes from an array in the order of
+ * `normal_state`.
+ */
+fn from_normal(f: [f64; 4], direction: &Direction) -> hydro_euler::euler_2d::Conserved {
+    match direction {
+        Direction::Radial => hydro_euler::euler_2d::Conserved(f[0], f[1], f[2], f[3]),
+        Direction::Polar  => hydro_euler::euler_2d::Conserved(f[0], f[2], f[1], f[3]),
+    }
+}
+
+fn total_energy(w: [f64; 4], g: f64) -> f64 {
+    let [d, un, ut, p] = w;
+    p / (g - 1.0) + 0.5 * d * (un * un + ut * ut)
+}
+
+fn normal_flux(w: [f64; 4], g: f64) -> [f64; 4] {
+    let [d, un, ut, p] = w;
+    [d * un, d * un * un + p, d * un * ut, (total_energy(w, g) + p) * un]
+}
+
+fn sound_speed(w: [f64; 4], g: f64) -> f64 {
+    (g * w[3] / w[0]).sqrt()
+}
+
+
+
+
+/**
+ * HLLC approximate Riemann solver (Toro, Spruce & Speares 1994) for the
+ * Newtonian Euler equations, which restores the contact wave missing from
+ * HLLE. The scalar concentration is advected with the mass flux, and is taken
+ * from the upwind side of the contact.
+ */
+fn riemann_hllc_scalar(
+    pl: hydro_euler::euler_2d::Primitive,
+    pr: hydro_euler::euler_2d::Primitive,
+    sl: f64,
+    sr: f64,
+    direction: Direction,
+    g: f64) -> (hydro_euler::euler_2d::Conserved, f64)
+{
+    let (wl, wr) = (normal_state(pl, &direction), normal_state(pr, &direction));
+    let [dl, ul, _, p_l] = wl;
+    let [dr, ur, _, p_r] = wr;
+    let al = f64::min(ul - sound_speed(wl, g), ur - sound_speed(wr, g));
+    let ar = f64::max(ul + sound_speed(wl, g), ur + sound_speed(wr, g));
+    let a_star = (p_r - p_l + dl * ul * (al - ul) - dr * ur * (ar - ur)) / (dl * (al - ul) - dr * (ar - ur));
+
+    let star_flux = |w: [f64; 4], a: f64| {
+        let [d, un, ut, p] = w;
+        let f = normal_flux(w, g);
+        let u = [d, d * un, d * ut, total_energy(w, g)];
+        let k = d * (a - un) / (a - a_star);
+        let e = u[3] / d + (a_star - un) * (a_star + p / (d * (a - un)));
+        let u_star = [k, k * a_star, k * ut, k * e];
+        [0, 1, 2, 3].map(|n| f[n] + a * (u_star[n] - u[n]))
+    };
+    let f = if 0.0 <= al {
+        normal_flux(wl, g)
+    } else if 0.0 <= a_star {
+        star_flux(wl, al)
+    } else if 0.0 <= ar {
+        star_flux(wr, ar)
+    } else {
+        normal_flux(wr, g)
+    };
+    let scalar_flux = f[0] * if f[0] > 0.0 { sl } else { sr };
+    (from_normal(f, &direction), scalar_flux)
+}
+
+
+
+
+/**
+ * Godunov flux from the exact solution of the Riemann problem, sampled on the
+ * interface. The scalar is taken from the upwind side of the contact. Where
+ * the two states would leave a vacuum between them, the HLLC flux is used
+ * instead.
+ */
+fn riemann_exact_scalar(
+    pl: hydro_euler::euler_2d::Primitive,
+    pr: hydro_euler::euler_2d::Primitive,
+    sl: f64,
+    sr: f64,
+    direction: Direction,
+    g: f64) -> (hydro_euler::euler_2d::Conserved, f64)
+{
+    let (wl, wr) = (normal_state(pl, &direction), normal_state(pr, &direction));
+
+    match exact_riemann_state(wl, wr, 0.0, g) {
+        Some(w) => {
+            let f = normal_flux(w, g);
+            let scalar_flux = f[0] * if f[0] > 0.0 { sl } else { sr };
+            (from_normal(f, &direction), scalar_flux)
+        }
+        None => riemann_hllc_scalar(pl, pr, sl, sr, direction, g),
+    }
+}
+
+
+
+
+/**
+ * Sample the exact solution of the Riemann problem for the gamma-law Euler
+ * equations (Toro 2009, chapter 4) at the similarity coordinate xi = x / t,
+ * with the initial discontinuity at x = 0 and the left and right states
+ * varying along x. This is intended for verification runs and for reference
+ * solutions. Returns `None` if the states would leave a vacuum between them.
+ */
+pub fn sample_exact_riemann(
+    pl: hydro_euler::euler_2d::Primitive,
+    pr: hydro_euler::euler_2d::Primitive,
+    xi: f64,
+    gamma_law_index: f64) -> Option<hydro_euler::euler_2d::Primitive>
+{
+    let w = exact_riemann_state([pl.0, pl.1, pl.2, pl.3], [pr.0, pr.1, pr.2, pr.3], xi, gamma_law_index)?;
+    Some(hydro_euler::euler_2d::Primitive(w[0], w[1], w[2], w[3]))
+}
+
+/**
+ * Return the pressure and normal velocity in the star region between the
+ * nonlinear waves, found by Newton iteration on the pressure, or `None` if
+ * the states would leave a vacuum between them.
+ */
+fn exact_riemann_star(wl: [f64; 4], wr: [f64; 4], g: f64) -> Option<(f64, f64)> {
+    let [dl, ul, _, p_l] = wl;
+    let [dr, ur, _, p_r] = wr;
+    let (cl, cr) = (sound_speed(wl, g), sound_speed(wr, g));
+
+    if 2.0 / (g - 1.0) * (cl + cr) <= ur - ul {
+        return None
+    }
+
+    // The velocity change across the wave on one side, as a function of the
+    // star pressure, and its derivative: a shock if the pressure increases,
+    // otherwise a rarefaction.
+    let wave = |p: f64, d: f64, pk: f64, c: f64| {
+        if p > pk {
+            let a = 2.0 / ((g + 1.0) * d);
+            let b = (g - 1.0) / (g + 1.0) * pk;
+            let q = (a / (p + b)).sqrt();
+            ((p - pk) * q, q * (1.0 - 0.5 * (p - pk) / (p + b)))
+        } else {
+            let x = p / pk;
+            (2.0 * c / (g - 1.0) * (x.powf(0.5 * (g - 1.0) / g) - 1.0), x.powf(-0.5 * (g + 1.0) / g) / (d * c))
+        }
+    };
+    let tolerance = 1e-12 * f64::min(p_l, p_r);
+    let mut p = f64::max(tolerance, 0.5 * (p_l + p_r) - 0.125 * (ur - ul) * (dl + dr) * (cl + cr));
+
+    for _ in 0..100 {
+        let (fl, dfl) = wave(p, dl, p_l, cl);
+        let (fr, dfr) = wave(p, dr, p_r, cr);
+        let p_new = f64::max(tolerance, p - (fl + fr + ur - ul) / (dfl + dfr));
+        let change = 2.0 * (p_new - p).abs() / (p_new + p);
+        p = p_new;
+
+        if change < 1e-12 {
+            break
+        }
+    }
+    let (fl, _) = wave(p, dl, p_l, cl);
+    let (fr, _) = wave(p, dr, p_r, cr);
+    Some((p, 0.5 * (ul + ur) + 0.5 * (fr - fl)))
+}
+
+/**
+ * Sample the exact Riemann solution at xi = x / t, for states in the order of
+ * `normal_state`. The transverse velocity is that on the same side of the
+ * contact.
+ */
+fn exact_riemann_state(wl: [f64; 4], wr: [f64; 4], xi: f64, g: f64) -> Option<[f64; 4]> {
+    let (p_star, u_star) = exact_riemann_star(wl, wr, g)?;
+    let gm = (g - 1.0) / (g + 1.0);
+
+    // The sample is on the side of the contact the state w is on; s is -1
+    // for the left state and +1 for the right state, so that the waves on
+    // either side are handled by the same formulas.
+    let sample = |w: [f64; 4], s: f64| {
+        let [d, u, ut, p] = w;
+        let c = sound_speed(w, g);
+        let x = p_star / p;
+
+        if p_star > p {
+            let shock_speed = u + s * c * ((g + 1.0) / (2.0 * g) * x + (g - 1.0) / (2.0 * g)).sqrt();
+
+            if s * (xi - shock_speed) >= 0.0 {
+                w
+            } else {
+                [d * (x + gm) / (gm * x + 1.0), u_star, ut, p_star]
+            }
+        } else {
+            let head_speed = u + s * c;
+            let tail_speed = u_star + s * c * x.powf(0.5 * (g - 1.0) / g);
+
+            if s * (xi - head_speed) >= 0.0 {
+                w
+            } else if s * (xi - tail_speed) <= 0.0 {
+                [d * x.powf(1.0 / g), u_star, ut, p_star]
+            } else {
+                let f = 2.0 / (g + 1.0) - s * gm / c * (u - xi);
+                [d * f.powf(2.0 / (g - 1.0)), 2.0 / (g + 1.0) * (-s * c + 0.5 * (g - 1.0) * u + xi), ut, p * f.powf(2.0 * g / (g - 1.0))]
+            }
+        }
+    };
+    if xi <= u_star {
+        Some(sample(wl, -1.0))
+    } else {
+        Some(sample(wr, 1.0))
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hydro_euler::euler_2d::Primitive;
+
+    #[test]
+    fn exact_riemann_solver_reproduces_the_sod_star_state() {
+        let (wl, wr) = ([1.0, 0.0, 0.0, 1.0], [0.125, 0.0, 0.0, 0.1]);
+        let (p_star, u_star) = exact_riemann_star(wl, wr, 1.4).unwrap();
+        assert!((p_star - 0.30313).abs() < 1e-5);
+        assert!((u_star - 0.92745).abs() < 1e-5);
+
+        let p = sample_exact_riemann(Primitive(1.0, 0.0, 0.0, 1.0), Primitive(0.125, 0.0, 0.0, 0.1), 1.0, 1.4).unwrap();
+        assert!((p.0 - 0.26557).abs() < 1e-5);
+    }
+}
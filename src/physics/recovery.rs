@@ -0,0 +1,283 @@
+use serde::{Serialize, Deserialize};
+use hydro_srhd::srhd_2d::{Conserved, Primitive, RecoveredPrimitive};
+
+/// Largest number of iterations of the iterative recovery methods
+static MAX_ITERATIONS: usize = 200;
+
+/// Relative tolerance on the pressure residual for the iterative methods
+static TOLERANCE: f64 = 1e-10;
+
+
+
+
+/**
+ * An algorithm to recover the primitive state from the conserved state in
+ * relativistic hydro. The relativistic hydro tries the methods in its
+ * recovery chain in order, until one of them succeeds.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum RecoveryMethod {
+
+    /// The root finder of the hydro library. A state it recovers with a
+    /// negative pressure is kept, with the pressure raised to 1e-3 of the
+    /// density.
+    Library,
+
+    /// Newton-Raphson iteration on the pressure
+    Newton,
+
+    /// Bisection on the pressure, between the smallest pressure allowed by
+    /// the momentum and the largest allowed by the energy. Slower than
+    /// Newton's method, but it cannot diverge.
+    Bisection,
+
+    /// Ignore the energy, and recover the state on the adiabat
+    /// p = adiabat ρ^Γ from the density and momentum. This always succeeds
+    /// for a positive density, but does not conserve energy, so it is meant
+    /// to be the last method in the chain.
+    Entropy { adiabat: f64 },
+}
+
+
+
+
+/**
+ * The outcome of the primitive recovery in a single zone: whether it needed
+ * a fallback, and the number of root-finder iterations it took over all the
+ * methods it tried
+ */
+#[derive(Clone, Copy, Default)]
+pub struct RecoveryReport {
+    pub fallback: bool,
+    pub iterations: u32,
+}
+
+
+
+
+// ============================================================================
+impl RecoveryMethod {
+
+    /**
+     * Try to recover the primitive state with this method. Returns the
+     * primitive, and whether it needed a fix which does not conserve the
+     * given state, or `None` if the method failed.
+     */
+    pub fn recover(&self, u: &Conserved, gamma_law_index: f64) -> Option<(Primitive, bool)> {
+        self.recover_counting(u, gamma_law_index).0
+    }
+
+    /**
+     * Same as `recover`, but also return the number of root-finder
+     * iterations the method took, whether or not it succeeded. The root
+     * finder of the hydro library does not report its iterations, so they
+     * are counted as zero.
+     */
+    pub fn recover_counting(&self, u: &Conserved, gamma_law_index: f64) -> (Option<(Primitive, bool)>, u32) {
+        match self {
+            Self::Library => match u.to_primitive(gamma_law_index) {
+                RecoveredPrimitive::Success(p) => (Some((p, false)), 0),
+                RecoveredPrimitive::NegativePressure(p) => (Some((Primitive(p.0, p.1, p.2, 1e-3 * p.0), true)), 0),
+                RecoveredPrimitive::RootFinderFailed(_) => (None, 0),
+            },
+            Self::Newton => {
+                let (p, iterations) = newton(u, gamma_law_index);
+                (p.map(|p| (p, false)), iterations)
+            }
+            Self::Bisection => {
+                let (p, iterations) = bisection(u, gamma_law_index);
+                (p.map(|p| (p, false)), iterations)
+            }
+            Self::Entropy { adiabat } => {
+                let (p, iterations) = isentropic(u, gamma_law_index, *adiabat);
+                (p.map(|p| (p, true)), iterations)
+            }
+        }
+    }
+}
+
+
+
+
+/**
+ * Return the smallest pressure for which the velocity implied by the
+ * momentum and energy is below the speed of light.
+ */
+fn smallest_pressure(u: &Conserved) -> f64 {
+    let s = u.1.hypot(u.2);
+    (s - u.3 - u.0).max(0.0)
+}
+
+/**
+ * Return the primitive state implied by the conserved state and a trial
+ * pressure, the residual (Γ - 1) ρ ε - p of the equation of state, and the
+ * usual approximation v² c_s² - 1 to its derivative. Returns `None` if the
+ * trial pressure implies a superluminal velocity.
+ */
+fn state_at_pressure(u: &Conserved, p: f64, gamma_law_index: f64) -> Option<(Primitive, f64, f64)> {
+    let (d, s1, s2, tau) = (u.0, u.1, u.2, u.3);
+    let e = tau + d + p;
+    let v2 = (s1 * s1 + s2 * s2) / (e * e);
+
+    if !(v2 < 1.0) {
+        return None
+    }
+    let w = 1.0 / (1.0 - v2).sqrt();
+    let rho = d / w;
+    let eps = (tau + d * (1.0 - w) + p * (1.0 - w * w)) / (d * w);
+    let cs2 = (gamma_law_index - 1.0) * gamma_law_index * eps / (1.0 + gamma_law_index * eps);
+    let residual = (gamma_law_index - 1.0) * rho * eps - p;
+
+    Some((Primitive(rho, w * s1 / e, w * s2 / e, p), residual, v2 * cs2 - 1.0))
+}
+
+/**
+ * Return the given primitive if its density and pressure are positive and
+ * all its components are finite.
+ */
+fn physical(p: Primitive) -> Option<Primitive> {
+    let finite = p.0.is_finite() && p.1.is_finite() && p.2.is_finite() && p.3.is_finite();
+
+    if finite && p.0 > 0.0 && p.3 > 0.0 {
+        Some(p)
+    } else {
+        None
+    }
+}
+
+/**
+ * Recover the primitive state by Newton-Raphson iteration on the pressure.
+ * Steps which would go below the smallest allowed pressure are replaced by
+ * steps halfway to it. Also returns the number of iterations taken.
+ */
+fn newton(u: &Conserved, gamma_law_index: f64) -> (Option<Primitive>, u32) {
+    if u.0 <= 0.0 {
+        return (None, 0)
+    }
+    let p_min = smallest_pressure(u);
+    let mut p = ((gamma_law_index - 1.0) * u.3).max(2.0 * p_min).max(1e-10 * u.0);
+
+    for n in 1..=MAX_ITERATIONS as u32 {
+        let (primitive, f, df) = match state_at_pressure(u, p, gamma_law_index) {
+            Some(state) => state,
+            None => return (None, n),
+        };
+        if f.abs() <= TOLERANCE * p {
+            return (physical(primitive), n)
+        }
+        p = (p - f / df).max(0.5 * (p + p_min));
+    }
+    (None, MAX_ITERATIONS as u32)
+}
+
+/**
+ * Recover the primitive state by bisection on the pressure. The residual is
+ * no larger than (Γ - 1) τ - p, so the root is below (Γ - 1) τ. Also returns
+ * the number of iterations taken.
+ */
+fn bisection(u: &Conserved, gamma_law_index: f64) -> (Option<Primitive>, u32) {
+    if u.0 <= 0.0 {
+        return (None, 0)
+    }
+    let mut lo = smallest_pressure(u);
+    let mut hi = (gamma_law_index - 1.0) * u.3;
+    let mut iterations = 0;
+
+    if hi <= lo {
+        return (None, 0)
+    }
+    while iterations < MAX_ITERATIONS as u32 {
+        let mid = 0.5 * (lo + hi);
+        iterations += 1;
+
+        match state_at_pressure(u, mid, gamma_law_index) {
+            Some((_, f, _)) if f < 0.0 => hi = mid,
+            _ => lo = mid,
+        }
+        if hi - lo <= TOLERANCE * hi {
+            break
+        }
+    }
+    let primitive = match state_at_pressure(u, hi, gamma_law_index) {
+        Some((primitive, f, _)) if f.abs() <= TOLERANCE.sqrt() * hi => physical(primitive),
+        _ => None,
+    };
+    (primitive, iterations)
+}
+
+/**
+ * Recover the primitive state on the adiabat p = K ρ^Γ from the lab-frame
+ * density and the momentum, ignoring the energy. The momentum magnitude is
+ * D h u for the four-velocity magnitude u, which is found by bisection; it is
+ * no larger than the momentum over the density, since h >= 1. Also returns
+ * the number of iterations taken.
+ */
+fn isentropic(u: &Conserved, gamma_law_index: f64, adiabat: f64) -> (Option<Primitive>, u32) {
+    let (d, s1, s2) = (u.0, u.1, u.2);
+    let s = s1.hypot(s2);
+
+    if d <= 0.0 {
+        return (None, 0)
+    }
+    let state = |u: f64| {
+        let rho = d / (1.0 + u * u).sqrt();
+        let p = adiabat * rho.powf(gamma_law_index);
+        let h = 1.0 + gamma_law_index / (gamma_law_index - 1.0) * p / rho;
+        (rho, p, d * h * u - s)
+    };
+    let (mut lo, mut hi) = (0.0, s / d);
+    let mut iterations = 0;
+
+    while iterations < MAX_ITERATIONS as u32 {
+        let mid = 0.5 * (lo + hi);
+        iterations += 1;
+
+        if state(mid).2 < 0.0 {
+            lo = mid
+        } else {
+            hi = mid
+        }
+        if hi - lo <= TOLERANCE * hi {
+            break
+        }
+    }
+    let four_velocity = 0.5 * (lo + hi);
+    let (rho, p, _) = state(four_velocity);
+    let (nr, nq) = if s > 0.0 { (s1 / s, s2 / s) } else { (0.0, 0.0) };
+
+    (physical(Primitive(rho, four_velocity * nr, four_velocity * nq, p)), iterations)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_iterations_recover_a_fast_hot_state() {
+        let p = Primitive(1.0, 20.0, -3.0, 5.0);
+        let u = p.to_conserved(4.0 / 3.0);
+
+        for method in &[RecoveryMethod::Newton, RecoveryMethod::Bisection] {
+            let (q, fallback) = method.recover(&u, 4.0 / 3.0).unwrap();
+            assert!(!fallback);
+            assert!((q.0 - p.0).abs() < 1e-6 * p.0);
+            assert!((q.1 - p.1).abs() < 1e-6 * p.1.abs());
+            assert!((q.3 - p.3).abs() < 1e-6 * p.3);
+        }
+    }
+
+    #[test]
+    fn iterative_methods_count_their_iterations() {
+        let u = Primitive(1.0, 20.0, -3.0, 5.0).to_conserved(4.0 / 3.0);
+
+        for method in &[RecoveryMethod::Newton, RecoveryMethod::Bisection] {
+            let (p, iterations) = method.recover_counting(&u, 4.0 / 3.0);
+            assert!(p.is_some());
+            assert!(iterations > 0 && iterations < MAX_ITERATIONS as u32);
+        }
+        assert_eq!(RecoveryMethod::Library.recover_counting(&u, 4.0 / 3.0).1, 0);
+    }
+}
@@ -1,7 +1,8 @@
 use serde::{Serialize, Deserialize};
 use godunov_core::piecewise_linear;
 use godunov_core::runge_kutta::RungeKuttaOrder;
-use crate::physics::{AnyPrimitive, RiemannSolver, Direction, HydroErrorType, LIGHT_SPEED};
+use crate::physics::{AnyPrimitive, RiemannSolver, Direction, HydroErrorType, Positivity, RecoveryMethod, RecoveryReport, Reconstruction, StiffSources, light_speed};
+use crate::physics::constants::gravitational_constant;
 use crate::traits::Hydrodynamics;
 use crate::galmod::GalacticModel;
 
@@ -27,9 +28,76 @@ pub struct RelativisticHydro {
     /// Runge-Kutta order: [RK1 | RK2 | RK3]
     pub runge_kutta_order: RungeKuttaOrder,
 
+    /// Spatial reconstruction: [PLM | PPM | WENO5]. PPM is less diffusive
+    /// at contacts and shocks, and WENO5 is fifth-order accurate in smooth
+    /// flows; both need at least 3 guard zones. Defaults to PLM.
+    #[serde(default)]
+    pub reconstruction: Reconstruction,
+
+    /// If given, zones inside strong shocks are reconstructed at first
+    /// order, which suppresses post-shock oscillations. A zone is flagged
+    /// when its neighbors approach each other, and the pressure between them
+    /// differs by more than this fraction of the smaller one (e.g. 0.5).
+    #[serde(default)]
+    pub shock_flattening: Option<f64>,
+
+    /// If given, the fluxes through all the faces of zones in or next to a
+    /// strong shock are computed with the HLLE solver, whatever the Riemann
+    /// solver, which suppresses the carbuncle and odd-even decoupling at
+    /// grid-aligned shocks. Shocks are detected as for shock flattening,
+    /// with this pressure jump threshold (e.g. 0.5).
+    #[serde(default)]
+    pub carbuncle_fix: Option<f64>,
+
+    /// Compute the flux of the passive scalar from the mass flux and the
+    /// scalar concentration on the upwind side of each face, limited to the
+    /// range of the concentrations in the two zones. This keeps the
+    /// concentration within the range of its initial values, so it can be
+    /// used as a reliable material mask.
+    #[serde(default)]
+    pub consistent_scalar_advection: bool,
+
+    /// If given, face fluxes which would leave a zone with negative mass or
+    /// energy are recomputed at first order, and zones which are still
+    /// unphysical, or whose density or pressure falls below the floors, are
+    /// reset to the floors. Applications are counted for each block and
+    /// reported in the products.
+    #[serde(default)]
+    pub positivity: Option<Positivity>,
+
+    /// Discretize the model acceleration so that the initial model, which
+    /// must then be a static, hydrostatic equilibrium, is preserved to
+    /// machine precision. The deviations from the initial model are
+    /// reconstructed radially, and the radial acceleration in each zone is
+    /// the one which balances the discretized pressure gradient of the
+    /// initial model. Cannot be combined with source splitting.
+    #[serde(default)]
+    pub well_balanced: bool,
+
+    /// If given, cooling, heating, and drag source terms, which are
+    /// integrated implicitly in half steps before and after each hydro
+    /// update, so that short cooling or drag times do not limit the time
+    /// step.
+    #[serde(default)]
+    pub stiff_sources: Option<StiffSources>,
+
+    /// Reconstruct the characteristic variables of the primitive system
+    /// normal to each face, rather than the primitive variables, which
+    /// reduces the oscillations at strong shocks and contact discontinuities.
+    /// Only available with PLM reconstruction.
+    #[serde(default)]
+    pub characteristic_reconstruction: bool,
+
     /// Riemann solver: [HLLE | HLLC]
     pub riemann_solver: RiemannSolver,
 
+    /// Primitive recovery methods, tried in order until one succeeds:
+    /// [Library | Newton | Bisection | Entropy: {adiabat: K}]. Zones which
+    /// need any but the first method count as recovery fallbacks in the
+    /// work maps and block diagnostics. Defaults to [Library].
+    #[serde(default = "RelativisticHydro::default_recovery")]
+    pub recovery: Vec<RecoveryMethod>,
+
     /// Define the time step based on the maximum signal speed. If false,
     /// assume the speed of light.
     #[serde(default)]
@@ -39,6 +107,89 @@ pub struct RelativisticHydro {
 
 
 
+// ============================================================================
+impl RelativisticHydro {
+    fn default_recovery() -> Vec<RecoveryMethod> {
+        vec![RecoveryMethod::Library]
+    }
+}
+
+
+
+
+/**
+ * Characteristic variables of the one-dimensional relativistic primitive
+ * system normal to a face, linearized about a reference state. The
+ * variables are the two acoustic invariants, dp ∓ ρ h c_s / Γ du_n (for the
+ * normal four-velocity u_n and Lorentz factor Γ), the entropy ρ - p / h c_s²,
+ * and the transverse four-velocity.
+ */
+struct CharacteristicBasis {
+    direction: Direction,
+    impedance: f64,
+    entropy_slope: f64,
+}
+
+
+
+
+// ============================================================================
+impl CharacteristicBasis {
+
+    fn new(gamma_law_index: f64, reference: &AnyPrimitive, direction: Direction) -> Self {
+        let (d, p) = (reference.mass_density, reference.gas_pressure);
+        let h = 1.0 + gamma_law_index / (gamma_law_index - 1.0) * p / d;
+        let cs2 = gamma_law_index * p / (d * h);
+        let lorentz_factor = (1.0 + reference.velocity_r.powi(2) + reference.velocity_q.powi(2)).sqrt();
+
+        Self {
+            direction,
+            impedance: d * h * cs2.sqrt() / lorentz_factor,
+            entropy_slope: 1.0 / (h * cs2),
+        }
+    }
+
+    fn normal_and_transverse(&self, a: &AnyPrimitive) -> (f64, f64) {
+        match self.direction {
+            Direction::Radial => (a.velocity_r, a.velocity_q),
+            Direction::Polar  => (a.velocity_q, a.velocity_r),
+        }
+    }
+
+    fn project(&self, a: &AnyPrimitive) -> [f64; 4] {
+        let (un, ut) = self.normal_and_transverse(a);
+        let p = a.gas_pressure;
+        [
+            p - self.impedance * un,
+            a.mass_density - p * self.entropy_slope,
+            ut,
+            p + self.impedance * un,
+        ]
+    }
+
+    /**
+     * Transform a vector of characteristic variables (or differences of
+     * them) back to the primitive variables.
+     */
+    fn unproject(&self, w: [f64; 4]) -> AnyPrimitive {
+        let p = 0.5 * (w[0] + w[3]);
+        let un = 0.5 * (w[3] - w[0]) / self.impedance;
+        let (velocity_r, velocity_q) = match self.direction {
+            Direction::Radial => (un, w[2]),
+            Direction::Polar  => (w[2], un),
+        };
+        AnyPrimitive {
+            velocity_r,
+            velocity_q,
+            mass_density: w[1] + p * self.entropy_slope,
+            gas_pressure: p,
+        }
+    }
+}
+
+
+
+
 // ============================================================================
 impl Hydrodynamics for RelativisticHydro {
     type Conserved = hydro_srhd::srhd_2d::Conserved;
@@ -48,6 +199,30 @@ impl Hydrodynamics for RelativisticHydro {
         if self.plm_theta < 1.0 || self.plm_theta > 2.0 {
             anyhow::bail!("plm_theta must be in the range [1, 2]")            
         }
+        if self.shock_flattening.map_or(false, |x| x <= 0.0) {
+            anyhow::bail!("the shock flattening threshold must be positive")
+        }
+        if self.carbuncle_fix.map_or(false, |x| x <= 0.0) {
+            anyhow::bail!("the carbuncle fix threshold must be positive")
+        }
+        if let Some(positivity) = &self.positivity {
+            positivity.validate()?
+        }
+        if let Some(stiff_sources) = &self.stiff_sources {
+            stiff_sources.validate()?
+        }
+        if self.characteristic_reconstruction && !matches!(self.reconstruction, Reconstruction::PLM) {
+            anyhow::bail!("characteristic reconstruction is only available with PLM")
+        }
+        if let RiemannSolver::Exact = self.riemann_solver {
+            anyhow::bail!("the exact Riemann solver is only available for Newtonian hydro")
+        }
+        if self.recovery.is_empty() {
+            anyhow::bail!("the recovery chain needs at least one method")
+        }
+        if self.recovery.iter().any(|method| matches!(method, RecoveryMethod::Entropy { adiabat } if *adiabat <= 0.0)) {
+            anyhow::bail!("the adiabat of the entropy recovery must be positive")
+        }
         //if self.cfl_number < 0.0 || self.cfl_number > 0.7 {
         //    anyhow::bail!("cfl_number must be in the range [0.0, 0.7]")
         //}
@@ -58,6 +233,34 @@ impl Hydrodynamics for RelativisticHydro {
         self.runge_kutta_order
     }
 
+    fn reconstruction(&self) -> Reconstruction {
+        self.reconstruction
+    }
+
+    fn shock_flattening(&self) -> Option<f64> {
+        self.shock_flattening
+    }
+
+    fn carbuncle_fix(&self) -> Option<f64> {
+        self.carbuncle_fix
+    }
+
+    fn consistent_scalar_advection(&self) -> bool {
+        self.consistent_scalar_advection
+    }
+
+    fn positivity(&self) -> Option<Positivity> {
+        self.positivity
+    }
+
+    fn well_balanced(&self) -> bool {
+        self.well_balanced
+    }
+
+    fn stiff_sources(&self) -> Option<StiffSources> {
+        self.stiff_sources
+    }
+
     fn plm_gradient_primitive(&self, a: &Self::Primitive, b: &Self::Primitive, c: &Self::Primitive) -> Self::Primitive {
         piecewise_linear::plm_gradient4(self.plm_theta, a, b, c)
     }
@@ -66,7 +269,25 @@ impl Hydrodynamics for RelativisticHydro {
         piecewise_linear::plm_gradient(self.plm_theta, a, b, c)
     }
 
+    fn plm_gradient_primitive_along(&self, a: &Self::Primitive, b: &Self::Primitive, c: &Self::Primitive, direction: Direction) -> Self::Primitive {
+        if !self.characteristic_reconstruction {
+            return self.plm_gradient_primitive(a, b, c)
+        }
+        let basis = CharacteristicBasis::new(self.gamma_law_index, &self.any(b), direction);
+        let (wa, wb, wc) = (basis.project(&self.any(a)), basis.project(&self.any(b)), basis.project(&self.any(c)));
+        let mut gradient = [0.0; 4];
+
+        for k in 0..4 {
+            gradient[k] = piecewise_linear::plm_gradient(self.plm_theta, &wa[k], &wb[k], &wc[k]);
+        }
+        self.interpret(&basis.unproject(gradient))
+    }
+
     fn try_to_primitive(&self, u:Self::Conserved) -> Result<Self::Primitive, HydroErrorType>{
+        self.try_to_primitive_reporting(u).map(|(p, _)| p)
+    }
+
+    fn try_to_primitive_reporting(&self, u: Self::Conserved) -> Result<(Self::Primitive, RecoveryReport), HydroErrorType> {
 
         if u.lab_frame_density() < 0.0 {
             return Err(HydroErrorType::NegativeDensity(u.lab_frame_density()))
@@ -75,17 +296,17 @@ impl Hydrodynamics for RelativisticHydro {
             return Err(HydroErrorType::NegativeEnergyDensity(u.energy_density()))
         }
 
-        let valid_primitive = match u.to_primitive(self.gamma_law_index) {
-            hydro_srhd::srhd_2d::RecoveredPrimitive::Success(p) => p,
-            hydro_srhd::srhd_2d::RecoveredPrimitive::NegativePressure(p) => {
-                hydro_srhd::srhd_2d::Primitive(p.0, p.1, p.2, 1e-3 * p.0)
-            }
-            hydro_srhd::srhd_2d::RecoveredPrimitive::RootFinderFailed(u) => {
-                return Err(HydroErrorType::RootFinderFailed(u))?
-            }
-        };
+        let mut iterations = 0;
+
+        for (n, method) in self.recovery.iter().enumerate() {
+            let (recovered, method_iterations) = method.recover_counting(&u, self.gamma_law_index);
+            iterations += method_iterations;
 
-        Ok(valid_primitive)
+            if let Some((p, fallback)) = recovered {
+                return Ok((p, RecoveryReport { fallback: fallback || n > 0, iterations }))
+            }
+        }
+        Err(HydroErrorType::RootFinderFailed(u))
     }
 
     fn to_primitive(&self, u: Self::Conserved) -> Self::Primitive {
@@ -97,14 +318,14 @@ impl Hydrodynamics for RelativisticHydro {
     }
 
     fn max_signal_speed(&self, p: Self::Primitive) -> f64 {
-        p.max_signal_speed(self.gamma_law_index) * LIGHT_SPEED
+        p.max_signal_speed(self.gamma_law_index) * light_speed()
     }
 
     fn global_signal_speed(&self) -> Option<f64> {
         if self.adaptive_time_step {
             None
         } else {
-            Some(LIGHT_SPEED)
+            Some(light_speed())
         }
     }
 
@@ -125,22 +346,33 @@ impl Hydrodynamics for RelativisticHydro {
         let mode = match self.riemann_solver {
             RiemannSolver::HLLE => hydro_srhd::srhd_2d::RiemannSolverMode::HlleFlux,
             RiemannSolver::HLLC => hydro_srhd::srhd_2d::RiemannSolverMode::HllcFlux,
+            RiemannSolver::Exact => unreachable!("rejected by validate"),
         };            
         let axis = match direction {
             Direction::Radial => hydro_srhd::geometry::Direction::X,
             Direction::Polar  => hydro_srhd::geometry::Direction::Y,
         };
         let (f, g, _) = hydro_srhd::srhd_2d::riemann_hllc_scalar(pl, pr, sl, sr, axis, self.gamma_law_index, mode);
-        (f * LIGHT_SPEED, g * LIGHT_SPEED)
+        (f * light_speed(), g * light_speed())
+    }
+
+    fn intercell_flux_hlle(&self, pl: Self::Primitive, pr: Self::Primitive, sl: f64, sr: f64, direction: Direction) -> (Self::Conserved, f64) {
+        let axis = match direction {
+            Direction::Radial => hydro_srhd::geometry::Direction::X,
+            Direction::Polar  => hydro_srhd::geometry::Direction::Y,
+        };
+        let mode = hydro_srhd::srhd_2d::RiemannSolverMode::HlleFlux;
+        let (f, g, _) = hydro_srhd::srhd_2d::riemann_hllc_scalar(pl, pr, sl, sr, axis, self.gamma_law_index, mode);
+        (f * light_speed(), g * light_speed())
     }
 
     fn geometrical_source_terms(&self, p: Self::Primitive, coordinate: (f64, f64)) -> Self::Conserved {
-        p.spherical_geometry_source_terms(coordinate.0, coordinate.1, self.gamma_law_index) * LIGHT_SPEED
+        p.spherical_geometry_source_terms(coordinate.0, coordinate.1, self.gamma_law_index) * light_speed()
     }
 
     fn gravitational_source_terms(&self, p: Self::Primitive, coordinate: (f64, f64)) -> Self::Conserved {
         let h0 = p.specific_enthalpy(self.gamma_law_index);
-        let gmod = GalacticModel {g: 6.67e-8,
+        let gmod = GalacticModel {g: gravitational_constant(),
                                    m_b: 3.377e43,
                                    a_b: 8.98e20,
                                    v_h: 1.923e7,
@@ -157,13 +389,22 @@ impl Hydrodynamics for RelativisticHydro {
         let gz = gmod.g_field_z(1e22, coordinate.0*cosq + 1.5e20).total();
         
         let gd = 0.0;
-        let gr = p.lorentz_factor() * p.mass_density() * h0 * gz * cosq / LIGHT_SPEED / LIGHT_SPEED;
-        let gq = -p.lorentz_factor() * p.mass_density() * h0 * gz * sinq / LIGHT_SPEED / LIGHT_SPEED;
-        let ge = p.lorentz_factor() * p.mass_density() * h0 * gz * cosq / LIGHT_SPEED * (p.gamma_beta_1()*cosq - p.gamma_beta_2()*sinq);
+        let gr = p.lorentz_factor() * p.mass_density() * h0 * gz * cosq / light_speed() / light_speed();
+        let gq = -p.lorentz_factor() * p.mass_density() * h0 * gz * sinq / light_speed() / light_speed();
+        let ge = p.lorentz_factor() * p.mass_density() * h0 * gz * cosq / light_speed() * (p.gamma_beta_1()*cosq - p.gamma_beta_2()*sinq);
 
         hydro_srhd::srhd_2d::Conserved(gd, gr, gq, ge)
     }
 
+    fn acceleration_source_terms(&self, p: Self::Primitive, acceleration: (f64, f64)) -> Self::Conserved {
+        let (ar, aq) = acceleration;
+        let w = p.lorentz_factor() * p.mass_density() * p.specific_enthalpy(self.gamma_law_index);
+        let gr = w * p.lorentz_factor() * ar / light_speed();
+        let gq = w * p.lorentz_factor() * aq / light_speed();
+        let ge = w * (p.gamma_beta_1() * ar + p.gamma_beta_2() * aq) / light_speed();
+        hydro_srhd::srhd_2d::Conserved(0.0, gr, gq, ge)
+    }
+
     fn cfl_number(&self) -> f64 {
         self.cfl_number
     }
@@ -180,6 +421,14 @@ impl crate::traits::Conserved for hydro_srhd::srhd_2d::Conserved {
     fn lab_frame_mass(&self) -> f64 {
         self.lab_frame_density()
     }
+
+    fn energy(&self) -> f64 {
+        self.energy_density()
+    }
+
+    fn radial_momentum(&self) -> f64 {
+        self.momentum_1()
+    }
 }
 
 impl crate::traits::Arithmetic for hydro_srhd::srhd_2d::Primitive {
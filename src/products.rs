@@ -1,11 +1,15 @@
 use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
 use serde::{Serialize, Deserialize};
-use ndarray::{ArcArray, Ix1, Ix2};
-use crate::app::{self, Configuration, AnyHydro, AnyState};
-use crate::mesh::{BlockIndex, GridGeometry};
-use crate::physics::{AnyPrimitive, HydroError};
+use ndarray::{Array, ArcArray, Ix1, Ix2};
+use crate::app::{self, Configuration, AnyHydro, AnyState, ProductsDelta, ReferenceEpoch};
+use crate::diagnostics::BlockWork;
+use crate::io;
+use crate::mesh::{BlockIndex, GridGeometry, Mesh, POLE_TOLERANCE};
+use crate::physics::{AnyPrimitive, HydroError, light_speed};
 use crate::products;
-use crate::state::{BlockState, State};
+use crate::state::{BlockState, ExcisedTotals, PositivityCounts, State};
 use crate::traits::{Conserved, Hydrodynamics};
 
 
@@ -25,6 +29,48 @@ pub struct BlockProducts {
 
 
 
+/**
+ * Timestamps relating the simulation time to a physical reference epoch. The
+ * arrival times are for an on-axis observer, relative to a photon emitted
+ * from the origin at the epoch.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ObserverTimes {
+	pub time_since_epoch: f64,
+	pub gps_time: Option<f64>,
+	pub extraction_radii: Vec<f64>,
+	pub arrival_times: Vec<f64>,
+}
+
+
+
+
+/**
+ * Header of a sparse products file, which only contains the blocks that
+ * changed since the products file it is based on
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeltaHeader {
+	pub base: String,
+	pub block_indexes: Vec<BlockIndex>,
+}
+
+
+
+
+/**
+ * Entry in the index manifest of a products snapshot which was split across
+ * several files: the name of a part file, and the blocks it contains
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProductsPart {
+	pub filename: String,
+	pub block_indexes: Vec<BlockIndex>,
+}
+
+
+
+
 /**
  * Useful data for post-processing and plotting
  */
@@ -34,6 +80,96 @@ pub struct Products {
 	pub blocks: HashMap<BlockIndex, BlockProducts>,
 	pub config: Configuration,
 	pub version: String,
+	#[serde(default)]
+	pub observer_times: Option<ObserverTimes>,
+	#[serde(default)]
+	pub delta: Option<DeltaHeader>,
+	#[serde(default)]
+	pub parts: Option<Vec<ProductsPart>>,
+	#[serde(default)]
+	pub excised: ExcisedTotals,
+	#[serde(default)]
+	pub positivity: HashMap<BlockIndex, PositivityCounts>,
+}
+
+
+
+
+/**
+ * Keeps track of the block data most recently written to products files, to
+ * decide which blocks need to be written to the next sparse products file
+ */
+#[derive(Default)]
+pub struct DeltaEncoder {
+	written: HashMap<BlockIndex, BlockProducts>,
+	previous_filename: Option<String>,
+	since_keyframe: usize,
+}
+
+
+
+
+/**
+ * Measured per-block compute cost, and per-zone primitive recovery fallback
+ * counts and root-finder iterations (the mean and largest per update),
+ * suitable for plotting as a heat map
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockWorkProducts {
+	pub radial_vertices: ArcArray<f64, Ix1>,
+	pub polar_vertices: ArcArray<f64, Ix1>,
+	pub seconds_per_zone_update: f64,
+	pub updates: u64,
+	pub recovery_fallbacks: ArcArray<u64, Ix2>,
+	#[serde(default)]
+	pub mean_recovery_iterations: ArcArray<f64, Ix2>,
+	#[serde(default)]
+	pub max_recovery_iterations: ArcArray<u64, Ix2>,
+}
+
+
+
+
+/**
+ * Work map accumulated since the previous work map was written
+ */
+#[derive(Serialize, Deserialize)]
+pub struct WorkProducts {
+	pub time: f64,
+	pub blocks: HashMap<BlockIndex, BlockWorkProducts>,
+	pub config: Configuration,
+	pub version: String,
+}
+
+
+
+
+/**
+ * A one-dimensional radial profile of a products snapshot, either averaged
+ * over the polar angle or along a single polar angle
+ */
+pub struct RadialProfile {
+	pub time: f64,
+	pub polar_angle: Option<f64>,
+	pub rows: Vec<RadialProfileRow>,
+}
+
+
+
+
+/**
+ * One radius of a radial profile. For the angle average, the density and
+ * pressure are averaged over volume, and the velocities, Lorentz factor, and
+ * scalar over mass.
+ */
+pub struct RadialProfileRow {
+	pub radius: f64,
+	pub mass_density: f64,
+	pub gas_pressure: f64,
+	pub velocity_r: f64,
+	pub velocity_q: f64,
+	pub lorentz_factor: f64,
+	pub scalar: f64,
 }
 
 
@@ -64,6 +200,51 @@ impl BlockProducts {
 			scalar: scalar.to_shared(),
 		})
 	}
+
+	/**
+	 * Same as `try_from_block_state`, except the block data is first remapped
+	 * onto `num_polar_zones` evenly spaced polar zones spanning the same
+	 * extent. The remapping operates on the conserved quantities, so the
+	 * mass, momentum, energy and scalar mass in each radial row are
+	 * preserved.
+	 */
+	pub fn try_from_block_state_regridded<H, C>(state: &BlockState<C>, hydro: &H, geometry: &GridGeometry, num_polar_zones: usize) -> Result::<Self, HydroError>
+	where
+		H: Hydrodynamics<Conserved = C>,
+		C: Conserved {
+
+		let source_vertices = &geometry.polar_vertices;
+		let q0 = source_vertices[0];
+		let q1 = source_vertices[source_vertices.len() - 1];
+		let target_vertices = Array::linspace(q0, q1, num_polar_zones + 1);
+		let target_geometry = GridGeometry::from_vertices(geometry.radial_vertices.to_owned(), target_vertices.clone());
+
+		let regridded = state.remapped_polar(source_vertices.view(), target_vertices.view());
+		Self::try_from_block_state(&regridded, hydro, &target_geometry)
+	}
+
+	/**
+	 * Return the largest relative change of any primitive or scalar field,
+	 * in any zone, between this block and another one. If the blocks have
+	 * different vertices, the change is infinite.
+	 */
+	pub fn max_relative_change(&self, other: &Self) -> f64 {
+		if self.radial_vertices != other.radial_vertices || self.polar_vertices != other.polar_vertices {
+			return f64::INFINITY
+		}
+		let change = |a: f64, b: f64| {
+			let scale = a.abs().max(b.abs());
+			if scale > 0.0 { (a - b).abs() / scale } else { 0.0 }
+		};
+		let primitive = self.primitive.iter().zip(other.primitive.iter()).map(|(a, b)| {
+			change(a.velocity_r, b.velocity_r)
+				.max(change(a.velocity_q, b.velocity_q))
+				.max(change(a.mass_density, b.mass_density))
+				.max(change(a.gas_pressure, b.gas_pressure))
+		});
+		let scalar = self.scalar.iter().zip(other.scalar.iter()).map(|(&a, &b)| change(a, b));
+		primitive.chain(scalar).fold(0.0, f64::max)
+	}
 }
 
 
@@ -76,11 +257,15 @@ impl Products {
 		H: Hydrodynamics<Conserved = C>,
 		C: Conserved {
 
-		let geometry = config.mesh.grid_blocks_geometry(state.time);
+		let geometry = state.block_geometry(&config.mesh);
 		let mut blocks = HashMap::new();
 
 		for (index, block_state) in &state.solution {
-			blocks.insert(*index, BlockProducts::try_from_block_state(block_state, hydro, &geometry[index])?);
+			let block = match config.control.products_polar_regrid {
+				Some(num_polar_zones) => BlockProducts::try_from_block_state_regridded(block_state, hydro, &geometry[index], num_polar_zones)?,
+				None => BlockProducts::try_from_block_state(block_state, hydro, &geometry[index])?,
+			};
+			blocks.insert(*index, block);
 		}
 
 		Ok(Products{
@@ -88,8 +273,139 @@ impl Products {
 			blocks: blocks,
 			config: config.clone(),
 			version: app::VERSION_AND_BUILD.to_string(),
+			observer_times: config.control.reference_epoch.as_ref().map(|epoch| ObserverTimes::new(epoch, state.time)),
+			delta: None,
+			parts: None,
+			excised: state.excised,
+			positivity: state.positivity.clone(),
 		})
 	}
+
+	/**
+	 * Read a products file. If it is a sparse products file, the complete
+	 * snapshot is reconstructed by reading the chain of files it is based on,
+	 * back to the most recent keyframe. If it is the index manifest of a
+	 * split snapshot, the blocks are read from the part files. Files in the
+	 * chain and part files are looked up in the same directory as the given
+	 * file.
+	 */
+	pub fn from_file(filename: &str) -> anyhow::Result<Self> {
+		let mut products: Self = io::read_cbor(filename)?;
+
+		for part in products.parts.take().unwrap_or_default() {
+			let part_filename = Path::new(filename).with_file_name(&part.filename);
+			let part_products: Self = io::read_cbor(&part_filename.to_string_lossy())?;
+			products.blocks.extend(part_products.blocks);
+		}
+
+		if let Some(delta) = products.delta.take() {
+			let base_filename = Path::new(filename).with_file_name(&delta.base);
+			let mut base = Self::from_file(&base_filename.to_string_lossy())?;
+			let mut blocks = HashMap::new();
+
+			for index in delta.block_indexes {
+				let block = products.blocks.remove(&index)
+					.or_else(|| base.blocks.remove(&index))
+					.ok_or_else(|| anyhow::anyhow!("block {:?} is missing from {} and the files it is based on", index, filename))?;
+				blocks.insert(index, block);
+			}
+			products.blocks = blocks;
+		}
+		Ok(products)
+	}
+
+	/**
+	 * Write these products to a file. If a maximum file size (bytes) is given
+	 * and the encoded products would exceed it, the blocks are instead
+	 * distributed over part files named like `prods.0012.part003.cbor`, and
+	 * the given file becomes an index manifest listing the parts and the
+	 * blocks in each. The manifest has the time, configuration, and other
+	 * metadata, but no blocks. A single block larger than the limit is
+	 * written to a part file of its own. The size limit applies to the
+	 * uncompressed encoding, if the files are compressed.
+	 */
+	pub fn write(mut self, filename: &str, max_file_size: Option<u64>, compression: Option<io::Compression>) -> anyhow::Result<()> {
+		let max_file_size = match max_file_size {
+			Some(max_file_size) => max_file_size,
+			None => return Ok(io::write_cbor_compressed(&self, filename, compression)?),
+		};
+		let blocks = std::mem::take(&mut self.blocks);
+		let header_size = encoded_size(&self)?;
+		let mut indexes: Vec<_> = blocks.keys().cloned().collect();
+		indexes.sort();
+
+		let mut groups: Vec<(Vec<BlockIndex>, u64)> = Vec::new();
+
+		for index in indexes {
+			let size = encoded_size(&blocks[&index])?;
+
+			match groups.last_mut() {
+				Some((group, total)) if *total + size <= max_file_size => {
+					group.push(index);
+					*total += size;
+				}
+				_ => groups.push((vec![index], header_size + size)),
+			}
+		}
+		if groups.len() <= 1 {
+			self.blocks = blocks;
+			return Ok(io::write_cbor_compressed(&self, filename, compression)?)
+		}
+
+		let path = Path::new(filename);
+		let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+		let mut blocks = blocks;
+		let mut parts = Vec::new();
+
+		for (n, (block_indexes, _)) in groups.into_iter().enumerate() {
+			let part_name = format!("{}.part{:03}.cbor", stem, n);
+			let part = Self {
+				time: self.time,
+				blocks: block_indexes.iter().map(|index| (*index, blocks.remove(index).unwrap())).collect(),
+				config: self.config.clone(),
+				version: self.version.clone(),
+				observer_times: self.observer_times.clone(),
+				delta: None,
+				parts: None,
+				excised: self.excised,
+				positivity: self.positivity.clone(),
+			};
+			io::write_cbor_compressed(&part, &path.with_file_name(&part_name).to_string_lossy(), compression)?;
+			parts.push(ProductsPart{filename: part_name, block_indexes});
+		}
+		self.parts = Some(parts);
+		Ok(io::write_cbor_compressed(&self, filename, compression)?)
+	}
+
+	/**
+	 * Return the radius of the jet head: the outer edge of the outermost zone
+	 * touching either pole where the scalar concentration exceeds the given
+	 * threshold. Edges of the polar extent which are not on a pole, such as
+	 * the lateral edge of a wedge, are not searched. Returns `None` if no
+	 * such zone exists.
+	 */
+	pub fn jet_head_radius(&self, scalar_threshold: f64) -> Option<f64> {
+		let mut radius: Option<f64> = None;
+
+		for block in self.blocks.values() {
+			let (nr, nq) = block.scalar.dim();
+			let q = &block.polar_vertices;
+			let axis_columns: Vec<_> = [(0, q[0] <= POLE_TOLERANCE), (nq - 1, q[nq] >= PI - POLE_TOLERANCE)]
+				.iter()
+				.filter(|(_, on_pole)| *on_pole)
+				.map(|(j, _)| *j)
+				.collect();
+
+			for i in 0..nr {
+				if axis_columns.iter().any(|&j| block.scalar[(i, j)] > scalar_threshold) {
+					let r = block.radial_vertices[i + 1];
+					radius = Some(radius.map_or(r, |radius| radius.max(r)));
+				}
+			}
+		}
+		radius
+	}
+
 	pub fn try_from_app(app: &app::App) -> Result::<Self, HydroError> {
 		match (&app.state, &app.config.hydro) {
 			(AnyState::Newtonian(state), AnyHydro::Newtonian(hydro)) => {
@@ -102,3 +418,344 @@ impl Products {
 		}
 	}
 }
+
+
+
+
+// ============================================================================
+impl DeltaEncoder {
+
+	/**
+	 * Prepare products which are about to be written to the given file name.
+	 * Returns either a complete keyframe, or sparse products containing only
+	 * the blocks that changed beyond the tolerance since they were last
+	 * written. The first products file written by an encoder is always a
+	 * keyframe.
+	 */
+	pub fn encode(&mut self, mut products: Products, filename: &str, settings: &ProductsDelta) -> Products {
+		let keyframe = self.previous_filename.is_none() || self.since_keyframe + 1 >= settings.keyframe_cadence;
+		let base = self.previous_filename.replace(filename.to_string());
+
+		if keyframe {
+			self.since_keyframe = 0;
+			self.written = products.blocks.clone();
+			return products
+		}
+		self.since_keyframe += 1;
+
+		let mut block_indexes: Vec<_> = products.blocks.keys().cloned().collect();
+		block_indexes.sort();
+
+		let written = &mut self.written;
+		written.retain(|index, _| products.blocks.contains_key(index));
+		products.blocks.retain(|index, block| {
+			let changed = written.get(index).map_or(true, |old| old.max_relative_change(block) > settings.tolerance);
+			if changed {
+				written.insert(*index, block.clone());
+			}
+			changed
+		});
+
+		let base = base.unwrap();
+		let base = Path::new(&base).file_name().map_or(base.clone(), |name| name.to_string_lossy().into_owned());
+		products.delta = Some(DeltaHeader{base, block_indexes});
+		products
+	}
+}
+
+
+
+
+/**
+ * Return the number of bytes in the CBOR encoding of a value.
+ */
+fn encoded_size<T: Serialize>(value: &T) -> anyhow::Result<u64> {
+	let mut buffer = Vec::new();
+	ciborium::ser::into_writer(value, &mut buffer)?;
+	Ok(buffer.len() as u64)
+}
+
+
+
+
+// ============================================================================
+impl ObserverTimes {
+	pub fn new(epoch: &ReferenceEpoch, time: f64) -> Self {
+		let time_since_epoch = time - epoch.simulation_time;
+
+		ObserverTimes{
+			time_since_epoch,
+			gps_time: epoch.gps_time.map(|t| t + time_since_epoch),
+			extraction_radii: epoch.extraction_radii.clone(),
+			arrival_times: epoch.extraction_radii.iter().map(|r| time_since_epoch - r / light_speed()).collect(),
+		}
+	}
+}
+
+
+
+
+// ============================================================================
+impl WorkProducts {
+
+	/**
+	 * Create a work map from the work recorded on each block. The scale
+	 * factor is that of the mesh when the map is written, so the block radii
+	 * follow moving faces. Blocks which were not updated (for example because
+	 * they were frozen) are given zero cost per zone update.
+	 */
+	pub fn from_work(work: HashMap<BlockIndex, BlockWork>, time: f64, scale_factor: f64, config: &Configuration) -> Self {
+		let blocks = work
+			.into_iter()
+			.map(|(index, work)| {
+				let level = config.mesh.block_level(work.recovery_fallbacks.dim());
+				let geometry = config.mesh.subgrid_at_level(index, level).geometry().expanded(scale_factor);
+				let block = BlockWorkProducts{
+					radial_vertices: geometry.radial_vertices.clone(),
+					polar_vertices: geometry.polar_vertices.clone(),
+					seconds_per_zone_update: work.seconds_per_zone_update(),
+					updates: work.updates,
+					recovery_fallbacks: work.recovery_fallbacks.to_shared(),
+					mean_recovery_iterations: work.recovery_iterations.mapv(|n| n as f64 / work.updates.max(1) as f64).to_shared(),
+					max_recovery_iterations: work.max_recovery_iterations.to_shared(),
+				};
+				(index, block)
+			})
+			.collect();
+
+		WorkProducts{
+			time,
+			blocks,
+			config: config.clone(),
+			version: app::VERSION_AND_BUILD.to_string(),
+		}
+	}
+}
+
+
+
+
+/**
+ * Return the index of the zone between the given polar vertices which
+ * contains the given angle. The last zone is closed at its upper vertex, so
+ * that an angle on the upper edge of the polar extent, such as π, is found.
+ */
+fn polar_zone_containing(vertices: &ArcArray<f64, Ix1>, angle: f64) -> Option<usize> {
+	let nq = vertices.len() - 1;
+	(0..nq).find(|&j| vertices[j] <= angle && (angle < vertices[j + 1] || j == nq - 1 && angle <= vertices[nq]))
+}
+
+
+
+
+// ============================================================================
+impl RadialProfileRow {
+
+	/**
+	 * Average the given zones at one radius, each given by its primitive
+	 * state, scalar concentration, and weight (proportional to its volume).
+	 * Returns `None` if there are no zones.
+	 */
+	fn average<I>(radius: f64, zones: I, relativistic: bool) -> Option<Self>
+	where
+		I: IntoIterator<Item = (AnyPrimitive, f64, f64)> {
+
+		let mut volume = 0.0;
+		let mut mass = 0.0;
+		let mut row = Self {
+			radius,
+			mass_density: 0.0,
+			gas_pressure: 0.0,
+			velocity_r: 0.0,
+			velocity_q: 0.0,
+			lorentz_factor: 0.0,
+			scalar: 0.0,
+		};
+		for (p, scalar, dv) in zones {
+			let dm = p.mass_density * dv;
+			let u2 = p.velocity_r.powi(2) + p.velocity_q.powi(2);
+			let lorentz_factor = if relativistic {
+				(1.0 + u2).sqrt()
+			} else {
+				1.0 / (1.0 - u2 / (light_speed() * light_speed())).sqrt()
+			};
+			volume += dv;
+			mass += dm;
+			row.mass_density += p.mass_density * dv;
+			row.gas_pressure += p.gas_pressure * dv;
+			row.velocity_r += p.velocity_r * dm;
+			row.velocity_q += p.velocity_q * dm;
+			row.lorentz_factor += lorentz_factor * dm;
+			row.scalar += scalar * dm;
+		}
+		if volume == 0.0 {
+			return None
+		}
+		row.mass_density /= volume;
+		row.gas_pressure /= volume;
+		row.velocity_r /= mass;
+		row.velocity_q /= mass;
+		row.lorentz_factor /= mass;
+		row.scalar /= mass;
+		Some(row)
+	}
+}
+
+
+
+
+// ============================================================================
+impl RadialProfile {
+
+	/**
+	 * Compute the radial profile of the given products, averaged over the
+	 * polar angle if `polar_angle` is `None`, and otherwise along the polar
+	 * zones containing the given angle (radians).
+	 */
+	pub fn from_products(products: &Products, polar_angle: Option<f64>) -> Self {
+		let relativistic = matches!(products.config.hydro, AnyHydro::Relativistic(_));
+		let mut indexes: Vec<_> = products.blocks.keys().cloned().collect();
+		indexes.sort();
+
+		let mut rows = Vec::new();
+
+		for index in indexes {
+			let block = &products.blocks[&index];
+			let (nr, nq) = block.primitive.dim();
+			let cut = polar_angle.map(|q| polar_zone_containing(&block.polar_vertices, q));
+
+			for i in 0..nr {
+				let (r0, r1) = (block.radial_vertices[i], block.radial_vertices[i + 1]);
+				let zones = (0..nq)
+					.filter(|&j| match cut {
+						Some(cut) => cut == Some(j),
+						None => true,
+					})
+					.map(|j| {
+						let weight = block.polar_vertices[j].cos() - block.polar_vertices[j + 1].cos();
+						(block.primitive[(i, j)].clone(), block.scalar[(i, j)], weight)
+					});
+				rows.extend(RadialProfileRow::average(0.5 * (r0 + r1), zones, relativistic));
+			}
+		}
+		Self { time: products.time, polar_angle, rows }
+	}
+
+	/**
+	 * Compute the radial profile along the polar zones containing the given
+	 * angle (radians) directly from a solution state. Only the zones along
+	 * the cut have their primitive state recovered, so this is much cheaper
+	 * than making the products.
+	 */
+	pub fn try_from_state<H, C>(state: &State<C>, hydro: &H, mesh: &Mesh, polar_angle: f64, relativistic: bool) -> Result<Self, HydroError>
+	where
+		H: Hydrodynamics<Conserved = C>,
+		C: Conserved {
+
+		let geometry = state.block_geometry(mesh);
+		let mut indexes: Vec<_> = state.solution.keys().cloned().collect();
+		indexes.sort();
+
+		let mut rows = Vec::new();
+
+		for index in indexes {
+			let block = &state.solution[&index];
+			let geometry = &geometry[&index];
+			let j = match polar_zone_containing(&geometry.polar_vertices, polar_angle) {
+				Some(j) => j,
+				None => continue,
+			};
+			for i in 0..block.conserved.dim().0 {
+				let u = block.conserved[(i, j)];
+				let p = hydro
+					.try_to_primitive(u / geometry.cell_volumes[(i, j)])
+					.map_err(|e| e.at_position(geometry.cell_centers[(i, j)]))?;
+				let scalar = block.scalar_mass[(i, j)] / u.lab_frame_mass();
+				let radius = 0.5 * (geometry.radial_vertices[i] + geometry.radial_vertices[i + 1]);
+				rows.extend(RadialProfileRow::average(radius, std::iter::once((hydro.any(&p), scalar, 1.0)), relativistic));
+			}
+		}
+		Ok(Self { time: state.time, polar_angle: Some(polar_angle), rows })
+	}
+
+	/**
+	 * Write this profile to a plain text file, with a commented header and
+	 * one row per radius, which can be read by gnuplot or `numpy.loadtxt`.
+	 */
+	pub fn write_text(&self, filename: &str) -> std::io::Result<()> {
+		use std::io::Write;
+
+		println!("write {}", filename);
+		let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+
+		writeln!(file, "# time = {:.10e}", self.time)?;
+		match self.polar_angle {
+			Some(q) => writeln!(file, "# polar angle = {:.6} rad", q)?,
+			None => writeln!(file, "# averaged over polar angle")?,
+		}
+		writeln!(file, "# {:>16} {:>18} {:>18} {:>18} {:>18} {:>18} {:>18}",
+			"radius", "mass_density", "gas_pressure", "velocity_r", "velocity_q", "lorentz_factor", "scalar")?;
+
+		for row in &self.rows {
+			writeln!(file, "{:>18.10e} {:>18.10e} {:>18.10e} {:>18.10e} {:>18.10e} {:>18.10e} {:>18.10e}",
+				row.radius, row.mass_density, row.gas_pressure, row.velocity_r, row.velocity_q, row.lorentz_factor, row.scalar)?;
+		}
+		file.flush()
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn products(densities: &[f64], config: &Configuration) -> Products {
+		let blocks = densities.iter().enumerate().map(|(i, &d)| {
+			let block = BlockProducts {
+				radial_vertices: Array::linspace(1.0 + i as f64, 2.0 + i as f64, 5).to_shared(),
+				polar_vertices: Array::linspace(0.0, PI, 3).to_shared(),
+				primitive: Array::from_elem((4, 2), AnyPrimitive { velocity_r: 0.0, velocity_q: 0.0, mass_density: d, gas_pressure: 1.0 }).to_shared(),
+				scalar: Array::zeros((4, 2)).to_shared(),
+			};
+			((i as i32, 0), block)
+		}).collect();
+
+		Products {
+			time: 0.0,
+			blocks,
+			config: config.clone(),
+			version: app::VERSION_AND_BUILD.to_string(),
+			observer_times: None,
+			delta: None,
+			parts: None,
+			excised: ExcisedTotals::default(),
+			positivity: HashMap::new(),
+		}
+	}
+
+	#[test]
+	fn sparse_products_read_back_as_the_complete_snapshot() {
+		let directory = std::env::temp_dir().join(format!("kilonova-delta-{}", std::process::id()));
+		let filename = |n: usize| directory.join(format!("prods.{:04}.cbor", n)).to_string_lossy().into_owned();
+		let config: Configuration = serde_yaml::from_str(include_str!("../setups/jet_in_cloud.yaml")).unwrap();
+		let settings = ProductsDelta { tolerance: 1e-6, keyframe_cadence: 10 };
+		let mut encoder = DeltaEncoder::default();
+		std::fs::create_dir_all(&directory).unwrap();
+
+		let keyframe = encoder.encode(products(&[1.0, 1.0, 1.0], &config), &filename(0), &settings);
+		assert!(keyframe.delta.is_none());
+		keyframe.write(&filename(0), None, None).unwrap();
+
+		let sparse = encoder.encode(products(&[1.0, 2.0, 1.0], &config), &filename(1), &settings);
+		assert_eq!(sparse.blocks.len(), 1);
+		sparse.write(&filename(1), None, None).unwrap();
+
+		let read = Products::from_file(&filename(1)).unwrap();
+		let density = |i: i32| read.blocks[&(i, 0)].primitive[(0, 0)].mass_density;
+		assert_eq!(read.blocks.len(), 3);
+		assert_eq!((density(0), density(1), density(2)), (1.0, 2.0, 1.0));
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+}
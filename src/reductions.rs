@@ -0,0 +1,269 @@
+use serde::{Serialize, Deserialize};
+use crate::products::ObserverTimes;
+use crate::traits::Conserved;
+
+
+
+
+/**
+ * Floating-point summation method used for global reductions
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Summation {
+
+    /// Plain summation in double precision
+    Double,
+
+    /// Compensated summation in double-double precision (about 106 bits)
+    Extended,
+}
+
+
+
+
+/**
+ * Totals of the conserved quantities over the whole grid
+ */
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ConservedTotals {
+    pub mass: f64,
+    pub energy: f64,
+}
+
+
+
+
+/**
+ * One row of the conserved quantities time series. All quantities are in cgs
+ * units, and energies exclude rest mass. If the run has a reference epoch,
+ * the row also has the time since the epoch and the observer-frame arrival
+ * times at the extraction surfaces.
+ */
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TimeSeriesSample {
+    pub time: f64,
+    pub mass: f64,
+    pub radial_momentum: f64,
+    pub kinetic_energy: f64,
+    pub thermal_energy: f64,
+    pub total_energy: f64,
+    pub scalar_mass: f64,
+    pub excised_mass: f64,
+    pub excised_energy: f64,
+    pub observer_times: Option<ObserverTimes>,
+}
+
+
+
+
+/**
+ * An accumulator which keeps its running sum as the unevaluated sum of two
+ * doubles. Round-off in each addition is captured exactly by the two-sum
+ * algorithm and carried in the low word, so the result is insensitive to the
+ * number and order of the terms, short of catastrophic cancellation beyond
+ * ~106 bits.
+ */
+#[derive(Clone, Copy, Default)]
+pub struct ExtendedSum {
+    hi: f64,
+    lo: f64,
+}
+
+
+
+
+// ============================================================================
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    let e = (a - (s - bb)) + (b - bb);
+    (s, e)
+}
+
+fn fast_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let e = b - (s - a);
+    (s, e)
+}
+
+
+
+
+// ============================================================================
+impl ExtendedSum {
+
+    /**
+     * Add a term to the running sum.
+     */
+    pub fn add(&mut self, x: f64) {
+        let (s, e) = two_sum(self.hi, x);
+        let (hi, lo) = fast_two_sum(s, self.lo + e);
+        self.hi = hi;
+        self.lo = lo;
+    }
+
+    /**
+     * Return the running sum, rounded to double precision.
+     */
+    pub fn value(&self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+
+
+
+// ============================================================================
+impl ConservedTotals {
+
+    /**
+     * Return the total mass and energy of the given conserved quantities,
+     * summed in double precision, and multiplied by a factor.
+     */
+    pub fn from_conserved<'a, C, I>(zones: I, factor: f64) -> Self
+    where
+        C: Conserved,
+        I: IntoIterator<Item = &'a C>
+    {
+        let totals = zones.into_iter().fold(Self::default(), |totals, u| Self {
+            mass: totals.mass + u.lab_frame_mass(),
+            energy: totals.energy + u.energy(),
+        });
+        Self {
+            mass: totals.mass * factor,
+            energy: totals.energy * factor,
+        }
+    }
+
+    /**
+     * Return the relative difference between these totals and another set,
+     * for each of the conserved quantities.
+     */
+    pub fn relative_difference(&self, other: &Self) -> Self {
+        let rel = |a: f64, b: f64| if a == b { 0.0 } else { (a - b).abs() / a.abs().max(b.abs()) };
+
+        Self {
+            mass: rel(self.mass, other.mass),
+            energy: rel(self.energy, other.energy),
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl TimeSeriesSample {
+
+    /**
+     * Append this sample to a CSV file, writing the column names first if
+     * the file does not exist yet.
+     */
+    pub fn append_csv(&self, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let exists = std::path::Path::new(filename).exists();
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(filename)?;
+
+        if !exists {
+            write!(file, "time,mass,radial_momentum,kinetic_energy,thermal_energy,total_energy,scalar_mass,excised_mass,excised_energy")?;
+
+            if let Some(observer_times) = &self.observer_times {
+                write!(file, ",time_since_epoch")?;
+
+                for n in 0..observer_times.arrival_times.len() {
+                    write!(file, ",arrival_time{}", n)?;
+                }
+            }
+            writeln!(file)?;
+        }
+        write!(file, "{:.10e},{:.10e},{:.10e},{:.10e},{:.10e},{:.10e},{:.10e},{:.10e},{:.10e}",
+            self.time,
+            self.mass,
+            self.radial_momentum,
+            self.kinetic_energy,
+            self.thermal_energy,
+            self.total_energy,
+            self.scalar_mass,
+            self.excised_mass,
+            self.excised_energy)?;
+
+        if let Some(observer_times) = &self.observer_times {
+            write!(file, ",{:.10e}", observer_times.time_since_epoch)?;
+
+            for t in &observer_times.arrival_times {
+                write!(file, ",{:.10e}", t)?;
+            }
+        }
+        writeln!(file)
+    }
+
+    /**
+     * Remove the rows of a CSV file written by `append_csv` whose time is at
+     * or after the given time. A restarted run calls this before its first
+     * sample, so the rows written after its checkpoint by the earlier run
+     * are replaced rather than duplicated. A missing file is left missing.
+     */
+    pub fn truncate_csv(filename: &str, time: f64) -> std::io::Result<()> {
+        let contents = match std::fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let row_time = |line: &str| line.split(',').next().and_then(|t| t.parse::<f64>().ok());
+        let kept: String = contents
+            .lines()
+            .filter(|line| row_time(line).map_or(true, |t| t < time))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        std::fs::write(filename, kept)
+    }
+}
+
+
+
+
+// ============================================================================
+impl std::ops::AddAssign for ConservedTotals {
+    fn add_assign(&mut self, other: Self) {
+        self.mass += other.mass;
+        self.energy += other.energy;
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extended_sum_recovers_small_terms_lost_in_double_precision() {
+        let mut sum = ExtendedSum::default();
+        let mut naive = 0.0;
+
+        for x in &[1.0, 1e-16, 1e-16, 1e-16, 1e-16, -1.0] {
+            sum.add(*x);
+            naive += x;
+        }
+        assert_eq!(naive, 0.0);
+        assert!(f64::abs(sum.value() - 4e-16) < 1e-30);
+    }
+
+    #[test]
+    fn extended_sum_is_insensitive_to_term_order() {
+        let terms: Vec<f64> = (0..10000).map(|i| 1.0 / (1.0 + i as f64).powi(2)).collect();
+        let mut forward = ExtendedSum::default();
+        let mut reverse = ExtendedSum::default();
+
+        for x in terms.iter() {
+            forward.add(*x);
+        }
+        for x in terms.iter().rev() {
+            reverse.add(*x);
+        }
+        assert_eq!(forward.value(), reverse.value());
+    }
+}
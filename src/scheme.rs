@@ -1,13 +1,869 @@
-use std::sync::Arc;
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::ops::{Add, Mul};
+use std::time::Instant;
 use futures::FutureExt;
 use futures::future::join_all;
 use tokio::runtime::Runtime;
-use ndarray::{Array, Axis, concatenate, s};
-use crate::mesh::{BlockIndex, GridGeometry, Mesh};
-use crate::physics::{Direction, HydroError};
-use crate::state::{State, BlockState};
-use crate::traits::{Conserved, Primitive, Hydrodynamics, InitialModel};
+use godunov_core::runge_kutta::{RungeKuttaOrder, WeightedAverageAsync};
+use num::rational::Rational64;
+use serde::{Serialize, Deserialize};
+use ndarray::{Array, ArcArray, ArrayView2, Axis, Ix1, Ix2, concatenate, s};
+use crate::app::Control;
+use crate::diagnostics::{SourceTerm, WorkLedger};
+use crate::mesh::{BlockIndex, Derefinement, FaceMotion, GridGeometry, Mesh, PolarBoundary, PolarRefocusing, Refinement, RefinementIndicator, ShockTracking};
+use crate::physics::{AnyPrimitive, Direction, HydroError, HydroErrorType, Reconstruction, StiffSources};
+use crate::reductions::ConservedTotals;
+use crate::state::{State, BlockState, PositivityCounts};
+use crate::traits::{BoundaryEdge, Conserved, Primitive, Hydrodynamics, InitialModel};
+
+
+
+/**
+ * Method used to integrate the hydrodynamics in time
+ */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeIntegrator {
+
+    /// The Runge-Kutta order given in the hydro section, where RK3 is the
+    /// third-order strong-stability-preserving method (Shu & Osher 1988)
+    Hydro,
+
+    /// MUSCL-Hancock: the PLM face states of each zone are advanced by half
+    /// a time step with the fluxes through the zone's own faces, and one
+    /// Riemann solve per face gives a second-order update in a single
+    /// stage. Needs PLM reconstruction.
+    MusclHancock,
+}
+
+
+
+
+/**
+ * Settings for operator splitting of the gravity and acceleration source
+ * terms. The terms are applied over half a time step before and after the
+ * hydro update, in mirrored order (Strang splitting), each with as many
+ * second-order subcycles as its timescale requires, so the coupling to the
+ * hydro is second-order accurate in time.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSplitting {
+
+    /// Largest fraction of a source term's timescale covered by one
+    /// subcycle. The timescale is the shortest, over the zones in a block,
+    /// of the mass or energy in the zone divided by the rate the term
+    /// changes it.
+    #[serde(default = "SourceSplitting::default_timescale_fraction")]
+    pub timescale_fraction: f64,
+
+    /// Largest number of subcycles for one term over half a time step
+    #[serde(default = "SourceSplitting::default_max_subcycles")]
+    pub max_subcycles: usize,
+}
+
+
+
+
+/**
+ * Policy for retrying a time step which failed, because the primitive
+ * recovery failed or the update produced non-finite values. The partial
+ * update is discarded, and the step is taken again from the same state with
+ * the time step reduced by a constant factor.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepRetry {
+
+    /// Factor by which the time step is reduced on each retry
+    #[serde(default = "StepRetry::default_dt_factor")]
+    pub dt_factor: f64,
+
+    /// Number of retries before the error is reported and the run stops
+    #[serde(default = "StepRetry::default_max_retries")]
+    pub max_retries: usize,
+}
+
+
+
+
+/**
+ * Settings for local time stepping. Each block takes steps of the smallest
+ * time step on the mesh, multiplied by the largest power of two (the block's
+ * "rung") allowed by its own CFL condition, so blocks of large zones at large
+ * radii take fewer steps than the blocks of tiny zones near the origin.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalTimeStepping {
+
+    /// The largest rung: no block takes steps longer than 2^max_rung times
+    /// the smallest time step
+    pub max_rung: u32,
+}
+
+
+
+
+/**
+ * The solver settings which stay fixed over a run, collected once from the
+ * control section and the hydro system, and passed to the integrators
+ * together
+ */
+#[derive(Clone)]
+pub struct SchemeSettings {
+
+    /// The number of iterations taken between side effects
+    pub fold: usize,
+
+    /// Method used to integrate the hydrodynamics in time
+    pub time_integrator: TimeIntegrator,
+
+    /// The Runge-Kutta order given in the hydro section
+    pub runge_kutta_order: RungeKuttaOrder,
+
+    /// Operator splitting of the gravity and acceleration source terms
+    pub source_splitting: Option<SourceSplitting>,
+
+    /// Local time stepping
+    pub local_time_stepping: Option<LocalTimeStepping>,
+
+    /// Policy for retrying failed steps
+    pub step_retry: Option<StepRetry>,
+}
+
+
+
+
+/**
+ * The fluxes of the conserved quantities and the scalar through the inner and
+ * outer faces of a block. These are kept to correct the fluxes at faces
+ * between blocks at different refinement levels, or on different rungs with
+ * local time stepping.
+ */
+struct BlockFaces<C> {
+    inner: (Array<C, Ix1>, Array<f64, Ix1>),
+    outer: (Array<C, Ix1>, Array<f64, Ix1>),
+}
+
+
+
+
+/**
+ * Time-integrated fluxes through the faces of each block, accumulated over
+ * the steps of an outer step with local time stepping
+ */
+type FluxLedger<C> = Mutex<HashMap<BlockIndex, BlockFaces<C>>>;
+
+
+
+
+/**
+ * Homologous motion of the radial faces over one time step, from the time
+ * `start`, when the scale factor of the mesh is `scale`, to `start + dt`,
+ * when every radius has grown by the factor `growth`. In between, the zone
+ * volumes grow linearly in time, so that the volume swept by the faces in
+ * each Runge-Kutta stage matches the change in the zone volumes, and a
+ * uniform state stays uniform.
+ */
+#[derive(Clone, Copy)]
+struct FaceSweep {
+    start: f64,
+    dt: f64,
+    scale: f64,
+    growth: f64,
+}
+
+
+
+
+// ============================================================================
+impl FaceSweep {
+
+    /**
+     * Return the factor by which the radii at the given time exceed those at
+     * the start of the step.
+     */
+    fn expansion(&self, time: f64) -> f64 {
+        (1.0 + (self.growth.powi(3) - 1.0) * (time - self.start) / self.dt).cbrt()
+    }
+
+    /**
+     * Return the rate at which a face with the given area and radius at the
+     * start of the step sweeps out volume.
+     */
+    fn swept_volume_rate(&self, area: f64, radius: f64) -> f64 {
+        area * radius * (self.growth.powi(3) - 1.0) / (3.0 * self.dt)
+    }
+}
+
+
+
+
+// ============================================================================
+impl SchemeSettings {
+
+    /**
+     * Collect the solver settings from the control section and the hydro
+     * system.
+     */
+    pub fn new<H: Hydrodynamics>(control: &Control, hydro: &H) -> Self {
+        Self {
+            fold: control.fold,
+            time_integrator: control.time_integrator,
+            runge_kutta_order: hydro.runge_kutta_order(),
+            source_splitting: control.source_splitting.clone(),
+            local_time_stepping: control.local_time_stepping.clone(),
+            step_retry: control.step_retry.clone(),
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+impl LocalTimeStepping {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_rung > 16 {
+            anyhow::bail!("local_time_stepping.max_rung must be at most 16")
+        }
+        Ok(())
+    }
+}
+
+
+
+
+// ============================================================================
+impl<C: Conserved> BlockFaces<C> {
+
+    /**
+     * Return these fluxes multiplied by the given factor.
+     */
+    fn scaled(&self, factor: f64) -> Self {
+        let scale = |f: &(Array<C, Ix1>, Array<f64, Ix1>)| (f.0.mapv(|x| x * factor), f.1.mapv(|x| x * factor));
+        Self { inner: scale(&self.inner), outer: scale(&self.outer) }
+    }
+
+    /**
+     * Add the given fluxes, multiplied by the given factor, to these ones.
+     */
+    fn add_scaled(&mut self, other: &Self, factor: f64) {
+        let add = |a: &mut (Array<C, Ix1>, Array<f64, Ix1>), b: &(Array<C, Ix1>, Array<f64, Ix1>)| {
+            a.0.zip_mut_with(&b.0, |x, &y| *x = *x + y * factor);
+            a.1.zip_mut_with(&b.1, |x, &y| *x += y * factor);
+        };
+        add(&mut self.inner, &other.inner);
+        add(&mut self.outer, &other.outer);
+    }
+}
+
+
+
+
+// ============================================================================
+impl StepRetry {
+
+    fn default_dt_factor() -> f64 {
+        0.5
+    }
+
+    fn default_max_retries() -> usize {
+        4
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.dt_factor <= 0.0 || self.dt_factor >= 1.0 {
+            anyhow::bail!("step_retry.dt_factor must be in the range (0, 1)")
+        }
+        Ok(())
+    }
+}
+
+
+
+
+// ============================================================================
+impl SourceSplitting {
+
+    fn default_timescale_fraction() -> f64 {
+        0.1
+    }
+
+    fn default_max_subcycles() -> usize {
+        1000
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.timescale_fraction <= 0.0 || self.max_subcycles == 0 {
+            anyhow::bail!("source_splitting must have timescale_fraction > 0 and max_subcycles >= 1")
+        }
+        Ok(())
+    }
+}
+
+
+
+
+// ============================================================================
+/**
+ * Return `num_guard` rows of guard zones for a block with the given shape,
+ * taken from the edge of its radial neighbor, which may be at a different
+ * refinement level. If the neighbor is on the inside (`inner` is true) the
+ * rows are taken from its outer edge, otherwise from its inner edge. The
+ * zones of a coarser neighbor are injected into the guard zones, and those
+ * of a finer neighbor are averaged.
+ */
+fn guard_zones<T>(neighbor: &ArcArray<T, Ix2>, shape: (usize, usize), num_guard: usize, inner: bool) -> Array<T, Ix2>
+where
+    T: Copy + Add<Output = T> + Mul<f64, Output = T>
+{
+    let (nr, nq) = shape;
+    let (mr, mq) = neighbor.dim();
+
+    if mr == nr {
+        return if inner {
+            neighbor.slice(s![mr - num_guard.., ..]).to_owned()
+        } else {
+            neighbor.slice(s![..num_guard, ..]).to_owned()
+        }
+    }
+    let row = |k: usize| if inner { mr - 1 - k } else { k };
+
+    Array::from_shape_fn((num_guard, nq), |(g, j)| {
+        let k = if inner { num_guard - 1 - g } else { g };
+
+        if mr > nr {
+            let (fr, fq) = (mr / nr, mq / nq);
+            let mut sum = neighbor[(row(k * fr), j * fq)];
+            for a in 0..fr {
+                for b in 0..fq {
+                    if (a, b) != (0, 0) {
+                        sum = sum + neighbor[(row(k * fr + a), j * fq + b)]
+                    }
+                }
+            }
+            sum * (1.0 / (fr * fq) as f64)
+        } else {
+            let (fr, fq) = (nr / mr, nq / mq);
+            neighbor[(row(k / fr), j / fq)]
+        }
+    })
+}
+
+/**
+ * Return the array with `n` copies of its first and last columns added on
+ * either side, so that reconstruction stencils can extend past the polar
+ * edges of the mesh.
+ */
+fn pad_polar<T: Clone>(x: ArrayView2<T>, n: usize) -> Array<T, Ix2> {
+    let (nr, nq) = x.dim();
+    Array::from_shape_fn((nr, nq + 2 * n), |(i, j)| x[(i, j.saturating_sub(n).min(nq - 1))].clone())
+}
+
+/**
+ * Return the values on the lower and upper edges of each zone along the given
+ * axis, from parabolas limited to preserve monotonicity (Colella & Woodward
+ * 1984). Zones within two of either end of the axis, which lack the full
+ * stencil, are left piecewise constant.
+ */
+fn ppm_edges(x: &Array<f64, Ix2>, axis: Axis) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+    let mut lower = x.clone();
+    let mut upper = x.clone();
+
+    for ((lane, mut lo), mut hi) in x.lanes(axis).into_iter().zip(lower.lanes_mut(axis)).zip(upper.lanes_mut(axis)) {
+        let a = lane.to_vec();
+
+        // The fourth-order interface value between zones k and k + 1, limited
+        // to lie between them.
+        let face = |k: usize| {
+            let v = 7.0 / 12.0 * (a[k] + a[k + 1]) - 1.0 / 12.0 * (a[k - 1] + a[k + 2]);
+            v.max(a[k].min(a[k + 1])).min(a[k].max(a[k + 1]))
+        };
+        for i in 2..a.len().saturating_sub(2) {
+            let (c, mut al, mut ar) = (a[i], face(i - 1), face(i));
+
+            if (ar - c) * (c - al) <= 0.0 {
+                al = c;
+                ar = c;
+            } else {
+                let (d, m) = (ar - al, c - 0.5 * (al + ar));
+                if d * m > d * d / 6.0 {
+                    al = 3.0 * c - 2.0 * ar
+                } else if -d * d / 6.0 > d * m {
+                    ar = 3.0 * c - 2.0 * al
+                }
+            }
+            lo[i] = al;
+            hi[i] = ar;
+        }
+    }
+    (lower, upper)
+}
+
+/**
+ * Return the value on the upper edge of the middle zone of a five-zone
+ * stencil, by fifth-order WENO interpolation (Jiang & Shu 1996). The
+ * smoothness tolerance is scaled by the magnitude of the values, so that the
+ * weights do not depend on the units.
+ */
+fn weno5_upper(a: [f64; 5]) -> f64 {
+    let q0 = (2.0 * a[0] - 7.0 * a[1] + 11.0 * a[2]) / 6.0;
+    let q1 = (-a[1] + 5.0 * a[2] + 2.0 * a[3]) / 6.0;
+    let q2 = (2.0 * a[2] + 5.0 * a[3] - a[4]) / 6.0;
+    let b0 = 13.0 / 12.0 * (a[0] - 2.0 * a[1] + a[2]).powi(2) + 0.25 * (a[0] - 4.0 * a[1] + 3.0 * a[2]).powi(2);
+    let b1 = 13.0 / 12.0 * (a[1] - 2.0 * a[2] + a[3]).powi(2) + 0.25 * (a[1] - a[3]).powi(2);
+    let b2 = 13.0 / 12.0 * (a[2] - 2.0 * a[3] + a[4]).powi(2) + 0.25 * (3.0 * a[2] - 4.0 * a[3] + a[4]).powi(2);
+    let eps = 1e-6 * a.iter().map(|x| x * x).fold(0.0, f64::max) + f64::MIN_POSITIVE;
+    let w0 = 0.1 / (eps + b0).powi(2);
+    let w1 = 0.6 / (eps + b1).powi(2);
+    let w2 = 0.3 / (eps + b2).powi(2);
+    (w0 * q0 + w1 * q1 + w2 * q2) / (w0 + w1 + w2)
+}
+
+/**
+ * Return the values on the lower and upper edges of each zone along the given
+ * axis, by WENO5 interpolation. Zones within two of either end of the axis
+ * are left piecewise constant.
+ */
+fn weno5_edges(x: &Array<f64, Ix2>, axis: Axis) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+    let mut lower = x.clone();
+    let mut upper = x.clone();
+
+    for ((lane, mut lo), mut hi) in x.lanes(axis).into_iter().zip(lower.lanes_mut(axis)).zip(upper.lanes_mut(axis)) {
+        let a = lane.to_vec();
+
+        for i in 2..a.len().saturating_sub(2) {
+            lo[i] = weno5_upper([a[i + 2], a[i + 1], a[i], a[i - 1], a[i - 2]]);
+            hi[i] = weno5_upper([a[i - 2], a[i - 1], a[i], a[i + 1], a[i + 2]]);
+        }
+    }
+    (lower, upper)
+}
+
+/**
+ * Return the values on the lower and upper edges of each zone along the given
+ * axis, using one of the higher-order reconstructions. PLM uses the gradients
+ * provided by the hydrodynamics instead.
+ */
+fn reconstruct_edges(x: &Array<f64, Ix2>, axis: Axis, reconstruction: Reconstruction) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+    match reconstruction {
+        Reconstruction::PLM => unreachable!("PLM uses the hydro gradients"),
+        Reconstruction::PPM => ppm_edges(x, axis),
+        Reconstruction::WENO5 => weno5_edges(x, axis),
+    }
+}
+
+/**
+ * Interpolate the primitive states onto the lower and upper edges of each
+ * zone along the given axis, one component at a time.
+ */
+fn interpolate_primitive_edges<H, P>(hydro: &H, p: &Array<P, Ix2>, axis: Axis, reconstruction: Reconstruction) -> (Array<P, Ix2>, Array<P, Ix2>)
+where
+    H: Hydrodynamics<Primitive = P>,
+    P: Primitive
+{
+    let any = p.map(|p| hydro.any(p));
+    let component = |f: fn(&AnyPrimitive) -> f64| reconstruct_edges(&any.map(f), axis, reconstruction);
+    let (d, vr, vq, pg) = (
+        component(|a| a.mass_density),
+        component(|a| a.velocity_r),
+        component(|a| a.velocity_q),
+        component(|a| a.gas_pressure),
+    );
+    let edge = |i: (usize, usize), upper: bool| {
+        let pick = |x: &(Array<f64, Ix2>, Array<f64, Ix2>)| if upper { x.1[i] } else { x.0[i] };
+        hydro.interpret(&AnyPrimitive {
+            velocity_r: pick(&vr),
+            velocity_q: pick(&vq),
+            mass_density: pick(&d),
+            gas_pressure: pick(&pg),
+        })
+    };
+    (Array::from_shape_fn(p.dim(), |i| edge(i, false)), Array::from_shape_fn(p.dim(), |i| edge(i, true)))
+}
+
+/**
+ * Reconstruct the primitive states onto the lower and upper edges of each
+ * zone along the given axis. Edge states with non-positive density or
+ * pressure, which the unlimited WENO interpolation can produce near strong
+ * shocks, are replaced by the zone state.
+ */
+fn reconstruct_primitive_edges<H, P>(hydro: &H, p: &Array<P, Ix2>, axis: Axis, reconstruction: Reconstruction) -> (Array<P, Ix2>, Array<P, Ix2>)
+where
+    H: Hydrodynamics<Primitive = P>,
+    P: Primitive
+{
+    let (lower, upper) = interpolate_primitive_edges(hydro, p, axis, reconstruction);
+    let physical = |e: &P, p: &P| {
+        let a = hydro.any(e);
+        if a.mass_density > 0.0 && a.gas_pressure > 0.0 { *e } else { *p }
+    };
+    (
+        ndarray::azip![&lower, p].apply_collect(physical),
+        ndarray::azip![&upper, p].apply_collect(physical),
+    )
+}
+
+/**
+ * Return the primitive states on the inner and outer sides of the radial
+ * faces of a block, reconstructed from the extended array `pe`, which has
+ * `ng` guard zones on either side of the block's `nr` zones. If `deviations`
+ * is true, the array holds deviations from an equilibrium rather than
+ * states, so it is reconstructed one component at a time, and the edge
+ * values are not checked for positive density and pressure.
+ */
+fn radial_faces<H, P>(hydro: &H, pe: &Array<P, Ix2>, ng: usize, nr: usize, reconstruction: Reconstruction, deviations: bool) -> (Array<P, Ix2>, Array<P, Ix2>)
+where
+    H: Hydrodynamics<Primitive = P>,
+    P: Primitive
+{
+    match reconstruction {
+        Reconstruction::PLM => {
+            // The gradient array is offset by one row from the extended
+            // array, since it lacks the outermost guard zones.
+            let gx = if deviations {
+                ndarray_ops::map_stencil3(pe, Axis(0), |a, b, c| hydro.plm_gradient_primitive(a, b, c))
+            } else {
+                ndarray_ops::map_stencil3(pe, Axis(0), |a, b, c| hydro.plm_gradient_primitive_along(a, b, c, Direction::Radial))
+            };
+            let pxl = pe.slice(s![ng - 1..nr + ng,     ..]);
+            let pxr = pe.slice(s![ng    ..nr + ng + 1, ..]);
+            let gxl = gx.slice(s![ng - 2..nr + ng - 1, ..]);
+            let gxr = gx.slice(s![ng - 1..nr + ng,     ..]);
+            (
+                Array::from_shape_fn(pxl.dim(), |i| pxl[i] + gxl[i] * 0.5),
+                Array::from_shape_fn(pxr.dim(), |i| pxr[i] - gxr[i] * 0.5),
+            )
+        }
+        _ => {
+            let (pel, peu) = if deviations {
+                interpolate_primitive_edges(hydro, pe, Axis(0), reconstruction)
+            } else {
+                reconstruct_primitive_edges(hydro, pe, Axis(0), reconstruction)
+            };
+            (
+                peu.slice(s![ng - 1..nr + ng,     ..]).to_owned(),
+                pel.slice(s![ng    ..nr + ng + 1, ..]).to_owned(),
+            )
+        }
+    }
+}
+
+/**
+ * States in the zones of an extended primitive array for the MUSCL-Hancock
+ * integrator, indexed like the extended array. The face states are pairs of
+ * the primitive state and the scalar.
+ */
+struct HancockStates<P> {
+    inner: Array<(P, f64), Ix2>,
+    outer: Array<(P, f64), Ix2>,
+    lower: Array<(P, f64), Ix2>,
+    upper: Array<(P, f64), Ix2>,
+    center: Array<P, Ix2>,
+}
+
+/**
+ * Return the MUSCL-Hancock states in the zones of the extended primitive and
+ * scalar arrays `pe` and `se`: the PLM states on the faces of each zone, and
+ * the zone state, advanced by half a time step with the physical fluxes of
+ * the face states through the zone's own faces, and the geometrical source
+ * terms. No Riemann problems are solved. The guard zones are given radial
+ * vertices which continue the spacing of the block's own. The outermost
+ * rows, which have no slopes, and states which would become unphysical, are
+ * left unchanged.
+ */
+fn hancock_states<H, C, P>(hydro: &H, pe: &Array<P, Ix2>, se: &Array<f64, Ix2>, geometry: &GridGeometry, ng: usize, dt: f64, one_dimensional: bool) -> HancockStates<P>
+where
+    H: Hydrodynamics<Conserved = C, Primitive = P>,
+    C: Conserved,
+    P: Primitive
+{
+    let (n, nq) = pe.dim();
+    let rv = &geometry.radial_vertices;
+    let nv = rv.len();
+    let inner_ratio = if nv > 2 { (rv[2] - rv[1]) / (rv[1] - rv[0]) } else { 1.0 };
+    let outer_ratio = if nv > 2 { (rv[nv - 1] - rv[nv - 2]) / (rv[nv - 2] - rv[nv - 3]) } else { 1.0 };
+    let mut vertices = vec![0.0; n + 1];
+
+    for k in 0..nv {
+        vertices[ng + k] = rv[k]
+    }
+    for k in (0..ng).rev() {
+        let dr = (vertices[k + 2] - vertices[k + 1]) / inner_ratio;
+        vertices[k] = (vertices[k + 1] - dr).max(0.5 * vertices[k + 1]);
+    }
+    for k in ng + nv..n + 1 {
+        vertices[k] = vertices[k - 1] + (vertices[k - 1] - vertices[k - 2]) * outer_ratio;
+    }
+    let extended = GridGeometry::from_vertices(Array::from(vertices), geometry.polar_vertices.to_owned());
+
+    // The radial gradient arrays are offset by one row from the extended
+    // arrays, since they lack the outermost rows.
+    let gx = ndarray_ops::map_stencil3(pe, Axis(0), |a, b, c| hydro.plm_gradient_primitive_along(a, b, c, Direction::Radial));
+    let hx = ndarray_ops::map_stencil3(se, Axis(0), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
+    let (gy, hy) = if one_dimensional {
+        (None, None)
+    } else {
+        let gy = ndarray_ops::map_stencil3(pe, Axis(1), |a, b, c| hydro.plm_gradient_primitive_along(a, b, c, Direction::Polar));
+        let hy = ndarray_ops::map_stencil3(se, Axis(1), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
+        (Some(ndarray_ops::extend_default_2d(gy, 0, 0, 1, 1)), Some(ndarray_ops::extend_default_2d(hy, 0, 0, 1, 1)))
+    };
+
+    let flux = |(q, s): (P, f64), direction: Direction| hydro.intercell_flux(q, q, s, s, direction);
+    let advance = |(q, s): (P, f64), du: C, ds: f64| {
+        match hydro.try_to_primitive(hydro.to_conserved(q) + du) {
+            Ok(q1) if hydro.any(&q1).mass_density > 0.0 && hydro.any(&q1).gas_pressure > 0.0 => {
+                (q1, (s * q.lorentz_factor() + ds) / q1.lorentz_factor())
+            }
+            _ => (q, s),
+        }
+    };
+    let unchanged = Array::from_shape_fn((n, nq), |i| (pe[i], se[i]));
+    let mut states = HancockStates {
+        inner: unchanged.clone(),
+        outer: unchanged.clone(),
+        lower: unchanged.clone(),
+        upper: unchanged,
+        center: pe.clone(),
+    };
+
+    for k in 1..n - 1 {
+        for j in 0..nq {
+            let (p, s) = (pe[(k, j)], se[(k, j)]);
+            let dv = extended.cell_volumes[(k, j)];
+            let (g, h) = (gx[(k - 1, j)], hx[(k - 1, j)]);
+            let (inner, outer) = ((p - g * 0.5, s - h * 0.5), (p + g * 0.5, s + h * 0.5));
+            let (fi, fo) = (flux(inner, Direction::Radial), flux(outer, Direction::Radial));
+            let (ai, ao) = (extended.radial_face_areas[(k, j)], extended.radial_face_areas[(k + 1, j)]);
+
+            let mut du = hydro.geometrical_source_terms(p, extended.cell_centers[(k, j)]) * dv - (fo.0 * ao - fi.0 * ai);
+            let mut ds = fi.1 * ai - fo.1 * ao;
+            let mut faces = None;
+
+            if let (Some(gy), Some(hy)) = (&gy, &hy) {
+                let (g, h) = (gy[(k, j)], hy[(k, j)]);
+                let (lower, upper) = ((p - g * 0.5, s - h * 0.5), (p + g * 0.5, s + h * 0.5));
+                let (fl, fu) = (flux(lower, Direction::Polar), flux(upper, Direction::Polar));
+                let (al, au) = (extended.polar_face_areas[(k, j)], extended.polar_face_areas[(k, j + 1)]);
+                du = du - (fu.0 * au - fl.0 * al);
+                ds += fl.1 * al - fu.1 * au;
+                faces = Some((lower, upper));
+            }
+            let (du, ds) = (du * (0.5 * dt / dv), ds * (0.5 * dt / dv));
+
+            states.inner[(k, j)] = advance(inner, du, ds);
+            states.outer[(k, j)] = advance(outer, du, ds);
+            states.center[(k, j)] = advance((p, s), du, ds).0;
+
+            if let Some((lower, upper)) = faces {
+                states.lower[(k, j)] = advance(lower, du, ds);
+                states.upper[(k, j)] = advance(upper, du, ds);
+            }
+        }
+    }
+    states
+}
+
+/**
+ * Return the primitive state beyond an edge of the polar extent, given the
+ * state in the zone next to it: the mirror image of the state for a
+ * reflecting wall, or the state itself for an outflow boundary.
+ */
+fn polar_guard_state<H, P>(hydro: &H, p: P, boundary: PolarBoundary) -> P
+where
+    H: Hydrodynamics<Primitive = P>,
+    P: Primitive
+{
+    match boundary {
+        PolarBoundary::Reflecting => {
+            let a = hydro.any(&p);
+            hydro.interpret(&AnyPrimitive { velocity_q: -a.velocity_q, ..a })
+        }
+        PolarBoundary::Outflow => p,
+    }
+}
+
+/**
+ * Return an array which is true in zones inside strong shocks, in either
+ * direction: zones whose two neighbors approach each other, and whose
+ * pressures differ by more than the given fraction of the smaller one.
+ */
+fn shock_zones<H, P>(hydro: &H, p: &Array<P, Ix2>, threshold: f64) -> Array<bool, Ix2>
+where
+    H: Hydrodynamics<Primitive = P>,
+    P: Primitive
+{
+    let a = p.map(|p| hydro.any(p));
+    let (n0, n1) = a.dim();
+    let compressed = |l: &AnyPrimitive, r: &AnyPrimitive, dv: f64| {
+        dv < 0.0 && (r.gas_pressure - l.gas_pressure).abs() > threshold * l.gas_pressure.min(r.gas_pressure)
+    };
+    Array::from_shape_fn((n0, n1), |(i, j)| {
+        let radial = i > 0 && i + 1 < n0 && {
+            let (l, r) = (&a[(i - 1, j)], &a[(i + 1, j)]);
+            compressed(l, r, r.velocity_r - l.velocity_r)
+        };
+        let polar = j > 0 && j + 1 < n1 && {
+            let (l, r) = (&a[(i, j - 1)], &a[(i, j + 1)]);
+            compressed(l, r, r.velocity_q - l.velocity_q)
+        };
+        radial || polar
+    })
+}
+
+/**
+ * Return an array which is true in zones where the given array, or any of
+ * the eight zones around it, is true.
+ */
+fn dilate(flags: &Array<bool, Ix2>) -> Array<bool, Ix2> {
+    let (n0, n1) = flags.dim();
+    Array::from_shape_fn((n0, n1), |(i, j)| {
+        (i.saturating_sub(1)..(i + 2).min(n0)).any(|a| (j.saturating_sub(1)..(j + 2).min(n1)).any(|b| flags[(a, b)]))
+    })
+}
+
+/**
+ * Average the conserved quantities and the scalar mass over the merged axis
+ * group at each pole, in each row of a block, weighting by the zone volumes.
+ * The totals in each group are unchanged.
+ */
+fn merge_axis_zones<C: Conserved>(conserved: &mut Array<C, Ix2>, scalar_mass: &mut Array<f64, Ix2>, geometry: &GridGeometry) {
+    let (nr, nq) = conserved.dim();
+    let (north, south) = geometry.axis_zones;
+
+    for group in [0..north, nq - south..nq].iter().filter(|group| group.len() > 1) {
+        for i in 0..nr {
+            let dv = &geometry.cell_volumes;
+            let volume: f64 = group.clone().map(|j| dv[(i, j)]).sum();
+            let total = group.clone().skip(1).fold(conserved[(i, group.start)], |sum, j| sum + conserved[(i, j)]);
+            let total_scalar: f64 = group.clone().map(|j| scalar_mass[(i, j)]).sum();
+
+            for j in group.clone() {
+                conserved[(i, j)] = total * (dv[(i, j)] / volume);
+                scalar_mass[(i, j)] = total_scalar * dv[(i, j)] / volume;
+            }
+        }
+    }
+}
+
+/**
+ * Sum each group of `n` consecutive entries in the given array.
+ */
+fn sum_groups<T>(x: &Array<T, Ix1>, n: usize) -> Array<T, Ix1>
+where
+    T: Copy + Add<Output = T>
+{
+    Array::from_shape_fn(x.len() / n, |j| (1..n).fold(x[j * n], |sum, b| sum + x[j * n + b]))
+}
+
+/**
+ * Correct the updated blocks next to finer blocks, so that the fluxes through
+ * the faces they share are the sums of the fine-block fluxes. This makes the
+ * update conservative at the boundaries between refinement levels. Frozen
+ * blocks, which have no face fluxes, are left alone.
+ */
+fn reflux<C: Conserved>(solution: &mut HashMap<BlockIndex, BlockState<C>>, faces: &HashMap<BlockIndex, BlockFaces<C>>, dt: f64) {
+    let num_rows: HashMap<_, _> = solution.iter().map(|(&index, block)| (index, block.conserved.dim().0)).collect();
+
+    for (index, block) in solution.iter_mut() {
+        let nr = num_rows[index];
+        let il = (index.0 - 1, index.1);
+        let ir = (index.0 + 1, index.1);
+        let finer = |neighbor: &BlockIndex| faces.contains_key(neighbor) && num_rows.get(neighbor).map_or(false, |&n| n > nr);
+
+        if !faces.contains_key(index) || (!finer(&il) && !finer(&ir)) {
+            continue
+        }
+        let mut conserved = block.conserved.to_owned();
+        let mut scalar_mass = block.scalar_mass.to_owned();
+        let nq = conserved.dim().1;
+
+        if finer(&il) {
+            let (fc, gc) = &faces[index].inner;
+            let (ff, gf) = &faces[&il].outer;
+            let ff = sum_groups(ff, ff.len() / nq);
+            let gf = sum_groups(gf, gf.len() / nq);
+
+            for j in 0..nq {
+                conserved[(0, j)] = conserved[(0, j)] + (ff[j] - fc[j]) * dt;
+                scalar_mass[(0, j)] += (gf[j] - gc[j]) * dt;
+            }
+        }
+        if finer(&ir) {
+            let (fc, gc) = &faces[index].outer;
+            let (ff, gf) = &faces[&ir].inner;
+            let ff = sum_groups(ff, ff.len() / nq);
+            let gf = sum_groups(gf, gf.len() / nq);
+
+            for j in 0..nq {
+                conserved[(nr - 1, j)] = conserved[(nr - 1, j)] + (fc[j] - ff[j]) * dt;
+                scalar_mass[(nr - 1, j)] += (gc[j] - gf[j]) * dt;
+            }
+        }
+        block.conserved = conserved.to_shared();
+        block.scalar_mass = scalar_mass.to_shared();
+    }
+}
+
+/**
+ * Make the time-integrated fluxes through the faces between blocks on
+ * different rungs agree, after an outer step with local time stepping. Of two
+ * such neighbors, the block which is coarser in space, or if they are at the
+ * same level, the one on the higher rung, is corrected to the fluxes of the
+ * other.
+ */
+fn correct_rung_interfaces<C: Conserved>(solution: &mut HashMap<BlockIndex, BlockState<C>>, fluxes: &HashMap<BlockIndex, BlockFaces<C>>, rungs: &HashMap<BlockIndex, u32>) {
+    let num_polar: HashMap<_, _> = solution.iter().map(|(index, block)| (*index, block.conserved.dim().1)).collect();
+
+    let defers_to = |index: &BlockIndex, neighbor: &BlockIndex| {
+        fluxes.contains_key(index) && fluxes.contains_key(neighbor) && rungs[index] != rungs[neighbor] && match num_polar[neighbor].cmp(&num_polar[index]) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => rungs[neighbor] < rungs[index],
+        }
+    };
+
+    for (index, block) in solution.iter_mut() {
+        let il = (index.0 - 1, index.1);
+        let ir = (index.0 + 1, index.1);
+
+        if !defers_to(index, &il) && !defers_to(index, &ir) {
+            continue
+        }
+        let mut conserved = block.conserved.to_owned();
+        let mut scalar_mass = block.scalar_mass.to_owned();
+        let (nr, nq) = conserved.dim();
+
+        if defers_to(index, &il) {
+            let (fc, gc) = &fluxes[index].inner;
+            let (ff, gf) = &fluxes[&il].outer;
+            let ff = sum_groups(ff, ff.len() / nq);
+            let gf = sum_groups(gf, gf.len() / nq);
+
+            for j in 0..nq {
+                conserved[(0, j)] = conserved[(0, j)] + (ff[j] - fc[j]);
+                scalar_mass[(0, j)] += gf[j] - gc[j];
+            }
+        }
+        if defers_to(index, &ir) {
+            let (fc, gc) = &fluxes[index].outer;
+            let (ff, gf) = &fluxes[&ir].inner;
+            let ff = sum_groups(ff, ff.len() / nq);
+            let gf = sum_groups(gf, gf.len() / nq);
+
+            for j in 0..nq {
+                conserved[(nr - 1, j)] = conserved[(nr - 1, j)] + (fc[j] - ff[j]);
+                scalar_mass[(nr - 1, j)] += gc[j] - gf[j];
+            }
+        }
+        block.conserved = conserved.to_shared();
+        block.scalar_mass = scalar_mass.to_shared();
+    }
+}
+
 
 
 
@@ -18,197 +874,1367 @@ async fn try_advance_rk<H, M, C, P>(
     model: &M,
     mesh: &Mesh,
     geometry: &HashMap<BlockIndex, GridGeometry>,
-    dt: f64,
-    runtime: &Runtime) -> anyhow::Result<State<C>, HydroError>
+    frozen: &HashSet<BlockIndex>,
+    dt: f64,
+    stage_weight: f64,
+    settings: &SchemeSettings,
+    sweep: Option<FaceSweep>,
+    ledger: Option<&FluxLedger<C>>,
+    runtime: &Runtime,
+    work: &WorkLedger) -> anyhow::Result<State<C>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C, Primitive = P>,
+    M: InitialModel,
+    C: Conserved,
+    P: Primitive
+{
+    let mut stage_map = HashMap::new();
+    let mut new_state_vec = Vec::new();
+    let time = state.time;
+    let split_sources = settings.source_splitting.is_some();
+    let muscl_hancock = matches!(settings.time_integrator, TimeIntegrator::MusclHancock);
+    let well_balanced = hydro.well_balanced();
+    let positivity = hydro.positivity();
+    let ambient_floors = positivity.map_or(false, |positivity| positivity.uses_ambient());
+
+    // The model state in each zone, which sets the floors with the Ambient
+    // strategy.
+    let ambient_state = |geometry: &GridGeometry| {
+        if ambient_floors {
+            Some(geometry.cell_centers.mapv(|c| model.primitive_at(c, time)))
+        } else {
+            None
+        }
+    };
+    // With moving faces, the blocks have the geometry at the time of the
+    // stage; the given geometry is that at the start of the step.
+    let stage_geometry = |geometry: &GridGeometry| match sweep {
+        Some(sweep) => geometry.expanded(sweep.expansion(time)),
+        None => geometry.clone(),
+    };
+    let mut stage_primitive_and_scalar = |index: BlockIndex, state: BlockState<C>, hydro: H, geometry: GridGeometry| {
+        let equilibrium = if well_balanced {
+            Some(geometry.cell_centers.mapv(|c| hydro.interpret(&model.primitive_at(c, time))).to_shared())
+        } else {
+            None
+        };
+        let ambient = ambient_state(&geometry);
+        let stage = async move {
+            let start = Instant::now();
+            let (mut p, recovery) = state.try_to_primitive_reporting(&hydro, &geometry)?;
+            let mut floored = 0;
+
+            if let Some(positivity) = positivity {
+                for (i, p) in p.indexed_iter_mut() {
+                    let positivity = positivity.at(ambient.as_ref().map(|a| &a[i]));
+
+                    if let Some(clamped) = positivity.clamp(&hydro.any(p)) {
+                        *p = hydro.interpret(&clamped);
+                        floored += 1;
+                    }
+                }
+            }
+            let s = state.scalar_mass / &geometry.cell_volumes / p.map(P::lorentz_factor);
+            let seconds = start.elapsed().as_secs_f64();
+            Ok::<_, HydroError>( ( p.to_shared(), s.to_shared(), recovery.to_shared(), floored, seconds, equilibrium ) )
+        };
+        stage_map.insert(index, runtime.spawn(stage).map(|f| f.unwrap()).shared());
+    };
+
+    let schedule = work.schedule(state.solution.keys().cloned());
+
+    for index in &schedule {
+        stage_primitive_and_scalar(*index, state.solution[index].clone(), hydro.clone(), stage_geometry(&geometry[index]))
+    }
+
+    let one_dimensional = mesh.one_dimensional();
+    let (inner_bnd_index, outer_bnd_index) = state.inner_outer_boundary_indexes();
+    let scale = sweep.map_or(state.scale_factor, |sweep| sweep.scale * sweep.expansion(time));
+    let inner_bnd_geom = mesh.subgrid(inner_bnd_index).geometry().expanded(scale);
+    let outer_bnd_geom = mesh.subgrid(outer_bnd_index).geometry().expanded(scale);
+    let inner_bnd_state = BlockState::from_model_boundary(model, hydro, &inner_bnd_geom, state.time, BoundaryEdge::Inner);
+    let outer_bnd_state = BlockState::from_model_boundary(model, hydro, &outer_bnd_geom, state.time, BoundaryEdge::Outer);
+    stage_primitive_and_scalar(inner_bnd_index, inner_bnd_state, hydro.clone(), inner_bnd_geom);
+    stage_primitive_and_scalar(outer_bnd_index, outer_bnd_state, hydro.clone(), outer_bnd_geom);
+
+    // Putting the stage map under Arc can be important for performance.
+    // Without it, the map is deep-copied for each block in the loop below.
+    // Although each entry is a lightweight object, the map itself can have
+    // ~1000 entries, and cloning it ~1000 times can induce measurable
+    // overhead.
+    let stage_map = Arc::new(stage_map);
+
+    // The source term budget is recorded for each Runge-Kutta stage, and
+    // weighted by the stage's share of the full update.
+    let budget_weight = dt * stage_weight;
+    let (inner_block_index, outer_block_index) = state.inner_outer_block_indexes();
+    let num_guard = mesh.num_guard_zones;
+    let polar_boundary = mesh.polar_boundary;
+    let reconstruction = hydro.reconstruction();
+    let shock_flattening = hydro.shock_flattening();
+    let carbuncle_fix = hydro.carbuncle_fix();
+    let consistent_scalar = hydro.consistent_scalar_advection();
+
+    for (&index, state) in schedule.iter().filter(|index| !frozen.contains(index)).map(|index| (index, &state.solution[index])) {
+
+        let hydro = hydro.clone();
+        let state = state.clone();
+        let stage_map = stage_map.clone();
+
+        // The rate at which each radial face sweeps out volume, from its
+        // area and radius at the start of the step.
+        let swept_volume_rate = sweep.map(|sweep| {
+            let (rv, da) = (&geometry[&index].radial_vertices, &geometry[&index].radial_face_areas);
+            Array::from_shape_fn(da.dim(), |(f, j)| sweep.swept_volume_rate(da[(f, j)], rv[f]))
+        });
+        let geometry = stage_geometry(&geometry[&index]);
+        let acceleration = if split_sources {
+            None
+        } else {
+            Some(geometry.cell_centers.mapv(|c| model.acceleration_at(c, state.time)))
+        };
+
+        // The equilibrium primitive state on the radial faces, for
+        // well-balanced gravity.
+        let face_equilibrium = if well_balanced {
+            let (rv, cc) = (&geometry.radial_vertices, &geometry.cell_centers);
+            Some(Array::from_shape_fn(geometry.radial_face_areas.dim(), |(f, j)| {
+                hydro.interpret(&model.primitive_at((rv[f], cc[(0, j)].1), state.time))
+            }))
+        } else {
+            None
+        };
+        let ambient = ambient_state(&geometry);
+
+        let entry = async move {
+            let il = (index.0 - 1, index.1);
+            let i0 = (index.0,     index.1);
+            let ir = (index.0 + 1, index.1);
+
+            let (pl, sl, .., el) = stage_map[&il].clone().await?;
+            let (p0, s0, .., e0) = stage_map[&i0].clone().await?;
+            let (pr, sr, .., er) = stage_map[&ir].clone().await?;
+            let start = Instant::now();
+            let shape = p0.dim();
+            let (ng, nr) = (num_guard, shape.0);
+            let pe = concatenate(Axis(0), &[guard_zones(&pl, shape, ng, true).view(), p0.view(), guard_zones(&pr, shape, ng, false).view()]).unwrap();
+            let se = concatenate(Axis(0), &[guard_zones(&sl, shape, ng, true).view(), s0.view(), guard_zones(&sr, shape, ng, false).view()]).unwrap();
+
+            // The equilibrium primitive state in the zones of the extended
+            // array and on the radial faces, for well-balanced gravity.
+            let equilibrium = match (el, e0, er, face_equilibrium) {
+                (Some(el), Some(e0), Some(er), Some(pf_eq)) => {
+                    let pe_eq = concatenate(Axis(0), &[guard_zones(&el, shape, ng, true).view(), e0.view(), guard_zones(&er, shape, ng, false).view()]).unwrap();
+                    Some((pe_eq, pf_eq))
+                }
+                _ => None,
+            };
+
+            // Zones inside strong shocks are reconstructed at first order.
+            let shocked = shock_flattening.map(|threshold| shock_zones(&hydro, &pe, threshold));
+
+            // Faces of zones in or next to strong shocks use the HLLE solver,
+            // to suppress the carbuncle.
+            let hybrid = carbuncle_fix.map(|threshold| dilate(&shock_zones(&hydro, &pe, threshold)));
+
+            // With MUSCL-Hancock, the face states are advanced by half a time
+            // step before the Riemann problems are solved.
+            let hancock = if muscl_hancock {
+                Some(hancock_states(&hydro, &pe, &se, &geometry, ng, dt, one_dimensional))
+            } else {
+                None
+            };
+            let riemann = |pl: P, pr: P, sl: f64, sr: f64, direction: Direction, dissipative: bool| {
+                if dissipative {
+                    hydro.intercell_flux_hlle(pl, pr, sl, sr, direction)
+                } else {
+                    hydro.intercell_flux(pl, pr, sl, sr, direction)
+                }
+            };
+
+            // With consistent scalar advection, the scalar flux is the mass
+            // flux times the upwind face concentration, bounded by the
+            // concentrations `zl` and `zr` in the zones on either side. The
+            // scalar arrays hold the comoving scalar density, so they are
+            // divided by the mass density to get concentrations.
+            let concentration = |p: &P, s: f64| s / hydro.any(p).mass_density;
+            let scalar_flux = |f: C, sl: f64, sr: f64, zl: f64, zr: f64| {
+                let m = f.lab_frame_mass();
+                let s = if m > 0.0 { sl } else { sr };
+                (f, m * s.max(zl.min(zr)).min(zl.max(zr)))
+            };
+
+            // Compute the fluxes through the radial faces, and through the
+            // polar faces if the mesh is two-dimensional. Zones flagged in
+            // the given array, which is indexed like the extended primitive
+            // array, are reconstructed at first order.
+            let fluxes = |flat: Option<&Array<bool, Ix2>>| {
+
+                // The primitive states on either side of each radial face.
+                // With well-balanced gravity, the deviations from the
+                // equilibrium are reconstructed, and added to the equilibrium
+                // state on the face.
+                let (qxl, qxr) = match &equilibrium {
+                    None => radial_faces(&hydro, &pe, ng, nr, reconstruction, false),
+                    Some((pe_eq, pf_eq)) => {
+                        let de = Array::from_shape_fn(pe.dim(), |i| pe[i] - pe_eq[i]);
+                        let (dxl, dxr) = radial_faces(&hydro, &de, ng, nr, reconstruction, true);
+                        let physical = |q: P, zone: P| {
+                            let a = hydro.any(&q);
+                            if a.mass_density > 0.0 && a.gas_pressure > 0.0 { q } else { zone }
+                        };
+                        (
+                            Array::from_shape_fn(dxl.dim(), |(f, j)| physical(pf_eq[(f, j)] + dxl[(f, j)], pe[(ng - 1 + f, j)])),
+                            Array::from_shape_fn(dxr.dim(), |(f, j)| physical(pf_eq[(f, j)] + dxr[(f, j)], pe[(ng + f, j)])),
+                        )
+                    }
+                };
+
+                // The scalar on either side of each radial face.
+                let (txl, txr) = match reconstruction {
+                    Reconstruction::PLM => {
+                        let hx = ndarray_ops::map_stencil3(&se, Axis(0), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
+                        let sxl = se.slice(s![ng - 1..nr + ng,     ..]);
+                        let sxr = se.slice(s![ng    ..nr + ng + 1, ..]);
+                        let hxl = hx.slice(s![ng - 2..nr + ng - 1, ..]);
+                        let hxr = hx.slice(s![ng - 1..nr + ng,     ..]);
+                        (
+                            Array::from_shape_fn(sxl.dim(), |i| sxl[i] + hxl[i] * 0.5),
+                            Array::from_shape_fn(sxr.dim(), |i| sxr[i] - hxr[i] * 0.5),
+                        )
+                    }
+                    _ => {
+                        let (sel, seu) = reconstruct_edges(&se, Axis(0), reconstruction);
+                        (
+                            seu.slice(s![ng - 1..nr + ng,     ..]).to_owned(),
+                            sel.slice(s![ng    ..nr + ng + 1, ..]).to_owned(),
+                        )
+                    }
+                };
+
+                let (mut qxl, mut qxr, mut txl, mut txr) = (qxl, qxr, txl, txr);
+
+                if let Some(hancock) = &hancock {
+                    for (f, j) in ndarray::indices(qxl.dim()) {
+                        let (q, t) = hancock.outer[(ng - 1 + f, j)];
+                        qxl[(f, j)] = q;
+                        txl[(f, j)] = t;
+                        let (q, t) = hancock.inner[(ng + f, j)];
+                        qxr[(f, j)] = q;
+                        txr[(f, j)] = t;
+                    }
+                }
+
+                if let Some(flat) = flat {
+                    for (f, j) in ndarray::indices(qxl.dim()) {
+                        if flat[(ng - 1 + f, j)] {
+                            qxl[(f, j)] = pe[(ng - 1 + f, j)];
+                            txl[(f, j)] = se[(ng - 1 + f, j)];
+                        }
+                        if flat[(ng + f, j)] {
+                            qxr[(f, j)] = pe[(ng + f, j)];
+                            txr[(f, j)] = se[(ng + f, j)];
+                        }
+                    }
+                }
+
+                let godunov_x = Array::from_shape_fn(qxl.dim(), |(f, j)| {
+                    let dissipative = hybrid.as_ref().map_or(false, |h| h[(ng - 1 + f, j)] || h[(ng + f, j)]);
+                    let flux = riemann(qxl[(f, j)], qxr[(f, j)], txl[(f, j)], txr[(f, j)], Direction::Radial, dissipative);
+                    let (l, r) = ((ng - 1 + f, j), (ng + f, j));
+
+                    if consistent_scalar {
+                        scalar_flux(flux.0, concentration(&qxl[(f, j)], txl[(f, j)]), concentration(&qxr[(f, j)], txr[(f, j)]), concentration(&pe[l], se[l]), concentration(&pe[r], se[r]))
+                    } else {
+                        flux
+                    }
+                });
+
+                let mut fx = godunov_x.mapv(|(f, _)| f) * &geometry.radial_face_areas;
+                let mut gx = godunov_x.mapv(|(_, g)| g) * &geometry.radial_face_areas;
+
+                // A moving face sweeps the material on the side it moves
+                // into over to the other side.
+                if let Some(rate) = &swept_volume_rate {
+                    for (f, j) in ndarray::indices(fx.dim()) {
+                        let (q, t) = if rate[(f, j)] > 0.0 { (qxr[(f, j)], txr[(f, j)]) } else { (qxl[(f, j)], txl[(f, j)]) };
+                        fx[(f, j)] = fx[(f, j)] - hydro.to_conserved(q) * rate[(f, j)];
+                        gx[(f, j)] -= t * q.lorentz_factor() * rate[(f, j)];
+                    }
+                }
+
+                if one_dimensional {
+                    return (fx, gx, None)
+                }
+                let nq = p0.dim().1;
+
+                // The primitive and scalar states on either side of each
+                // polar face between the zones.
+                let (qyl, qyr, tyl, tyr) = match reconstruction {
+                    Reconstruction::PLM => {
+                        let gy = ndarray_ops::map_stencil3(&pe, Axis(1), |a, b, c| hydro.plm_gradient_primitive_along(a, b, c, Direction::Polar));
+                        let gy = ndarray_ops::extend_default_2d(gy, 0, 0, 1, 1);
+                        let hy = ndarray_ops::map_stencil3(&se, Axis(1), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
+                        let hy = ndarray_ops::extend_default_2d(hy, 0, 0, 1, 1);
+
+                        let pyl = pe.slice(s![ng..nr + ng,  ..-1]);
+                        let pyr = pe.slice(s![ng..nr + ng, 1..  ]);
+                        let gyl = gy.slice(s![ng..nr + ng,  ..-1]);
+                        let gyr = gy.slice(s![ng..nr + ng, 1..  ]);
+                        let syl = se.slice(s![ng..nr + ng,  ..-1]);
+                        let syr = se.slice(s![ng..nr + ng, 1..  ]);
+                        let hyl = hy.slice(s![ng..nr + ng,  ..-1]);
+                        let hyr = hy.slice(s![ng..nr + ng, 1..  ]);
+                        (
+                            Array::from_shape_fn(pyl.dim(), |i| pyl[i] + gyl[i] * 0.5),
+                            Array::from_shape_fn(pyr.dim(), |i| pyr[i] - gyr[i] * 0.5),
+                            Array::from_shape_fn(syl.dim(), |i| syl[i] + hyl[i] * 0.5),
+                            Array::from_shape_fn(syr.dim(), |i| syr[i] - hyr[i] * 0.5),
+                        )
+                    }
+                    _ => {
+                        // The stencils extend past the polar edges into
+                        // copies of the edge zones. The fluxes through the
+                        // edges themselves are set below.
+                        let pp = pad_polar(pe.slice(s![ng..nr + ng, ..]), 2);
+                        let sp = pad_polar(se.slice(s![ng..nr + ng, ..]), 2);
+                        let (ppl, ppu) = reconstruct_primitive_edges(&hydro, &pp, Axis(1), reconstruction);
+                        let (spl, spu) = reconstruct_edges(&sp, Axis(1), reconstruction);
+                        (
+                            ppu.slice(s![.., 2..nq + 1]).to_owned(),
+                            ppl.slice(s![.., 3..nq + 2]).to_owned(),
+                            spu.slice(s![.., 2..nq + 1]).to_owned(),
+                            spl.slice(s![.., 3..nq + 2]).to_owned(),
+                        )
+                    }
+                };
+
+                let (mut qyl, mut qyr, mut tyl, mut tyr) = (qyl, qyr, tyl, tyr);
+
+                if let Some(hancock) = &hancock {
+                    for (i, j) in ndarray::indices(qyl.dim()) {
+                        let (q, t) = hancock.upper[(ng + i, j)];
+                        qyl[(i, j)] = q;
+                        tyl[(i, j)] = t;
+                        let (q, t) = hancock.lower[(ng + i, j + 1)];
+                        qyr[(i, j)] = q;
+                        tyr[(i, j)] = t;
+                    }
+                }
+
+                if let Some(flat) = flat {
+                    for (i, j) in ndarray::indices(qyl.dim()) {
+                        if flat[(ng + i, j)] {
+                            qyl[(i, j)] = pe[(ng + i, j)];
+                            tyl[(i, j)] = se[(ng + i, j)];
+                        }
+                        if flat[(ng + i, j + 1)] {
+                            qyr[(i, j)] = pe[(ng + i, j + 1)];
+                            tyr[(i, j)] = se[(ng + i, j + 1)];
+                        }
+                    }
+                }
+
+                let godunov_y = Array::from_shape_fn(qyl.dim(), |(i, j)| {
+                    let dissipative = hybrid.as_ref().map_or(false, |h| h[(ng + i, j)] || h[(ng + i, j + 1)]);
+                    let flux = riemann(qyl[(i, j)], qyr[(i, j)], tyl[(i, j)], tyr[(i, j)], Direction::Polar, dissipative);
+                    let (l, r) = ((ng + i, j), (ng + i, j + 1));
+
+                    if consistent_scalar {
+                        scalar_flux(flux.0, concentration(&qyl[(i, j)], tyl[(i, j)]), concentration(&qyr[(i, j)], tyr[(i, j)]), concentration(&pe[l], se[l]), concentration(&pe[r], se[r]))
+                    } else {
+                        flux
+                    }
+                });
+
+                // The fluxes through the polar edges of the mesh vanish on the
+                // poles, where the face area is zero. Away from the poles,
+                // the edges are reflecting walls or outflow boundaries.
+                let reflect = |p: P| polar_guard_state(hydro, p, polar_boundary);
+                let godunov_y = Array::from_shape_fn((p0.dim().0, nq + 1), |(i, j)| {
+                    if j == 0 {
+                        hydro.intercell_flux(reflect(p0[(i, 0)]), p0[(i, 0)], s0[(i, 0)], s0[(i, 0)], Direction::Polar)
+                    } else if j == nq {
+                        hydro.intercell_flux(p0[(i, nq - 1)], reflect(p0[(i, nq - 1)]), s0[(i, nq - 1)], s0[(i, nq - 1)], Direction::Polar)
+                    } else {
+                        godunov_y[(i, j - 1)]
+                    }
+                });
+
+                let fy = godunov_y.mapv(|(f, _)| f) * &geometry.polar_face_areas;
+                let gy = godunov_y.mapv(|(_, g)| g) * &geometry.polar_face_areas;
+
+                (fx, gx, Some((fy, gy)))
+            };
+
+            let (mut fx, mut gx, mut fy) = fluxes(shocked.as_ref());
+            let mut budget = Vec::new();
+
+            if index.0 == inner_block_index.0 {
+                budget.push((SourceTerm::InnerBoundary, ConservedTotals::from_conserved(fx.slice(s![0, ..]), budget_weight)));
+            }
+            if index.0 == outer_block_index.0 {
+                budget.push((SourceTerm::OuterBoundary, ConservedTotals::from_conserved(fx.slice(s![-1, ..]), -budget_weight)));
+            }
+
+            // With MUSCL-Hancock, the source terms are evaluated with the
+            // zone states at the half step.
+            let ps = match &hancock {
+                Some(hancock) => hancock.center.slice(s![ng..nr + ng, ..]),
+                None => p0.view(),
+            };
+
+            // Operator-split gravity and acceleration are applied outside of
+            // the hydro update, leaving only the geometrical source terms.
+            let sc = if split_sources {
+                ndarray::azip![&ps, &geometry.source_centers, &geometry.cell_volumes]
+                    .apply_collect(|&p, &c, &dv| hydro.geometrical_source_terms(p, c) * dv)
+            } else {
+                let acceleration = acceleration.as_ref().unwrap();
+
+                // With well-balanced gravity, the radial acceleration in each
+                // zone is replaced by the one which holds the equilibrium in
+                // place against the discretized pressure gradient, and the
+                // other source terms. Applied to the actual state, it scales
+                // with the inertia of the zone.
+                let balanced = equilibrium.as_ref().map(|(pe_eq, pf_eq)| {
+                    let fe = ndarray::azip![pf_eq, &geometry.radial_face_areas]
+                        .apply_collect(|&p, &da| hydro.intercell_flux(p, p, 0.0, 0.0, Direction::Radial).0.radial_momentum() * da);
+
+                    Array::from_shape_fn(p0.dim(), |(i, j)| {
+                        let (p, c, dv) = (pe_eq[(ng + i, j)], geometry.cell_centers[(i, j)], geometry.cell_volumes[(i, j)]);
+                        let mut balance = fe[(i + 1, j)] - fe[(i, j)] - hydro.geometrical_source_terms(p, geometry.source_centers[(i, j)]).radial_momentum() * dv;
+
+                        if !one_dimensional {
+                            balance -= hydro.gravitational_source_terms(p, c).radial_momentum() * dv
+                        }
+                        let inertia = hydro.acceleration_source_terms(p, (1.0, 0.0)).radial_momentum() * dv;
+                        (balance / inertia, acceleration[(i, j)].1)
+                    })
+                });
+                let acceleration = balanced.as_ref().unwrap_or(acceleration);
+                let sa = ndarray::azip![&ps, acceleration, &geometry.cell_volumes]
+                    .apply_collect(|&p, &a, &dv| hydro.acceleration_source_terms(p, a) * dv);
+                budget.push((SourceTerm::Acceleration, ConservedTotals::from_conserved(&sa, budget_weight)));
+
+                if one_dimensional {
+                    ndarray::azip![&ps, &geometry.source_centers, &geometry.cell_volumes, &sa]
+                        .apply_collect(|&p, &c, &dv, &a| hydro.geometrical_source_terms(p, c) * dv + a)
+                } else {
+                    let sg = ndarray::azip![&ps, &geometry.cell_centers, &geometry.cell_volumes]
+                        .apply_collect(|&p, &c, &dv| hydro.gravitational_source_terms(p, c) * dv);
+                    budget.push((SourceTerm::Gravity, ConservedTotals::from_conserved(&sg, budget_weight)));
+
+                    ndarray::azip![
+                        &ps,
+                        &geometry.source_centers,
+                        &geometry.cell_volumes,
+                        &sg,
+                        &sa]
+                    .apply_collect(|&p, &c, &dv, &g, &a| hydro.geometrical_source_terms(p, c) * dv + g + a)
+                }
+            };
+
+            // Return the changes in the conserved quantities and the scalar
+            // mass over the time step, given the face fluxes.
+            let update = |fx: &Array<C, Ix2>, gx: &Array<f64, Ix2>, fy: &Option<(Array<C, Ix2>, Array<f64, Ix2>)>| match fy {
+                None => {
+                    let du = ndarray::azip![&sc, fx.slice(s![..-1,..]), fx.slice(s![ 1..,..])].apply_collect(|&s, &a, &b| (s - (b - a)) * dt);
+                    let ds = ndarray::azip![     gx.slice(s![..-1,..]), gx.slice(s![ 1..,..])].apply_collect(|&a, &b| (b - a) * -dt);
+                    (du, ds)
+                }
+                Some((fy, gy)) => {
+                    let du = ndarray::azip![
+                        &sc,
+                        fx.slice(s![..-1,..]),
+                        fx.slice(s![ 1..,..]),
+                        fy.slice(s![..,..-1]),
+                        fy.slice(s![.., 1..])]
+                    .apply_collect(|&s, &a, &b, &c, &d| (s - (b - a) - (d - c)) * dt);
+
+                    let ds = ndarray::azip![
+                        gx.slice(s![..-1,..]),
+                        gx.slice(s![ 1..,..]),
+                        gy.slice(s![..,..-1]),
+                        gy.slice(s![.., 1..])]
+                    .apply_collect(|&a, &b, &c, &d| ((b - a) + (d - c)) * -dt);
+                    (du, ds)
+                }
+            };
+
+            let (mut du, mut ds) = update(&fx, &gx, &fy);
+            let mut counts = PositivityCounts::default();
+
+            if let Some(positivity) = positivity {
+                let unphysical = ndarray::azip![&state.conserved, &du].apply_collect(|&u, &du| {
+                    let u = u + du;
+                    u.lab_frame_mass() <= 0.0 || u.energy() <= 0.0
+                });
+                let num_unphysical = unphysical.iter().filter(|&&x| x).count();
+
+                // The fluxes are recomputed at first order in the zones which
+                // would become unphysical and their neighbors, so that every
+                // face of those zones gets a first-order flux.
+                if num_unphysical > 0 {
+                    let nq = p0.dim().1;
+                    let flagged = |i: i64, j: i64| {
+                        i >= 0 && j >= 0 && i < nr as i64 && j < nq as i64 && unphysical[(i as usize, j as usize)]
+                    };
+                    let flat = Array::from_shape_fn(pe.dim(), |(i, j)| {
+                        let (k, l) = (i as i64 - ng as i64, j as i64);
+                        shocked.as_ref().map_or(false, |shocked| shocked[(i, j)])
+                            || flagged(k, l)
+                            || flagged(k - 1, l)
+                            || flagged(k + 1, l)
+                            || flagged(k, l - 1)
+                            || flagged(k, l + 1)
+                    });
+                    let (mut fx_limited, mut gx_limited, fy_limited) = fluxes(Some(&flat));
+
+                    // The fluxes through the block's radial boundaries are
+                    // kept, so they still match those of the neighboring
+                    // blocks, and the update remains conservative.
+                    for &f in &[0, nr] {
+                        fx_limited.row_mut(f).assign(&fx.row(f));
+                        gx_limited.row_mut(f).assign(&gx.row(f));
+                    }
+                    fx = fx_limited;
+                    gx = gx_limited;
+                    fy = fy_limited;
+
+                    let (du_limited, ds_limited) = update(&fx, &gx, &fy);
+                    du = du_limited;
+                    ds = ds_limited;
+                    counts.limited += num_unphysical as u64;
+                }
+            }
+
+            let mut conserved = &state.conserved + &du;
+            let mut scalar_mass = &state.scalar_mass + &ds;
+            merge_axis_zones(&mut conserved, &mut scalar_mass, &geometry);
+
+            if let Some(positivity) = positivity {
+                for (i, u) in conserved.indexed_iter_mut() {
+                    if u.lab_frame_mass() <= 0.0 || u.energy() <= 0.0 {
+                        let floor = positivity.at(ambient.as_ref().map(|a| &a[i])).floor_primitive();
+                        *u = hydro.to_conserved(hydro.interpret(&floor)) * geometry.cell_volumes[i];
+                        counts.floored += 1;
+                    }
+                }
+            }
+
+            let new_state = BlockState {
+                conserved: conserved.to_shared(),
+                scalar_mass: scalar_mass.to_shared(),
+            };
+            let faces = BlockFaces {
+                inner: (fx.slice(s![0, ..]).to_owned(), gx.slice(s![0, ..]).to_owned()),
+                outer: (fx.slice(s![-1, ..]).to_owned(), gx.slice(s![-1, ..]).to_owned()),
+            };
+            Ok::<_, HydroError>((index, new_state, faces, start.elapsed().as_secs_f64(), budget, counts))
+        };
+        new_state_vec.push(runtime.spawn(entry));
+    }
+    let updated = join_all(new_state_vec).await
+        .into_iter()
+        .map(|f| f.unwrap())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.with_model())?;
+
+    let mut solution = HashMap::new();
+    let mut faces = HashMap::new();
+
+    let mut positivity_counts = state.positivity.clone();
+
+    for (index, new_state, block_faces, seconds, budget, counts) in updated {
+        let (_, _, recovery, floored, stage_seconds, _) = stage_map[&index].clone().await?;
+        work.record(index, seconds + stage_seconds, &recovery);
+
+        if positivity.is_some() {
+            let entry = positivity_counts.entry(index).or_default();
+            entry.limited += counts.limited;
+            entry.floored += counts.floored + floored;
+        }
+
+        for (term, totals) in budget {
+            work.record_source(term, totals);
+        }
+        solution.insert(index, new_state);
+        faces.insert(index, block_faces);
+    }
+
+    if let Some(ledger) = ledger {
+        let mut ledger = ledger.lock().unwrap();
+
+        for (index, block_faces) in &faces {
+            match ledger.get_mut(index) {
+                Some(total) => total.add_scaled(block_faces, budget_weight),
+                None => {
+                    ledger.insert(*index, block_faces.scaled(budget_weight));
+                }
+            }
+        }
+    }
+
+    for index in frozen {
+        if let Some(block) = state.solution.get(index) {
+            solution.insert(*index, block.clone());
+        }
+    }
+
+    if mesh.refinement.is_some() {
+        reflux(&mut solution, &faces, dt);
+    }
+
+    Ok(State {
+        time: state.time + dt,
+        iteration: state.iteration + 1,
+        solution: solution,
+        field_initializations: state.field_initializations.clone(),
+        excised: state.excised,
+        positivity: positivity_counts,
+        scale_factor: state.scale_factor,
+    })
+}
+
+
+
+
+// ============================================================================
+fn add_outer_block<H, M, C>(
+    state: &mut State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    geometry: &mut HashMap<BlockIndex, GridGeometry>)
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let (_, outer_index) = state.inner_outer_block_indexes();
+    let new_block_index = (outer_index.0 + 1, outer_index.1);
+    let new_block_geometry = mesh.subgrid_at_level(new_block_index, mesh.static_level(new_block_index)).geometry();
+    let new_block_state = BlockState::from_model(model, hydro, &new_block_geometry, state.time);
+
+    geometry.insert(new_block_index, new_block_geometry);
+    state.solution.insert(new_block_index, new_block_state);
+}
+
+fn remove_inner_block<C: Conserved>(state: &mut State<C>, geometry: &mut HashMap<BlockIndex, GridGeometry>) {
+    let (inner_index, _) = state.inner_outer_block_indexes();
+    geometry.remove(&inner_index);
+
+    if let Some(block) = state.solution.remove(&inner_index) {
+        state.excised.add_block(&block);
+    }
+}
+
+fn add_remove_blocks<H, M, C>(
+    state: &mut State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    geometry: &mut HashMap<BlockIndex, GridGeometry>)
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let (inner_index, outer_index) = state.inner_outer_block_indexes();
+
+    if mesh.subgrid_extent(inner_index).outer_radius < mesh.inner_excision_surface(state.time) && state.solution.len() > 1 {
+        remove_inner_block(state, geometry);
+    }
+
+    if mesh.subgrid_extent(outer_index).outer_radius < mesh.outer_excision_surface(state.time) {
+        add_outer_block(state, hydro, model, mesh, geometry);
+    }    
+}
+
+
+
+
+/**
+ * Add a block at the outer edge of the mesh if the outermost block has been
+ * reached by a shock, which is detected as a gas pressure above that of the
+ * initial model by the configured factor. If the mesh then spans more than
+ * the allowed number of decades in radius, the innermost block is removed.
+ */
+fn track_outer_shock<H, M, C>(
+    state: &mut State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    tracking: &ShockTracking,
+    geometry: &mut HashMap<BlockIndex, GridGeometry>) -> Result<(), HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let (_, outer_index) = state.inner_outer_block_indexes();
+    let outer_geometry = &geometry[&outer_index];
+    let shocked = state.solution[&outer_index]
+        .try_to_primitive(hydro, outer_geometry)?
+        .iter()
+        .zip(outer_geometry.cell_centers.iter())
+        .any(|(p, &c)| hydro.any(p).gas_pressure > tracking.pressure_ratio * model.primitive_at(c, state.time).gas_pressure);
+
+    if shocked {
+        add_outer_block(state, hydro, model, mesh, geometry);
+    }
+
+    if let Some(max_decades) = tracking.max_decades {
+        let (inner_index, outer_index) = state.inner_outer_block_indexes();
+        let r0 = mesh.subgrid_extent(inner_index).inner_radius;
+        let r1 = mesh.subgrid_extent(outer_index).outer_radius;
+
+        if (r1 / r0).log10() > max_decades && state.solution.len() > 1 {
+            remove_inner_block(state, geometry);
+        }
+    }
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+/**
+ * Return the largest difference between adjacent zones of a block, in either
+ * direction, relative to the smaller of the two values if `relative` is true.
+ */
+fn max_jump(x: &Array<f64, Ix2>, relative: bool) -> f64 {
+    let jump = |(a, b): (&f64, &f64)| if relative {
+        (a - b).abs() / a.min(*b)
+    } else {
+        (a - b).abs()
+    };
+    let radial = x.slice(s![..-1, ..]).iter().zip(x.slice(s![1.., ..]).iter()).map(jump);
+    let polar  = x.slice(s![.., ..-1]).iter().zip(x.slice(s![.., 1..]).iter()).map(jump);
+    radial.chain(polar).fold(0.0, f64::max)
+}
+
+
+
+
+/**
+ * Refine the blocks where the refinement indicator is above the refinement
+ * threshold, and coarsen those where it is below the coarsening threshold,
+ * by one level. The geometry of the affected blocks is updated.
+ */
+fn refine_coarsen_blocks<H, C>(
+    state: &mut State<C>,
+    hydro: &H,
+    mesh: &Mesh,
+    refinement: &Refinement,
+    geometry: &mut HashMap<BlockIndex, GridGeometry>) -> Result<(), HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved
+{
+    for (index, block) in state.solution.iter_mut() {
+        let level = block.level(mesh);
+        let indicator = match refinement.indicator {
+            RefinementIndicator::DensityGradient => {
+                let p = block.try_to_primitive(hydro, &geometry[index])?;
+                max_jump(&p.mapv(|p| hydro.any(&p).mass_density), true)
+            }
+            RefinementIndicator::ScalarContrast => {
+                let s = ndarray::azip![&block.scalar_mass, &block.conserved].apply_collect(|&s, u| s / u.lab_frame_mass());
+                max_jump(&s, false)
+            }
+        };
+
+        let new_block = if indicator > refinement.refine_threshold && level < refinement.max_level as i32 {
+            block.refined(mesh, *index)
+        } else if indicator < refinement.coarsen_threshold && level > mesh.static_level(*index) {
+            block.coarsened(mesh)
+        } else {
+            continue
+        };
+        geometry.insert(*index, mesh.subgrid_at_level(*index, new_block.level(mesh)).geometry());
+        *block = new_block;
+    }
+    Ok(())
+}
+
+
+
+
+/**
+ * Return the radius of the forward shock: the outermost zone center where the
+ * gas pressure exceeds the initial model pressure by the given factor, or
+ * zero if there is no such zone.
+ */
+fn forward_shock_radius<H, M, C>(
+    state: &State<C>,
+    hydro: &H,
+    model: &M,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    pressure_ratio: f64) -> Result<f64, HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let mut indexes: Vec<_> = state.solution.keys().cloned().collect();
+    indexes.sort();
+
+    for index in indexes.iter().rev() {
+        let geometry = &geometry[index];
+        let radius = state.solution[index]
+            .try_to_primitive(hydro, geometry)?
+            .iter()
+            .zip(geometry.cell_centers.iter())
+            .filter(|(p, c)| hydro.any(p).gas_pressure > pressure_ratio * model.primitive_at(**c, state.time).gas_pressure)
+            .map(|(_, &c)| c.0)
+            .fold(0.0, f64::max);
+
+        if radius > 0.0 {
+            return Ok(radius)
+        }
+    }
+    Ok(0.0)
+}
+
+
+
+
+/**
+ * Coarsen the blocks at or below the base resolution which are well behind
+ * the forward shock and smooth, by one level. Coarsened blocks which are no
+ * longer far enough behind the shock, or have become rough, are refined
+ * again. Blocks in refined regions are left alone.
+ */
+fn derefine_behind_shock<H, M, C>(
+    state: &mut State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    derefinement: &Derefinement,
+    geometry: &mut HashMap<BlockIndex, GridGeometry>) -> Result<(), HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let shock_radius = forward_shock_radius(state, hydro, model, geometry, derefinement.pressure_ratio)?;
+    let min_level = -(derefinement.max_levels as i32);
+
+    for (index, block) in state.solution.iter_mut() {
+        let level = block.level(mesh);
+
+        if level > 0 || mesh.static_level(*index) > 0 {
+            continue
+        }
+        let behind = mesh.subgrid_extent(*index).outer_radius < derefinement.shock_radius_fraction * shock_radius;
+        let p = block.try_to_primitive(hydro, &geometry[index])?;
+        let jump = max_jump(&p.mapv(|p| hydro.any(&p).mass_density), true);
+
+        let new_block = if behind && jump < derefinement.smoothness && level > min_level {
+            block.coarsened(mesh)
+        } else if level < 0 && (!behind || jump > 2.0 * derefinement.smoothness) {
+            block.refined(mesh, *index)
+        } else {
+            continue
+        };
+        geometry.insert(*index, mesh.subgrid_at_level(*index, new_block.level(mesh)).geometry());
+        *block = new_block;
+    }
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+/**
+ * Return the indexes of the blocks whose conserved mass and energy changed by
+ * less than the given fraction in every zone, between two states.
+ */
+fn quiescent_blocks<C: Conserved>(before: &State<C>, after: &State<C>, tolerance: f64) -> HashSet<BlockIndex> {
+    let unchanged = |u0: &C, u1: &C| {
+        (u1.lab_frame_mass() - u0.lab_frame_mass()).abs() <= tolerance * u0.lab_frame_mass().abs() &&
+        (u1.energy() - u0.energy()).abs() <= tolerance * u0.energy().abs()
+    };
+    after.solution
+        .iter()
+        .filter(|(index, block)| before.solution.get(index).map_or(false, |b| {
+            b.conserved.dim() == block.conserved.dim() && b.conserved.iter().zip(block.conserved.iter()).all(|(u0, u1)| unchanged(u0, u1))
+        }))
+        .map(|(&index, _)| index)
+        .collect()
+}
+
+
+
+
+/**
+ * Return the indexes of the blocks which are frozen: those which are
+ * quiescent, and whose two radial neighbors are too. Blocks at the edges of
+ * the mesh are never frozen. A frozen block is reactivated as soon as one of
+ * its neighbors is disturbed.
+ */
+fn frozen_blocks(quiescent: &HashSet<BlockIndex>) -> HashSet<BlockIndex> {
+    quiescent
+        .iter()
+        .filter(|index| quiescent.contains(&(index.0 - 1, index.1)) && quiescent.contains(&(index.0 + 1, index.1)))
+        .cloned()
+        .collect()
+}
+
+
+
+
+// ============================================================================
+/**
+ * Return the time step allowed by the CFL condition, and the block which sets
+ * it. With a global signal speed, the time step is set by the smallest zone
+ * spacing. Otherwise it is recomputed from the maximum signal speed in each
+ * block, in parallel on the runtime, so the step adapts to changes in the
+ * flow, such as spikes in the Lorentz factor.
+ */
+async fn try_time_step<H, C>(
+    state: &State<C>,
+    hydro: &H,
+    mesh: &Mesh,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    runtime: &Runtime) -> Result<(f64, BlockIndex), HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved
+{
+    if hydro.global_signal_speed().is_some() {
+        let (index, ..) = state.inner_outer_block_indexes();
+        return Ok((state.time_step(hydro, mesh)?, index))
+    }
+    Ok(smallest_time_step(&try_block_time_steps(state, hydro, geometry, runtime).await?))
+}
+
+/**
+ * Return the mass-weighted mean of v_r / r over the blocks, which sets the
+ * expansion rate of the mesh with the mean_flow face motion. The radial
+ * velocity in each zone is its radial mass flux over its lab-frame density.
+ */
+fn mean_expansion_rate<H, C, P>(state: &State<C>, hydro: &H, geometry: &HashMap<BlockIndex, GridGeometry>) -> Result<f64, HydroError>
 where
     H: Hydrodynamics<Conserved = C, Primitive = P>,
-    M: InitialModel,
     C: Conserved,
     P: Primitive
 {
-    let mut stage_map = HashMap::new();
-    let mut new_state_vec = Vec::new();
-    let mut stage_primitive_and_scalar = |index: BlockIndex, state: BlockState<C>, hydro: H, geometry: GridGeometry| {
-        let stage = async move {
-            let p = state.try_to_primitive(&hydro, &geometry)?;
-            let s = state.scalar_mass / &geometry.cell_volumes / p.map(P::lorentz_factor);
-            Ok::<_, HydroError>( ( p.to_shared(), s.to_shared() ) )
-        };
-        stage_map.insert(index, runtime.spawn(stage).map(|f| f.unwrap()).shared());
-    };
+    let (mut mass, mut weighted) = (0.0, 0.0);
 
-    for (index, state) in &state.solution {
-        stage_primitive_and_scalar(index.clone(), state.clone(), hydro.clone(), geometry[index].clone())
+    for (index, block) in &state.solution {
+        let geometry = &geometry[index];
+        let p = block.try_to_primitive(hydro, geometry)?;
+
+        for ((&p, &(r, _)), &dv) in p.iter().zip(geometry.cell_centers.iter()).zip(geometry.cell_volumes.iter()) {
+            let d = hydro.to_conserved(p).lab_frame_mass();
+            let v = hydro.intercell_flux(p, p, 0.0, 0.0, Direction::Radial).0.lab_frame_mass() / d;
+            mass += d * dv;
+            weighted += d * dv * v / r;
+        }
     }
+    Ok(if mass > 0.0 { weighted / mass } else { 0.0 })
+}
 
-    let one_dimensional = mesh.num_polar_zones == 1;
-    let (inner_bnd_index, outer_bnd_index) = state.inner_outer_boundary_indexes();
-    let inner_bnd_geom = mesh.subgrid(inner_bnd_index).geometry();
-    let outer_bnd_geom = mesh.subgrid(outer_bnd_index).geometry();
-    let inner_bnd_state = BlockState::from_model(model, hydro, &inner_bnd_geom, state.time);
-    let outer_bnd_state = BlockState::from_model(model, hydro, &outer_bnd_geom, state.time);
-    stage_primitive_and_scalar(inner_bnd_index, inner_bnd_state, hydro.clone(), inner_bnd_geom);
-    stage_primitive_and_scalar(outer_bnd_index, outer_bnd_state, hydro.clone(), outer_bnd_geom);
+/**
+ * Return the time step allowed by the CFL condition on each block, computed
+ * in parallel on the runtime.
+ */
+async fn try_block_time_steps<H, C>(
+    state: &State<C>,
+    hydro: &H,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    runtime: &Runtime) -> Result<HashMap<BlockIndex, f64>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved
+{
+    let block_dt = state.solution.iter().map(|(&index, block)| {
+        let (block, hydro, geometry) = (block.clone(), hydro.clone(), geometry[&index].clone());
+        runtime.spawn(async move {
+            let dt = match hydro.global_signal_speed() {
+                Some(speed) => geometry.cell_linear_dimension().iter().fold(f64::MAX, |dt, dl| dt.min(dl / speed)) * hydro.cfl_number(),
+                None => block.try_time_step(&hydro, &geometry)?,
+            };
+            Ok::<_, HydroError>((index, dt))
+        })
+    });
+    join_all(block_dt).await
+        .into_iter()
+        .map(|f| f.unwrap())
+        .collect()
+}
 
-    // Putting the stage map under Arc can be important for performance.
-    // Without it, the map is deep-copied for each block in the loop below.
-    // Although each entry is a lightweight object, the map itself can have
-    // ~1000 entries, and cloning it ~1000 times can induce measurable
-    // overhead.
-    let stage_map = Arc::new(stage_map);
+/**
+ * Return the smallest of the given block time steps, and the block it
+ * belongs to.
+ */
+fn smallest_time_step(block_dt: &HashMap<BlockIndex, f64>) -> (f64, BlockIndex) {
+    block_dt.iter().fold((f64::MAX, (0, 0)), |min, (&index, &dt)| if dt < min.0 { (dt, index) } else { min })
+}
 
-    for (&index, state) in &state.solution {
+/**
+ * Return the position of a zone whose mass or energy is not finite, if there
+ * is one.
+ */
+fn first_non_finite_zone<C: Conserved>(state: &State<C>, geometry: &HashMap<BlockIndex, GridGeometry>) -> Option<(f64, f64)> {
+    state.solution.iter().find_map(|(index, block)| {
+        block.conserved.iter()
+            .zip(geometry[index].cell_centers.iter())
+            .find(|(u, _)| !u.lab_frame_mass().is_finite() || !u.energy().is_finite())
+            .map(|(_, &c)| c)
+    })
+}
 
-        let hydro = hydro.clone();
-        let state = state.clone();
-        let stage_map = stage_map.clone();
-        let geometry = geometry[&index].clone();
 
-        let entry = async move {
-            let il = (index.0 - 1, index.1);
-            let i0 = (index.0,     index.1);
-            let ir = (index.0 + 1, index.1);
 
-            let (pl, sl) = stage_map[&il].clone().await?;
-            let (p0, s0) = stage_map[&i0].clone().await?;
-            let (pr, sr) = stage_map[&ir].clone().await?;
-            let pe = concatenate(Axis(0), &[pl.slice(s![-2.., ..]), p0.view(), pr.slice(s![..2, ..])]).unwrap();
-            let se = concatenate(Axis(0), &[sl.slice(s![-2.., ..]), s0.view(), sr.slice(s![..2, ..])]).unwrap();
-
-            let gx = ndarray_ops::map_stencil3(&pe, Axis(0), |a, b, c| hydro.plm_gradient_primitive(a, b, c));
-            let hx = ndarray_ops::map_stencil3(&se, Axis(0), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
-            let pxl = pe.slice(s![1..-2, ..]);
-            let pxr = pe.slice(s![2..-1, ..]);
-            let gxl = gx.slice(s![ ..-1, ..]);
-            let gxr = gx.slice(s![1..  , ..]);
-            let sxl = se.slice(s![1..-2, ..]);
-            let sxr = se.slice(s![2..-1, ..]);
-            let hxl = hx.slice(s![ ..-1, ..]);
-            let hxr = hx.slice(s![1..  , ..]);
-
-            let godunov_x = Array::from_shape_fn(pxl.dim(), |i| {
-                hydro.intercell_flux(
-                    pxl[i] + gxl[i] * 0.5, pxr[i] - gxr[i] * 0.5,
-                    sxl[i] + hxl[i] * 0.5, sxr[i] - hxr[i] * 0.5, Direction::Radial)
-            });
-
-            let fx = godunov_x.mapv(|(f, _)| f) * &geometry.radial_face_areas;
-            let gx = godunov_x.mapv(|(_, g)| g) * &geometry.radial_face_areas;
-
-            let (du, ds) = if one_dimensional {
-                let sc = ndarray::azip![&p0, &geometry.cell_centers, &geometry.cell_volumes]
-                    .apply_collect(|&p, &c, &dv| hydro.geometrical_source_terms(p, c) * dv);
-                let du = ndarray::azip![&sc, fx.slice(s![..-1,..]), fx.slice(s![ 1..,..])].apply_collect(|&s, &a, &b| (s - (b - a)) * dt);
-                let ds = ndarray::azip![     gx.slice(s![..-1,..]), gx.slice(s![ 1..,..])].apply_collect(|&a, &b| (b - a) * -dt);
-
-                (du, ds)
-            } else {
-                let gy = ndarray_ops::map_stencil3(&pe, Axis(1), |a, b, c| hydro.plm_gradient_primitive(a, b, c));
-                let gy = ndarray_ops::extend_default_2d(gy, 0, 0, 1, 1);
-                let hy = ndarray_ops::map_stencil3(&se, Axis(1), |a, b, c| hydro.plm_gradient_scalar(a, b, c));
-                let hy = ndarray_ops::extend_default_2d(hy, 0, 0, 1, 1);
-
-                let pyl = pe.slice(s![2..-2,  ..-1]);
-                let pyr = pe.slice(s![2..-2, 1..  ]);
-                let gyl = gy.slice(s![2..-2,  ..-1]);
-                let gyr = gy.slice(s![2..-2, 1..  ]);
-                let syl = se.slice(s![2..-2,  ..-1]);
-                let syr = se.slice(s![2..-2, 1..  ]);
-                let hyl = hy.slice(s![2..-2,  ..-1]);
-                let hyr = hy.slice(s![2..-2, 1..  ]);
-
-                let godunov_y = Array::from_shape_fn(pyl.dim(), |i| {
-                    hydro.intercell_flux(
-                        pyl[i] + gyl[i] * 0.5, pyr[i] - gyr[i] * 0.5,
-                        syl[i] + hyl[i] * 0.5, syr[i] - hyr[i] * 0.5, Direction::Polar)
+
+// ============================================================================
+/**
+ * Apply the operator-split source terms (gravity on two-dimensional meshes,
+ * and the model acceleration) to the blocks which are not frozen, over the
+ * time interval `dt`. The terms are applied one after the other, in reverse
+ * order if `reversed` is true, each with second-order (Heun) subcycles short
+ * enough to resolve the term's timescale in the block. The acceleration is
+ * evaluated at the given time, which should be the middle of the interval.
+ * The time of the state is not changed.
+ */
+async fn try_apply_split_sources<H, M, C>(
+    state: State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    frozen: &HashSet<BlockIndex>,
+    splitting: &SourceSplitting,
+    time: f64,
+    dt: f64,
+    reversed: bool,
+    runtime: &Runtime,
+    work: &WorkLedger) -> Result<State<C>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let mut terms = if mesh.one_dimensional() {
+        vec![SourceTerm::Acceleration]
+    } else {
+        vec![SourceTerm::Gravity, SourceTerm::Acceleration]
+    };
+    if reversed {
+        terms.reverse()
+    }
+
+    let updates = state.solution.iter().filter(|(index, _)| !frozen.contains(*index)).map(|(&index, block)| {
+        let (block, hydro, geometry) = (block.clone(), hydro.clone(), geometry[&index].clone());
+        let (terms, splitting) = (terms.clone(), splitting.clone());
+        let acceleration = geometry.cell_centers.mapv(|c| model.acceleration_at(c, time));
+
+        runtime.spawn(async move {
+            let mut block = block;
+            let mut budget = Vec::new();
+
+            for term in terms {
+                let rate = |block: &BlockState<C>| -> Result<Array<C, Ix2>, HydroError> {
+                    let p = block.try_to_primitive(&hydro, &geometry)?;
+                    Ok(match term {
+                        SourceTerm::Gravity => ndarray::azip![&p, &geometry.cell_centers, &geometry.cell_volumes]
+                            .apply_collect(|&p, &c, &dv| hydro.gravitational_source_terms(p, c) * dv),
+                        _ => ndarray::azip![&p, &acceleration, &geometry.cell_volumes]
+                            .apply_collect(|&p, &a, &dv| hydro.acceleration_source_terms(p, a) * dv),
+                    })
+                };
+                let mut s = rate(&block)?;
+                let mut added = Array::<C, Ix2>::default(s.dim());
+
+                let timescale = block.conserved.iter().zip(s.iter()).fold(f64::MAX, |t, (u, s)| {
+                    let tm = (u.lab_frame_mass() / s.lab_frame_mass()).abs();
+                    let te = (u.energy() / s.energy()).abs();
+                    t.min(tm).min(te)
                 });
+                let subcycles = ((dt / (splitting.timescale_fraction * timescale)).ceil() as usize).max(1).min(splitting.max_subcycles);
+                let h = dt / subcycles as f64;
+
+                for n in 0..subcycles {
+                    if n > 0 {
+                        s = rate(&block)?;
+                    }
+                    let predicted = BlockState {
+                        conserved: (&block.conserved + &s.mapv(|s| s * h)).to_shared(),
+                        scalar_mass: block.scalar_mass.clone(),
+                    };
+                    let s_mean = (&s + &rate(&predicted)?).mapv(|s| s * 0.5);
+                    block.conserved = (&block.conserved + &s_mean.mapv(|s| s * h)).to_shared();
+                    added = added + &s_mean;
+                }
+                budget.push((term, ConservedTotals::from_conserved(&added, h)));
+            }
+            Ok::<_, HydroError>((index, block, budget))
+        })
+    });
+
+    let updated = join_all(updates).await
+        .into_iter()
+        .map(|f| f.unwrap())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.with_model())?;
 
-                let fy = ndarray_ops::extend_default_2d(godunov_y.mapv(|(f, _)| f), 0, 0, 1, 1) * &geometry.polar_face_areas;
-                let gy = ndarray_ops::extend_default_2d(godunov_y.mapv(|(_, g)| g), 0, 0, 1, 1) * &geometry.polar_face_areas;
+    let mut state = state;
 
-                let sc = ndarray::azip![
-                    &p0,
-                    &geometry.cell_centers,
-                    &geometry.cell_volumes]
-                //.apply_collect(|&p, &c, &dv| hydro.geometrical_source_terms(p, c) * dv);
-                .apply_collect(|&p, &c, &dv| hydro.geometrical_source_terms(p, c) * dv + hydro.gravitational_source_terms(p, c) * dv);
+    for (index, block, budget) in updated {
+        for (term, totals) in budget {
+            work.record_source(term, totals);
+        }
+        state.solution.insert(index, block);
+    }
+    Ok(state)
+}
 
-                let du = ndarray::azip![
-                    &sc,
-                    fx.slice(s![..-1,..]),
-                    fx.slice(s![ 1..,..]),
-                    fy.slice(s![..,..-1]),
-                    fy.slice(s![.., 1..])]
-                .apply_collect(|&s, &a, &b, &c, &d| (s - (b - a) - (d - c)) * dt);
 
-                let ds = ndarray::azip![
-                    gx.slice(s![..-1,..]),
-                    gx.slice(s![ 1..,..]),
-                    gy.slice(s![..,..-1]),
-                    gy.slice(s![.., 1..])]
-                .apply_collect(|&a, &b, &c, &d| ((b - a) + (d - c)) * -dt);
 
-                (du, ds)
-            };
 
-            let new_state = BlockState {
-                conserved: (&state.conserved + &du).to_shared(),
-                scalar_mass: (&state.scalar_mass + &ds).to_shared(),
+// ============================================================================
+/**
+ * Apply the cooling, heating, and drag source terms over a time `dt` to the
+ * blocks which are not frozen, integrating each exactly in every zone. The
+ * drag is applied first, or last if `reversed` is true, so that the halves
+ * of a Strang split step are mirror images. For relativistic hydro, drag
+ * lowers the Lorentz factor, so the comoving density and pressure are raised
+ * with it to conserve the lab-frame mass at a fixed temperature.
+ */
+async fn try_apply_stiff_sources<H, C, P>(
+    state: State<C>,
+    hydro: &H,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    frozen: &HashSet<BlockIndex>,
+    stiff_sources: StiffSources,
+    dt: f64,
+    reversed: bool,
+    runtime: &Runtime,
+    work: &WorkLedger) -> Result<State<C>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C, Primitive = P>,
+    C: Conserved,
+    P: Primitive
+{
+    let updates = state.solution.iter().filter(|(index, _)| !frozen.contains(*index)).map(|(&index, block)| {
+        let (block, hydro, geometry) = (block.clone(), hydro.clone(), geometry[&index].clone());
+
+        runtime.spawn(async move {
+            let p = block.try_to_primitive(&hydro, &geometry)?;
+            let apply = |term: SourceTerm, p: &Array<P, Ix2>| match term {
+                SourceTerm::Drag => p.mapv(|p| {
+                    let a = stiff_sources.drag(&hydro.any(&p), dt);
+                    let w = p.lorentz_factor() / hydro.interpret(&a).lorentz_factor();
+                    hydro.interpret(&AnyPrimitive { mass_density: a.mass_density * w, gas_pressure: a.gas_pressure * w, ..a })
+                }),
+                _ => p.mapv(|p| hydro.interpret(&stiff_sources.heat_cool(&hydro.any(&p), dt))),
             };
-            Ok::<_, HydroError>((index, new_state))
-        };
-        new_state_vec.push(runtime.spawn(entry));
-    }
-    let solution = join_all(new_state_vec).await
+            let (first, second) = if reversed {
+                (SourceTerm::HeatingCooling, SourceTerm::Drag)
+            } else {
+                (SourceTerm::Drag, SourceTerm::HeatingCooling)
+            };
+            let p1 = apply(first, &p);
+            let p2 = apply(second, &p1);
+            let conserved = |p: &Array<P, Ix2>| ndarray::azip![p, &geometry.cell_volumes].apply_collect(|&p, &dv| hydro.to_conserved(p) * dv);
+            let (u1, u2) = (conserved(&p1), conserved(&p2));
+
+            let budget = vec![
+                (first, ConservedTotals::from_conserved(&(&u1 - &block.conserved), 1.0)),
+                (second, ConservedTotals::from_conserved(&(&u2 - &u1), 1.0)),
+            ];
+            let mut block = block;
+            block.conserved = u2.to_shared();
+            Ok::<_, HydroError>((index, block, budget))
+        })
+    });
+
+    let updated = join_all(updates).await
         .into_iter()
         .map(|f| f.unwrap())
-        .collect::<Result<_, _>>()
+        .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.with_model())?;
 
-    Ok(State {
-        time: state.time + dt,
-        iteration: state.iteration + 1,
-        solution: solution,
-    })
+    let mut state = state;
+
+    for (index, block, budget) in updated {
+        for (term, totals) in budget {
+            work.record_source(term, totals);
+        }
+        state.solution.insert(index, block);
+    }
+    Ok(state)
 }
 
 
 
 
 // ============================================================================
-fn add_remove_blocks<H, M, C>(
-    state: &mut State<C>,
+/**
+ * Advance the blocks which are not frozen by one step of length `dt`, with
+ * the time integrator in the given settings. If a flux ledger is given, the
+ * time-integrated fluxes through the inner and outer faces of the updated
+ * blocks are added to it. If a face sweep is given, the radial faces move
+ * over the step; the given geometry is that at the start of the step.
+ */
+async fn try_integrate<H, M, C>(
+    state: State<C>,
     hydro: &H,
     model: &M,
     mesh: &Mesh,
-    geometry: &mut HashMap<BlockIndex, GridGeometry>)
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    frozen: &HashSet<BlockIndex>,
+    dt: f64,
+    settings: &SchemeSettings,
+    sweep: Option<FaceSweep>,
+    ledger: Option<&FluxLedger<C>>,
+    runtime: &Runtime,
+    work: &WorkLedger) -> Result<State<C>, HydroError>
 where
     H: Hydrodynamics<Conserved = C>,
     M: InitialModel,
     C: Conserved
 {
-    let (inner_index, outer_index) = state.inner_outer_block_indexes();
-    let solution = &mut state.solution;
+    let update = |state, stage_weight| async move {
+        try_advance_rk(state, hydro, model, mesh, geometry, frozen, dt, stage_weight, settings, sweep, ledger, runtime, work).await
+    };
+
+    match settings.time_integrator {
+        TimeIntegrator::Hydro => match settings.runge_kutta_order {
 
-    if mesh.subgrid_extent(inner_index).outer_radius < mesh.inner_excision_surface(state.time) {
-        geometry.remove(&inner_index);
-        solution.remove(&inner_index);
+            // The stages of RK3 (Shu & Osher 1988) have the weights 1/6,
+            // 1/6, and 2/3 in the full update, which the source term budget
+            // and the flux ledger need, so they are taken here rather than
+            // by the Runge-Kutta scheme.
+            RungeKuttaOrder::RK3 => {
+                let s1 = update(state.clone(), 1.0 / 6.0).await?;
+                let s2 = update(s1, 1.0 / 6.0).await?.weighted_average(Rational64::new(3, 4), &state, runtime).await;
+                let s3 = update(s2, 2.0 / 3.0).await?.weighted_average(Rational64::new(1, 3), &state, runtime).await;
+                Ok(s3)
+            }
+            runge_kutta => {
+                let stage_weight = match runge_kutta {
+                    RungeKuttaOrder::RK1 => 1.0,
+                    _ => 1.0 / 2.0,
+                };
+                runge_kutta.try_advance_async(state, |state| update(state, stage_weight), runtime).await
+            }
+        }
+        TimeIntegrator::MusclHancock => {
+            try_advance_rk(state, hydro, model, mesh, geometry, frozen, dt, 1.0, settings, sweep, ledger, runtime, work).await
+        }
     }
+}
 
-    if mesh.subgrid_extent(outer_index).outer_radius < mesh.outer_excision_surface(state.time) {
-        let new_block_index = (outer_index.0 + 1, outer_index.1);
-        let new_block_geometry = mesh.subgrid(new_block_index).geometry();
-        let new_block_state = BlockState::from_model(model, hydro, &new_block_geometry, state.time);
 
-        geometry.insert(new_block_index, new_block_geometry);
-        solution.insert(new_block_index, new_block_state);
-    }    
+
+
+// ============================================================================
+/**
+ * Advance the state by one outer step with local time stepping. A block on
+ * rung k takes 2^(K - k) steps of length dt 2^k, where K is the largest rung.
+ * The blocks whose steps end at the same time are updated together, with
+ * the others frozen, finer rungs first. Afterwards, the fluxes through the
+ * faces between blocks on different rungs are made to agree over the outer
+ * step, so the update is conservative.
+ */
+async fn try_advance_local_time_steps<H, M, C>(
+    state: State<C>,
+    hydro: &H,
+    model: &M,
+    mesh: &Mesh,
+    geometry: &HashMap<BlockIndex, GridGeometry>,
+    frozen: &HashSet<BlockIndex>,
+    rungs: &HashMap<BlockIndex, u32>,
+    dt: f64,
+    settings: &SchemeSettings,
+    runtime: &Runtime,
+    work: &WorkLedger) -> Result<State<C>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    M: InitialModel,
+    C: Conserved
+{
+    let max_rung = rungs.values().cloned().max().unwrap_or(0);
+    let (start_time, iteration) = (state.time, state.iteration);
+    let ledger = Mutex::new(HashMap::new());
+    let mut state = state;
+
+    for n in 0..1u64 << max_rung {
+        for rung in 0..=max_rung {
+            let steps = 1u64 << rung;
+
+            if (n + 1) % steps != 0 {
+                continue
+            }
+            let waiting: HashSet<_> = state.solution.keys()
+                .filter(|index| frozen.contains(index) || rungs.get(index) != Some(&rung))
+                .cloned()
+                .collect();
+
+            if waiting.len() == state.solution.len() {
+                continue
+            }
+            state.time = start_time + (n + 1 - steps) as f64 * dt;
+            state = try_integrate(state, hydro, model, mesh, geometry, &waiting, dt * steps as f64, settings, None, Some(&ledger), runtime, work).await?;
+        }
+    }
+    state.time = start_time + (1u64 << max_rung) as f64 * dt;
+    state.iteration = iteration + 1;
+    correct_rung_interfaces(&mut state.solution, &ledger.into_inner().unwrap(), rungs);
+
+    Ok(state)
+}
+
+
+
+
+// ============================================================================
+/**
+ * Refocus the polar zones on the jet: measure the jet opening angle, move the
+ * geometric polar spacing toward the ratio which puts the configured number
+ * of zones within it, and remap the solution conservatively onto the new
+ * polar zones. The mesh is updated with the new spacing. Nothing changes if
+ * no zone is fast enough to count as jet material.
+ */
+pub fn refocus_polar_zones<H, C>(state: State<C>, hydro: &H, mesh: &mut Mesh, refocusing: &PolarRefocusing) -> Result<State<C>, HydroError>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved
+{
+    let opening_angle = match state.jet_opening_angle(hydro, mesh, refocusing.velocity_threshold)? {
+        Some(opening_angle) => opening_angle,
+        None => return Ok(state),
+    };
+    let old_mesh = mesh.clone();
+    mesh.polar_spacing = refocusing.refocused(mesh.polar_spacing, opening_angle, mesh.num_polar_zones, mesh.polar_extent());
+    Ok(state.remapped_polar(&old_mesh, mesh))
 }
 
 
 
 
 // ============================================================================
+/**
+ * Advance the state by `fold` iterations, but stop early at the simulation
+ * time `until`, when a side effect comes due. The time step is recomputed
+ * from the CFL condition on every iteration, and the last one is shortened
+ * so the state lands exactly on that time. If source splitting is given, the
+ * gravity and acceleration source terms are applied over half a step before
+ * and after the hydro update, rather than in the Runge-Kutta stages. Stiff
+ * cooling, heating, and drag terms are always integrated exactly, in half
+ * steps around the update. With local time stepping, each iteration is one
+ * outer step. If a retry policy is given, a step which fails, or leaves
+ * non-finite values on the grid, is discarded and taken again with a smaller
+ * time step. If the mesh faces move, the block geometry is expanded along
+ * with the state's scale factor after each step.
+ */
 pub fn advance<H, M, C>(
     mut state: State<C>,
     hydro: &H,
@@ -216,25 +2242,252 @@ pub fn advance<H, M, C>(
     mesh: &Mesh,
     geometry: &mut HashMap<BlockIndex, GridGeometry>,
     runtime: &Runtime,
-    fold: usize) -> anyhow::Result<State<C>, HydroError>
+    work: &WorkLedger,
+    settings: &SchemeSettings,
+    until: f64) -> anyhow::Result<State<C>, HydroError>
 where
     H: Hydrodynamics<Conserved = C>,
     M: InitialModel,
     C: Conserved
 {
-    let runge_kutta = hydro.runge_kutta_order();
-    let dt = state.time_step(hydro, mesh)?;
+    if let Some(refinement) = &mesh.refinement {
+        refine_coarsen_blocks(&mut state, hydro, mesh, refinement, geometry)?;
+    }
+    if let Some(derefinement) = &mesh.derefinement {
+        derefine_behind_shock(&mut state, hydro, model, mesh, derefinement, geometry)?;
+    }
+    let mut quiescent = HashSet::new();
+    let source_splitting = settings.source_splitting.as_ref();
+    let local_time_stepping = settings.local_time_stepping.as_ref();
 
-    for _ in 0..fold {
+    for _ in 0..settings.fold {
 
+        if state.time >= until {
+            break
+        }
         if mesh.moving_excision_surfaces() {
             add_remove_blocks(&mut state, hydro, model, mesh, geometry);
         }
-        let update = |state| async {
-            try_advance_rk(state, hydro, model, mesh, geometry, dt, &runtime).await
+        if let Some(tracking) = &mesh.shock_tracking {
+            track_outer_shock(&mut state, hydro, model, mesh, tracking, geometry)?;
+        }
+        let frozen = frozen_blocks(&quiescent);
+        let (frozen, geometry_now) = (&frozen, &*geometry);
+
+        // With local time stepping, each block is put on the highest rung
+        // its CFL condition allows, and the outer step is the longest step
+        // of any block. Rungs are capped so that the outer step does not
+        // pass the time `until`.
+        let (dt_cfl, limiting_block, rungs) = match local_time_stepping {
+            None => {
+                let (dt, limiting_block) = runtime.block_on(try_time_step(&state, hydro, mesh, geometry_now, runtime))?;
+                (dt, limiting_block, HashMap::new())
+            }
+            Some(local_time_stepping) => {
+                let block_dt = runtime.block_on(try_block_time_steps(&state, hydro, geometry_now, runtime))?;
+                let (dt, limiting_block) = smallest_time_step(&block_dt);
+                let mut max_rung = local_time_stepping.max_rung;
+
+                while max_rung > 0 && dt * (1u64 << max_rung) as f64 > until - state.time {
+                    max_rung -= 1
+                }
+                let rungs: HashMap<_, _> = block_dt.iter()
+                    .map(|(&index, &block_dt)| (index, ((block_dt / dt).log2().floor().max(0.0) as u32).min(max_rung)))
+                    .collect();
+                (dt, limiting_block, rungs)
+            }
         };
+        let dt = dt_cfl.min(until - state.time);
+        let previous = mesh.freeze_tolerance.map(|tolerance| (state.clone(), tolerance));
 
-        state = runtime.block_on(runge_kutta.try_advance_async(state, update, runtime))?;
+        // The rate H at which the radii of the moving faces grow, dr/dt = H r.
+        let expansion_rate = match mesh.face_motion {
+            None => None,
+            Some(FaceMotion::Homologous { time_offset }) => Some(1.0 / (state.time + time_offset)),
+            Some(FaceMotion::MeanFlow) => Some(mean_expansion_rate(&state, hydro, geometry_now)?),
+        };
+
+        // Return the state after a step of length dt, and with moving faces,
+        // the block geometry at the end of the step.
+        let take_step = |mut state: State<C>, dt: f64| -> Result<(State<C>, Option<HashMap<BlockIndex, GridGeometry>>), HydroError> {
+            let outer_dt = dt * (1u64 << rungs.values().cloned().max().unwrap_or(0)) as f64;
+            let sweep = expansion_rate.map(|rate| FaceSweep { start: state.time, dt, scale: state.scale_factor, growth: 1.0 + rate * dt });
+            let moved = sweep.map(|sweep| geometry_now.iter().map(|(&index, g)| (index, g.expanded(sweep.growth))).collect::<HashMap<_, _>>());
+            let geometry_end = moved.as_ref().unwrap_or(geometry_now);
+
+            if let Some(stiff_sources) = hydro.stiff_sources() {
+                state = runtime.block_on(try_apply_stiff_sources(state, hydro, geometry_now, frozen, stiff_sources, 0.5 * outer_dt, false, runtime, work))?;
+            }
+
+            if let Some(splitting) = source_splitting {
+                let time = state.time + 0.25 * outer_dt;
+                state = runtime.block_on(try_apply_split_sources(state, hydro, model, mesh, geometry_now, frozen, splitting, time, 0.5 * outer_dt, false, runtime, work))?;
+            }
+
+            state = if local_time_stepping.is_some() {
+                runtime.block_on(try_advance_local_time_steps(state, hydro, model, mesh, geometry_now, frozen, &rungs, dt, settings, runtime, work))?
+            } else {
+                runtime.block_on(try_integrate(state, hydro, model, mesh, geometry_now, frozen, dt, settings, sweep, None, runtime, work))?
+            };
+
+            if let Some(sweep) = sweep {
+                state.scale_factor = sweep.scale * sweep.growth;
+            }
+
+            if let Some(splitting) = source_splitting {
+                let time = state.time - 0.25 * outer_dt;
+                state = runtime.block_on(try_apply_split_sources(state, hydro, model, mesh, geometry_end, frozen, splitting, time, 0.5 * outer_dt, true, runtime, work))?;
+            }
+
+            if let Some(stiff_sources) = hydro.stiff_sources() {
+                state = runtime.block_on(try_apply_stiff_sources(state, hydro, geometry_end, frozen, stiff_sources, 0.5 * outer_dt, true, runtime, work))?;
+            }
+            Ok((state, moved))
+        };
+
+        // The work recorded by a failed attempt is discarded along with it,
+        // and the time step is recorded once an attempt is accepted.
+        let (next, moved, dt, retries) = match &settings.step_retry {
+            None => {
+                let (next, moved) = take_step(state, dt)?;
+                (next, moved, dt, 0)
+            }
+            Some(step_retry) => {
+                let mut dt = dt;
+                let mut retries = 0;
+
+                loop {
+                    let snapshot = work.snapshot();
+                    let error = match take_step(state.clone(), dt) {
+                        Ok((next, moved)) => match first_non_finite_zone(&next, moved.as_ref().unwrap_or(geometry_now)) {
+                            None => break (next, moved, dt, retries),
+                            Some(position) => HydroErrorType::NotFinite.at_position(position),
+                        }
+                        Err(error) => error,
+                    };
+                    if retries == step_retry.max_retries {
+                        return Err(error)
+                    }
+                    work.restore(snapshot);
+                    retries += 1;
+                    dt *= step_retry.dt_factor;
+                }
+            }
+        };
+        work.record_time_step(dt, limiting_block, retries as u64);
+        state = next;
+
+        if let Some(moved) = moved {
+            *geometry = moved;
+        }
+        if let Some((previous, tolerance)) = previous {
+            quiescent = quiescent_blocks(&previous, &state, tolerance);
+        }
     }
     Ok(state)
 }
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hydro_euler::euler_2d::Conserved as Euler;
+    use crate::physics::NewtonianHydro;
+
+    fn block(nr: usize, nq: usize, u: impl Fn(usize) -> Euler) -> BlockState<Euler> {
+        BlockState {
+            conserved: Array::from_shape_fn((nr, nq), |(i, _)| u(i)).to_shared(),
+            scalar_mass: Array::zeros((nr, nq)).to_shared(),
+        }
+    }
+
+    fn block_faces(nq: usize, inner: Euler, outer: Euler) -> BlockFaces<Euler> {
+        BlockFaces {
+            inner: (Array::from_elem(nq, inner), Array::zeros(nq)),
+            outer: (Array::from_elem(nq, outer), Array::zeros(nq)),
+        }
+    }
+
+    fn totals(solution: &HashMap<BlockIndex, BlockState<Euler>>) -> (f64, f64) {
+        let zones = || solution.values().flat_map(|block| block.conserved.iter());
+        (zones().map(|u| u.lab_frame_mass()).sum(), zones().map(|u| u.energy()).sum())
+    }
+
+    fn edge_error(reconstruction: Reconstruction, n: usize) -> f64 {
+        let h = 1.0 / n as f64;
+        let x = Array::from_shape_fn((n, 1), |(i, _)| (f64::exp((i + 1) as f64 * h) - f64::exp(i as f64 * h)) / h);
+        let (lower, upper) = reconstruct_edges(&x, Axis(0), reconstruction);
+
+        (2..n - 2)
+            .map(|i| f64::max(
+                (lower[(i, 0)] - f64::exp(i as f64 * h)).abs(),
+                (upper[(i, 0)] - f64::exp((i + 1) as f64 * h)).abs()))
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn reflux_conserves_the_totals_across_a_refinement_boundary() {
+        let (u, dt) = (Euler(1.0, 0.0, 0.0, 2.0), 0.1);
+        let (fine_flux, coarse_flux) = (Euler(0.3, 0.1, 0.0, 0.5), Euler(0.5, 0.2, 0.0, 0.8));
+        let mut solution = HashMap::new();
+        let mut faces = HashMap::new();
+
+        solution.insert((0, 0), block(4, 4, |i| if i == 3 { u - fine_flux * dt } else { u }));
+        solution.insert((1, 0), block(2, 2, |i| if i == 0 { u + coarse_flux * dt } else { u }));
+        faces.insert((0, 0), block_faces(4, Euler::default(), fine_flux));
+        faces.insert((1, 0), block_faces(2, coarse_flux, Euler::default()));
+        reflux(&mut solution, &faces, dt);
+
+        let (mass, energy) = totals(&solution);
+        assert!((mass - 20.0).abs() < 1e-12);
+        assert!((energy - 40.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ppm_edges_converge_at_high_order_on_a_smooth_profile() {
+        assert!(edge_error(Reconstruction::PPM, 32) / edge_error(Reconstruction::PPM, 64) > 8.0);
+    }
+
+    #[test]
+    fn weno5_edges_converge_at_high_order_on_a_smooth_profile() {
+        assert!(edge_error(Reconstruction::WENO5, 32) / edge_error(Reconstruction::WENO5, 64) > 8.0);
+    }
+
+    #[test]
+    fn rung_interface_correction_conserves_the_totals() {
+        let u = Euler(1.0, 0.0, 0.0, 2.0);
+        let (lower_rung_flux, upper_rung_flux) = (Euler(0.03, 0.01, 0.0, 0.05), Euler(0.02, 0.01, 0.0, 0.04));
+        let mut solution = HashMap::new();
+        let mut fluxes = HashMap::new();
+        let mut rungs = HashMap::new();
+
+        solution.insert((0, 0), block(4, 2, |i| if i == 3 { u - lower_rung_flux } else { u }));
+        solution.insert((1, 0), block(4, 2, |i| if i == 0 { u + upper_rung_flux } else { u }));
+        fluxes.insert((0, 0), block_faces(2, Euler::default(), lower_rung_flux));
+        fluxes.insert((1, 0), block_faces(2, upper_rung_flux, Euler::default()));
+        rungs.insert((0, 0), 0);
+        rungs.insert((1, 0), 1);
+        correct_rung_interfaces(&mut solution, &fluxes, &rungs);
+
+        let (mass, energy) = totals(&solution);
+        assert!((mass - 16.0).abs() < 1e-12);
+        assert!((energy - 32.0).abs() < 1e-12);
+        assert_eq!(solution[&(0, 0)].conserved[(3, 0)].lab_frame_mass(), (u - lower_rung_flux).lab_frame_mass());
+    }
+
+    #[test]
+    fn outflow_polar_boundary_lets_material_leave_the_wedge() {
+        let hydro: NewtonianHydro = serde_yaml::from_str("
+            gamma_law_index: 1.666666666666667
+            plm_theta: 1.5
+            cfl_number: 0.3
+            runge_kutta_order: RK2").unwrap();
+        let p = hydro.interpret(&AnyPrimitive { velocity_r: 0.0, velocity_q: 0.5, mass_density: 1.0, gas_pressure: 1.0 });
+        let mass_flux = |boundary| hydro.intercell_flux(p, polar_guard_state(&hydro, p, boundary), 0.0, 0.0, Direction::Polar).0.lab_frame_mass();
+
+        assert!(mass_flux(PolarBoundary::Outflow) > 0.0);
+        assert!(mass_flux(PolarBoundary::Reflecting).abs() < 1e-12);
+    }
+}
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::ops::Add;
 use num::ToPrimitive;
 use num::rational::Rational64;
 use serde::{Serialize, Deserialize};
-use ndarray::{Array, ArcArray, Ix2};
+use ndarray::{Array, ArcArray, ArrayView1, Ix2};
 use godunov_core::runge_kutta;
-use crate::physics::HydroError;
+use crate::physics::{AnyPrimitive, HydroError, RecoveryReport, light_speed};
+use crate::reductions::{ConservedTotals, ExtendedSum, Summation, TimeSeriesSample};
+use crate::app::ReferenceEpoch;
+use crate::products::ObserverTimes;
 use crate::traits::{
+    BoundaryEdge,
     Conserved,
     Hydrodynamics,
     InitialModel,
@@ -21,17 +27,91 @@ use crate::mesh::{
 
 
 /**
- * The solution state for an individual grid block
+ * The solution state for an individual grid block. Fields other than the
+ * conserved quantities may be missing from older checkpoints, in which case
+ * they are loaded empty, and initialized on restart (see
+ * [`State::initialize_missing_fields`]).
  */
 #[derive(Clone, Serialize, Deserialize)]
 pub struct BlockState<C: Conserved> {
     pub conserved: ArcArray<C, Ix2>,
+    #[serde(default)]
     pub scalar_mass: ArcArray<f64, Ix2>,
 }
 
 
 
 
+/**
+ * Record of a solution field which was missing from a checkpoint, and was
+ * initialized when the run was restarted
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FieldInitialization {
+
+    /// The simulation time of the restart
+    pub time: f64,
+
+    /// The code version which performed the initialization
+    pub version: String,
+
+    /// The name of the field
+    pub field: String,
+
+    /// Where the values came from: the initial model, or a default value
+    pub source: String,
+
+    /// The number of blocks where the field was initialized
+    pub num_blocks: usize,
+}
+
+
+
+
+/**
+ * Running totals of the conserved quantities in blocks which were removed
+ * from the inner edge of the mesh, so that the global budget can be audited
+ */
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ExcisedTotals {
+
+    /// The number of blocks removed
+    pub num_blocks: usize,
+
+    /// The lab-frame mass removed
+    pub mass: f64,
+
+    /// The energy removed (excluding rest mass, for relativistic
+    /// hydrodynamics)
+    pub energy: f64,
+
+    /// The radial momentum removed
+    pub radial_momentum: f64,
+
+    /// The scalar mass removed
+    pub scalar_mass: f64,
+}
+
+
+
+
+/**
+ * Number of times the positivity-preserving limiter and the floors were
+ * applied in a block, summed over zones and Runge-Kutta stages
+ */
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PositivityCounts {
+
+    /// Zones whose update was recomputed with first-order fluxes
+    pub limited: u64,
+
+    /// Zones reset to, or clamped at, the density and pressure floors
+    pub floored: u64,
+}
+
+
+
+
 /**
  * The full solution state for the simulation
  */
@@ -40,6 +120,26 @@ pub struct State<C: Conserved> {
     pub time: f64,
     pub iteration: Rational64,
     pub solution: HashMap<BlockIndex, BlockState<C>>,
+
+    /// Fields initialized on restarts, because they were missing from the
+    /// checkpoint
+    #[serde(default)]
+    pub field_initializations: Vec<FieldInitialization>,
+
+    /// Conserved quantities in blocks removed from the inner edge of the
+    /// mesh
+    #[serde(default)]
+    pub excised: ExcisedTotals,
+
+    /// Applications of the positivity-preserving limiter and floors in each
+    /// block since the start of the run
+    #[serde(default)]
+    pub positivity: HashMap<BlockIndex, PositivityCounts>,
+
+    /// Ratio of the radii of the mesh faces to their radii at the start of
+    /// the run, which is 1 unless the faces move (see `FaceMotion`)
+    #[serde(default = "unit_scale_factor")]
+    pub scale_factor: f64,
 }
 
 
@@ -57,8 +157,29 @@ impl<C: Conserved> BlockState<C> {
         M: InitialModel,
         H: Hydrodynamics<Conserved = C>
     {
-        let scalar      = geometry.cell_centers.mapv(|c| model.scalar_at(c, time));
-        let primitive   = geometry.cell_centers.mapv(|c| hydro.interpret(&model.primitive_at(c, time)));
+        Self::from_primitive_fn(hydro, geometry, |c| model.primitive_at(c, time), |c| model.scalar_at(c, time))
+    }
+
+    /**
+     * Generate the state of a ghost block beyond the given edge of the mesh,
+     * from the model's boundary primitive state.
+     */
+    pub fn from_model_boundary<M, H>(model: &M, hydro: &H, geometry: &GridGeometry, time: f64, edge: BoundaryEdge) -> Self
+    where
+        M: InitialModel,
+        H: Hydrodynamics<Conserved = C>
+    {
+        Self::from_primitive_fn(hydro, geometry, |c| model.boundary_primitive_at(edge, c, time), |c| model.scalar_at(c, time))
+    }
+
+    fn from_primitive_fn<H, F, G>(hydro: &H, geometry: &GridGeometry, primitive_at: F, scalar_at: G) -> Self
+    where
+        H: Hydrodynamics<Conserved = C>,
+        F: Fn((f64, f64)) -> AnyPrimitive,
+        G: Fn((f64, f64)) -> f64
+    {
+        let scalar      = geometry.cell_centers.mapv(|c| scalar_at(c));
+        let primitive   = geometry.cell_centers.mapv(|c| hydro.interpret(&primitive_at(c)));
         let conserved   = primitive.mapv(|p| hydro.to_conserved(p)) * &geometry.cell_volumes;
         let scalar_mass = conserved.mapv(|u| u.lab_frame_mass()) * scalar;
 
@@ -92,6 +213,192 @@ impl<C: Conserved> BlockState<C> {
             .collect();
         Ok(Array::from_shape_vec(u.dim(), x?).unwrap())
     }
+
+    /**
+     * Same as [`BlockState::try_to_primitive`], but also return an array of
+     * the recovery report in each zone: whether the primitive recovery
+     * needed a fallback, and how many root-finder iterations it took.
+     */
+    pub fn try_to_primitive_reporting<H, P>(
+        &self,
+        hydro: &H,
+        geometry: &GridGeometry) -> anyhow::Result<(Array<P, Ix2>, Array<RecoveryReport, Ix2>), HydroError>
+    where
+        H: Hydrodynamics<Conserved = C, Primitive = P>,
+        C: Conserved,
+        P: Primitive
+    {
+        let u = &self.conserved / &geometry.cell_volumes;
+        let x: Result<Vec<_>, _> = u
+            .iter()
+            .zip(geometry.cell_centers.iter())
+            .map(|(&u, &rq)| hydro
+                .try_to_primitive_reporting(u)
+                .map_err(|e| e.at_position(rq)))
+            .collect();
+        let (p, report): (Vec<_>, Vec<_>) = x?.into_iter().unzip();
+        Ok((Array::from_shape_vec(u.dim(), p).unwrap(), Array::from_shape_vec(u.dim(), report).unwrap()))
+    }
+
+    /**
+     * Return the largest time step allowed on this block by the CFL
+     * condition, from the maximum signal speed in each zone.
+     */
+    pub fn try_time_step<H>(&self, hydro: &H, geometry: &GridGeometry) -> Result<f64, HydroError>
+    where
+        H: Hydrodynamics<Conserved = C>
+    {
+        let dt = self
+            .try_to_primitive(hydro, geometry)?
+            .iter()
+            .zip(&geometry.cell_linear_dimension())
+            .fold(f64::MAX, |dt, (p, dl)| dt.min(dl / hydro.max_signal_speed(*p)));
+        Ok(dt * hydro.cfl_number())
+    }
+
+    /**
+     * Return the refinement level of this block on the given mesh, deduced
+     * from its number of radial zones.
+     */
+    pub fn level(&self, mesh: &Mesh) -> i32 {
+        mesh.block_level(self.conserved.dim())
+    }
+
+    /**
+     * Return a copy of this block at the next finer refinement level. Each
+     * zone is split into 2 x 2 zones (2 x 1 on a one-dimensional mesh), which
+     * receive the conserved quantities of the parent zone in proportion to
+     * their volumes, so the block totals are unchanged.
+     */
+    pub fn refined(&self, mesh: &Mesh, index: BlockIndex) -> Self {
+        let level = self.level(mesh);
+        let mq = mesh.polar_refinement_ratio();
+        let coarse = mesh.subgrid_at_level(index, level).geometry();
+        let fine = mesh.subgrid_at_level(index, level + 1).geometry();
+        let parent = |(i, j): (usize, usize)| (i / 2, j / mq);
+        let fraction = Array::from_shape_fn(fine.cell_volumes.dim(), |i| fine.cell_volumes[i] / coarse.cell_volumes[parent(i)]);
+
+        Self {
+            conserved: Array::from_shape_fn(fraction.dim(), |i| self.conserved[parent(i)] * fraction[i]).to_shared(),
+            scalar_mass: Array::from_shape_fn(fraction.dim(), |i| self.scalar_mass[parent(i)] * fraction[i]).to_shared(),
+        }
+    }
+
+    /**
+     * Return a copy of this block remapped conservatively from the given
+     * polar vertices to new ones spanning the same extent, which may have a
+     * different number of zones. The remapping operates on the conserved
+     * quantities, so the mass, momentum, energy and scalar mass in each
+     * radial row are preserved.
+     */
+    pub fn remapped_polar(&self, old_vertices: ArrayView1<f64>, new_vertices: ArrayView1<f64>) -> Self {
+        let nr = self.conserved.dim().0;
+        let nq = new_vertices.len() - 1;
+        let mut conserved = Array::from_elem((nr, nq), C::default());
+        let mut scalar_mass = Array::zeros((nr, nq));
+
+        for (j, k, fraction) in polar_overlap_fractions(old_vertices, new_vertices) {
+            for i in 0..nr {
+                conserved[(i, k)] = conserved[(i, k)] + self.conserved[(i, j)] * fraction;
+                scalar_mass[(i, k)] += self.scalar_mass[(i, j)] * fraction;
+            }
+        }
+
+        Self {
+            conserved: conserved.to_shared(),
+            scalar_mass: scalar_mass.to_shared(),
+        }
+    }
+
+    /**
+     * Return a copy of this block at the next coarser refinement level, where
+     * each zone holds the sum of the conserved quantities in its 2 x 2 (or
+     * 2 x 1) child zones.
+     */
+    pub fn coarsened(&self, mesh: &Mesh) -> Self {
+        let mq = mesh.polar_refinement_ratio();
+
+        Self {
+            conserved: restrict(&self.conserved, mq),
+            scalar_mass: restrict(&self.scalar_mass, mq),
+        }
+    }
+}
+
+
+
+
+/**
+ * The scale factor of states from before the mesh faces could move
+ */
+fn unit_scale_factor() -> f64 {
+    1.0
+}
+
+/**
+ * Return a list of (j, k, f) triples, where f is the fraction of the volume of
+ * the source polar zone j which lies inside the target polar zone k. Only
+ * overlapping zone pairs are included.
+ */
+fn polar_overlap_fractions(source: ArrayView1<f64>, target: ArrayView1<f64>) -> Vec<(usize, usize, f64)> {
+    let mut fractions = Vec::new();
+
+    for j in 0..source.len() - 1 {
+        for k in 0..target.len() - 1 {
+            let lower = source[j].max(target[k]);
+            let upper = source[j + 1].min(target[k + 1]);
+
+            if lower < upper {
+                let overlap = f64::cos(lower) - f64::cos(upper);
+                let total = f64::cos(source[j]) - f64::cos(source[j + 1]);
+                fractions.push((j, k, overlap / total));
+            }
+        }
+    }
+    fractions
+}
+
+/**
+ * Sum each group of 2 radial by `mq` polar zones in the given array.
+ */
+fn restrict<T>(x: &ArcArray<T, Ix2>, mq: usize) -> ArcArray<T, Ix2>
+where
+    T: Copy + Add<Output = T>
+{
+    let (nr, nq) = x.dim();
+
+    Array::from_shape_fn((nr / 2, nq / mq), |(i, j)| {
+        let mut sum = x[(2 * i, j * mq)];
+        for a in 0..2 {
+            for b in 0..mq {
+                if (a, b) != (0, 0) {
+                    sum = sum + x[(2 * i + a, j * mq + b)]
+                }
+            }
+        }
+        sum
+    }).to_shared()
+}
+
+
+
+
+// ============================================================================
+impl ExcisedTotals {
+
+    /**
+     * Add the contents of a removed block to the totals.
+     */
+    pub fn add_block<C: Conserved>(&mut self, block: &BlockState<C>) {
+        self.num_blocks += 1;
+
+        for u in block.conserved.iter() {
+            self.mass += u.lab_frame_mass();
+            self.energy += u.energy();
+            self.radial_momentum += u.radial_momentum();
+        }
+        self.scalar_mass += block.scalar_mass.sum();
+    }
 }
 
 
@@ -111,7 +418,93 @@ impl<C: Conserved> State<C> {
     {
         let iteration = Rational64::new(0, 1);
         let solution = geometry.iter().map(|(&i, g)| (i, BlockState::from_model(model, hydro, g, time))).collect();
-        Self{time, iteration, solution}
+        Self{time, iteration, solution, field_initializations: Vec::new(), excised: ExcisedTotals::default(), positivity: HashMap::new(), scale_factor: 1.0}
+    }
+
+    /**
+     * Initialize the fields which are missing from blocks in this state,
+     * typically because it was loaded from a checkpoint written before the
+     * field existed, or with the field disabled. The scalar is taken from
+     * the model at the current time, and weighted by the existing mass in
+     * each zone. Each initialization is recorded in the state, and returned.
+     */
+    pub fn initialize_missing_fields<M: InitialModel>(&mut self, model: &M, mesh: &Mesh, version: &str) -> Vec<FieldInitialization> {
+        let time = self.time;
+        let mut num_blocks = 0;
+
+        for (index, block) in self.solution.iter_mut() {
+            if block.scalar_mass.dim() != block.conserved.dim() {
+                let geometry = mesh.subgrid_at_level(*index, block.level(mesh)).geometry().expanded(self.scale_factor);
+                let scalar = geometry.cell_centers.mapv(|c| model.scalar_at(c, time));
+                block.scalar_mass = (block.conserved.mapv(|u| u.lab_frame_mass()) * scalar).to_shared();
+                num_blocks += 1;
+            }
+        }
+        let mut initialized = Vec::new();
+
+        if num_blocks > 0 {
+            initialized.push(FieldInitialization {
+                time,
+                version: version.to_string(),
+                field: "scalar_mass".to_string(),
+                source: "model".to_string(),
+                num_blocks,
+            });
+        }
+        self.field_initializations.extend(initialized.iter().cloned());
+        initialized
+    }
+
+    /**
+     * Return the geometry of each block in this state, at its refinement
+     * level and the current scale factor of the mesh.
+     */
+    pub fn block_geometry(&self, mesh: &Mesh) -> HashMap<BlockIndex, GridGeometry> {
+        self.solution
+            .iter()
+            .map(|(&index, block)| (index, mesh.subgrid_at_level(index, block.level(mesh)).geometry().expanded(self.scale_factor)))
+            .collect()
+    }
+
+    /**
+     * Return a copy of this state remapped conservatively from the polar
+     * zones of one mesh to those of another, which differs from it only in
+     * the polar spacing.
+     */
+    pub fn remapped_polar(&self, old_mesh: &Mesh, new_mesh: &Mesh) -> Self {
+        let solution = self.solution.iter().map(|(&index, block)| {
+            let level = block.level(old_mesh);
+            let old = old_mesh.subgrid_at_level(index, level).geometry().polar_vertices;
+            let new = new_mesh.subgrid_at_level(index, level).geometry().polar_vertices;
+            (index, block.remapped_polar(old.view(), new.view()))
+        }).collect();
+
+        Self { solution, ..self.clone() }
+    }
+
+    /**
+     * Return the largest angle from the nearer pole of any zone where the
+     * radial velocity (the four-velocity for relativistic hydrodynamics)
+     * exceeds the given threshold, or `None` if there is no such zone.
+     */
+    pub fn jet_opening_angle<H, P>(&self, hydro: &H, mesh: &Mesh, velocity_threshold: f64) -> Result<Option<f64>, HydroError>
+    where
+        H: Hydrodynamics<Conserved = C, Primitive = P>,
+        P: Primitive,
+    {
+        let geometry = self.block_geometry(mesh);
+        let mut opening_angle: Option<f64> = None;
+
+        for (index, block) in &self.solution {
+            let primitive = block.try_to_primitive(hydro, &geometry[index])?;
+
+            for (p, &(_, q)) in primitive.iter().zip(geometry[index].cell_centers.iter()) {
+                if hydro.any(p).velocity_r > velocity_threshold {
+                    opening_angle = Some(opening_angle.unwrap_or(0.0).max(q.min(PI - q)));
+                }
+            }
+        }
+        Ok(opening_angle)
     }
 
     /**
@@ -121,6 +514,82 @@ impl<C: Conserved> State<C> {
         self.solution.values().map(|solution| solution.conserved.len()).sum()
     }
 
+    /**
+     * Return the total mass and energy on the grid, accumulated with the
+     * given floating-point summation method.
+     */
+    pub fn totals(&self, summation: Summation) -> ConservedTotals {
+        let zones = self.solution.values().flat_map(|block| block.conserved.iter());
+
+        match summation {
+            Summation::Double => {
+                zones.fold(ConservedTotals::default(), |totals, u| ConservedTotals {
+                    mass: totals.mass + u.lab_frame_mass(),
+                    energy: totals.energy + u.energy(),
+                })
+            }
+            Summation::Extended => {
+                let mut mass = ExtendedSum::default();
+                let mut energy = ExtendedSum::default();
+
+                for u in zones {
+                    mass.add(u.lab_frame_mass());
+                    energy.add(u.energy());
+                }
+                ConservedTotals {
+                    mass: mass.value(),
+                    energy: energy.value(),
+                }
+            }
+        }
+    }
+
+    /**
+     * Return a sample of the conserved quantities time series. The kinetic
+     * energy is that of the bulk motion, which for relativistic
+     * hydrodynamics is the lab-frame mass times the Lorentz factor minus
+     * one, and the thermal energy is the rest of the total energy. The
+     * relativistic quantities, which are in units with c = 1, are converted
+     * to cgs. With a reference epoch, the sample includes the time since the
+     * epoch and the arrival times at its extraction surfaces.
+     */
+    pub fn time_series_sample<H, P>(&self, hydro: &H, mesh: &Mesh, relativistic: bool, reference_epoch: Option<&ReferenceEpoch>) -> Result<TimeSeriesSample, HydroError>
+    where
+        H: Hydrodynamics<Conserved = C, Primitive = P>,
+        P: Primitive,
+    {
+        let geometry = self.block_geometry(mesh);
+        let (momentum_unit, energy_unit) = if relativistic { (light_speed(), light_speed() * light_speed()) } else { (1.0, 1.0) };
+        let mut sample = TimeSeriesSample {
+            time: self.time,
+            observer_times: reference_epoch.map(|epoch| ObserverTimes::new(epoch, self.time)),
+            ..TimeSeriesSample::default()
+        };
+
+        for (index, block) in &self.solution {
+            let primitive = block.try_to_primitive(hydro, &geometry[index])?;
+
+            for (u, p) in block.conserved.iter().zip(primitive.iter()) {
+                let p = hydro.any(p);
+                let u2 = p.velocity_r.powi(2) + p.velocity_q.powi(2);
+                let kinetic = if relativistic {
+                    u.lab_frame_mass() * ((1.0 + u2).sqrt() - 1.0)
+                } else {
+                    0.5 * u.lab_frame_mass() * u2
+                };
+                sample.mass += u.lab_frame_mass();
+                sample.radial_momentum += u.radial_momentum() * momentum_unit;
+                sample.kinetic_energy += kinetic * energy_unit;
+                sample.total_energy += u.energy() * energy_unit;
+            }
+            sample.scalar_mass += block.scalar_mass.sum();
+        }
+        sample.thermal_energy = sample.total_energy - sample.kinetic_energy;
+        sample.excised_mass = self.excised.mass;
+        sample.excised_energy = self.excised.energy * energy_unit;
+        Ok(sample)
+    }
+
     /**
      * Return the indexes of "ghost blocks" just inside and outside the mesh
      * radial extent.
@@ -146,18 +615,13 @@ impl<C: Conserved> State<C> {
     {
         if let Some(max_signal_speed) = hydro.global_signal_speed() {
             let (index, ..) = self.inner_outer_block_indexes();
-            Ok(hydro.cfl_number() * mesh.smallest_spacing(index) / max_signal_speed)
+            let max_level = self.solution.values().map(|block| block.level(mesh)).max().unwrap_or(0);
+            Ok(hydro.cfl_number() * mesh.smallest_spacing(index) * self.scale_factor / max_signal_speed / 2f64.powi(max_level))
         } else {
-            Ok(self.solution.iter().try_fold(f64::MAX, |dt, (index, state)| {
-                let geometry = mesh.subgrid(*index).geometry();
-                let block_dt = state
-                    .try_to_primitive(hydro, &geometry)?
-                    .iter()
-                    .zip(&geometry.cell_linear_dimension())
-                    .fold(dt, |dt, (p, dl)| dt.min(dl / hydro.max_signal_speed(*p))
-                );
-                Ok(dt.min(block_dt))
-            })? * hydro.cfl_number())
+            self.solution.iter().try_fold(f64::MAX, |dt, (index, state)| {
+                let geometry = mesh.subgrid_at_level(*index, state.level(mesh)).geometry().expanded(self.scale_factor);
+                Ok(dt.min(state.try_time_step(hydro, &geometry)?))
+            })
         }
     }
 
@@ -207,6 +671,10 @@ impl<C: Conserved> runge_kutta::WeightedAverage for State<C> {
             time:      self.time      * (-bf + 1.) + s0.time      * bf,
             iteration: self.iteration * (-br + 1 ) + s0.iteration * br,
             solution: s_avg.into_iter().collect(),
+            field_initializations: self.field_initializations,
+            excised: self.excised,
+            positivity: self.positivity,
+            scale_factor: self.scale_factor,
         }
     }
 }
@@ -240,6 +708,10 @@ impl<C: Conserved> runge_kutta::WeightedAverageAsync for State<C> {
             time:      self.time      * (-bf + 1.) + s0.time      * bf,
             iteration: self.iteration * (-br + 1 ) + s0.iteration * br,
             solution: join_all(s_avg).await.into_iter().collect(),
+            field_initializations: self.field_initializations,
+            excised: self.excised,
+            positivity: self.positivity,
+            scale_factor: self.scale_factor,
         }
     }
 }
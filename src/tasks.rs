@@ -1,5 +1,7 @@
 use std::time::Instant;
+use num::rational::Rational64;
 use serde::{Serialize, Deserialize};
+use crate::app::Control;
 
 
 
@@ -22,6 +24,11 @@ pub struct RecurringTask {
 
     #[serde(skip, default = "usize::default")]
     pub count_this_run: usize,
+
+    /// The simulation iteration when this task was last performed in this
+    /// run, or zero if it has not been
+    #[serde(skip, default = "Rational64::default")]
+    pub last_iteration: Rational64,
 }
 
 
@@ -40,6 +47,26 @@ pub struct Tasks {
     /// post-processing
     pub write_products: RecurringTask,
 
+    /// Output the measured compute cost and primitive recovery fallbacks
+    #[serde(default)]
+    pub write_work_map: RecurringTask,
+
+    /// Append the global totals to the conserved quantities time series
+    #[serde(default)]
+    pub write_time_series: RecurringTask,
+
+    /// Output the one-dimensional cuts along the configured polar angles
+    #[serde(default)]
+    pub write_angular_cuts: RecurringTask,
+
+    /// Print the mass and energy added by each source term
+    #[serde(default)]
+    pub print_source_budget: RecurringTask,
+
+    /// Refocus the polar zones on the jet opening angle
+    #[serde(default)]
+    pub refocus_polar_zones: RecurringTask,
+
     /// Print the loop message
     pub iteration_message: RecurringTask,
 
@@ -62,6 +89,7 @@ impl RecurringTask {
             next_time: start_time,
             last_performed: Instant::now(),
             count_this_run: 0,
+            last_iteration: Rational64::default(),
         }
     }
 
@@ -80,6 +108,12 @@ impl RecurringTask {
     }
 }
 
+impl Default for RecurringTask {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
 
 
 
@@ -89,8 +123,42 @@ impl Tasks {
         Self {
             write_checkpoint: RecurringTask::new(start_time),
             write_products: RecurringTask::new(start_time),
+            write_work_map: RecurringTask::new(start_time),
+            write_time_series: RecurringTask::new(start_time),
+            write_angular_cuts: RecurringTask::new(start_time),
+            print_source_budget: RecurringTask::new(start_time),
+            refocus_polar_zones: RecurringTask::new(start_time),
             iteration_message: RecurringTask::new(start_time),
             report_progress: RecurringTask::new(start_time),
         }
     }
+
+    /**
+     * Return the earliest simulation time after `time` at which an output
+     * task enabled in the given control is due, or the final time if that
+     * comes first. The solver uses this to avoid stepping past output times
+     * when many iterations are folded between side effects.
+     */
+    pub fn next_output_time(&self, control: &Control, time: f64) -> f64 {
+        let mut due = Vec::new();
+
+        if control.checkpoint_task_interval().is_some() {
+            due.push(self.write_checkpoint.next_time)
+        }
+        if control.products_task_interval().is_some() {
+            due.push(self.write_products.next_time)
+        }
+        if control.work_map_task_interval().is_some() {
+            due.push(self.write_work_map.next_time)
+        }
+        if control.time_series_task_interval().is_some() {
+            due.push(self.write_time_series.next_time)
+        }
+        if control.angular_cuts.is_some() {
+            due.push(self.write_angular_cuts.next_time)
+        }
+        due.into_iter()
+            .filter(|&t| t > time)
+            .fold(control.final_time, f64::min)
+    }
 }
@@ -1,7 +1,7 @@
 use std::ops::{Add, Sub, Mul, Div};
 use serde::Serialize;
 use godunov_core::runge_kutta::RungeKuttaOrder;
-use crate::physics::{AnyPrimitive, Direction, HydroErrorType};
+use crate::physics::{AnyPrimitive, Direction, HydroErrorType, Positivity, Reconstruction, RecoveryReport, StiffSources};
 
 
 
@@ -15,11 +15,34 @@ pub trait Arithmetic: Add<Output=Self> + Sub<Output=Self> + Mul<f64, Output=Self
 
 
 
+/**
+ * Identifies the inner or outer radial boundary of the mesh
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryEdge {
+    Inner,
+    Outer,
+}
+
+
+
+
 /**
  * Conserved field type for the hydrodynamics system
  */
 pub trait Conserved: 'static + Clone + Copy + Send + Sync + Arithmetic + Default {
     fn lab_frame_mass(&self) -> f64;
+
+    /**
+     * Return the energy of this conserved state. For relativistic
+     * hydrodynamics this excludes the rest-mass energy.
+     */
+    fn energy(&self) -> f64;
+
+    /**
+     * Return the radial momentum of this conserved state.
+     */
+    fn radial_momentum(&self) -> f64;
 }
 
 
@@ -62,6 +85,51 @@ pub trait Hydrodynamics: 'static + Clone + Send {
      */
     fn runge_kutta_order(&self) -> RungeKuttaOrder;
 
+    /**
+     * Return the spatial reconstruction, which may be a user configurable
+     * value.
+     */
+    fn reconstruction(&self) -> Reconstruction;
+
+    /**
+     * Return the relative pressure jump across a compressed zone above which
+     * the reconstruction falls back to first order, if shock flattening is
+     * enabled.
+     */
+    fn shock_flattening(&self) -> Option<f64>;
+
+    /**
+     * Return the relative pressure jump above which the faces of a zone in
+     * or next to a shock use the HLLE solver, if the carbuncle fix is
+     * enabled.
+     */
+    fn carbuncle_fix(&self) -> Option<f64>;
+
+    /**
+     * Return whether the scalar flux is computed consistently with the mass
+     * flux, from the upwind concentration, rather than by the Riemann
+     * solver.
+     */
+    fn consistent_scalar_advection(&self) -> bool;
+
+    /**
+     * Return the density and pressure floors, if the positivity-preserving
+     * limiter is enabled.
+     */
+    fn positivity(&self) -> Option<Positivity>;
+
+    /**
+     * Return whether the model acceleration is discretized to preserve the
+     * hydrostatic equilibrium of the initial model.
+     */
+    fn well_balanced(&self) -> bool;
+
+    /**
+     * Return the cooling, heating, and drag source terms, if any, which are
+     * integrated implicitly.
+     */
+    fn stiff_sources(&self) -> Option<StiffSources>;
+
     /**
      * Compute the PLM difference from a stencil of colinear primitive
      * states.
@@ -74,6 +142,15 @@ pub trait Hydrodynamics: 'static + Clone + Send {
      */
     fn plm_gradient_scalar(&self, a: &f64, b: &f64, c: &f64) -> f64;
 
+    /**
+     * Return the limited gradient of the primitive state along the given
+     * direction, for the reconstruction of the face states. The default
+     * ignores the direction, and limits each primitive variable on its own.
+     */
+    fn plm_gradient_primitive_along(&self, a: &Self::Primitive, b: &Self::Primitive, c: &Self::Primitive, _direction: Direction) -> Self::Primitive {
+        self.plm_gradient_primitive(a, b, c)
+    }
+
     /**
      * Try to convert from a conserved to a primitive hydrodynamic state,
      * returning an appropriate error type if the conversion failed. This
@@ -81,6 +158,16 @@ pub trait Hydrodynamics: 'static + Clone + Send {
      */
     fn try_to_primitive(&self, u: Self::Conserved) -> Result<Self::Primitive, HydroErrorType>;
 
+    /**
+     * Same as `try_to_primitive`, but also report whether the recovery needed
+     * a fallback (such as a pressure floor) to yield a valid state, and how
+     * many root-finder iterations it took. Systems without fallbacks or
+     * iterative recovery can rely on the default implementation.
+     */
+    fn try_to_primitive_reporting(&self, u: Self::Conserved) -> Result<(Self::Primitive, RecoveryReport), HydroErrorType> {
+        self.try_to_primitive(u).map(|p| (p, RecoveryReport::default()))
+    }
+
     /**
      * Convert from a conserved to a primitive hydrodynamic state. This function
      * is is permitted to panic if the conversion fails.
@@ -123,6 +210,12 @@ pub trait Hydrodynamics: 'static + Clone + Send {
      */
     fn intercell_flux(&self, pl: Self::Primitive, pr: Self::Primitive, sl: f64, sr: f64, direction: Direction) -> (Self::Conserved, f64);
 
+    /**
+     * Same as `intercell_flux`, but always with the HLLE solver, whose
+     * extra dissipation is used at strong shocks by the carbuncle fix.
+     */
+    fn intercell_flux_hlle(&self, pl: Self::Primitive, pr: Self::Primitive, sl: f64, sr: f64, direction: Direction) -> (Self::Conserved, f64);
+
     /**
      * Return the geometrical source terms (conserved quantity per unit volume)
      * for the given primitive state and r-theta coordinate.
@@ -135,6 +228,13 @@ pub trait Hydrodynamics: 'static + Clone + Send {
      */
     fn gravitational_source_terms(&self, p: Self::Primitive, coordinate: (f64, f64)) -> Self::Conserved;
 
+    /**
+     * Return the source terms (conserved quantity per unit volume) due to a
+     * uniform-field acceleration (g_r, g_theta) in cm/s^2, for the given
+     * primitive state.
+     */
+    fn acceleration_source_terms(&self, p: Self::Primitive, acceleration: (f64, f64)) -> Self::Conserved;
+
     /**
      * Return the CFL number to be used
      */
@@ -167,4 +267,22 @@ pub trait InitialModel: Clone {
       * Return the scalar concentration at the given r-theta coordinate.
       */
      fn scalar_at(&self, coordinate: (f64, f64), time: f64) -> f64;
+
+     /**
+      * Return the primitive state used to fill the ghost zones beyond the
+      * given edge of the mesh. Models which feed inflow through a boundary
+      * can override this; the default is to evaluate the model itself.
+      */
+     fn boundary_primitive_at(&self, _edge: BoundaryEdge, coordinate: (f64, f64), time: f64) -> AnyPrimitive {
+         self.primitive_at(coordinate, time)
+     }
+
+     /**
+      * Return the externally imposed acceleration (g_r, g_theta), in cm/s^2,
+      * at the given r-theta coordinate. Models used to test gravity can
+      * override this; the default is no acceleration.
+      */
+     fn acceleration_at(&self, _coordinate: (f64, f64), _time: f64) -> (f64, f64) {
+         (0.0, 0.0)
+     }
 }
@@ -0,0 +1,407 @@
+use crate::app::{AnyModel, Configuration};
+use crate::diagnostics::WorkLedger;
+use crate::lookup_table_v2::{self, LookupTable};
+use crate::mesh::Mesh;
+use crate::models::JetInStar;
+use crate::physics::AnyPrimitive;
+use crate::products::Products;
+use crate::scheme::{self, SchemeSettings};
+use crate::state::State;
+use crate::traits::{Conserved, Hydrodynamics};
+
+
+
+
+/**
+ * Default scalar concentration above which a zone is considered to be jet
+ * material. The jet_in_star and jet_in_cloud models tag the jet with a scalar
+ * of 100, and the ambient material with 1 or less.
+ */
+pub static JET_SCALAR_THRESHOLD: f64 = 10.0;
+
+
+
+
+/**
+ * Pressure jump, relative to the ambient pressure, which marks the shock in
+ * a blast wave profile
+ */
+pub static SHOCK_PRESSURE_JUMP: f64 = 0.1;
+
+
+
+
+/**
+ * Reference jet head curve of the jet_in_star preset, from the analytic head
+ * velocity of a collimated jet (Bromberg et al. 2011) through the progenitor
+ * of Duffell & MacFadyen (2015). It is regenerated by tools/jet-head-reference.
+ */
+static JET_IN_STAR_REFERENCE: &str = include_str!("../setups/jet_in_star_jet_head.csv");
+
+
+
+
+/**
+ * A reference curve of the jet head radius (cm) versus time (s), such as
+ * one digitized from a published figure. The curve is read from a two-column
+ * table, in any format accepted by [`LookupTable::from_delimited_table`], and
+ * may be stretched in time and radius to apply to a different model.
+ */
+pub struct ReferenceCurve {
+    table: LookupTable<2>,
+    time_scale: f64,
+    radius_scale: f64,
+}
+
+
+
+
+/**
+ * The jet head radius measured from a simulation output, together with the
+ * reference curve value at the same time, if one is available
+ */
+pub struct JetHeadSample {
+    pub time: f64,
+    pub radius: Option<f64>,
+    pub reference: Option<f64>,
+}
+
+
+
+
+// ============================================================================
+impl ReferenceCurve {
+
+    /**
+     * Load a reference curve from a delimited ASCII file.
+     */
+    pub fn from_file(filename: &str) -> Result<Self, lookup_table_v2::Error> {
+        Ok(Self { table: LookupTable::from_delimited_file(filename)?, time_scale: 1.0, radius_scale: 1.0 })
+    }
+
+    /**
+     * Return the reference curve shipped for the jet_in_star model, scaled to
+     * the given model: radii by its stellar radius, and times by its
+     * predicted breakout time (see [`scaled_breakout_time`]), both relative
+     * to the jet_in_star preset. For a model with the preset parameters, the
+     * curve is used as tabulated.
+     */
+    pub fn jet_in_star(model: &JetInStar) -> anyhow::Result<Self> {
+        let preset = jet_in_star_preset()?;
+        let reference = Self {
+            table: LookupTable::from_delimited_table(JET_IN_STAR_REFERENCE)?,
+            time_scale: 1.0,
+            radius_scale: 1.0,
+        };
+        Ok(Self {
+            time_scale: breakout_time_scaling(model, &preset),
+            radius_scale: model.stellar_radius() / preset.stellar_radius(),
+            ..reference
+        })
+    }
+
+    /**
+     * Return the reference value at time t, or `None` if t is outside the
+     * tabulated time range.
+     */
+    pub fn sample(&self, t: f64) -> Option<f64> {
+        let (t0, t1) = self.table.domain();
+        let t = t / self.time_scale;
+
+        if t > t0 && t <= t1 {
+            Some(self.radius_scale * self.table.sample(t)[1])
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Return the time when the reference curve first reaches the given
+     * radius, or `None` if it does not reach it within the tabulated range.
+     */
+    pub fn crossing_time(&self, radius: f64) -> Option<f64> {
+        let (t0, t1) = self.table.domain();
+        let num_samples = 1000;
+        let samples: Vec<_> = (0..=num_samples)
+            .map(|i| t0 + (t1 - t0) * i as f64 / num_samples as f64)
+            .map(|t| JetHeadSample { time: self.time_scale * t, radius: Some(self.radius_scale * self.table.sample(t)[1]), reference: None })
+            .collect();
+        breakout_time(&samples, radius)
+    }
+}
+
+
+
+
+// ============================================================================
+impl JetHeadSample {
+
+    /**
+     * Measure the jet head radius in the given products, and sample the
+     * reference curve (if any) at the products time.
+     */
+    pub fn from_products(products: &Products, reference: Option<&ReferenceCurve>, scalar_threshold: f64) -> Self {
+        Self {
+            time: products.time,
+            radius: products.jet_head_radius(scalar_threshold),
+            reference: reference.and_then(|curve| curve.sample(products.time)),
+        }
+    }
+
+    /**
+     * Return the deviation of the measured radius from the reference value,
+     * relative to the reference value.
+     */
+    pub fn relative_deviation(&self) -> Option<f64> {
+        match (self.radius, self.reference) {
+            (Some(r), Some(r_ref)) => Some((r - r_ref) / r_ref),
+            _ => None,
+        }
+    }
+}
+
+
+
+
+/**
+ * Return the time when the jet head first crosses the given radius, linearly
+ * interpolated between the samples, which must be ordered in time. Returns
+ * `None` if the jet head never reaches that radius.
+ */
+pub fn breakout_time(samples: &[JetHeadSample], radius: f64) -> Option<f64> {
+    let mut previous: Option<(f64, f64)> = None;
+
+    for sample in samples {
+        if let Some(r) = sample.radius {
+            if r >= radius {
+                return match previous {
+                    Some((t0, r0)) => Some(t0 + (sample.time - t0) * (radius - r0) / (r - r0)),
+                    None => Some(sample.time),
+                }
+            }
+            previous = Some((sample.time, r));
+        }
+    }
+    None
+}
+
+
+
+
+/**
+ * Return the radius beyond which the jet is considered to have broken out of
+ * the progenitor, for models where that is meaningful.
+ */
+pub fn breakout_radius(model: &AnyModel) -> Option<f64> {
+    match model {
+        AnyModel::JetInStar(model) => Some(model.stellar_radius()),
+        _ => None,
+    }
+}
+
+
+
+
+
+/**
+ * Return the breakout time of the given jet_in_star model expected from the
+ * shipped reference curve, by the breakout time scaling of a collimated jet
+ * (Bromberg et al. 2011), t_b ~ L^(-1/3) θ^(4/3) R^(2/3) M^(1/3). Here L is
+ * the true luminosity of one jet, θ the engine opening angle, R the stellar
+ * radius, and M ~ ρc R0³ the progenitor mass scale. The scaling assumes the
+ * progenitor has the shape of the default profile.
+ */
+pub fn scaled_breakout_time(model: &JetInStar) -> anyhow::Result<f64> {
+    let preset = jet_in_star_preset()?;
+    let reference = ReferenceCurve::jet_in_star(&preset)?;
+    let breakout = reference.crossing_time(preset.stellar_radius())
+        .ok_or_else(|| anyhow::anyhow!("the jet_in_star reference curve does not reach the stellar surface"))?;
+    Ok(breakout * breakout_time_scaling(model, &preset))
+}
+
+
+
+
+/**
+ * Return the ratio of the breakout times of two jet_in_star models, by the
+ * scaling given for [`scaled_breakout_time`].
+ */
+fn breakout_time_scaling(model: &JetInStar, reference: &JetInStar) -> f64 {
+    let jet_luminosity = |m: &JetInStar| m.engine_energy / m.engine_duration * (1.0 - m.engine_theta.cos()) / 2.0;
+    let mass_scale = |m: &JetInStar| m.progenitor_rho_c * m.progenitor_r0.powi(3);
+
+    (jet_luminosity(model) / jet_luminosity(reference)).powf(-1.0 / 3.0)
+        * (model.engine_theta / reference.engine_theta).powf(4.0 / 3.0)
+        * (model.stellar_radius() / reference.stellar_radius()).powf(2.0 / 3.0)
+        * (mass_scale(model) / mass_scale(reference)).powf(1.0 / 3.0)
+}
+
+
+
+
+/**
+ * Return the jet_in_star model of the preset the reference curve was made
+ * for.
+ */
+fn jet_in_star_preset() -> anyhow::Result<JetInStar> {
+    let config: serde_yaml::Value = serde_yaml::from_str(include_str!("../setups/jet_in_star.yaml"))?;
+    Ok(serde_yaml::from_value(config["model"]["jet_in_star"].clone())?)
+}
+
+
+
+
+/**
+ * The shock radius and peak post-shock state of a blast wave, measured
+ * along one radial ray
+ */
+#[derive(Clone, Copy)]
+pub struct BlastProfile {
+    pub shock_radius: f64,
+    pub peak_density: f64,
+    pub peak_pressure: f64,
+    pub peak_gamma_beta: f64,
+}
+
+
+
+
+// ============================================================================
+impl BlastProfile {
+
+    /**
+     * Measure the blast profile from a radial ray of (r, primitive) pairs,
+     * ordered by radius. The shock is the outermost zone where the pressure
+     * exceeds the ambient pressure by [`SHOCK_PRESSURE_JUMP`], and the peak
+     * values are taken over the swept-up material, between the initial blast
+     * radius and the shock. Returns `None` if no shock is found.
+     */
+    pub fn from_ray(ray: &[(f64, AnyPrimitive)], blast_radius: f64, ambient_pressure: f64) -> Option<Self> {
+        let shock = ray.iter().rposition(|(_, p)| p.gas_pressure > ambient_pressure * (1.0 + SHOCK_PRESSURE_JUMP))?;
+        let swept_up = ray[..=shock].iter().filter(|(r, _)| *r > blast_radius).map(|(_, p)| p);
+
+        let mut profile = Self {
+            shock_radius: ray[shock].0,
+            peak_density: 0.0,
+            peak_pressure: 0.0,
+            peak_gamma_beta: 0.0,
+        };
+        for p in swept_up {
+            profile.peak_density = profile.peak_density.max(p.mass_density);
+            profile.peak_pressure = profile.peak_pressure.max(p.gas_pressure);
+            profile.peak_gamma_beta = profile.peak_gamma_beta.max(p.velocity_r);
+        }
+        Some(profile)
+    }
+
+    /**
+     * Return the relative error of each measured quantity with respect to a
+     * reference profile.
+     */
+    pub fn relative_error(&self, reference: &Self) -> Self {
+        let rel = |a: f64, b: f64| if b == 0.0 { 0.0 } else { (a - b) / b };
+
+        Self {
+            shock_radius: rel(self.shock_radius, reference.shock_radius),
+            peak_density: rel(self.peak_density, reference.peak_density),
+            peak_pressure: rel(self.peak_pressure, reference.peak_pressure),
+            peak_gamma_beta: rel(self.peak_gamma_beta, reference.peak_gamma_beta),
+        }
+    }
+}
+
+
+
+
+/**
+ * Return the radial rays of (r, primitive) pairs in the products, one for
+ * each polar zone. The radius is the zone's outer vertex.
+ */
+pub fn radial_rays(products: &Products) -> Vec<Vec<(f64, AnyPrimitive)>> {
+    let mut blocks: Vec<_> = products.blocks.iter().collect();
+    blocks.sort_by_key(|(index, _)| **index);
+
+    let num_polar_zones = blocks.first().map_or(0, |(_, block)| block.primitive.dim().1);
+
+    (0..num_polar_zones).map(|j| {
+        blocks.iter().flat_map(|(_, block)| {
+            (0..block.primitive.dim().0).map(move |i| (block.radial_vertices[i + 1], block.primitive[(i, j)].clone()))
+        }).collect()
+    }).collect()
+}
+
+
+
+
+/**
+ * Evolve a state up to the given time, without any side effects. This is
+ * used to compute reference solutions.
+ */
+pub fn evolve<H, C>(mut state: State<C>, hydro: &H, model: &AnyModel, mesh: &Mesh, settings: &SchemeSettings, final_time: f64, num_threads: usize) -> anyhow::Result<State<C>>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved,
+{
+    let mut geometry = state.block_geometry(mesh);
+    let settings = SchemeSettings { fold: 1, ..settings.clone() };
+    let work = WorkLedger::default();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(num_threads)
+        .build()?;
+
+    while state.time < final_time {
+        state = scheme::advance(state, hydro, model, mesh, &mut geometry, &runtime, &work, &settings, final_time)?;
+    }
+    Ok(state)
+}
+
+
+
+
+/**
+ * Compute a high-resolution, one-dimensional reference solution for the given
+ * configuration, evolved from the configured start time up to the given time.
+ * The radial resolution is increased by the given refinement factor.
+ */
+pub fn one_dimensional_reference<H, C>(hydro: &H, config: &Configuration, time: f64, refinement: usize) -> anyhow::Result<Products>
+where
+    H: Hydrodynamics<Conserved = C>,
+    C: Conserved,
+{
+    let mut config = config.clone();
+    let num_radial_zones = (refinement as f64 / config.mesh.zone_dlogr()).ceil() as usize;
+
+    config.mesh = Mesh {
+        num_polar_zones: 1,
+        polar_refocusing: None,
+        num_radial_zones: Some(num_radial_zones),
+        ..config.mesh
+    };
+    let start_time = config.control.start_time;
+    let geometry = config.mesh.grid_blocks_geometry(start_time);
+    let state = State::from_model(&config.model, hydro, &geometry, start_time);
+    let settings = SchemeSettings::new(&config.control, hydro);
+    let state = evolve(state, hydro, &config.model, &config.mesh, &settings, time, config.control.num_threads())?;
+
+    Ok(Products::try_from_state(&state, hydro, &config)?)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakout_time_follows_the_luminosity_scaling() {
+        let preset = jet_in_star_preset().unwrap();
+        let brighter = JetInStar { engine_energy: 8.0 * preset.engine_energy, ..preset.clone() };
+        let t0 = scaled_breakout_time(&preset).unwrap();
+        let t1 = scaled_breakout_time(&brighter).unwrap();
+
+        assert!(t0 > 6.0 && t0 < 7.0);
+        assert!((t1 - 0.5 * t0).abs() < 1e-12 * t0);
+        assert!((ReferenceCurve::jet_in_star(&brighter).unwrap().crossing_time(brighter.stellar_radius()).unwrap() - t1).abs() < 1e-2 * t1);
+    }
+}
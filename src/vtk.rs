@@ -0,0 +1,103 @@
+use std::io::Write;
+use crate::physics::AnyPrimitive;
+use crate::products::Products;
+
+/// VTK cell type of a four-vertex quadrilateral
+static VTK_QUAD: i32 = 9;
+
+
+
+
+/**
+ * Write products to a legacy-format binary VTK file, as an unstructured grid
+ * in the meridional (x-z) plane, with x = r sin(θ) and z = r cos(θ). Each
+ * zone is a quadrilateral between its four vertices, so the cell geometry is
+ * exact up to the curvature of the polar faces. The cell data are the
+ * primitive fields, the scalar, and the velocity (the four-velocity for
+ * relativistic hydro) as a vector in the x-z plane. The file can be opened
+ * in ParaView or VisIt, where the Rotational Extrusion filter recovers the
+ * three-dimensional axisymmetric picture.
+ */
+pub fn write_vtk(products: &Products, filename: &str) -> anyhow::Result<()> {
+    let mut indexes: Vec<_> = products.blocks.keys().cloned().collect();
+    indexes.sort();
+
+    let mut points = Vec::new();
+    let mut cells = Vec::new();
+    let mut primitives: Vec<(AnyPrimitive, f64, f64)> = Vec::new();
+
+    for index in &indexes {
+        let block = &products.blocks[index];
+        let (nr, nq) = block.primitive.dim();
+        let offset = points.len() / 3;
+
+        for i in 0..=nr {
+            for j in 0..=nq {
+                let (r, q) = (block.radial_vertices[i], block.polar_vertices[j]);
+                points.extend_from_slice(&[r * q.sin(), 0.0, r * q.cos()]);
+            }
+        }
+        for i in 0..nr {
+            for j in 0..nq {
+                let vertex = |i: usize, j: usize| (offset + i * (nq + 1) + j) as i32;
+                let q = 0.5 * (block.polar_vertices[j] + block.polar_vertices[j + 1]);
+                cells.extend_from_slice(&[4, vertex(i, j), vertex(i + 1, j), vertex(i + 1, j + 1), vertex(i, j + 1)]);
+                primitives.push((block.primitive[(i, j)].clone(), block.scalar[(i, j)], q));
+            }
+        }
+    }
+    let num_points = points.len() / 3;
+    let num_cells = primitives.len();
+    let field = |f: fn(&(AnyPrimitive, f64, f64)) -> f64| primitives.iter().map(f).collect::<Vec<_>>();
+    let velocity: Vec<_> = primitives.iter().flat_map(|(p, _, q)| {
+        vec![p.velocity_r * q.sin() + p.velocity_q * q.cos(), 0.0, p.velocity_r * q.cos() - p.velocity_q * q.sin()]
+    }).collect();
+
+    println!("write {}", filename);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+
+    writeln!(file, "# vtk DataFile Version 3.0")?;
+    writeln!(file, "kilonova products t={:.6e}", products.time)?;
+    writeln!(file, "BINARY\nDATASET UNSTRUCTURED_GRID")?;
+    writeln!(file, "POINTS {} double", num_points)?;
+    write_big_endian_f64(&mut file, &points)?;
+    writeln!(file, "\nCELLS {} {}", num_cells, cells.len())?;
+    write_big_endian_i32(&mut file, &cells)?;
+    writeln!(file, "\nCELL_TYPES {}", num_cells)?;
+    write_big_endian_i32(&mut file, &vec![VTK_QUAD; num_cells])?;
+    writeln!(file, "\nCELL_DATA {}", num_cells)?;
+
+    for (name, data) in &[
+        ("mass_density", field(|(p, _, _)| p.mass_density)),
+        ("velocity_r", field(|(p, _, _)| p.velocity_r)),
+        ("velocity_q", field(|(p, _, _)| p.velocity_q)),
+        ("gas_pressure", field(|(p, _, _)| p.gas_pressure)),
+        ("scalar", field(|(_, s, _)| *s)),
+    ] {
+        writeln!(file, "SCALARS {} double 1\nLOOKUP_TABLE default", name)?;
+        write_big_endian_f64(&mut file, data)?;
+        writeln!(file)?;
+    }
+    writeln!(file, "VECTORS velocity double")?;
+    write_big_endian_f64(&mut file, &velocity)?;
+    writeln!(file)?;
+    Ok(())
+}
+
+
+
+
+// ============================================================================
+fn write_big_endian_f64<W: Write>(writer: &mut W, data: &[f64]) -> std::io::Result<()> {
+    for x in data {
+        writer.write_all(&x.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_big_endian_i32<W: Write>(writer: &mut W, data: &[i32]) -> std::io::Result<()> {
+    for x in data {
+        writer.write_all(&x.to_be_bytes())?;
+    }
+    Ok(())
+}